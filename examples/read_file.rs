@@ -0,0 +1,87 @@
+//! Reads a file with overlapped I/O, the completion coming back from `poll`.
+//! Windows only, as is `Registry::register_handle`.
+
+#[cfg(windows)]
+use iocp_wrapper::event;
+#[cfg(windows)]
+use iocp_wrapper::{Events, Poll, Token};
+#[cfg(windows)]
+use std::fs::{self, OpenOptions};
+#[cfg(windows)]
+use std::io;
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
+#[cfg(windows)]
+use std::os::windows::io::AsRawHandle;
+#[cfg(windows)]
+use std::ptr::null_mut;
+#[cfg(windows)]
+use std::{env, mem};
+#[cfg(windows)]
+use winapi::shared::winerror::ERROR_IO_PENDING;
+#[cfg(windows)]
+use winapi::um::fileapi::ReadFile;
+#[cfg(windows)]
+use winapi::um::minwinbase::OVERLAPPED;
+#[cfg(windows)]
+use winapi::um::winbase::FILE_FLAG_OVERLAPPED;
+#[cfg(windows)]
+use winapi::um::winnt::HANDLE;
+
+#[cfg(windows)]
+const FILE: Token = Token(0);
+
+#[cfg(windows)]
+fn main() -> io::Result<()> {
+    let path = env::temp_dir().join("iocp-wrapper-read-file.txt");
+    fs::write(&path, "read without blocking\n")?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_OVERLAPPED)
+        .open(&path)?;
+    poll.registry().register_handle(&file, FILE)?;
+
+    //Both have to stay put until the completion is back
+    let mut overlapped: Box<OVERLAPPED> = Box::new(unsafe { mem::zeroed() });
+    let mut buf = vec![0; 4096];
+    let r = unsafe {
+        ReadFile(
+            file.as_raw_handle() as HANDLE,
+            buf.as_mut_ptr() as *mut _,
+            buf.len() as u32,
+            null_mut(),
+            &mut *overlapped,
+        )
+    };
+    //Finished right away or not, the completion is queued on the port
+    if r == 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(ERROR_IO_PENDING as i32) {
+            return Err(e);
+        }
+    }
+
+    loop {
+        poll.poll(&mut events, None)?;
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            if event::token(event) != FILE {
+                continue;
+            }
+            if let Some(completion) = event::io_completion(event) {
+                let n = completion.result()?;
+                print!("{} bytes: {}", n, String::from_utf8_lossy(&buf[..n]));
+                drop(file);
+                return fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("overlapped I/O is only there on Windows");
+}
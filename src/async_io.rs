@@ -0,0 +1,422 @@
+use crate::event::{self, Source};
+use crate::interests::Interests;
+use crate::poll::{Poll, Registry};
+use crate::sys::Events;
+use crate::token::Token;
+use std::collections::HashMap;
+use std::future;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{self, Context};
+use std::thread;
+
+/// A source for futures to wait on, polled by a reactor thread.
+///
+/// The reactor is a thread of its own, started by the first `Async` and
+/// polling for the rest of the process, so no runtime is needed: any
+/// executor will do, even `futures::executor::block_on`. A task waiting for
+/// a direction has its waker called once the source is ready for it.
+///
+/// Readiness is a hint, nothing more. A waker may be called for nothing, and
+/// an operation may still fail with `WouldBlock` after [`Async::readable`]
+/// completed: try it, and wait again if it would block, which is what
+/// [`Async::read_with`] and [`Async::write_with`] do. One task at a time
+/// waits for each direction, a waker replaces the one before.
+///
+/// The source is registered with the reactor while a task waits for it, and
+/// deregistered when the `Async` is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use futures::executor::block_on;
+/// use iocp_wrapper::{Async, IoSource};
+/// use std::io::Read;
+/// use std::net::TcpStream;
+///
+/// let stream = TcpStream::connect("127.0.0.1:8080")?;
+/// stream.set_nonblocking(true)?;
+/// let stream = Async::new(IoSource::new(stream))?;
+///
+/// let mut buf = [0; 1024];
+/// let n = block_on(stream.read_with(|stream| (&**stream).read(&mut buf)))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Async<T: Source + Send + Sync + 'static> {
+    reactor: Arc<Reactor>,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Source + Send + Sync + 'static> Async<T> {
+    /// Wraps `io`, which has to be in non-blocking mode already, starting the
+    /// reactor if it isn't running yet.
+    pub fn new(io: T) -> io::Result<Async<T>> {
+        let reactor = reactor()?;
+        let token = Token(reactor.next_token.fetch_add(1, Ordering::Relaxed));
+        let shared = Arc::new(Shared {
+            io,
+            token,
+            state: Mutex::new(State::default()),
+        });
+        reactor
+            .sources
+            .lock()
+            .unwrap()
+            .insert(token, shared.clone());
+        Ok(Async { reactor, shared })
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.shared.io
+    }
+
+    /// Completes once the source is readable, or has an error or a hang-up
+    /// to report. Otherwise `cx` is woken up when it is.
+    pub fn poll_readable(&self, cx: &mut Context<'_>) -> task::Poll<io::Result<()>> {
+        self.poll_ready(cx, Interests::READABLE)
+    }
+
+    /// Completes once the source is writable, or has an error or a hang-up
+    /// to report. Otherwise `cx` is woken up when it is.
+    pub fn poll_writable(&self, cx: &mut Context<'_>) -> task::Poll<io::Result<()>> {
+        self.poll_ready(cx, Interests::WRITABLE)
+    }
+
+    /// Waits for the source to be readable, see [`Async::poll_readable`].
+    pub async fn readable(&self) -> io::Result<()> {
+        future::poll_fn(|cx| self.poll_readable(cx)).await
+    }
+
+    /// Waits for the source to be writable, see [`Async::poll_writable`].
+    pub async fn writable(&self) -> io::Result<()> {
+        future::poll_fn(|cx| self.poll_writable(cx)).await
+    }
+
+    /// Runs `op` until it doesn't fail with `WouldBlock`, waiting for the
+    /// source to be readable in between.
+    pub async fn read_with<F, R>(&self, mut op: F) -> io::Result<R>
+    where
+        F: FnMut(&T) -> io::Result<R>,
+    {
+        loop {
+            match op(self.get_ref()) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => self.readable().await?,
+                result => return result,
+            }
+        }
+    }
+
+    /// Runs `op` until it doesn't fail with `WouldBlock`, waiting for the
+    /// source to be writable in between.
+    pub async fn write_with<F, R>(&self, mut op: F) -> io::Result<R>
+    where
+        F: FnMut(&T) -> io::Result<R>,
+    {
+        loop {
+            match op(self.get_ref()) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => self.writable().await?,
+                result => return result,
+            }
+        }
+    }
+
+    fn poll_ready(&self, cx: &mut Context<'_>, interest: Interests) -> task::Poll<io::Result<()>> {
+        if self.reactor.stopped.load(Ordering::SeqCst) {
+            return task::Poll::Ready(Err(io::Error::other("the reactor thread stopped polling")));
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        let direction = state.direction(interest);
+        if direction.ready {
+            direction.ready = false;
+            return task::Poll::Ready(Ok(()));
+        }
+        match direction.waker {
+            Some(ref waker) if waker.will_wake(cx.waker()) => {}
+            _ => direction.waker = Some(cx.waker().clone()),
+        }
+
+        let registry = &self.reactor.registry;
+        let io = &self.shared.io;
+        let token = self.shared.token;
+        let result = match state.interests {
+            Some(current) if current.contains(interest) => Ok(current),
+            Some(current) => registry
+                .reregister(io, token, current | interest)
+                .map(|_| current | interest),
+            None => registry.register(io, token, interest).map(|_| interest),
+        };
+        match result {
+            Ok(interests) => {
+                state.interests = Some(interests);
+                task::Poll::Pending
+            }
+            Err(e) => {
+                state.direction(interest).waker = None;
+                task::Poll::Ready(Err(e))
+            }
+        }
+    }
+}
+
+impl<T: Source + Send + Sync + 'static> Drop for Async<T> {
+    fn drop(&mut self) {
+        self.reactor
+            .sources
+            .lock()
+            .unwrap()
+            .remove(&self.shared.token);
+        if self.shared.state.lock().unwrap().interests.take().is_some() {
+            let _ = self.reactor.registry.deregister(&self.shared.io);
+        }
+    }
+}
+
+struct Shared<T> {
+    io: T,
+    token: Token,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    //what the source is registered for, None while it isn't
+    interests: Option<Interests>,
+    readable: Direction,
+    writable: Direction,
+}
+
+impl State {
+    fn direction(&mut self, interest: Interests) -> &mut Direction {
+        if interest == Interests::READABLE {
+            &mut self.readable
+        } else {
+            &mut self.writable
+        }
+    }
+}
+
+#[derive(Default)]
+struct Direction {
+    //an event came that no task took yet
+    ready: bool,
+    waker: Option<task::Waker>,
+}
+
+//What the reactor sees of a source, whatever its type. The wakers to call
+//are added to `wakers`, for the reactor to call once it let go of its locks.
+trait Entry: Send + Sync {
+    fn fire(
+        &self,
+        registry: &Registry,
+        readable: bool,
+        writable: bool,
+        wakers: &mut Vec<task::Waker>,
+    );
+
+    fn stop(&self, wakers: &mut Vec<task::Waker>);
+}
+
+impl<T: Source + Send + Sync> Entry for Shared<T> {
+    fn fire(
+        &self,
+        registry: &Registry,
+        readable: bool,
+        writable: bool,
+        wakers: &mut Vec<task::Waker>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let mut left = state.interests;
+        if readable {
+            state.readable.ready = true;
+            wakers.extend(state.readable.waker.take());
+            left = left.and_then(|interests| interests.remove(Interests::READABLE));
+        }
+        if writable {
+            state.writable.ready = true;
+            wakers.extend(state.writable.waker.take());
+            left = left.and_then(|interests| interests.remove(Interests::WRITABLE));
+        }
+
+        //Readiness is level-triggered: left registered for, what nobody
+        //waits for anymore would be reported on every poll. Should this
+        //fail, the registration stays as it was, for extra wake-ups only.
+        if left != state.interests {
+            let result = match left {
+                Some(interests) => registry.reregister(&self.io, self.token, interests),
+                None => registry.deregister(&self.io),
+            };
+            if result.is_ok() {
+                state.interests = left;
+            }
+        }
+    }
+
+    fn stop(&self, wakers: &mut Vec<task::Waker>) {
+        let mut state = self.state.lock().unwrap();
+        wakers.extend(state.readable.waker.take());
+        wakers.extend(state.writable.waker.take());
+    }
+}
+
+struct Reactor {
+    //the thread has the `Poll`, this is another handle on it
+    registry: Registry,
+    sources: Mutex<HashMap<Token, Arc<dyn Entry>>>,
+    next_token: AtomicUsize,
+    stopped: AtomicBool,
+}
+
+lazy_static! {
+    //Started by the first `Async`, running for the rest of the process
+    static ref REACTOR: Mutex<Option<Arc<Reactor>>> = Mutex::new(None);
+}
+
+fn reactor() -> io::Result<Arc<Reactor>> {
+    let mut reactor = REACTOR.lock().unwrap();
+    match *reactor {
+        Some(ref reactor) => Ok(reactor.clone()),
+        None => {
+            let started = Reactor::start()?;
+            *reactor = Some(started.clone());
+            Ok(started)
+        }
+    }
+}
+
+impl Reactor {
+    fn start() -> io::Result<Arc<Reactor>> {
+        let mut poll = Poll::new()?;
+        let reactor = Arc::new(Reactor {
+            registry: poll.registry().duplicate(),
+            sources: Mutex::new(HashMap::new()),
+            next_token: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+        });
+        let thread_reactor = reactor.clone();
+        thread::Builder::new()
+            .name("iocp-wrapper-reactor".into())
+            .spawn(move || thread_reactor.run(&mut poll))?;
+        Ok(reactor)
+    }
+
+    fn run(&self, poll: &mut Poll) {
+        let mut events = Events::with_capacity(256);
+        //Woken with no lock held: a task woken may run right away on another
+        //thread, and drop or make an `Async`, which takes `sources`
+        let mut wakers = Vec::new();
+        loop {
+            match poll.poll(&mut events, None) {
+                Ok(()) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+            let sources = self.sources.lock().unwrap();
+            for i in 0..events.len() {
+                let event = events.get(i).unwrap();
+                //Gone since, if not there
+                if let Some(source) = sources.get(&event::token(event)) {
+                    //Errors and hang-ups end both directions
+                    let closed = event::is_error(event) || event::is_hup(event);
+                    let readable =
+                        closed || event::is_readable(event) || event::is_read_closed(event);
+                    source.fire(
+                        &self.registry,
+                        readable,
+                        closed || event::is_writable(event),
+                        &mut wakers,
+                    );
+                }
+            }
+            drop(sources);
+            for waker in wakers.drain(..) {
+                waker.wake();
+            }
+        }
+
+        //Nothing to wait for anymore, those waiting find out
+        self.stopped.store(true, Ordering::SeqCst);
+        for source in self.sources.lock().unwrap().values() {
+            source.stop(&mut wakers);
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+#[test]
+fn test_async_echo() -> io::Result<()> {
+    use crate::IoSource;
+    use futures::executor::block_on;
+    use futures::future;
+    use futures::task::noop_waker;
+    use std::io::{Read, Write};
+    use std::net::{self, Shutdown};
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let listener = Async::new(IoSource::new(listener))?;
+    let client = net::TcpStream::connect(listener.get_ref().local_addr()?)?;
+    client.set_nonblocking(true)?;
+    let client = Async::new(IoSource::new(client))?;
+
+    //Nothing to read yet, however often asked
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    assert!(client.poll_readable(&mut cx).is_pending());
+    assert!(client.poll_readable(&mut cx).is_pending());
+
+    let server = async {
+        let (server, _) = listener.read_with(|listener| listener.accept()).await?;
+        server.set_nonblocking(true)?;
+        let server = Async::new(IoSource::new(server))?;
+
+        let mut buf = [0; 1024];
+        loop {
+            let n = server
+                .read_with(|server| (&**server).read(&mut buf))
+                .await?;
+            if n == 0 {
+                return Ok(());
+            }
+            let mut sent = 0;
+            while sent < n {
+                sent += server
+                    .write_with(|server| server.do_io(|mut server| server.write(&buf[sent..n])))
+                    .await?;
+            }
+        }
+    };
+    let pings = async {
+        let mut buf = [0; 1024];
+        for round in 0..3 {
+            let ping = format!("ping {}", round);
+            client
+                .write_with(|client| client.do_io(|mut client| client.write_all(ping.as_bytes())))
+                .await?;
+            let mut echo = Vec::new();
+            while echo.len() < ping.len() {
+                let n = client
+                    .read_with(|client| (&**client).read(&mut buf))
+                    .await?;
+                echo.extend_from_slice(&buf[..n]);
+            }
+            assert_eq!(echo, ping.as_bytes());
+        }
+        client.get_ref().shutdown(Shutdown::Write)
+    };
+    let (served, pinged): (io::Result<()>, io::Result<()>) = block_on(future::join(server, pings));
+    served?;
+    pinged?;
+
+    //Each is deregistered when dropped
+    drop(client);
+    drop(listener);
+    assert_eq!(reactor()?.registry.selector().registered_count(), 0);
+
+    Ok(())
+}
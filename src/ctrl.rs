@@ -0,0 +1,281 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event::{self, Event};
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::readiness::Readiness;
+use crate::sys::Selector;
+use crate::token::Token;
+use miow::iocp::CompletionStatus;
+use miow::Overlapped;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+use winapi::um::consoleapi::SetConsoleCtrlHandler;
+use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT};
+
+//Completion key of console signals, see `ACCEPT_KEY`
+pub(crate) const CTRL_KEY: usize = 6;
+
+//The handler is one per process, and routes to every registered `CtrlC`
+lazy_static! {
+    static ref ROUTES: Mutex<Routes> = Mutex::new(Routes {
+        sources: 0,
+        routes: HashMap::new(),
+    });
+}
+
+//Numbers registrations. Never 0: the id is passed as the overlapped pointer,
+//and a null one is a wakeup
+static NEXT_ROUTE: AtomicUsize = AtomicUsize::new(1);
+
+struct Routes {
+    //`CtrlC`s alive, the handler is installed while there are any
+    sources: usize,
+    routes: HashMap<usize, (Selector, Token)>,
+}
+
+/// A console signal, see [`event::ctrl_signal`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CtrlSignal {
+    CtrlC,
+    CtrlBreak,
+    /// The console is being closed. The process is ended shortly after
+    /// anyway, there is little time to react.
+    Close,
+}
+
+impl CtrlSignal {
+    fn from_raw(ctrl_type: DWORD) -> Option<CtrlSignal> {
+        match ctrl_type {
+            CTRL_C_EVENT => Some(CtrlSignal::CtrlC),
+            CTRL_BREAK_EVENT => Some(CtrlSignal::CtrlBreak),
+            CTRL_CLOSE_EVENT => Some(CtrlSignal::Close),
+            _ => None,
+        }
+    }
+}
+
+/// Reports Ctrl-C, Ctrl-Break and the console closing as readable events,
+/// each with a [`CtrlSignal`].
+///
+/// The first `CtrlC` installs a console control handler, the last one to be
+/// dropped removes it. While a `CtrlC` is registered a signal is reported
+/// to it instead of ending the process, to all of them if there are a few.
+/// With none registered the signal is passed on to the next handler, by
+/// default the one ending the process.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::event::ctrl_signal;
+/// use iocp_wrapper::{CtrlC, CtrlSignal, Events, Interests, Poll, Token};
+///
+/// let mut poll = Poll::new()?;
+/// let ctrl_c = CtrlC::new()?;
+/// poll.registry()
+///     .register(&ctrl_c, Token(0), Interests::READABLE)?;
+///
+/// let mut events = Events::with_capacity(8);
+/// loop {
+///     poll.poll(&mut events, None)?;
+///     let stop = (0..events.len())
+///         .any(|i| ctrl_signal(events.get(i).unwrap()) == Some(CtrlSignal::CtrlC));
+///     if stop {
+///         break;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CtrlC {
+    route: Mutex<Option<usize>>,
+}
+
+impl CtrlC {
+    pub fn new() -> io::Result<CtrlC> {
+        let mut routes = ROUTES.lock().unwrap();
+        if routes.sources == 0 && unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        routes.sources += 1;
+        Ok(CtrlC {
+            route: Mutex::new(None),
+        })
+    }
+}
+
+//Called on a thread of its own for each signal
+unsafe extern "system" fn handler(ctrl_type: DWORD) -> BOOL {
+    if CtrlSignal::from_raw(ctrl_type).is_none() {
+        return FALSE;
+    }
+    let routes = ROUTES.lock().unwrap();
+    if routes.routes.is_empty() {
+        return FALSE;
+    }
+    for (&id, &(ref selector, _)) in routes.routes.iter() {
+        let status = CompletionStatus::new(ctrl_type, CTRL_KEY, id as *mut Overlapped);
+        //The port is gone with its poll: nobody left to tell
+        let _ = selector.port().post(status);
+    }
+    TRUE
+}
+
+//Handles a completion taken from the port with `CTRL_KEY`
+pub(crate) fn complete(status: &CompletionStatus, events: &mut Vec<Event>) {
+    let id = status.overlapped() as usize;
+    //Dropped if the route went away since it was posted
+    let token = match ROUTES.lock().unwrap().routes.get(&id) {
+        Some(&(_, token)) => token,
+        None => return,
+    };
+    if let Some(signal) = CtrlSignal::from_raw(status.bytes_transferred()) {
+        events.push(Event::from_ctrl(Readiness::READABLE, signal, token));
+    }
+}
+
+impl event::Source for CtrlC {
+    fn register(&self, registry: &Registry, token: Token, _interests: Interests) -> io::Result<()> {
+        let mut route = self.route.lock().unwrap();
+        if route.is_some() {
+            return Err(misuse(
+                ErrorKind::AlreadyRegistered,
+                Some(token),
+                "ctrl-c source is already registered",
+            ));
+        }
+        *route = Some(add_route(registry.selector(), token));
+        Ok(())
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        let mut route = self.route.lock().unwrap();
+        let id = route.take().ok_or_else(|| not_registered(Some(token)))?;
+        //A fresh id, so what was posted for the old token is dropped
+        ROUTES.lock().unwrap().routes.remove(&id);
+        *route = Some(add_route(registry.selector(), token));
+        Ok(())
+    }
+
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        let id = self
+            .route
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| not_registered(None))?;
+        ROUTES.lock().unwrap().routes.remove(&id);
+        Ok(())
+    }
+}
+
+fn add_route(selector: &Selector, token: Token) -> usize {
+    let id = NEXT_ROUTE.fetch_add(1, Ordering::Relaxed);
+    ROUTES
+        .lock()
+        .unwrap()
+        .routes
+        .insert(id, (selector.clone(), token));
+    id
+}
+
+fn not_registered(token: Option<Token>) -> io::Error {
+    misuse(
+        ErrorKind::NotRegistered,
+        token,
+        "ctrl-c source is not registered",
+    )
+}
+
+impl Drop for CtrlC {
+    fn drop(&mut self) {
+        let route = self.route.lock().unwrap().take();
+        let mut routes = ROUTES.lock().unwrap();
+        if let Some(id) = route {
+            routes.routes.remove(&id);
+        }
+        routes.sources -= 1;
+        if routes.sources == 0 {
+            unsafe { SetConsoleCtrlHandler(Some(handler), FALSE) };
+        }
+    }
+}
+
+//Runs in a process group of its own, see `test_ctrl_break`
+#[test]
+fn test_ctrl_break_child() -> io::Result<()> {
+    use crate::event::{ctrl_signal, token};
+    use crate::{Events, Poll};
+    use std::time::Duration;
+    use winapi::um::processthreadsapi::GetCurrentProcessId;
+    use winapi::um::wincon::GenerateConsoleCtrlEvent;
+
+    if std::env::var_os("IOCP_WRAPPER_CTRL_CHILD").is_none() {
+        return Ok(());
+    }
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let ctrl_c = CtrlC::new()?;
+    poll.registry()
+        .register(&ctrl_c, Token(4), Interests::READABLE)?;
+
+    //The group is numbered after the process that started it, this one
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, GetCurrentProcessId()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    poll.poll(&mut events, Some(Duration::from_secs(5)))?;
+    assert_eq!(events.len(), 1);
+    let event = events.get(0).unwrap();
+    assert_eq!(token(event), Token(4));
+    assert!(event::is_readable(event));
+    println!("ctrl: {:?}", ctrl_signal(event));
+
+    //Not routed anymore, nothing is reported
+    poll.registry().deregister(&ctrl_c)?;
+    let other = CtrlC::new()?;
+    poll.registry()
+        .register(&other, Token(5), Interests::READABLE)?;
+    drop(ctrl_c);
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, GetCurrentProcessId()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    poll.poll(&mut events, Some(Duration::from_secs(5)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(token(events.get(0).unwrap()), Token(5));
+    println!("ctrl again: {:?}", ctrl_signal(events.get(0).unwrap()));
+    Ok(())
+}
+
+#[test]
+fn test_ctrl_break() -> io::Result<()> {
+    use std::io::Read;
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use winapi::um::winbase::CREATE_NEW_PROCESS_GROUP;
+
+    //A signal to itself would reach this process, and the test harness too
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(&["--exact", "ctrl::test_ctrl_break_child", "--nocapture"])
+        .env("IOCP_WRAPPER_CTRL_CHILD", "1")
+        .creation_flags(CREATE_NEW_PROCESS_GROUP)
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut output = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut output)?;
+    assert!(child.wait()?.success(), "{}", output);
+    assert!(output.contains("ctrl: Some(CtrlBreak)"), "{}", output);
+    assert!(output.contains("ctrl again: Some(CtrlBreak)"), "{}", output);
+
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use crate::token::Token;
+#[cfg(windows)]
+use std::os::windows::io::RawSocket;
+use std::{error, fmt, io};
+#[cfg(windows)]
+use winapi::um::winsock2::SOCKET;
+
+/// The system error a registered socket ran into, and which socket it was.
+///
+/// Errors the system returns while one socket is registered, reregistered,
+/// deregistered or polled for come back as an `io::Error` of the same kind,
+/// carrying this: its message tells the token and the socket, as in
+/// `The handle is invalid. (os error 6) (token=3 socket=0x1a4)`. The
+/// original error, and its `raw_os_error()`, are a [`SocketError::of`] away,
+/// or the `source()` of this.
+///
+/// Errors of this crate's own, such as a socket that isn't registered, are
+/// told by an [`Error`] instead.
+#[cfg(windows)]
+#[derive(Debug)]
+pub struct SocketError {
+    token: Token,
+    socket: RawSocket,
+    source: io::Error,
+}
+
+#[cfg(windows)]
+impl SocketError {
+    /// The `SocketError` carried by `error`, if any.
+    pub fn of(error: &io::Error) -> Option<&SocketError> {
+        error.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+
+    pub fn token(&self) -> Token {
+        self.token
+    }
+
+    pub fn socket(&self) -> RawSocket {
+        self.socket
+    }
+
+    /// The error as the system returned it.
+    pub fn error(&self) -> &io::Error {
+        &self.source
+    }
+
+    /// Shorthand for `self.error().raw_os_error()`.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.source.raw_os_error()
+    }
+}
+
+#[cfg(windows)]
+impl fmt::Display for SocketError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "{} (token={} socket={:#x})",
+            self.source,
+            usize::from(self.token),
+            self.socket
+        )
+    }
+}
+
+#[cfg(windows)]
+impl error::Error for SocketError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+//Attaches the socket's context to `error`, keeping its kind
+#[cfg(windows)]
+pub(crate) fn with_socket(source: io::Error, token: Token, socket: SOCKET) -> io::Error {
+    //Already told, by a lower layer
+    if SocketError::of(&source).is_some() {
+        return source;
+    }
+    io::Error::new(
+        source.kind(),
+        SocketError {
+            token,
+            socket: socket as RawSocket,
+            source,
+        },
+    )
+}
+
+/// What a misuse of the API reported with an [`Error`] was.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The source isn't registered, or no longer is. Comes as
+    /// `io::ErrorKind::NotFound`.
+    NotRegistered,
+    /// The source is registered already. Comes as
+    /// `io::ErrorKind::AlreadyExists`.
+    AlreadyRegistered,
+    /// The [`Poll`](crate::Poll) the source was registered with was dropped.
+    /// Comes as `io::ErrorKind::Other`.
+    PollDropped,
+    /// The interests aren't supported on this platform. Comes as
+    /// `io::ErrorKind::InvalidInput`.
+    UnsupportedInterests,
+}
+
+impl ErrorKind {
+    fn io_kind(self) -> io::ErrorKind {
+        match self {
+            ErrorKind::NotRegistered => io::ErrorKind::NotFound,
+            ErrorKind::AlreadyRegistered => io::ErrorKind::AlreadyExists,
+            ErrorKind::PollDropped => io::ErrorKind::Other,
+            ErrorKind::UnsupportedInterests => io::ErrorKind::InvalidInput,
+        }
+    }
+}
+
+/// A misuse of the API, carried by the `io::Error` it is reported with.
+///
+/// Tells what went wrong without matching on messages, through
+/// [`Error::of`] and [`Error::kind`].
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    token: Option<Token>,
+    message: &'static str,
+}
+
+impl Error {
+    /// The `Error` carried by `error`, if any.
+    pub fn of(error: &io::Error) -> Option<&Error> {
+        error.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The token the failed call was made with, None if it took none.
+    pub fn token(&self) -> Option<Token> {
+        self.token
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+//The only way these are made, so the `io::ErrorKind` always matches
+pub(crate) fn misuse(kind: ErrorKind, token: Option<Token>, message: &'static str) -> io::Error {
+    io::Error::new(
+        kind.io_kind(),
+        Error {
+            kind,
+            token,
+            message,
+        },
+    )
+}
+
+#[cfg(windows)]
+#[test]
+fn test_with_socket() {
+    let error = with_socket(io::Error::from_raw_os_error(6), Token(3), 0x1a4);
+    assert_eq!(error.kind(), io::Error::from_raw_os_error(6).kind());
+    let message = error.to_string();
+    assert!(
+        message.ends_with("(os error 6) (token=3 socket=0x1a4)"),
+        "{}",
+        message
+    );
+    assert_eq!(SocketError::of(&error).unwrap().raw_os_error(), Some(6));
+    let source = error::Error::source(SocketError::of(&error).unwrap()).unwrap();
+    let source = source.downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.raw_os_error(), Some(6));
+
+    //Told once only
+    let error = with_socket(error, Token(3), 0x1a4);
+    assert_eq!(error.to_string(), message);
+    assert!(SocketError::of(&io::Error::from_raw_os_error(6)).is_none());
+}
+
+#[test]
+fn test_misuse() {
+    let kinds = [
+        (ErrorKind::NotRegistered, io::ErrorKind::NotFound),
+        (ErrorKind::AlreadyRegistered, io::ErrorKind::AlreadyExists),
+        (ErrorKind::PollDropped, io::ErrorKind::Other),
+        (ErrorKind::UnsupportedInterests, io::ErrorKind::InvalidInput),
+    ];
+    for &(kind, io_kind) in kinds.iter() {
+        let error = misuse(kind, Some(Token(7)), "message");
+        assert_eq!(error.kind(), io_kind);
+        assert_eq!(error.to_string(), "message");
+        let inner = Error::of(&error).unwrap();
+        assert_eq!(inner.kind(), kind);
+        assert_eq!(inner.token(), Some(Token(7)));
+    }
+    assert!(Error::of(&io::Error::from_raw_os_error(6)).is_none());
+}
@@ -1,16 +1,92 @@
+use crate::interests::Interests;
+use crate::sys;
 use crate::token::Token;
+use crate::Registry;
+use std::io;
 
-use crate::ready::Ready;
+use crate::readiness::Readiness;
+
+//What events carry on this platform besides readiness, e.g. the IO completions
+//of Windows. Backends may have nothing to add.
+#[allow(unused_imports)]
+pub use crate::sys::event::*;
 
 #[derive(Debug, Clone)]
 pub struct Event {
     token: Token,
-    readiness: Ready,
+    readiness: Readiness,
+    sys: sys::Event,
 }
 
 impl Event {
-    pub(crate) fn new(readiness: Ready, token: Token) -> Event {
-        Event { token, readiness }
+    pub(crate) fn new(readiness: Readiness, token: Token) -> Event {
+        //A unit struct off Windows, there is nothing else to carry
+        #[cfg(windows)]
+        let sys = sys::Event::default();
+        #[cfg(not(windows))]
+        let sys = sys::Event;
+        Event::with_sys(readiness, token, sys)
+    }
+
+    pub(crate) fn with_sys(readiness: Readiness, token: Token, sys: sys::Event) -> Event {
+        Event {
+            token,
+            readiness,
+            sys,
+        }
+    }
+
+    //What the backend keeps of the event, for its own accessors: not every
+    //backend keeps anything
+    #[allow(dead_code)]
+    pub(crate) fn sys(&self) -> &sys::Event {
+        &self.sys
+    }
+
+    /// What happened to the socket.
+    pub fn readiness(&self) -> Readiness {
+        self.readiness
+    }
+
+    #[cfg(any(
+        windows,
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "macos"
+    ))]
+    pub(crate) fn add_readiness(&mut self, readiness: Readiness) {
+        self.readiness = self.readiness | readiness;
+    }
+}
+
+/// Serialized as `{"token": .., "readiness": [..]}`, readiness being the names
+/// of its flags, e.g. `["READABLE", "HUP"]`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Event {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let readiness = self.readiness;
+        let flags: Vec<&str> = [
+            (readiness.is_readable(), "READABLE"),
+            (readiness.is_writable(), "WRITABLE"),
+            (readiness.is_error(), "ERROR"),
+            (readiness.is_hup(), "HUP"),
+            (readiness.is_priority(), "PRIORITY"),
+            (readiness.is_read_closed(), "READ_CLOSED"),
+            (readiness.is_aio(), "AIO"),
+            (readiness.is_lio(), "LIO"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect();
+
+        let mut event = serializer.serialize_struct("Event", 2)?;
+        event.serialize_field("token", &self.token)?;
+        event.serialize_field("readiness", &flags)?;
+        event.end()
     }
 }
 
@@ -38,6 +114,10 @@ pub fn is_priority(event: &Event) -> bool {
     event.readiness.is_priority()
 }
 
+pub fn is_read_closed(event: &Event) -> bool {
+    event.readiness.is_read_closed()
+}
+
 pub fn is_aio(event: &Event) -> bool {
     event.readiness.is_aio()
 }
@@ -45,3 +125,29 @@ pub fn is_aio(event: &Event) -> bool {
 pub fn is_lio(event: &Event) -> bool {
     event.readiness.is_lio()
 }
+
+/// Something that can be registered with a [`Registry`].
+///
+/// Implemented by the types in `net` on Windows, by [`IoSource`] and
+/// `SourceFd` on Unix. These methods are called by [`Registry::register`] and
+/// friends, not directly.
+///
+/// [`IoSource`]: crate::IoSource
+pub trait Source {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()>;
+
+    fn reregister(&self, registry: &Registry, token: Token, interests: Interests)
+        -> io::Result<()>;
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()>;
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_event_serialize() {
+    let event = Event::new(Readiness::READABLE | Readiness::WRITABLE, Token(3));
+    assert_eq!(
+        serde_json::to_string(&event).unwrap(),
+        r#"{"token":3,"readiness":["READABLE","WRITABLE"]}"#
+    );
+}
@@ -1,5 +1,7 @@
+use std::convert::TryFrom;
 use std::num::NonZeroU8;
-use std::{fmt, ops};
+use std::str::FromStr;
+use std::{error, fmt, ops};
 
 /// Interests used in registering.
 ///
@@ -9,7 +11,10 @@ use std::{fmt, ops};
 /// event will be returned from a call to [`poll`].
 ///
 /// The size of `Option<Interests>` should be identical to itself.
-#[derive(Copy, PartialEq, Eq, Clone, PartialOrd, Ord)]
+///
+/// The bit values, as converted to and from `u8`, are stable: they only change
+/// with a new major version of this crate.
+#[derive(Copy, PartialEq, Eq, Clone, PartialOrd, Ord, Hash)]
 #[repr(transparent)]
 pub struct Interests(NonZeroU8);
 
@@ -29,26 +34,111 @@ const WRITABLE: u8 = 0b0_010;
 const AIO: u8 = 0b0_100;
 #[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
 const LIO: u8 = 0b1_000;
+// Same value as `Readiness`'s priority.
+#[cfg_attr(
+    not(any(windows, target_os = "linux", target_os = "android")),
+    allow(dead_code)
+)]
+const PRIORITY: u8 = 0b0001_0000;
+// Same value as `Readiness`'s read closed.
+#[cfg_attr(
+    not(any(windows, target_os = "linux", target_os = "android")),
+    allow(dead_code)
+)]
+const READ_CLOSED: u8 = 0b1000_0000;
+
+//Every bit with a constant on this platform
+const fn defined_bits() -> u8 {
+    #[allow(unused_mut)]
+    let mut bits = READABLE | WRITABLE;
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    {
+        bits |= PRIORITY | READ_CLOSED;
+    }
+    #[cfg(any(
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "macos"
+    ))]
+    {
+        bits |= AIO;
+    }
+    #[cfg(target_os = "freebsd")]
+    {
+        bits |= LIO;
+    }
+    bits
+}
 
 impl Interests {
+    //Only ever called with non-zero bits. Evaluated at compile time for the
+    //constants below, where a zero would fail the build instead.
+    const fn new(bits: u8) -> Interests {
+        match NonZeroU8::new(bits) {
+            Some(bits) => Interests(bits),
+            None => panic!("empty interests"),
+        }
+    }
+
     /// Returns a `Interests` set representing readable interests.
-    pub const READABLE: Interests = Interests(unsafe { NonZeroU8::new_unchecked(READABLE) });
+    pub const READABLE: Interests = Interests::new(READABLE);
 
     /// Returns a `Interests` set representing writable interests.
-    pub const WRITABLE: Interests = Interests(unsafe { NonZeroU8::new_unchecked(WRITABLE) });
+    pub const WRITABLE: Interests = Interests::new(WRITABLE);
 
     /// Returns a `Interests` set representing AIO completion interests.
+    ///
+    /// AIO requests report to a `Poll` through a `sigevent`, see
+    /// `Registry::aio_sigevent` on FreeBSD. Registered with a descriptor, this
+    /// asks for nothing.
     #[cfg(any(
         target_os = "dragonfly",
         target_os = "freebsd",
         target_os = "ios",
         target_os = "macos"
     ))]
-    pub const AIO: Interests = Interests(unsafe { NonZeroU8::new_unchecked(AIO) });
+    pub const AIO: Interests = Interests::new(AIO);
 
     /// Returns a `Interests` set representing LIO completion interests.
+    ///
+    /// Like `AIO`, for the lists of `lio_listio()`.
     #[cfg(target_os = "freebsd")]
-    pub const LIO: Interests = Interests(unsafe { NonZeroU8::new_unchecked(LIO) });
+    pub const LIO: Interests = Interests::new(LIO);
+
+    /// Returns a `Interests` set representing priority interests.
+    ///
+    /// On Windows and Linux this is out-of-band data waiting to be read. It is
+    /// only reported to registrations that include it, not as part of
+    /// `READABLE`.
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    pub const PRIORITY: Interests = Interests::new(PRIORITY);
+
+    /// Returns a `Interests` set representing read closed interests.
+    ///
+    /// Reports the peer shutting down its write side, without reporting
+    /// ordinary data the way `READABLE` does. May be registered on its own.
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    pub const READ_CLOSED: Interests = Interests::new(READ_CLOSED);
+
+    /// Returns every interest supported on this platform.
+    ///
+    /// `READABLE` and `WRITABLE` everywhere, plus `PRIORITY` and `READ_CLOSED`
+    /// on Windows and Linux, `AIO` and `LIO` where they exist.
+    pub const fn all() -> Interests {
+        Interests::new(defined_bits())
+    }
+
+    /// Returns true if `self` is [`Interests::all`].
+    pub const fn is_all(self) -> bool {
+        self.0.get() == defined_bits()
+    }
+
+    //Only bits with a constant on this platform, anything else can't be
+    //registered.
+    pub(crate) fn is_supported(self) -> bool {
+        self.0.get() & !defined_bits() == 0
+    }
 
     /// Returns true if the value includes readable readiness.
     pub fn is_readable(self) -> bool {
@@ -60,6 +150,16 @@ impl Interests {
         (self.0.get() & WRITABLE) != 0
     }
 
+    /// Returns true if the value includes priority readiness.
+    pub fn is_priority(self) -> bool {
+        (self.0.get() & PRIORITY) != 0
+    }
+
+    /// Returns true if the value includes read closed readiness.
+    pub fn is_read_closed(self) -> bool {
+        (self.0.get() & READ_CLOSED) != 0
+    }
+
     /// Returns true if `Interests` contains AIO readiness
     pub fn is_aio(self) -> bool {
         (self.0.get() & AIO) != 0
@@ -70,9 +170,93 @@ impl Interests {
         (self.0.get() & LIO) != 0
     }
 
-    #[cfg(windows)]
-    pub(crate) fn as_u8(self) -> u8 {
-        self.0.get()
+    /// Returns true if every interest in `other` is also in `self`.
+    ///
+    /// This is a subset check: `READABLE.contains(READABLE | WRITABLE)` is
+    /// false.
+    pub const fn contains(self, other: Interests) -> bool {
+        (self.0.get() & other.0.get()) == other.0.get()
+    }
+
+    /// Adds `other` to `self`.
+    ///
+    /// Same as `self | other`, but usable in constants:
+    ///
+    /// ```
+    /// use iocp_wrapper::Interests;
+    ///
+    /// const CLIENT_INTERESTS: Interests = Interests::READABLE.add(Interests::WRITABLE);
+    ///
+    /// assert_eq!(CLIENT_INTERESTS, Interests::READABLE | Interests::WRITABLE);
+    /// ```
+    pub const fn add(self, other: Interests) -> Interests {
+        //Or-ing two non-zero values can't give zero
+        Interests::new(self.0.get() | other.0.get())
+    }
+
+    /// Removes `other` from `self`.
+    ///
+    /// Returns `None` if nothing is left, as `Interests` can't be empty.
+    /// Bits of `other` not in `self` are ignored.
+    ///
+    /// Use [`Registry::reregister`] to apply the result to a live registration.
+    /// Usable in constants as well:
+    ///
+    /// ```
+    /// use iocp_wrapper::Interests;
+    ///
+    /// const BOTH: Interests = Interests::READABLE.add(Interests::WRITABLE);
+    /// const READ_ONLY: Option<Interests> = BOTH.remove(Interests::WRITABLE);
+    /// const NOTHING: Option<Interests> = Interests::READABLE.remove(Interests::READABLE);
+    ///
+    /// assert_eq!(READ_ONLY, Some(Interests::READABLE));
+    /// assert_eq!(NOTHING, None);
+    /// ```
+    ///
+    /// [`Registry::reregister`]: crate::Registry::reregister
+    pub const fn remove(self, other: Interests) -> Option<Interests> {
+        match NonZeroU8::new(self.0.get() & !other.0.get()) {
+            Some(bits) => Some(Interests(bits)),
+            None => None,
+        }
+    }
+
+    /// Returns the single interests making up `self`.
+    ///
+    /// They come in order of increasing bit value: `READABLE`, `WRITABLE`,
+    /// `AIO`, `LIO`, `PRIORITY`, `READ_CLOSED`, skipping those not in `self`.
+    pub fn iter(self) -> impl ExactSizeIterator<Item = Interests> {
+        Iter(self.0.get())
+    }
+
+    //Name of the constant for a single interest
+    fn name(self) -> Option<&'static str> {
+        match self.0.get() {
+            READABLE => Some("READABLE"),
+            WRITABLE => Some("WRITABLE"),
+            AIO => Some("AIO"),
+            LIO => Some("LIO"),
+            PRIORITY => Some("PRIORITY"),
+            READ_CLOSED => Some("READ_CLOSED"),
+            _ => None,
+        }
+    }
+
+    //Names separated by " | ". Never fails: bits without a name, which only
+    //unsafe code could set, are printed as numbers.
+    fn fmt_flags(self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut one = false;
+        for flag in self.iter() {
+            if one {
+                write!(fmt, " | ")?
+            }
+            match flag.name() {
+                Some(name) => write!(fmt, "{}", name)?,
+                None => write!(fmt, "{:#04x}", flag.0.get())?,
+            }
+            one = true
+        }
+        Ok(())
     }
 }
 
@@ -81,7 +265,149 @@ impl ops::BitOr for Interests {
 
     #[inline]
     fn bitor(self, other: Self) -> Self {
-        Interests(unsafe { NonZeroU8::new_unchecked(self.0.get() | other.0.get()) })
+        self.add(other)
+    }
+}
+
+impl From<Interests> for u8 {
+    /// The bits of the interests, see [`Interests`] on their stability.
+    fn from(interests: Interests) -> u8 {
+        interests.0.get()
+    }
+}
+
+impl TryFrom<u8> for Interests {
+    type Error = InvalidInterests;
+
+    /// Fails on zero, and on bits that have no constant on this platform.
+    fn try_from(bits: u8) -> Result<Interests, InvalidInterests> {
+        if bits & !defined_bits() != 0 {
+            return Err(InvalidInterests(bits));
+        }
+        NonZeroU8::new(bits)
+            .map(Interests)
+            .ok_or(InvalidInterests(bits))
+    }
+}
+
+/// Error converting a `u8` that isn't a valid [`Interests`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidInterests(u8);
+
+impl InvalidInterests {
+    /// The rejected value.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+}
+
+impl fmt::Display for InvalidInterests {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            write!(fmt, "interests can't be empty")
+        } else {
+            write!(fmt, "invalid interests bits: {:#010b}", self.0)
+        }
+    }
+}
+
+impl error::Error for InvalidInterests {}
+
+impl FromStr for Interests {
+    type Err = ParseInterestsError;
+
+    /// Parses constant names separated by `|`, e.g. `"READABLE | WRITABLE"`.
+    ///
+    /// Names are case-insensitive and may be surrounded by whitespace. Only the
+    /// constants available on this platform are accepted.
+    fn from_str(s: &str) -> Result<Interests, ParseInterestsError> {
+        let mut interests: Option<Interests> = None;
+
+        for name in s.split('|').map(str::trim) {
+            if name.is_empty() {
+                return Err(ParseInterestsError::Empty);
+            }
+
+            let flag = Interests::all()
+                .iter()
+                .find(|flag| flag.name().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+                .ok_or_else(|| ParseInterestsError::Unknown(name.to_owned()))?;
+            interests = Some(interests.map_or(flag, |interests| interests | flag));
+        }
+
+        interests.ok_or(ParseInterestsError::Empty)
+    }
+}
+
+/// Error parsing an [`Interests`] from a string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseInterestsError {
+    /// The string, or a name between `|`, is empty.
+    Empty,
+    /// Not the name of an interest on this platform.
+    Unknown(String),
+}
+
+impl fmt::Display for ParseInterestsError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseInterestsError::Empty => write!(fmt, "empty interest name"),
+            ParseInterestsError::Unknown(name) => write!(fmt, "unknown interest `{}`", name),
+        }
+    }
+}
+
+impl error::Error for ParseInterestsError {}
+
+/// Serialized as its `u8` bits.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Interests {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.0.get())
+    }
+}
+
+/// Deserialized from its `u8` bits, invalid ones are an error.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Interests {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Interests, D::Error> {
+        let bits = <u8 as serde::Deserialize>::deserialize(deserializer)?;
+        Interests::try_from(bits).map_err(serde::de::Error::custom)
+    }
+}
+
+//Remaining bits of an `Interests`, lowest first
+struct Iter(u8);
+
+impl Iterator for Iter {
+    type Item = Interests;
+
+    fn next(&mut self) -> Option<Interests> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let lowest = self.0 & self.0.wrapping_neg();
+        self.0 &= !lowest;
+        Some(Interests::new(lowest))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Iter {}
+
+//No `BitAndAssign`: the intersection may be empty, which `Interests` can't hold.
+impl ops::BitAnd for Interests {
+    type Output = Option<Interests>;
+
+    /// Interests in both sets, `None` if they are disjoint.
+    #[inline]
+    fn bitand(self, other: Self) -> Option<Interests> {
+        NonZeroU8::new(self.0.get() & other.0.get()).map(Interests)
     }
 }
 
@@ -94,47 +420,244 @@ impl ops::BitOrAssign for Interests {
 
 impl fmt::Debug for Interests {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut one = false;
-        if self.is_readable() {
-            if one {
-                write!(fmt, " | ")?
-            }
-            write!(fmt, "READABLE")?;
-            one = true
-        }
-        if self.is_writable() {
-            if one {
-                write!(fmt, " | ")?
-            }
-            write!(fmt, "WRITABLE")?;
-            one = true
+        self.fmt_flags(fmt)
+    }
+}
+
+/// Prints the names of the interests, e.g. `READABLE | WRITABLE`.
+impl fmt::Display for Interests {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_flags(fmt)
+    }
+}
+
+#[test]
+fn test_interests_remove() {
+    let both = Interests::READABLE | Interests::WRITABLE;
+
+    assert_eq!(both.remove(Interests::WRITABLE), Some(Interests::READABLE));
+    assert_eq!(both.remove(Interests::READABLE), Some(Interests::WRITABLE));
+    //Absent bits change nothing
+    assert_eq!(
+        Interests::READABLE.remove(Interests::WRITABLE),
+        Some(Interests::READABLE)
+    );
+    assert_eq!(Interests::READABLE.remove(Interests::READABLE), None);
+    assert_eq!(both.remove(both), None);
+}
+
+#[test]
+fn test_interests_contains() {
+    let all = [
+        Interests::READABLE,
+        Interests::WRITABLE,
+        Interests::READABLE | Interests::WRITABLE,
+    ];
+
+    for a in all.iter() {
+        for b in all.iter() {
+            let subset = (a.0.get() & b.0.get()) == b.0.get();
+            assert_eq!(a.contains(*b), subset, "{:?} contains {:?}", a, b);
         }
-        #[cfg(any(
-            target_os = "dragonfly",
-            target_os = "freebsd",
-            target_os = "ios",
-            target_os = "macos"
-        ))]
-        {
-            if self.is_aio() {
-                if one {
-                    write!(fmt, " | ")?
-                }
-                write!(fmt, "AIO")?;
-                one = true
+    }
+    assert!(!Interests::READABLE.contains(Interests::READABLE | Interests::WRITABLE));
+    assert!((Interests::READABLE | Interests::WRITABLE).contains(Interests::WRITABLE));
+}
+
+#[test]
+fn test_interests_bitand() {
+    let both = Interests::READABLE | Interests::WRITABLE;
+
+    assert_eq!(both & Interests::READABLE, Some(Interests::READABLE));
+    assert_eq!(Interests::WRITABLE & both, Some(Interests::WRITABLE));
+    assert_eq!(both & both, Some(both));
+    assert_eq!(Interests::READABLE & Interests::WRITABLE, None);
+}
+
+#[cfg(any(windows, target_os = "linux", target_os = "android"))]
+#[test]
+fn test_interests_priority() {
+    let all = Interests::READABLE | Interests::WRITABLE | Interests::PRIORITY;
+
+    assert!(Interests::PRIORITY.is_priority());
+    assert!(!Interests::PRIORITY.is_readable());
+    assert!(!(Interests::READABLE | Interests::WRITABLE).is_priority());
+    assert!(all.contains(Interests::PRIORITY));
+    assert_eq!(
+        all.remove(Interests::PRIORITY),
+        Some(Interests::READABLE | Interests::WRITABLE)
+    );
+    assert_eq!(all & Interests::PRIORITY, Some(Interests::PRIORITY));
+    assert_eq!(Interests::READABLE & Interests::PRIORITY, None);
+    assert_eq!(
+        format!("{:?}", Interests::READABLE | Interests::PRIORITY),
+        "READABLE | PRIORITY"
+    );
+}
+
+#[cfg(any(windows, target_os = "linux", target_os = "android"))]
+#[test]
+fn test_interests_iter() {
+    let flags = [
+        Interests::READABLE,
+        Interests::WRITABLE,
+        Interests::PRIORITY,
+        Interests::READ_CLOSED,
+    ];
+
+    //Every non-empty combination of the flags defined here
+    for mask in 1..(1 << flags.len()) {
+        let expected: Vec<_> = (0..flags.len())
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| flags[i])
+            .collect();
+        let interests = expected[1..]
+            .iter()
+            .fold(expected[0], |interests, flag| interests | *flag);
+
+        let iter = interests.iter();
+        assert_eq!(iter.len(), expected.len());
+        assert_eq!(iter.collect::<Vec<_>>(), expected);
+    }
+
+    assert_eq!(
+        format!("{:?}", Interests::READ_CLOSED | Interests::READABLE),
+        "READABLE | READ_CLOSED"
+    );
+}
+
+#[test]
+fn test_interests_u8_round_trip() {
+    #[allow(unused_mut)]
+    let mut flags = vec![Interests::READABLE, Interests::WRITABLE];
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    flags.extend([Interests::PRIORITY, Interests::READ_CLOSED]);
+    #[cfg(any(
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "macos"
+    ))]
+    flags.push(Interests::AIO);
+    #[cfg(target_os = "freebsd")]
+    flags.push(Interests::LIO);
+    let defined = flags.iter().fold(0, |bits, flag| bits | u8::from(*flag));
+
+    for bits in 0..=u8::MAX {
+        match Interests::try_from(bits) {
+            Ok(interests) => {
+                assert!(bits != 0 && bits & !defined == 0, "accepted {:#b}", bits);
+                assert_eq!(u8::from(interests), bits);
             }
-        }
-        #[cfg(any(target_os = "freebsd"))]
-        {
-            if self.is_lio() {
-                if one {
-                    write!(fmt, " | ")?
-                }
-                write!(fmt, "LIO")?;
-                one = true
+            Err(e) => {
+                assert!(bits == 0 || bits & !defined != 0, "rejected {:#b}", bits);
+                assert_eq!(e.bits(), bits);
             }
         }
-        debug_assert!(one, "printing empty interests");
-        Ok(())
     }
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_interests_serde() {
+    use crate::token::Token;
+
+    let interests = Interests::READABLE | Interests::WRITABLE;
+    let json = serde_json::to_string(&(Token(7), interests)).unwrap();
+    assert_eq!(json, "[7,3]");
+    let back: (Token, Interests) = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, (Token(7), interests));
+
+    assert!(serde_json::from_str::<Interests>("0").is_err());
+    assert!(serde_json::from_str::<Interests>("64").is_err());
+}
+
+#[test]
+fn test_interests_from_str() {
+    let both = Interests::READABLE | Interests::WRITABLE;
+
+    assert_eq!("READABLE".parse(), Ok(Interests::READABLE));
+    assert_eq!("READABLE|WRITABLE".parse(), Ok(both));
+    assert_eq!(" writable | Readable ".parse(), Ok(both));
+    assert_eq!("READABLE|READABLE".parse(), Ok(Interests::READABLE));
+
+    assert_eq!("".parse::<Interests>(), Err(ParseInterestsError::Empty));
+    assert_eq!("READABLE|".parse::<Interests>(), Err(ParseInterestsError::Empty));
+    assert_eq!(
+        "READABLE|BOGUS".parse::<Interests>(),
+        Err(ParseInterestsError::Unknown("BOGUS".to_owned()))
+    );
+    assert_eq!(
+        "READABLE|BOGUS".parse::<Interests>().unwrap_err().to_string(),
+        "unknown interest `BOGUS`"
+    );
+}
+
+#[test]
+fn test_interests_hash() {
+    use std::collections::HashMap;
+
+    let mut groups = HashMap::new();
+    for interests in &[
+        Interests::READABLE,
+        Interests::READABLE | Interests::WRITABLE,
+        Interests::WRITABLE | Interests::READABLE,
+        Interests::READABLE,
+    ] {
+        *groups.entry(*interests).or_insert(0) += 1;
+    }
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[&Interests::READABLE], 2);
+    assert_eq!(groups[&(Interests::READABLE | Interests::WRITABLE)], 2);
+}
+
+#[test]
+fn test_interests_display() {
+    assert_eq!(Interests::READABLE.to_string(), "READABLE");
+    assert_eq!(Interests::WRITABLE.to_string(), "WRITABLE");
+    assert_eq!(
+        (Interests::WRITABLE | Interests::READABLE).to_string(),
+        "READABLE | WRITABLE"
+    );
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    {
+        assert_eq!(Interests::PRIORITY.to_string(), "PRIORITY");
+        assert_eq!(Interests::READ_CLOSED.to_string(), "READ_CLOSED");
+        assert_eq!(
+            (Interests::READABLE | Interests::WRITABLE | Interests::PRIORITY | Interests::READ_CLOSED)
+                .to_string(),
+            "READABLE | WRITABLE | PRIORITY | READ_CLOSED"
+        );
+    }
+
+    //Bits without a constant don't make printing panic
+    let odd = Interests::new(0b100_0001);
+    assert_eq!(odd.to_string(), "READABLE | 0x40");
+    assert_eq!(format!("{:?}", odd), "READABLE | 0x40");
+}
+
+#[test]
+fn test_interests_all() {
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    assert_eq!(u8::from(Interests::all()), 0b1001_0011);
+    #[cfg(target_os = "freebsd")]
+    assert_eq!(u8::from(Interests::all()), 0b0000_1111);
+    #[cfg(any(target_os = "dragonfly", target_os = "ios", target_os = "macos"))]
+    assert_eq!(u8::from(Interests::all()), 0b0000_0111);
+    #[cfg(not(any(
+        windows,
+        target_os = "linux",
+        target_os = "android",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "macos"
+    )))]
+    assert_eq!(u8::from(Interests::all()), 0b0000_0011);
+
+    assert!(Interests::all().is_all());
+    assert!(Interests::all().is_supported());
+    assert!(!Interests::READABLE.is_all());
+    assert_eq!(Interests::all().iter().count(), Interests::all().iter().len());
+}
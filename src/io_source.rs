@@ -0,0 +1,183 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::sys::{AsRawSource, IoSourceState};
+use crate::token::Token;
+use crate::Registry;
+use std::io;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, RawSocket};
+
+/// Registration glue for any type wrapping a socket.
+///
+/// `IoSource` implements [`event::Source`] for the raw socket of `T`, its
+/// file descriptor on Unix, and derefs to `T`. Operations that write go
+/// through [`IoSource::do_io`], which keeps the selector's cached writable
+/// readiness honest.
+///
+/// # Examples
+///
+#[cfg_attr(feature = "os-poll", doc = "```no_run")]
+#[cfg_attr(not(feature = "os-poll"), doc = "```ignore")]
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::{Events, Interests, IoSource, Poll, Token};
+/// use std::io::Write;
+/// use std::net;
+///
+/// let stream = net::TcpStream::connect("127.0.0.1:8080")?;
+/// stream.set_nonblocking(true)?;
+/// let stream = IoSource::new(stream);
+///
+/// let mut poll = Poll::new()?;
+/// poll.registry()
+///     .register(&stream, Token(0), Interests::WRITABLE)?;
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// stream.do_io(|mut stream| stream.write(b"hello"))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct IoSource<T> {
+    inner: T,
+    state: IoSourceState,
+}
+
+impl<T: AsRawSource> IoSource<T> {
+    /// Wraps `io`, which has to be in non-blocking mode already.
+    pub fn new(io: T) -> IoSource<T> {
+        IoSource {
+            inner: io,
+            state: IoSourceState::new(),
+        }
+    }
+
+    /// Returns the wrapped value. A registration is not undone by this.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    //Whether registered with a selector right now
+    #[cfg(all(windows, feature = "net"))]
+    pub(crate) fn is_registered(&self) -> bool {
+        self.state.is_registered()
+    }
+
+    /// Runs `f` on the wrapped value. If it fails with `WouldBlock`, the cached
+    /// writable readiness is dropped, see [`Registry::clear_writable`].
+    ///
+    /// Readable readiness is never cached, reads don't need to go through here.
+    pub fn do_io<F, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce(&T) -> io::Result<R>,
+    {
+        self.state.do_io(&self.inner, f)
+    }
+}
+
+impl<T> Deref for IoSource<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for IoSource<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+#[cfg(windows)]
+impl<T: AsRawSocket> AsRawSocket for IoSource<T> {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+#[cfg(unix)]
+impl<T: AsRawFd> AsRawFd for IoSource<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<T: AsRawSource> event::Source for IoSource<T> {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        self.state.register(registry, &self.inner, token, interests)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.state
+            .reregister(registry, &self.inner, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        self.state.deregister(registry, &self.inner)
+    }
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_io_source_socket2() -> io::Result<()> {
+    use crate::event::is_writable;
+    use crate::{Events, Poll};
+    use socket2::{Domain, SockAddr, Socket, Type};
+    use std::io::{Read, Write};
+    use std::net;
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let socket = Socket::new(Domain::ipv4(), Type::stream(), None)?;
+    socket.set_nonblocking(true)?;
+    match socket.connect(&SockAddr::from(listener.local_addr()?)) {
+        //Unix tells a connect in progress apart from one that would block
+        #[cfg(unix)]
+        Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => {}
+        Err(ref e) if e.kind() != io::ErrorKind::WouldBlock => panic!("connect: {}", e),
+        _ => {}
+    }
+    let socket = IoSource::new(socket);
+    poll.registry()
+        .register(&socket, Token(0), Interests::WRITABLE)?;
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_writable(events.get(0).unwrap()));
+    let (mut server, _) = listener.accept()?;
+
+    //Filling the send buffer through `do_io` drops the cached readiness
+    let buf = [0; 64 * 1024];
+    let mut written = 0;
+    loop {
+        match socket.do_io(|mut socket| socket.write(&buf)) {
+            Ok(n) => written += n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    let mut rbuf = vec![0; 64 * 1024];
+    let mut read = 0;
+    while read < written {
+        read += server.read(&mut rbuf)?;
+    }
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_writable(events.get(0).unwrap()));
+
+    poll.registry().deregister(&socket)?;
+    Ok(())
+}
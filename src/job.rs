@@ -0,0 +1,288 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event;
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::sys::Selector;
+use crate::token::Token;
+use std::io;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+use std::ptr::null_mut;
+use std::sync::Mutex;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::jobapi2::{AssignProcessToJobObject, CreateJobObjectW};
+use winapi::um::winnt::{
+    HANDLE, JOB_OBJECT_MSG_ABNORMAL_EXIT_PROCESS, JOB_OBJECT_MSG_ACTIVE_PROCESS_LIMIT,
+    JOB_OBJECT_MSG_ACTIVE_PROCESS_ZERO, JOB_OBJECT_MSG_END_OF_JOB_TIME,
+    JOB_OBJECT_MSG_END_OF_PROCESS_TIME, JOB_OBJECT_MSG_EXIT_PROCESS,
+    JOB_OBJECT_MSG_JOB_MEMORY_LIMIT, JOB_OBJECT_MSG_NEW_PROCESS,
+    JOB_OBJECT_MSG_PROCESS_MEMORY_LIMIT,
+};
+
+/// A message of a [`JobObject`], see [`event::job_event`].
+///
+/// The process ids are those of the process the message is about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JobEvent {
+    /// The last process of the job exited.
+    ActiveProcessZero,
+    /// A process was added to the job, assigned or spawned from one in it.
+    NewProcess(u32),
+    ExitProcess(u32),
+    /// A process exited with an unhandled exception.
+    AbnormalExitProcess(u32),
+    ActiveProcessLimit,
+    ProcessMemoryLimit(u32),
+    JobMemoryLimit(u32),
+    EndOfJobTime,
+    EndOfProcessTime(u32),
+    /// A message not known here, with its value.
+    Other(u32, u32),
+}
+
+impl JobEvent {
+    pub(crate) fn new(message: u32, value: u32) -> JobEvent {
+        match message {
+            JOB_OBJECT_MSG_ACTIVE_PROCESS_ZERO => JobEvent::ActiveProcessZero,
+            JOB_OBJECT_MSG_NEW_PROCESS => JobEvent::NewProcess(value),
+            JOB_OBJECT_MSG_EXIT_PROCESS => JobEvent::ExitProcess(value),
+            JOB_OBJECT_MSG_ABNORMAL_EXIT_PROCESS => JobEvent::AbnormalExitProcess(value),
+            JOB_OBJECT_MSG_ACTIVE_PROCESS_LIMIT => JobEvent::ActiveProcessLimit,
+            JOB_OBJECT_MSG_PROCESS_MEMORY_LIMIT => JobEvent::ProcessMemoryLimit(value),
+            JOB_OBJECT_MSG_JOB_MEMORY_LIMIT => JobEvent::JobMemoryLimit(value),
+            JOB_OBJECT_MSG_END_OF_JOB_TIME => JobEvent::EndOfJobTime,
+            JOB_OBJECT_MSG_END_OF_PROCESS_TIME => JobEvent::EndOfProcessTime(value),
+            message => JobEvent::Other(message, value),
+        }
+    }
+
+    /// The process the message is about, if any.
+    pub fn process_id(&self) -> Option<u32> {
+        match *self {
+            JobEvent::NewProcess(pid)
+            | JobEvent::ExitProcess(pid)
+            | JobEvent::AbnormalExitProcess(pid)
+            | JobEvent::ProcessMemoryLimit(pid)
+            | JobEvent::JobMemoryLimit(pid)
+            | JobEvent::EndOfProcessTime(pid) => Some(pid),
+            _ => None,
+        }
+    }
+}
+
+/// Reports the messages of a job object as events, each with a
+/// [`JobEvent`] and no readiness.
+///
+/// The job posts its messages to the port itself. It can be associated with
+/// one port only, ever: once registered with a `Poll` the job can be
+/// deregistered and registered again with that `Poll`, not with another one.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::event::job_event;
+/// use iocp_wrapper::{Events, Interests, JobEvent, JobObject, Poll, Token};
+/// use std::process::Command;
+///
+/// let mut poll = Poll::new()?;
+/// let job = JobObject::new()?;
+/// poll.registry().register(&job, Token(0), Interests::READABLE)?;
+/// let child = Command::new("cmd").args(&["/c", "ping -n 2 127.0.0.1 >NUL"]).spawn()?;
+/// job.assign(&child)?;
+///
+/// let mut events = Events::with_capacity(8);
+/// loop {
+///     poll.poll(&mut events, None)?;
+///     let done = (0..events.len())
+///         .any(|i| job_event(events.get(i).unwrap()) == Some(JobEvent::ActiveProcessZero));
+///     if done {
+///         break;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct JobObject {
+    handle: HANDLE,
+    //the port the job is associated with, its id there, and whether it's
+    //registered now
+    port: Mutex<Option<(Selector, usize, bool)>>,
+}
+
+//The job handle is only touched through thread safe APIs
+unsafe impl Send for JobObject {}
+unsafe impl Sync for JobObject {}
+
+impl JobObject {
+    /// Creates an unnamed job, with no limits.
+    pub fn new() -> io::Result<JobObject> {
+        let handle = unsafe { CreateJobObjectW(null_mut(), null_mut()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { JobObject::from_raw_handle(handle as RawHandle) })
+    }
+
+    /// Assigns a process to the job, `process` being a `Child` for example.
+    pub fn assign<P: AsRawHandle + ?Sized>(&self, process: &P) -> io::Result<()> {
+        let r = unsafe { AssignProcessToJobObject(self.handle, process.as_raw_handle() as HANDLE) };
+        if r == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawHandle for JobObject {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.handle as RawHandle
+    }
+}
+
+/// Takes ownership of a job handle, which needs
+/// `JOB_OBJECT_SET_ATTRIBUTES` access to be registered.
+impl FromRawHandle for JobObject {
+    unsafe fn from_raw_handle(handle: RawHandle) -> JobObject {
+        JobObject {
+            handle: handle as HANDLE,
+            port: Mutex::new(None),
+        }
+    }
+}
+
+impl event::Source for JobObject {
+    fn register(&self, registry: &Registry, token: Token, _interests: Interests) -> io::Result<()> {
+        let selector = registry.selector();
+        let mut port = self.port.lock().unwrap();
+        match *port {
+            None => {
+                let id = selector.register_job(self.handle, token)?;
+                *port = Some((selector.clone(), id, true));
+                Ok(())
+            }
+            Some((_, _, true)) => Err(misuse(
+                ErrorKind::AlreadyRegistered,
+                Some(token),
+                "job object is already registered",
+            )),
+            Some((ref associated, id, ref mut registered)) => {
+                if !associated.same_port(selector) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "job object is associated with another poll",
+                    ));
+                }
+                selector.set_job_token(id, Some(token));
+                *registered = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        match *self.port.lock().unwrap() {
+            Some((ref associated, id, true)) if associated.same_port(registry.selector()) => {
+                associated.set_job_token(id, Some(token));
+                Ok(())
+            }
+            _ => Err(not_registered(Some(token))),
+        }
+    }
+
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        match *self.port.lock().unwrap() {
+            Some((ref associated, id, ref mut registered)) if *registered => {
+                associated.set_job_token(id, None);
+                *registered = false;
+                Ok(())
+            }
+            _ => Err(not_registered(None)),
+        }
+    }
+}
+
+fn not_registered(token: Option<Token>) -> io::Error {
+    misuse(
+        ErrorKind::NotRegistered,
+        token,
+        "job object is not registered",
+    )
+}
+
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        //The job lives on as long as its processes do, and keeps posting
+        if let Some((ref associated, id, _)) = *self.port.lock().unwrap() {
+            associated.set_job_token(id, None);
+        }
+        unsafe { CloseHandle(self.handle) };
+    }
+}
+
+#[test]
+fn test_job_object_messages() -> io::Result<()> {
+    use crate::event::{job_event, token};
+    use crate::{Events, Poll};
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let job = JobObject::new()?;
+    poll.registry()
+        .register(&job, Token(7), Interests::READABLE)?;
+
+    //Alive long enough to be assigned
+    let child = Command::new("cmd")
+        .args(&["/c", "ping -n 2 127.0.0.1 >NUL"])
+        .spawn()?;
+    let pid = child.id();
+    job.assign(&child)?;
+
+    let mut messages = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !messages.contains(&JobEvent::ActiveProcessZero) {
+        assert!(Instant::now() < deadline, "{:?}", messages);
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            assert_eq!(token(event), Token(7));
+            assert!(event.readiness().is_empty());
+            messages.push(job_event(event).unwrap());
+        }
+    }
+    assert_eq!(
+        messages,
+        [
+            JobEvent::NewProcess(pid),
+            JobEvent::ExitProcess(pid),
+            JobEvent::ActiveProcessZero
+        ]
+    );
+    assert_eq!(messages[1].process_id(), Some(pid));
+
+    //Associated for good, but messages are dropped while deregistered
+    poll.registry().deregister(&job)?;
+    let other = Poll::new()?;
+    let err = other
+        .registry()
+        .register(&job, Token(8), Interests::READABLE)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let child = Command::new("cmd")
+        .args(&["/c", "ping -n 2 127.0.0.1 >NUL"])
+        .spawn()?;
+    job.assign(&child)?;
+    for _ in 0..20 {
+        poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+        assert!(events.is_empty());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Readiness events and what can be registered, as in `mio::event`.
+
+use super::{Interest, Registry};
+use crate::event as base;
+use crate::sys;
+use crate::token::Token;
+use std::{io, slice};
+
+/// A readiness event, as `mio::event::Event`.
+#[derive(Debug, Clone)]
+pub struct Event {
+    inner: base::Event,
+}
+
+impl Event {
+    pub fn token(&self) -> Token {
+        base::token(&self.inner)
+    }
+
+    pub fn is_readable(&self) -> bool {
+        base::is_readable(&self.inner)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        base::is_writable(&self.inner)
+    }
+
+    pub fn is_error(&self) -> bool {
+        base::is_error(&self.inner)
+    }
+
+    /// Set on a hang-up, or for the peer shutting down its write side where
+    /// [`Interest::READ_CLOSED`] is registered for.
+    pub fn is_read_closed(&self) -> bool {
+        base::is_hup(&self.inner) || base::is_read_closed(&self.inner)
+    }
+
+    /// Set on a hang-up, or an error while writable, as from a failed
+    /// connect.
+    pub fn is_write_closed(&self) -> bool {
+        base::is_hup(&self.inner) || (self.is_error() && self.is_writable())
+    }
+
+    pub fn is_priority(&self) -> bool {
+        base::is_priority(&self.inner)
+    }
+
+    pub fn is_aio(&self) -> bool {
+        base::is_aio(&self.inner)
+    }
+
+    pub fn is_lio(&self) -> bool {
+        base::is_lio(&self.inner)
+    }
+}
+
+/// Events filled in by [`Poll::poll`], as `mio::Events`.
+///
+/// [`Poll::poll`]: super::Poll::poll
+#[derive(Debug)]
+pub struct Events {
+    pub(super) inner: sys::Events,
+    //Copied out of `inner` after each poll, for `iter` to hand out references
+    //to the events of this module
+    events: Vec<Event>,
+}
+
+impl Events {
+    pub fn with_capacity(capacity: usize) -> Events {
+        Events {
+            inner: sys::Events::with_capacity(capacity),
+            events: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.events.iter(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.events.clear();
+    }
+
+    pub(super) fn copy_out(&mut self) {
+        let inner = &self.inner;
+        self.events.clear();
+        self.events.extend((0..inner.len()).map(|idx| Event {
+            inner: inner.get(idx).unwrap().clone(),
+        }));
+    }
+}
+
+impl<'a> IntoIterator for &'a Events {
+    type Item = &'a Event;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterates over the events of [`Events`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    inner: slice::Iter<'a, Event>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Event;
+
+    fn next(&mut self) -> Option<&'a Event> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// Something that can be registered with a [`Registry`], as
+/// `mio::event::Source`.
+///
+/// Implemented for everything implementing the crate's own
+/// [`crate::event::Source`]. These methods are called by
+/// [`Registry::register`] and friends, not directly.
+pub trait Source {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()>;
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()>;
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()>;
+}
+
+impl<T: base::Source + ?Sized> Source for T {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        base::Source::register(self, &registry.inner, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        base::Source::reregister(self, &registry.inner, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        base::Source::deregister(self, &registry.inner)
+    }
+}
@@ -0,0 +1,203 @@
+//! The API of mio 0.7, for code written against it to switch over by its
+//! `use` lines alone.
+//!
+//! `use mio::{Events, Interest, Poll, Token}` becomes
+//! `use iocp_wrapper::mio_compat::{Events, Interest, Poll, Token}`, and the
+//! same goes for `mio::event`, `mio::net` and `mio::unix`. Names, signatures
+//! and semantics are mio's: sources are registered through `&mut`, events
+//! have their accessors as methods, [`Interest`] is [`crate::Interests`]
+//! under mio's name. Underneath is the rest of this crate.
+//!
+//! What is not the same, on purpose:
+//!
+//! - Readiness is level-triggered, not edge-triggered. Code reading or
+//!   accepting until `WouldBlock`, as mio asks for, works the same; code
+//!   stopping earlier gets the event again on the next poll.
+//! - Writable readiness is cached until a write fails with `WouldBlock`, see
+//!   [`crate::Registry::clear_writable`]. The sockets of [`net`] take care of
+//!   it, sources of one's own have to as well.
+//! - [`Event::is_read_closed`] is only set for the peer shutting down its
+//!   write side on Windows and Linux, for registrations asking for
+//!   [`Interest::READ_CLOSED`], or on a hang-up. Otherwise the source is
+//!   readable and reads return 0, which mio code handles anyway.
+//! - [`net`] only has TCP: `UdpSocket`, `UnixStream` and the rest are in the
+//!   crate's own [`crate::net`] on Windows, nowhere on Unix.
+//! - [`event::Source`] is implemented for everything implementing the crate's
+//!   own [`crate::event::Source`]. A type of one's own may implement either,
+//!   only the latter makes it registrable through both APIs.
+//! - There is no logging through the `log` crate.
+
+pub mod event;
+pub mod net;
+
+/// What is only on Unix, as in `mio::unix`.
+#[cfg(unix)]
+pub mod unix {
+    pub use crate::sys::unix::SourceFd;
+}
+
+pub use self::event::Events;
+pub use crate::interests::Interests as Interest;
+pub use crate::token::Token;
+
+use crate::{poll, waker};
+use std::io;
+use std::time::Duration;
+
+/// Polls for readiness events, as `mio::Poll`.
+///
+/// See [`crate::Poll`], which this is a layer over.
+pub struct Poll {
+    inner: poll::Poll,
+    registry: Registry,
+}
+
+impl Poll {
+    pub fn new() -> io::Result<Poll> {
+        let inner = poll::Poll::new()?;
+        let registry = Registry {
+            inner: inner.registry().duplicate(),
+        };
+        Ok(Poll { inner, registry })
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Waits for readiness events, blocking at most `timeout`, see
+    /// [`crate::Poll::poll`].
+    pub fn poll(&mut self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        let result = self.inner.poll(&mut events.inner, timeout);
+        events.copy_out();
+        result
+    }
+}
+
+/// Registers sources with a [`Poll`], as `mio::Registry`.
+pub struct Registry {
+    inner: poll::Registry,
+}
+
+impl Registry {
+    pub fn register<S>(&self, source: &mut S, token: Token, interests: Interest) -> io::Result<()>
+    where
+        S: event::Source + ?Sized,
+    {
+        source.register(self, token, interests)
+    }
+
+    pub fn reregister<S>(&self, source: &mut S, token: Token, interests: Interest) -> io::Result<()>
+    where
+        S: event::Source + ?Sized,
+    {
+        source.reregister(self, token, interests)
+    }
+
+    pub fn deregister<S>(&self, source: &mut S) -> io::Result<()>
+    where
+        S: event::Source + ?Sized,
+    {
+        source.deregister(self)
+    }
+
+    /// Returns another handle on the same poller, for other threads to
+    /// register with.
+    ///
+    /// Once the [`Poll`] is dropped, registering through it fails with
+    /// [`ErrorKind::PollDropped`](crate::ErrorKind::PollDropped).
+    pub fn try_clone(&self) -> io::Result<Registry> {
+        Ok(Registry {
+            inner: self.inner.duplicate(),
+        })
+    }
+}
+
+/// Wakes up a thread blocked in [`Poll::poll`], as `mio::Waker`.
+///
+/// See [`crate::Waker`].
+pub struct Waker {
+    inner: waker::Waker,
+}
+
+impl Waker {
+    pub fn new(registry: &Registry, token: Token) -> io::Result<Waker> {
+        waker::Waker::new(&registry.inner, token).map(|inner| Waker { inner })
+    }
+
+    pub fn wake(&self) -> io::Result<()> {
+        self.inner.wake()
+    }
+}
+
+#[cfg(any(windows, not(feature = "shell")))]
+#[test]
+fn test_mio_compat_source() -> io::Result<()> {
+    use std::sync::Arc;
+    use std::thread;
+
+    //A source of one's own, the way mio has them implemented
+    struct Listener {
+        inner: net::TcpListener,
+    }
+
+    impl event::Source for Listener {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            self.inner.register(registry, token, interests)
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            self.inner.reregister(registry, token, interests)
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+            self.inner.deregister(registry)
+        }
+    }
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let mut listener = Listener {
+        inner: net::TcpListener::bind("127.0.0.1:0".parse().unwrap())?,
+    };
+    let registry = poll.registry().try_clone()?;
+    registry.register(&mut listener, Token(1), Interest::READABLE)?;
+    let waker = Arc::new(Waker::new(poll.registry(), Token(2))?);
+
+    let addr = listener.inner.local_addr()?;
+    let remote = waker.clone();
+    let client = thread::spawn(move || {
+        let stream = std::net::TcpStream::connect(addr);
+        remote.wake().unwrap();
+        stream
+    });
+
+    let mut tokens = Vec::new();
+    while tokens.len() < 2 {
+        poll.poll(&mut events, None)?;
+        for event in &events {
+            assert!(event.token() == Token(2) || event.is_readable());
+            if !tokens.contains(&event.token()) {
+                tokens.push(event.token());
+            }
+        }
+    }
+    tokens.sort();
+    assert_eq!(tokens, [Token(1), Token(2)]);
+    let client = client.join().unwrap()?;
+    let (stream, _) = listener.inner.accept()?;
+    assert_eq!(stream.peer_addr()?, client.local_addr()?);
+
+    poll.registry().deregister(&mut listener)?;
+    Ok(())
+}
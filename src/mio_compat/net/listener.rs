@@ -0,0 +1,108 @@
+use super::TcpStream;
+use crate::event;
+use crate::interests::Interests;
+use crate::io_source::IoSource;
+use crate::poll::Registry;
+use crate::token::Token;
+use std::fmt;
+use std::io;
+use std::net::{self, SocketAddr};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+
+/// A non-blocking TCP listener, as `mio::net::TcpListener`.
+pub struct TcpListener {
+    inner: IoSource<net::TcpListener>,
+}
+
+impl TcpListener {
+    /// Binds a listener to `addr`, with `SO_REUSEADDR` as std sets it.
+    ///
+    /// Listeners report readable when a connection is waiting to be accepted.
+    pub fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+        let inner = net::TcpListener::bind(addr)?;
+        inner.set_nonblocking(true)?;
+        Ok(TcpListener::from_std(inner))
+    }
+
+    /// Wraps a listener created with std.
+    ///
+    /// The listener has to be in non-blocking mode already, see
+    /// [`net::TcpListener::set_nonblocking`].
+    pub fn from_std(inner: net::TcpListener) -> TcpListener {
+        TcpListener {
+            inner: IoSource::new(inner),
+        }
+    }
+
+    /// Accepts a connection, `WouldBlock` if none is waiting.
+    ///
+    /// The returned stream is non-blocking and closed on exec.
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self.inner.accept()?;
+        //Linux doesn't hand the listener's O_NONBLOCK down, the BSDs do
+        stream.set_nonblocking(true)?;
+        Ok((TcpStream::from_std(stream), addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.inner.ttl()
+    }
+
+    /// Returns and clears the pending error of the socket (`SO_ERROR`).
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+}
+
+impl fmt::Debug for TcpListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.inner, f)
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// The descriptor has to be in non-blocking mode already.
+impl FromRawFd for TcpListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> TcpListener {
+        TcpListener::from_std(net::TcpListener::from_raw_fd(fd))
+    }
+}
+
+/// Gives up ownership, the descriptor is not closed.
+impl IntoRawFd for TcpListener {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_inner().into_raw_fd()
+    }
+}
+
+impl event::Source for TcpListener {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
@@ -0,0 +1,16 @@
+//! TCP sockets, as in `mio::net`.
+//!
+//! On Windows these are the crate's own sockets of [`crate::net`], on Unix
+//! std's sockets registered through an [`IoSource`](crate::IoSource).
+
+#[cfg(unix)]
+mod listener;
+#[cfg(unix)]
+mod stream;
+
+#[cfg(unix)]
+pub use self::listener::TcpListener;
+#[cfg(unix)]
+pub use self::stream::TcpStream;
+#[cfg(windows)]
+pub use crate::net::{TcpListener, TcpStream};
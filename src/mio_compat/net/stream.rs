@@ -0,0 +1,269 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::io_source::IoSource;
+use crate::poll::Registry;
+use crate::token::Token;
+use std::fmt;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::mem;
+use std::net::{self, Shutdown, SocketAddr};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+
+/// A non-blocking TCP stream, as `mio::net::TcpStream`.
+pub struct TcpStream {
+    inner: IoSource<net::TcpStream>,
+}
+
+impl TcpStream {
+    /// Starts connecting to `addr` and returns right away.
+    ///
+    /// The connection is established once the stream reports writable: register
+    /// it for [`Interests::WRITABLE`] and wait for that event before using it.
+    pub fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+        let family = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        //Non-blocking and closed on exec right from the start where that can
+        //be asked for
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let ty = libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        let ty = libc::SOCK_STREAM;
+        let fd = cvt(unsafe { libc::socket(family, ty, 0) })?;
+        //Owned from here on, closed if anything below fails
+        let stream = unsafe { net::TcpStream::from_raw_fd(fd) };
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        {
+            stream.set_nonblocking(true)?;
+            cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        }
+
+        let (raw, len) = sockaddr(&addr);
+        let raw = &raw as *const libc::sockaddr_storage as *const libc::sockaddr;
+        match cvt(unsafe { libc::connect(fd, raw, len) }) {
+            //A connect in progress, not a `WouldBlock` on Unix
+            Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => {}
+            Err(e) => return Err(e),
+            Ok(_) => {}
+        }
+        Ok(TcpStream::from_std(stream))
+    }
+
+    /// Wraps a stream created with std.
+    ///
+    /// The stream has to be in non-blocking mode already, see
+    /// [`net::TcpStream::set_nonblocking`].
+    pub fn from_std(inner: net::TcpStream) -> TcpStream {
+        TcpStream {
+            inner: IoSource::new(inner),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Address of the peer, `NotConnected` while connecting.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        self.inner.nodelay()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.inner.ttl()
+    }
+
+    /// Returns and clears the pending error of the socket (`SO_ERROR`).
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+
+    /// Shuts down the read half, the write half, or both.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    /// Reads into `buf` without removing the data from the queue,
+    /// `WouldBlock` if nothing was received.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.peek(buf)
+    }
+}
+
+fn cvt(r: libc::c_int) -> io::Result<libc::c_int> {
+    if r == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(r)
+    }
+}
+
+//`addr` laid out for the C API, with its length
+fn sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let raw = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            raw.sin_family = libc::AF_INET as libc::sa_family_t;
+            raw.sin_port = addr.port().to_be();
+            raw.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let raw = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            raw.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            raw.sin6_port = addr.port().to_be();
+            raw.sin6_flowinfo = addr.flowinfo();
+            raw.sin6_addr.s6_addr = addr.ip().octets();
+            raw.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    //The BSDs have the length in the address as well
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    {
+        storage.ss_len = len as u8;
+    }
+    (storage, len as libc::socklen_t)
+}
+
+//Writes go through `do_io`, which drops the cached writable readiness once
+//the send buffer is full
+impl Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl Read for &TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self.inner).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        (&*self.inner).read_vectored(bufs)
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.do_io(|mut inner| inner.write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.do_io(|mut inner| inner.write_vectored(bufs))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.inner, f)
+    }
+}
+
+impl AsRawFd for TcpStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// The descriptor has to be in non-blocking mode already.
+impl FromRawFd for TcpStream {
+    unsafe fn from_raw_fd(fd: RawFd) -> TcpStream {
+        TcpStream::from_std(net::TcpStream::from_raw_fd(fd))
+    }
+}
+
+/// Gives up ownership, the descriptor is not closed.
+impl IntoRawFd for TcpStream {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_inner().into_raw_fd()
+    }
+}
+
+impl event::Source for TcpStream {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+#[cfg(not(feature = "shell"))]
+#[test]
+fn test_tcp_stream_connect() -> io::Result<()> {
+    use crate::mio_compat::net::TcpListener;
+    use crate::mio_compat::{Events, Interest, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+    let mut stream = TcpStream::connect(listener.local_addr()?)?;
+    poll.registry()
+        .register(&mut stream, Token(0), Interest::WRITABLE)?;
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    let event = events.iter().next().unwrap();
+    assert_eq!(event.token(), Token(0));
+    assert!(event.is_writable() && !event.is_write_closed());
+    assert_eq!(stream.peer_addr()?, listener.local_addr()?);
+
+    let (mut accepted, _) = listener.accept()?;
+    accepted.write_all(b"ping")?;
+    poll.registry()
+        .reregister(&mut stream, Token(1), Interest::READABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert!(events.iter().any(|event| event.token() == Token(1)));
+    let mut buf = [0; 8];
+    assert_eq!(stream.read(&mut buf)?, 4);
+    assert_eq!(&buf[..4], b"ping");
+
+    poll.registry().deregister(&mut stream)?;
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//Conversions between `SocketAddr` and the sockaddr structs winsock takes
+//and fills in.
+
+use crate::net::last_error;
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use winapi::ctypes::c_int;
+use winapi::shared::in6addr::IN6_ADDR;
+use winapi::shared::inaddr::IN_ADDR;
+use winapi::shared::ws2def::{AF_INET, AF_INET6, SOCKADDR, SOCKADDR_IN, SOCKADDR_STORAGE};
+use winapi::shared::ws2ipdef::SOCKADDR_IN6_LH;
+use winapi::um::winsock2::{getpeername, getsockname, SOCKET, SOCKET_ERROR};
+
+//Storage for either kind of address, as winsock takes them
+#[repr(C)]
+pub(crate) union SocketAddrCRepr {
+    v4: SOCKADDR_IN,
+    v6: SOCKADDR_IN6_LH,
+}
+
+impl SocketAddrCRepr {
+    pub(crate) fn as_ptr(&self) -> *const SOCKADDR {
+        self as *const _ as *const SOCKADDR
+    }
+}
+
+pub(crate) fn socket_addr(addr: &SocketAddr) -> (SocketAddrCRepr, c_int) {
+    match addr {
+        SocketAddr::V4(addr) => {
+            let mut sin_addr: IN_ADDR = unsafe { mem::zeroed() };
+            unsafe { *sin_addr.S_un.S_addr_mut() = u32::from_ne_bytes(addr.ip().octets()) };
+
+            let sockaddr_in = SOCKADDR_IN {
+                sin_family: AF_INET as _,
+                sin_port: addr.port().to_be(),
+                sin_addr,
+                sin_zero: [0; 8],
+            };
+            (
+                SocketAddrCRepr { v4: sockaddr_in },
+                mem::size_of::<SOCKADDR_IN>() as c_int,
+            )
+        }
+        SocketAddr::V6(addr) => {
+            let mut sin6_addr: IN6_ADDR = unsafe { mem::zeroed() };
+            unsafe { *sin6_addr.u.Byte_mut() = addr.ip().octets() };
+
+            let mut sockaddr_in6: SOCKADDR_IN6_LH = unsafe { mem::zeroed() };
+            sockaddr_in6.sin6_family = AF_INET6 as _;
+            sockaddr_in6.sin6_port = addr.port().to_be();
+            sockaddr_in6.sin6_addr = sin6_addr;
+            sockaddr_in6.sin6_flowinfo = addr.flowinfo();
+            unsafe { *sockaddr_in6.u.sin6_scope_id_mut() = addr.scope_id() };
+            (
+                SocketAddrCRepr { v6: sockaddr_in6 },
+                mem::size_of::<SOCKADDR_IN6_LH>() as c_int,
+            )
+        }
+    }
+}
+
+//Inverse of `socket_addr`, for addresses filled in by winsock
+pub(crate) fn to_socket_addr(storage: &SOCKADDR_STORAGE) -> io::Result<SocketAddr> {
+    match storage.ss_family as c_int {
+        AF_INET => {
+            let addr = unsafe { &*(storage as *const _ as *const SOCKADDR_IN) };
+            let ip = Ipv4Addr::from(unsafe { *addr.sin_addr.S_un.S_addr() }.to_ne_bytes());
+            Ok(SocketAddrV4::new(ip, u16::from_be(addr.sin_port)).into())
+        }
+        AF_INET6 => {
+            let addr = unsafe { &*(storage as *const _ as *const SOCKADDR_IN6_LH) };
+            let ip = Ipv6Addr::from(*unsafe { addr.sin6_addr.u.Byte() });
+            Ok(SocketAddrV6::new(
+                ip,
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                *unsafe { addr.u.sin6_scope_id() },
+            )
+            .into())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid address family",
+        )),
+    }
+}
+
+pub(crate) fn local_addr(socket: SOCKET) -> io::Result<SocketAddr> {
+    query_addr(socket, getsockname)
+}
+
+//NotConnected until a connect has gone through
+pub(crate) fn peer_addr(socket: SOCKET) -> io::Result<SocketAddr> {
+    query_addr(socket, getpeername)
+}
+
+fn query_addr(
+    socket: SOCKET,
+    query: unsafe extern "system" fn(SOCKET, *mut SOCKADDR, *mut c_int) -> c_int,
+) -> io::Result<SocketAddr> {
+    let mut storage: SOCKADDR_STORAGE = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<SOCKADDR_STORAGE>() as c_int;
+    if unsafe { query(socket, &mut storage as *mut _ as *mut SOCKADDR, &mut len) } == SOCKET_ERROR {
+        return Err(last_error());
+    }
+    to_socket_addr(&storage)
+}
+
+#[test]
+fn test_socket_addr_round_trip() {
+    use std::ptr;
+
+    let addrs: [SocketAddr; 3] = [
+        "192.168.1.20:8080".parse().unwrap(),
+        "[2001:db8::1]:443".parse().unwrap(),
+        SocketAddrV6::new("fe80::1".parse().unwrap(), 5353, 0x12345, 7).into(),
+    ];
+    for addr in &addrs {
+        let (raw, len) = socket_addr(addr);
+        let mut storage: SOCKADDR_STORAGE = unsafe { mem::zeroed() };
+        unsafe {
+            ptr::copy_nonoverlapping(
+                raw.as_ptr() as *const u8,
+                &mut storage as *mut _ as *mut u8,
+                len as usize,
+            )
+        };
+        assert_eq!(to_socket_addr(&storage).unwrap(), *addr);
+    }
+}
+
+#[test]
+fn test_socket_addr_v6_fields() {
+    //Filled in like winsock would, scope id and flowinfo in host order
+    let mut storage: SOCKADDR_STORAGE = unsafe { mem::zeroed() };
+    {
+        let raw = unsafe { &mut *(&mut storage as *mut _ as *mut SOCKADDR_IN6_LH) };
+        raw.sin6_family = AF_INET6 as _;
+        raw.sin6_port = 5353u16.to_be();
+        raw.sin6_flowinfo = 0xabcde;
+        unsafe { *raw.sin6_addr.u.Byte_mut() = "fe80::1".parse::<Ipv6Addr>().unwrap().octets() };
+        unsafe { *raw.u.sin6_scope_id_mut() = 0x0102_0304 };
+    }
+
+    let addr = match to_socket_addr(&storage).unwrap() {
+        SocketAddr::V6(addr) => addr,
+        addr => panic!("unexpected {}", addr),
+    };
+    assert_eq!(addr.flowinfo(), 0xabcde);
+    assert_eq!(addr.scope_id(), 0x0102_0304);
+
+    //And back, byte for byte
+    let (raw, len) = socket_addr(&addr.into());
+    assert_eq!(len as usize, mem::size_of::<SOCKADDR_IN6_LH>());
+    let bytes = |ptr: *const SOCKADDR| unsafe {
+        std::slice::from_raw_parts(ptr as *const u8, len as usize).to_vec()
+    };
+    assert_eq!(
+        bytes(raw.as_ptr()),
+        bytes(&storage as *const _ as *const SOCKADDR)
+    );
+}
@@ -0,0 +1,285 @@
+//! Non-blocking sockets, to be registered with a [`Registry`].
+//!
+//! [`Registry`]: crate::Registry
+
+mod addr;
+mod pair;
+mod pipe;
+mod serial;
+#[cfg(feature = "socket2")]
+mod socket2;
+mod tcp;
+mod udp;
+mod uds;
+mod watcher;
+
+pub use self::pair::{tcp_pair, udp_pair};
+pub use self::pipe::NamedPipe;
+pub use self::serial::{Parity, SerialPort, StopBits};
+pub use self::tcp::{TcpKeepalive, TcpListener, TcpSocket, TcpStream};
+pub use self::udp::{RecvMsg, UdpBuilder, UdpSocket};
+pub use self::uds::{UnixListener, UnixStream};
+pub use self::watcher::{Action, DirectoryWatcher, WatchFilter};
+
+pub(crate) use self::addr::{local_addr, peer_addr, socket_addr, to_socket_addr};
+pub(crate) use self::pipe::{complete as complete_pipe, PIPE_KEY};
+pub(crate) use self::tcp::{complete_accept, ACCEPT_KEY};
+pub(crate) use self::watcher::{complete as complete_watch, WATCH_KEY};
+pub(crate) use crate::sys::windows::net::{get_opt, last_error, set_opt};
+
+use crate::sys::windows::afd::init;
+use std::fmt;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::mem;
+use std::net::SocketAddr;
+use std::ptr::null_mut;
+use std::time::Duration;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{BOOL, DWORD};
+use winapi::shared::winerror::{WSAEMSGSIZE, WSAESHUTDOWN};
+use winapi::shared::ws2def::{
+    AF_INET, AF_INET6, IPPROTO_IP, IPPROTO_IPV6, SOCKADDR, SOCKADDR_STORAGE, SOL_SOCKET, SO_LINGER,
+    SO_RCVBUF, SO_SNDBUF, WSABUF,
+};
+use winapi::shared::ws2ipdef::{IPV6_UNICAST_HOPS, IPV6_V6ONLY, IP_TTL};
+use winapi::um::winsock2::{
+    closesocket, ioctlsocket, linger, WSARecv, WSARecvFrom, WSASend, WSASendTo, WSASocketW,
+    FIONBIO, INVALID_SOCKET, SOCKET, SOCKET_ERROR, SO_PROTOCOL_INFOW, WSAPROTOCOL_INFOW,
+    WSA_FLAG_NO_HANDLE_INHERIT, WSA_FLAG_OVERLAPPED,
+};
+
+pub(crate) fn family(addr: &SocketAddr) -> c_int {
+    match addr {
+        SocketAddr::V4(_) => AF_INET,
+        SocketAddr::V6(_) => AF_INET6,
+    }
+}
+
+//Non-blocking, and not inherited by child processes right from the start
+pub(crate) fn new_socket(family: c_int, ty: c_int) -> io::Result<SOCKET> {
+    init()?;
+
+    let socket = unsafe {
+        WSASocketW(
+            family,
+            ty,
+            0,
+            null_mut(),
+            0,
+            WSA_FLAG_OVERLAPPED | WSA_FLAG_NO_HANDLE_INHERIT,
+        )
+    };
+    if socket == INVALID_SOCKET {
+        return Err(last_error());
+    }
+
+    let mut nonblocking = 1;
+    if unsafe { ioctlsocket(socket, FIONBIO, &mut nonblocking) } != 0 {
+        let e = last_error();
+        unsafe { closesocket(socket) };
+        return Err(e);
+    }
+
+    Ok(socket)
+}
+
+//`IoSlice` and `IoSliceMut` are ABI compatible with WSABUF. If every buffer
+//is empty nothing is asked of winsock, the result is Ok(0) like for an empty
+//`read` or `write`.
+pub(crate) fn recv_vectored(socket: SOCKET, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+    if bufs.iter().all(|buf| buf.is_empty()) {
+        return Ok(0);
+    }
+
+    let mut received: DWORD = 0;
+    let mut flags: DWORD = 0;
+    let r = unsafe {
+        WSARecv(
+            socket,
+            bufs.as_mut_ptr() as *mut WSABUF,
+            bufs.len() as DWORD,
+            &mut received,
+            &mut flags,
+            null_mut(),
+            None,
+        )
+    };
+    if r == SOCKET_ERROR {
+        let e = last_error();
+        //Like std: reading after our own shutdown is the end of the stream
+        if e.raw_os_error() == Some(WSAESHUTDOWN as i32) {
+            return Ok(0);
+        }
+        return Err(e);
+    }
+    Ok(received as usize)
+}
+
+pub(crate) fn send_vectored(socket: SOCKET, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+    send_to_vectored(socket, bufs, None)
+}
+
+pub(crate) fn send_to_vectored(
+    socket: SOCKET,
+    bufs: &[IoSlice<'_>],
+    target: Option<&SocketAddr>,
+) -> io::Result<usize> {
+    if bufs.iter().all(|buf| buf.is_empty()) && target.is_none() {
+        return Ok(0);
+    }
+
+    let mut sent: DWORD = 0;
+    let r = match target {
+        Some(target) => {
+            let (raw_addr, len) = socket_addr(target);
+            unsafe {
+                WSASendTo(
+                    socket,
+                    bufs.as_ptr() as *mut WSABUF,
+                    bufs.len() as DWORD,
+                    &mut sent,
+                    0,
+                    raw_addr.as_ptr(),
+                    len,
+                    null_mut(),
+                    None,
+                )
+            }
+        }
+        None => unsafe {
+            WSASend(
+                socket,
+                bufs.as_ptr() as *mut WSABUF,
+                bufs.len() as DWORD,
+                &mut sent,
+                0,
+                null_mut(),
+                None,
+            )
+        },
+    };
+    if r == SOCKET_ERROR {
+        return Err(last_error());
+    }
+    Ok(sent as usize)
+}
+
+//A datagram larger than `bufs` fills them and the rest is dropped: that's how
+//much is reported as received, not an error.
+pub(crate) fn recv_from_vectored(
+    socket: SOCKET,
+    bufs: &mut [IoSliceMut<'_>],
+    flags: c_int,
+) -> io::Result<(usize, SocketAddr)> {
+    let mut storage: SOCKADDR_STORAGE = unsafe { mem::zeroed() };
+    let mut storage_len = mem::size_of::<SOCKADDR_STORAGE>() as c_int;
+    let mut received: DWORD = 0;
+    let mut flags = flags as DWORD;
+    let r = unsafe {
+        WSARecvFrom(
+            socket,
+            bufs.as_mut_ptr() as *mut WSABUF,
+            bufs.len() as DWORD,
+            &mut received,
+            &mut flags,
+            &mut storage as *mut _ as *mut SOCKADDR,
+            &mut storage_len,
+            null_mut(),
+            None,
+        )
+    };
+    let received = if r == SOCKET_ERROR {
+        let e = last_error();
+        if e.raw_os_error() != Some(WSAEMSGSIZE as i32) {
+            return Err(e);
+        }
+        bufs.iter().map(|buf| buf.len()).sum()
+    } else {
+        received as usize
+    };
+
+    Ok((received, to_socket_addr(&storage)?))
+}
+
+//`linger` counts in whole seconds
+pub(crate) fn set_linger(socket: SOCKET, dur: Option<Duration>) -> io::Result<()> {
+    let value = linger {
+        l_onoff: dur.is_some() as u16,
+        l_linger: dur.map_or(0, |dur| dur.as_secs().min(u16::max_value() as u64) as u16),
+    };
+    set_opt(socket, SOL_SOCKET, SO_LINGER, value)
+}
+
+pub(crate) fn linger(socket: SOCKET) -> io::Result<Option<Duration>> {
+    let value = get_opt::<linger>(socket, SOL_SOCKET, SO_LINGER)?;
+    Ok(if value.l_onoff == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(value.l_linger as u64))
+    })
+}
+
+pub(crate) fn set_recv_buffer_size(socket: SOCKET, size: u32) -> io::Result<()> {
+    set_buffer_size(socket, SO_RCVBUF, size)
+}
+
+pub(crate) fn recv_buffer_size(socket: SOCKET) -> io::Result<u32> {
+    get_opt::<c_int>(socket, SOL_SOCKET, SO_RCVBUF).map(|size| size as u32)
+}
+
+pub(crate) fn set_send_buffer_size(socket: SOCKET, size: u32) -> io::Result<()> {
+    set_buffer_size(socket, SO_SNDBUF, size)
+}
+
+pub(crate) fn send_buffer_size(socket: SOCKET) -> io::Result<u32> {
+    get_opt::<c_int>(socket, SOL_SOCKET, SO_SNDBUF).map(|size| size as u32)
+}
+
+fn set_buffer_size(socket: SOCKET, name: c_int, size: u32) -> io::Result<()> {
+    let size = size.min(c_int::max_value() as u32) as c_int;
+    set_opt(socket, SOL_SOCKET, name, size)
+}
+
+//Family the socket was created with, bound or not
+pub(crate) fn socket_family(socket: SOCKET) -> io::Result<c_int> {
+    get_opt::<WSAPROTOCOL_INFOW>(socket, SOL_SOCKET, SO_PROTOCOL_INFOW)
+        .map(|info| info.iAddressFamily)
+}
+
+//IP_TTL for IPv4 sockets, IPV6_UNICAST_HOPS for IPv6 ones
+fn ttl_opt(socket: SOCKET) -> io::Result<(c_int, c_int)> {
+    match socket_family(socket)? {
+        AF_INET6 => Ok((IPPROTO_IPV6 as c_int, IPV6_UNICAST_HOPS)),
+        _ => Ok((IPPROTO_IP, IP_TTL)),
+    }
+}
+
+pub(crate) fn set_ttl(socket: SOCKET, ttl: u32) -> io::Result<()> {
+    let (level, name) = ttl_opt(socket)?;
+    set_opt(socket, level, name, ttl as c_int)
+}
+
+pub(crate) fn ttl(socket: SOCKET) -> io::Result<u32> {
+    let (level, name) = ttl_opt(socket)?;
+    get_opt::<c_int>(socket, level, name).map(|ttl| ttl as u32)
+}
+
+pub(crate) fn set_only_v6(socket: SOCKET, only_v6: bool) -> io::Result<()> {
+    set_opt(socket, IPPROTO_IPV6 as c_int, IPV6_V6ONLY, only_v6 as BOOL)
+}
+
+pub(crate) fn only_v6(socket: SOCKET) -> io::Result<bool> {
+    get_opt::<BOOL>(socket, IPPROTO_IPV6 as c_int, IPV6_V6ONLY).map(|value| value != 0)
+}
+
+//Debug output for a value looked up on the fly, `<error>` if that failed
+pub(crate) struct OrError<T>(pub io::Result<T>);
+
+impl<T: fmt::Debug> fmt::Debug for OrError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Ok(ref value) => value.fmt(f),
+            Err(_) => f.write_str("<error>"),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use super::{TcpStream, UdpSocket};
+use crate::sys;
+use std::io;
+use std::net;
+
+/// Returns two TCP streams connected to each other over loopback, in
+/// non-blocking mode.
+///
+/// The listener in between is on an ephemeral port and closed before this
+/// returns. Somebody else connecting to it in the meantime is turned away:
+/// the accepted end is the one whose peer is the connecting stream.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::net::tcp_pair;
+/// use std::io::Write;
+///
+/// let (mut a, b) = tcp_pair()?;
+/// a.write(b"ping")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn tcp_pair() -> io::Result<(TcpStream, TcpStream)> {
+    let (connected, accepted) = sys::tcp_pair()?;
+    connected.set_nonblocking(true)?;
+    accepted.set_nonblocking(true)?;
+    Ok((
+        TcpStream::from_std(connected),
+        TcpStream::from_std(accepted),
+    ))
+}
+
+/// Returns two UDP sockets bound to ephemeral loopback ports and connected
+/// to each other, in non-blocking mode.
+pub fn udp_pair() -> io::Result<(UdpSocket, UdpSocket)> {
+    let a = net::UdpSocket::bind("127.0.0.1:0")?;
+    let b = net::UdpSocket::bind("127.0.0.1:0")?;
+    a.connect(b.local_addr()?)?;
+    b.connect(a.local_addr()?)?;
+
+    a.set_nonblocking(true)?;
+    b.set_nonblocking(true)?;
+    Ok((UdpSocket::from_std(a), UdpSocket::from_std(b)))
+}
+
+#[test]
+fn test_tcp_pair_concurrent() -> io::Result<()> {
+    use std::io::{Read, Write};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    //Each pair talks to itself only, however they were set up in parallel
+    let threads: Vec<_> = (0..8u8)
+        .map(|i| {
+            thread::spawn(move || -> io::Result<()> {
+                let (mut a, mut b) = tcp_pair()?;
+                assert_eq!(a.local_addr()?, b.peer_addr()?);
+                assert_eq!(a.peer_addr()?, b.local_addr()?);
+                assert_eq!(a.write(&[i])?, 1);
+
+                let mut buf = [0; 1];
+                let deadline = Instant::now() + Duration::from_secs(1);
+                loop {
+                    match b.read(&mut buf) {
+                        Ok(n) => {
+                            assert_eq!(&buf[..n], [i]);
+                            return Ok(());
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            assert!(Instant::now() < deadline, "nothing received");
+                            thread::sleep(Duration::from_millis(10));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_pair() -> io::Result<()> {
+    use std::thread;
+    use std::time::Duration;
+
+    let (a, b) = udp_pair()?;
+    //Non-blocking from the start
+    let mut buf = [0; 4];
+    assert_eq!(
+        a.recv(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    assert_eq!(b.send(b"ping")?, 4);
+    thread::sleep(Duration::from_millis(50));
+    let (n, from) = a.recv_from(&mut buf)?;
+    assert_eq!(&buf[..n], b"ping");
+    assert_eq!(from, b.local_addr()?);
+
+    Ok(())
+}
@@ -0,0 +1,744 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event::{self, Event};
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::readiness::Readiness;
+use crate::sys::Selector;
+use crate::token::Token;
+use miow::iocp::CompletionStatus;
+use miow::pipe;
+use miow::Overlapped;
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+use std::ptr::null_mut;
+use std::sync::{Arc, Mutex};
+use std::{cmp, fmt, mem};
+use winapi::shared::winerror::{
+    ERROR_BROKEN_PIPE, ERROR_IO_PENDING, ERROR_NO_DATA, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED,
+    ERROR_PIPE_LISTENING, ERROR_PIPE_NOT_CONNECTED,
+};
+use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::ioapiset::CancelIoEx;
+use winapi::um::minwinbase::OVERLAPPED;
+use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW};
+use winapi::um::winbase::{
+    FILE_FLAG_OVERLAPPED, PIPE_ACCESS_DUPLEX, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+    PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT, SECURITY_IDENTIFICATION,
+    SECURITY_SQOS_PRESENT,
+};
+use winapi::um::winnt::{GENERIC_READ, GENERIC_WRITE, HANDLE};
+
+//Completion key of named pipes, see `ACCEPT_KEY`
+pub(crate) const PIPE_KEY: usize = 2;
+
+//Size of the pipe buffers, and of a single overlapped read
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// A non-blocking named pipe, server or client end.
+///
+/// Named pipes are not sockets: instead of being polled, the pipe runs
+/// overlapped operations and reports their completions. One read is kept in
+/// flight into an internal buffer, `read` hands out what it got. A `write`
+/// copies the data and starts an overlapped write, the next one is
+/// `WouldBlock` until that completed.
+///
+/// Events only come with completions: after a readable event read until
+/// `WouldBlock`, after a writable event write until `WouldBlock`. Nothing is
+/// started before the pipe is registered, and a pipe stays tied to the
+/// registry it was first registered with.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::net::NamedPipe;
+/// use iocp_wrapper::{Events, Interests, Poll, Token};
+/// use std::io::Write;
+///
+/// let mut poll = Poll::new()?;
+/// let server = NamedPipe::new(r"\\.\pipe\iocp-wrapper-example")?;
+/// poll.registry()
+///     .register(&server, Token(0), Interests::READABLE | Interests::WRITABLE)?;
+/// let _ = server.connect();
+///
+/// let mut client = NamedPipe::open(r"\\.\pipe\iocp-wrapper-example")?;
+/// poll.registry()
+///     .register(&client, Token(1), Interests::WRITABLE)?;
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// client.write(b"hello")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct NamedPipe {
+    inner: Arc<Inner>,
+}
+
+//Shared with the operations in flight, which keep it alive
+struct Inner {
+    handle: pipe::NamedPipe,
+    io: Mutex<Io>,
+}
+
+struct Io {
+    //set on the first registration, the handle can't leave its port
+    selector: Option<Selector>,
+    registration: Option<(Token, Interests)>,
+    connected: bool,
+    connecting: bool,
+    //bumped by `disconnect`, operations started before belong to the last
+    //client: their completions only end the operation
+    generation: usize,
+    read: ReadState,
+    write: WriteState,
+    //select() call and index of the last event reported, like `Report`
+    report: Option<(usize, usize)>,
+}
+
+enum ReadState {
+    Idle,
+    Pending,
+    //received data, and how much of it was handed out
+    Ready(Vec<u8>, usize),
+    //the other end is closed, reads return 0
+    Closed,
+    Err(io::Error),
+}
+
+enum WriteState {
+    Idle,
+    Pending,
+    Err(io::Error),
+}
+
+enum OpKind {
+    Connect,
+    Read,
+    Write,
+    //no I/O, posted to report readiness the state already has
+    Wake,
+}
+
+//Like `AcceptOp`: owned by the kernel until its completion is taken from the
+//port, leaked if the port is closed first.
+#[repr(C)]
+struct PipeOp {
+    overlapped: OVERLAPPED,
+    kind: OpKind,
+    generation: usize,
+    buf: Vec<u8>,
+    inner: Arc<Inner>,
+}
+
+impl PipeOp {
+    fn new(inner: &Arc<Inner>, kind: OpKind, generation: usize, buf: Vec<u8>) -> *mut PipeOp {
+        Box::into_raw(Box::new(PipeOp {
+            overlapped: unsafe { mem::zeroed() },
+            kind,
+            generation,
+            buf,
+            inner: inner.clone(),
+        }))
+    }
+}
+
+impl NamedPipe {
+    /// Creates a server instance of the pipe `name`, like `\\.\pipe\name`.
+    ///
+    /// Further instances with the same name serve further clients. Remote
+    /// clients are rejected, and the handle isn't inherited by child
+    /// processes.
+    pub fn new<A: AsRef<OsStr>>(name: A) -> io::Result<NamedPipe> {
+        let name = wide(name.as_ref());
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX | FILE_FLAG_OVERLAPPED,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE as u32,
+                BUFFER_SIZE as u32,
+                0,
+                null_mut(),
+            )
+        };
+        NamedPipe::from_handle(handle, false)
+    }
+
+    /// Opens the client end of the pipe `name`, connected right away.
+    ///
+    /// `WouldBlock` if every server instance is taken: try again once the
+    /// server created another one.
+    pub fn open<A: AsRef<OsStr>>(name: A) -> io::Result<NamedPipe> {
+        let name = wide(name.as_ref());
+        let handle = unsafe {
+            CreateFileW(
+                name.as_ptr(),
+                GENERIC_READ | GENERIC_WRITE,
+                0,
+                null_mut(),
+                OPEN_EXISTING,
+                FILE_FLAG_OVERLAPPED | SECURITY_SQOS_PRESENT | SECURITY_IDENTIFICATION,
+                null_mut(),
+            )
+        };
+        NamedPipe::from_handle(handle, true)
+    }
+
+    pub(crate) fn from_handle(handle: HANDLE, connected: bool) -> io::Result<NamedPipe> {
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(pipe_error(io::Error::last_os_error()));
+        }
+
+        Ok(NamedPipe {
+            inner: Arc::new(Inner {
+                handle: unsafe { pipe::NamedPipe::from_raw_handle(handle) },
+                io: Mutex::new(Io {
+                    selector: None,
+                    registration: None,
+                    connected,
+                    connecting: false,
+                    generation: 0,
+                    read: ReadState::Idle,
+                    write: WriteState::Idle,
+                    report: None,
+                }),
+            }),
+        })
+    }
+
+    /// Waits for a client on a server instance.
+    ///
+    /// `Ok` if one is connected already. Otherwise `WouldBlock`, and a
+    /// writable event follows once a client connected. The pipe has to be
+    /// registered first.
+    pub fn connect(&self) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        if io.connected {
+            return Ok(());
+        }
+        if io.connecting {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        if io.selector.is_none() {
+            return Err(misuse(
+                ErrorKind::NotRegistered,
+                None,
+                "named pipe is not registered",
+            ));
+        }
+
+        let op = PipeOp::new(&self.inner, OpKind::Connect, io.generation, Vec::new());
+        let handle = self.inner.handle.as_raw_handle() as HANDLE;
+        //Overlapped, success is reported with ERROR_IO_PENDING too
+        if unsafe { ConnectNamedPipe(handle, &mut (*op).overlapped) } != 0 {
+            io.connecting = true;
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let e = io::Error::last_os_error();
+        match e.raw_os_error().map(|code| code as u32) {
+            Some(ERROR_IO_PENDING) => {
+                io.connecting = true;
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+            //The client came in before the call: nothing is queued for it
+            Some(ERROR_PIPE_CONNECTED) => {
+                drop(unsafe { Box::from_raw(op) });
+                io.connected = true;
+                start_read(&self.inner, &mut io);
+                wake(&self.inner, &io)
+            }
+            _ => {
+                drop(unsafe { Box::from_raw(op) });
+                Err(pipe_error(e))
+            }
+        }
+    }
+
+    /// Drops the client of a server instance, which can `connect` again.
+    ///
+    /// Data not read yet is lost, and so are writes still in flight.
+    pub fn disconnect(&self) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        self.inner.handle.disconnect()?;
+
+        io.connected = false;
+        io.generation += 1;
+        match io.read {
+            ReadState::Pending => {}
+            _ => io.read = ReadState::Idle,
+        }
+        match io.write {
+            WriteState::Pending => {}
+            _ => io.write = WriteState::Idle,
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn wide(name: &OsStr) -> Vec<u16> {
+    name.encode_wide().chain(Some(0)).collect()
+}
+
+//The pipe specific codes, as something callers can match on
+fn pipe_error(e: io::Error) -> io::Error {
+    let kind = match e.raw_os_error().map(|code| code as u32) {
+        //a server instance without client
+        Some(ERROR_PIPE_LISTENING) | Some(ERROR_PIPE_NOT_CONNECTED) => io::ErrorKind::NotConnected,
+        //the other end is closed, or being closed
+        Some(ERROR_BROKEN_PIPE) | Some(ERROR_NO_DATA) => io::ErrorKind::BrokenPipe,
+        Some(ERROR_PIPE_BUSY) => io::ErrorKind::WouldBlock,
+        _ => return e,
+    };
+    io::Error::new(kind, e)
+}
+
+//A closed pipe reads as end of file
+fn read_error(e: io::Error) -> ReadState {
+    if e.raw_os_error() == Some(ERROR_BROKEN_PIPE as i32) {
+        ReadState::Closed
+    } else {
+        ReadState::Err(pipe_error(e))
+    }
+}
+
+//Keeps one read in flight while connected
+fn start_read(inner: &Arc<Inner>, io: &mut Io) {
+    if io.selector.is_none() || !io.connected {
+        return;
+    }
+    match io.read {
+        ReadState::Idle => {}
+        _ => return,
+    }
+
+    let op = PipeOp::new(inner, OpKind::Read, io.generation, vec![0; BUFFER_SIZE]);
+    match unsafe {
+        inner
+            .handle
+            .read_overlapped(&mut (*op).buf, &mut (*op).overlapped)
+    } {
+        Ok(_) => io.read = ReadState::Pending,
+        Err(e) => {
+            drop(unsafe { Box::from_raw(op) });
+            io.read = read_error(e);
+            //Nothing completes for it, the failure still has to be reported
+            let _ = wake(inner, io);
+        }
+    }
+}
+
+fn start_write(inner: &Arc<Inner>, io: &mut Io, buf: Vec<u8>) -> io::Result<()> {
+    let op = PipeOp::new(inner, OpKind::Write, io.generation, buf);
+    match unsafe {
+        inner
+            .handle
+            .write_overlapped(&(*op).buf, &mut (*op).overlapped)
+    } {
+        Ok(_) => {
+            io.write = WriteState::Pending;
+            Ok(())
+        }
+        Err(e) => {
+            drop(unsafe { Box::from_raw(op) });
+            Err(pipe_error(e))
+        }
+    }
+}
+
+fn wake(inner: &Arc<Inner>, io: &Io) -> io::Result<()> {
+    let selector = match io.selector {
+        Some(ref selector) => selector,
+        None => return Ok(()),
+    };
+
+    let op = PipeOp::new(inner, OpKind::Wake, io.generation, Vec::new());
+    let status = CompletionStatus::new(0, PIPE_KEY, op as *mut Overlapped);
+    if let Err(e) = selector.port().post(status) {
+        drop(unsafe { Box::from_raw(op) });
+        return Err(e);
+    }
+    Ok(())
+}
+
+impl Io {
+    fn readiness(&self) -> Readiness {
+        let mut readiness = match self.read {
+            ReadState::Ready(..) => Readiness::READABLE,
+            ReadState::Closed => Readiness::READABLE | Readiness::HUP,
+            ReadState::Err(_) => Readiness::READABLE | Readiness::ERROR,
+            ReadState::Idle | ReadState::Pending => Readiness::EMPTY,
+        };
+        match self.write {
+            WriteState::Idle if self.connected => readiness = readiness | Readiness::WRITABLE,
+            WriteState::Err(_) => readiness = readiness | Readiness::WRITABLE | Readiness::ERROR,
+            _ => {}
+        }
+        readiness
+    }
+
+    //Like `SockState::report`, one event per select() call
+    fn report(&mut self, seq: usize, events: &mut Vec<Event>) {
+        let (token, interests) = match self.registration {
+            Some(registration) => registration,
+            None => return,
+        };
+        let wanted = Readiness::from(interests) | Readiness::ERROR | Readiness::HUP;
+        let readiness = self.readiness() & wanted;
+        if readiness.is_empty() {
+            return;
+        }
+
+        match self.report {
+            Some((last, index)) if last == seq => events[index].add_readiness(readiness),
+            _ => {
+                self.report = Some((seq, events.len()));
+                events.push(Event::new(readiness, token));
+            }
+        }
+    }
+}
+
+//Handles a completion taken from the port with `PIPE_KEY`
+pub(crate) unsafe fn complete(overlapped: *mut OVERLAPPED, seq: usize, events: &mut Vec<Event>) {
+    let mut op = Box::from_raw(overlapped as *mut PipeOp);
+    let result = match op.kind {
+        OpKind::Wake => Ok(0),
+        _ => op.inner.handle.result(&mut op.overlapped),
+    };
+    let PipeOp {
+        kind,
+        generation,
+        mut buf,
+        inner,
+        ..
+    } = *op;
+
+    let mut io = inner.io.lock().unwrap();
+    let stale = generation != io.generation;
+    match kind {
+        OpKind::Connect => {
+            io.connecting = false;
+            match result {
+                Ok(_) if !stale => io.connected = true,
+                Ok(_) => {}
+                Err(e) => io.write = WriteState::Err(pipe_error(e)),
+            }
+        }
+        OpKind::Read => {
+            io.read = match result {
+                _ if stale => ReadState::Idle,
+                //nothing was read, e.g. a zero sized message: read again
+                Ok(0) => ReadState::Idle,
+                Ok(n) => {
+                    buf.truncate(n);
+                    ReadState::Ready(buf, 0)
+                }
+                Err(e) => read_error(e),
+            };
+        }
+        OpKind::Write => {
+            io.write = match result {
+                _ if stale => WriteState::Idle,
+                Ok(n) if n < buf.len() => {
+                    buf.drain(..n);
+                    match start_write(&inner, &mut io, buf) {
+                        Ok(()) => return,
+                        Err(e) => WriteState::Err(e),
+                    }
+                }
+                Ok(_) => WriteState::Idle,
+                Err(e) => WriteState::Err(pipe_error(e)),
+            };
+        }
+        OpKind::Wake => {}
+    }
+
+    start_read(&inner, &mut io);
+    io.report(seq, events);
+}
+
+impl Read for NamedPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl<'a> Read for &'a NamedPipe {
+    /// Hands out what the read in flight received, `WouldBlock` while it
+    /// hasn't completed. Returns 0 once the other end is closed.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut io = self.inner.io.lock().unwrap();
+        let r = match mem::replace(&mut io.read, ReadState::Idle) {
+            ReadState::Ready(data, pos) => {
+                let n = cmp::min(buf.len(), data.len() - pos);
+                buf[..n].copy_from_slice(&data[pos..pos + n]);
+                if pos + n < data.len() {
+                    io.read = ReadState::Ready(data, pos + n);
+                }
+                Ok(n)
+            }
+            ReadState::Closed => {
+                io.read = ReadState::Closed;
+                Ok(0)
+            }
+            ReadState::Err(e) => Err(e),
+            ReadState::Idle if !io.connected && !io.connecting => {
+                Err(io::ErrorKind::NotConnected.into())
+            }
+            state => {
+                io.read = state;
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        };
+
+        start_read(&self.inner, &mut io);
+        r
+    }
+}
+
+impl Write for NamedPipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+impl<'a> Write for &'a NamedPipe {
+    /// Takes all of `buf` for one overlapped write, `WouldBlock` while the
+    /// previous one is in flight. A failed write is returned by the next call.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut io = self.inner.io.lock().unwrap();
+        match mem::replace(&mut io.write, WriteState::Idle) {
+            WriteState::Idle => {}
+            WriteState::Pending => {
+                io.write = WriteState::Pending;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            WriteState::Err(e) => return Err(e),
+        }
+        if !io.connected {
+            return Err(if io.connecting {
+                io::ErrorKind::WouldBlock.into()
+            } else {
+                io::ErrorKind::NotConnected.into()
+            });
+        }
+        if io.selector.is_none() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        start_write(&self.inner, &mut io, buf.to_vec())?;
+        Ok(buf.len())
+    }
+
+    //Writes in flight complete on their own
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for NamedPipe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let io = self.inner.io.lock().unwrap();
+        f.debug_struct("NamedPipe")
+            .field("handle", &self.inner.handle.as_raw_handle())
+            .field("connected", &io.connected)
+            .field("registered", &io.registration.is_some())
+            .finish()
+    }
+}
+
+impl AsRawHandle for NamedPipe {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.inner.handle.as_raw_handle()
+    }
+}
+
+impl event::Source for NamedPipe {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        if io.registration.is_some() {
+            return Err(misuse(
+                ErrorKind::AlreadyRegistered,
+                Some(token),
+                "named pipe is already registered",
+            ));
+        }
+
+        let port = registry.selector().port();
+        match io.selector {
+            Some(ref selector) if selector.port().as_raw_handle() != port.as_raw_handle() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "named pipe is tied to another registry",
+                ));
+            }
+            Some(_) => {}
+            None => {
+                port.add_handle(PIPE_KEY, &self.inner.handle)?;
+                io.selector = Some(registry.selector().clone());
+            }
+        }
+
+        io.registration = Some((token, interests));
+        start_read(&self.inner, &mut io);
+        wake(&self.inner, &io)
+    }
+
+    fn reregister(
+        &self,
+        _registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        if io.registration.is_none() {
+            return Err(misuse(
+                ErrorKind::NotRegistered,
+                Some(token),
+                "named pipe is not registered",
+            ));
+        }
+
+        io.registration = Some((token, interests));
+        wake(&self.inner, &io)
+    }
+
+    //Operations in flight go on, their completions are no longer reported
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        if io.registration.take().is_none() {
+            return Err(misuse(
+                ErrorKind::NotRegistered,
+                None,
+                "named pipe is not registered",
+            ));
+        }
+        Ok(())
+    }
+}
+
+//The handle itself is closed once the cancelled operations have completed
+impl Drop for NamedPipe {
+    fn drop(&mut self) {
+        //Nothing is reported for a pipe that's gone
+        self.inner.io.lock().unwrap().registration = None;
+        unsafe { CancelIoEx(self.inner.handle.as_raw_handle() as HANDLE, null_mut()) };
+    }
+}
+
+#[test]
+fn test_named_pipe_echo() -> io::Result<()> {
+    use crate::event::{is_readable, is_writable, token};
+    use crate::{Events, Poll};
+    use std::process;
+    use std::time::Duration;
+
+    let name = format!(r"\\.\pipe\iocp-wrapper-echo-{}", process::id());
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let mut server = NamedPipe::new(&name)?;
+    let e = server.read(&mut [0; 1]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+    poll.registry()
+        .register(&server, Token(0), Interests::READABLE | Interests::WRITABLE)?;
+    assert_eq!(
+        server.connect().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    let mut client = NamedPipe::open(&name)?;
+    poll.registry()
+        .register(&client, Token(1), Interests::READABLE | Interests::WRITABLE)?;
+
+    //The connect completing, and the client writable from the start
+    let mut writable = (false, false);
+    while writable != (true, true) {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "connection not reported");
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            match token(event) {
+                Token(0) => writable.0 |= is_writable(event),
+                Token(1) => writable.1 |= is_writable(event),
+                other => panic!("unexpected token {:?}", other),
+            }
+        }
+    }
+    server.connect()?;
+
+    //Client to server and back
+    assert_eq!(client.write(b"ping")?, 4);
+    let mut echoed = Vec::new();
+    let mut buf = [0; 16];
+    while echoed.len() < 4 {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "data not reported");
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            if !is_readable(event) {
+                continue;
+            }
+            match token(event) {
+                Token(0) => loop {
+                    match server.read(&mut buf) {
+                        Ok(n) => assert_eq!(server.write(&buf[..n])?, n),
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                },
+                Token(1) => loop {
+                    match client.read(&mut buf) {
+                        Ok(n) => echoed.extend_from_slice(&buf[..n]),
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                },
+                other => panic!("unexpected token {:?}", other),
+            }
+        }
+    }
+    assert_eq!(echoed, b"ping");
+
+    //The server sees the client leave as end of file
+    drop(client);
+    loop {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "hang-up not reported");
+        match server.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => panic!("{} bytes after the client left", n),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let e = server.write(b"gone").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+
+    //Ready for the next client
+    server.disconnect()?;
+    let e = server.read(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+    assert_eq!(
+        server.connect().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+    let _client = NamedPipe::open(&name)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_writable(events.get(0).unwrap()));
+
+    Ok(())
+}
@@ -0,0 +1,351 @@
+use super::pipe::{wide, NamedPipe};
+use crate::event;
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::token::Token;
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::ptr::null_mut;
+use std::{fmt, mem};
+use winapi::shared::minwindef::DWORD;
+use winapi::um::commapi::{GetCommState, SetCommState, SetCommTimeouts};
+use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::winbase::{
+    COMMTIMEOUTS, DCB, EVENPARITY, FILE_FLAG_OVERLAPPED, MARKPARITY, NOPARITY, ODDPARITY,
+    ONE5STOPBITS, ONESTOPBIT, SPACEPARITY, TWOSTOPBITS,
+};
+use winapi::um::winnt::{GENERIC_READ, GENERIC_WRITE, HANDLE};
+
+/// Parity checking of a [`SerialPort`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    Mark,
+    Space,
+}
+
+/// Stop bits of a [`SerialPort`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    OnePointFive,
+    Two,
+}
+
+/// A non-blocking serial port.
+///
+/// The I/O is that of a [`NamedPipe`]'s client end: one overlapped read is
+/// kept in flight, `read` hands out what it got and `write` starts an
+/// overlapped write, `WouldBlock` until the previous one completed. With the
+/// timeouts set by `open` a read completes as soon as a byte came in.
+///
+/// The settings are passed through to the port's `DCB`, unchanged settings
+/// are kept as they were.
+///
+/// [`NamedPipe`]: crate::net::NamedPipe
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::net::{Parity, SerialPort};
+/// use iocp_wrapper::{Events, Interests, Poll, Token};
+/// use std::io::Write;
+///
+/// let mut poll = Poll::new()?;
+/// let mut port = SerialPort::open("COM3")?;
+/// port.set_baud_rate(115_200)?;
+/// port.set_parity(Parity::None)?;
+/// poll.registry()
+///     .register(&port, Token(0), Interests::READABLE | Interests::WRITABLE)?;
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// port.write(b"AT\r")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SerialPort {
+    //Only its overlapped reads and writes are used, which are those of any
+    //handle
+    inner: NamedPipe,
+}
+
+impl SerialPort {
+    /// Opens the port `name`, like `COM3`. Ports past `COM9` need the
+    /// `\\.\COM10` form, which works for all of them.
+    pub fn open<A: AsRef<OsStr>>(name: A) -> io::Result<SerialPort> {
+        let name = wide(name.as_ref());
+        let handle = unsafe {
+            CreateFileW(
+                name.as_ptr(),
+                GENERIC_READ | GENERIC_WRITE,
+                0,
+                null_mut(),
+                OPEN_EXISTING,
+                FILE_FLAG_OVERLAPPED,
+                null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        let port = SerialPort::from_handle(handle)?;
+
+        //A read returns once there is at least a byte, and never times out
+        //for good: one that does completes empty and is started again
+        let mut timeouts = COMMTIMEOUTS {
+            ReadIntervalTimeout: DWORD::MAX,
+            ReadTotalTimeoutMultiplier: DWORD::MAX,
+            ReadTotalTimeoutConstant: DWORD::MAX - 1,
+            WriteTotalTimeoutMultiplier: 0,
+            WriteTotalTimeoutConstant: 0,
+        };
+        if unsafe { SetCommTimeouts(handle, &mut timeouts) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(port)
+    }
+
+    //Takes ownership of an overlapped handle, any that reads and writes
+    pub(crate) fn from_handle(handle: HANDLE) -> io::Result<SerialPort> {
+        Ok(SerialPort {
+            inner: NamedPipe::from_handle(handle, true)?,
+        })
+    }
+
+    fn state(&self) -> io::Result<DCB> {
+        let mut dcb: DCB = unsafe { mem::zeroed() };
+        dcb.DCBlength = mem::size_of::<DCB>() as DWORD;
+        if unsafe { GetCommState(self.handle(), &mut dcb) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(dcb)
+    }
+
+    fn set_state<F: FnOnce(&mut DCB)>(&self, f: F) -> io::Result<()> {
+        let mut dcb = self.state()?;
+        f(&mut dcb);
+        if unsafe { SetCommState(self.handle(), &mut dcb) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn handle(&self) -> HANDLE {
+        self.inner.as_raw_handle() as HANDLE
+    }
+
+    pub fn baud_rate(&self) -> io::Result<u32> {
+        self.state().map(|dcb| dcb.BaudRate)
+    }
+
+    pub fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+        self.set_state(|dcb| dcb.BaudRate = baud_rate)
+    }
+
+    pub fn data_bits(&self) -> io::Result<u8> {
+        self.state().map(|dcb| dcb.ByteSize)
+    }
+
+    /// 5 to 8, what the port supports.
+    pub fn set_data_bits(&self, data_bits: u8) -> io::Result<()> {
+        self.set_state(|dcb| dcb.ByteSize = data_bits)
+    }
+
+    pub fn parity(&self) -> io::Result<Parity> {
+        self.state().and_then(|dcb| match dcb.Parity {
+            NOPARITY => Ok(Parity::None),
+            ODDPARITY => Ok(Parity::Odd),
+            EVENPARITY => Ok(Parity::Even),
+            MARKPARITY => Ok(Parity::Mark),
+            SPACEPARITY => Ok(Parity::Space),
+            _ => Err(io::Error::other("unknown parity")),
+        })
+    }
+
+    /// Sets the parity, with checking on for anything but `None`.
+    pub fn set_parity(&self, parity: Parity) -> io::Result<()> {
+        let value = match parity {
+            Parity::None => NOPARITY,
+            Parity::Odd => ODDPARITY,
+            Parity::Even => EVENPARITY,
+            Parity::Mark => MARKPARITY,
+            Parity::Space => SPACEPARITY,
+        };
+        self.set_state(|dcb| {
+            dcb.Parity = value;
+            dcb.set_fParity((parity != Parity::None) as DWORD);
+        })
+    }
+
+    pub fn stop_bits(&self) -> io::Result<StopBits> {
+        self.state().and_then(|dcb| match dcb.StopBits {
+            ONESTOPBIT => Ok(StopBits::One),
+            ONE5STOPBITS => Ok(StopBits::OnePointFive),
+            TWOSTOPBITS => Ok(StopBits::Two),
+            _ => Err(io::Error::other("unknown stop bits")),
+        })
+    }
+
+    pub fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+        let value = match stop_bits {
+            StopBits::One => ONESTOPBIT,
+            StopBits::OnePointFive => ONE5STOPBITS,
+            StopBits::Two => TWOSTOPBITS,
+        };
+        self.set_state(|dcb| dcb.StopBits = value)
+    }
+}
+
+impl Read for SerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.inner).read(buf)
+    }
+}
+
+impl Read for &SerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.inner).read(buf)
+    }
+}
+
+impl Write for SerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.inner).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.inner).flush()
+    }
+}
+
+impl Write for &SerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.inner).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.inner).flush()
+    }
+}
+
+impl fmt::Debug for SerialPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SerialPort")
+            .field("handle", &self.inner.as_raw_handle())
+            .finish()
+    }
+}
+
+impl AsRawHandle for SerialPort {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.inner.as_raw_handle()
+    }
+}
+
+impl event::Source for SerialPort {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+//A pipe stands in for the device: the I/O is all the same
+#[test]
+fn test_serial_port_over_pipe() -> io::Result<()> {
+    use crate::event::{is_readable, is_writable, token};
+    use crate::{Events, Poll};
+    use std::process;
+    use std::time::Duration;
+
+    let name = format!(r"\\.\pipe\iocp-wrapper-serial-{}", process::id());
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let mut device = NamedPipe::new(&name)?;
+    poll.registry()
+        .register(&device, Token(0), Interests::READABLE)?;
+    let _ = device.connect();
+
+    let handle = unsafe {
+        CreateFileW(
+            wide(name.as_ref()).as_ptr(),
+            GENERIC_READ | GENERIC_WRITE,
+            0,
+            null_mut(),
+            OPEN_EXISTING,
+            FILE_FLAG_OVERLAPPED,
+            null_mut(),
+        )
+    };
+    assert_ne!(handle, INVALID_HANDLE_VALUE);
+    let mut port = SerialPort::from_handle(handle)?;
+    //No DCB on a pipe, the error is passed through
+    assert!(port.baud_rate().is_err());
+    assert!(port.set_parity(Parity::Even).is_err());
+
+    poll.registry()
+        .register(&port, Token(1), Interests::READABLE | Interests::WRITABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert!((0..events.len()).any(|i| {
+        let event = events.get(i).unwrap();
+        token(event) == Token(1) && is_writable(event)
+    }));
+    device.connect()?;
+
+    //Port to device, and the reply back
+    assert_eq!(port.write(b"AT\r")?, 3);
+    let mut buf = [0; 16];
+    let mut request = Vec::new();
+    let mut reply = Vec::new();
+    while reply.len() < 4 {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "data not reported");
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            if !is_readable(event) {
+                continue;
+            }
+            match token(event) {
+                Token(0) => loop {
+                    match device.read(&mut buf) {
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                    if request == b"AT\r" {
+                        assert_eq!(device.write(b"OK\r\n")?, 4);
+                    }
+                },
+                Token(1) => loop {
+                    match port.read(&mut buf) {
+                        Ok(n) => reply.extend_from_slice(&buf[..n]),
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                },
+                other => panic!("unexpected token {:?}", other),
+            }
+        }
+    }
+    assert_eq!(reply, b"OK\r\n");
+
+    Ok(())
+}
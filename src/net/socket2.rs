@@ -0,0 +1,193 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::net::{get_opt, TcpListener, TcpStream, UdpSocket};
+use crate::poll::Registry;
+use crate::sys::raw_source;
+use crate::token::Token;
+use ::socket2::Socket;
+use std::convert::TryFrom;
+use std::io;
+use std::os::windows::io::AsRawSocket;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::ws2def::{IPPROTO_TCP, IPPROTO_UDP, SOL_SOCKET, SO_ACCEPTCONN};
+use winapi::um::winsock2::{SOCKET, SOCK_DGRAM, SOCK_STREAM, SO_PROTOCOL_INFOW, WSAPROTOCOL_INFOW};
+
+//Registered as is, writes don't drop the cached writable readiness: wrap the
+//socket in an `IoSource` for that.
+impl event::Source for Socket {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        registry
+            .selector()
+            .register(raw_source(self), token, interests)
+            .map(|_| ())
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        registry
+            .selector()
+            .reregister(raw_source(self), None, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        registry.selector().deregister(raw_source(self), None)
+    }
+}
+
+//Type and protocol have to match, and only listeners may be listening
+fn check(
+    socket: &Socket,
+    ty: c_int,
+    protocol: c_int,
+    listening: bool,
+    what: &str,
+) -> io::Result<()> {
+    let raw = socket.as_raw_socket() as SOCKET;
+    let info = get_opt::<WSAPROTOCOL_INFOW>(raw, SOL_SOCKET, SO_PROTOCOL_INFOW)?;
+    let accepting = get_opt::<BOOL>(raw, SOL_SOCKET, SO_ACCEPTCONN)? != 0;
+
+    if info.iSocketType != ty || info.iProtocol != protocol || accepting != listening {
+        let msg = format!("not a {} socket", what);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    }
+    socket.set_nonblocking(true)
+}
+
+/// Takes over a connected or connecting TCP socket, making it non-blocking.
+impl TryFrom<Socket> for TcpStream {
+    type Error = io::Error;
+
+    fn try_from(socket: Socket) -> io::Result<TcpStream> {
+        check(
+            &socket,
+            SOCK_STREAM,
+            IPPROTO_TCP as c_int,
+            false,
+            "TCP stream",
+        )?;
+        Ok(TcpStream::from_std(socket.into_tcp_stream()))
+    }
+}
+
+/// Takes over a listening TCP socket, making it non-blocking.
+impl TryFrom<Socket> for TcpListener {
+    type Error = io::Error;
+
+    fn try_from(socket: Socket) -> io::Result<TcpListener> {
+        check(
+            &socket,
+            SOCK_STREAM,
+            IPPROTO_TCP as c_int,
+            true,
+            "listening TCP",
+        )?;
+        Ok(TcpListener::from_std(socket.into_tcp_listener()))
+    }
+}
+
+/// Takes over a UDP socket, making it non-blocking.
+impl TryFrom<Socket> for UdpSocket {
+    type Error = io::Error;
+
+    fn try_from(socket: Socket) -> io::Result<UdpSocket> {
+        check(&socket, SOCK_DGRAM, IPPROTO_UDP as c_int, false, "UDP")?;
+        Ok(UdpSocket::from_std(socket.into_udp_socket()))
+    }
+}
+
+#[test]
+fn test_socket2_conversions() -> io::Result<()> {
+    use crate::event::{is_readable, is_writable};
+    use crate::{Events, Poll};
+    use ::socket2::{Domain, Protocol, SockAddr, Type};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let socket = Socket::new(Domain::ipv4(), Type::stream(), Some(Protocol::tcp()))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SockAddr::from(
+        "127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap(),
+    ))?;
+    let addr = socket.local_addr()?;
+    //Not listening yet
+    let socket = match TcpListener::try_from(socket.try_clone()?) {
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => socket,
+        r => panic!("unexpected {:?}", r.map(|_| ())),
+    };
+    socket.listen(8)?;
+    let listener = TcpListener::try_from(socket)?;
+    poll.registry()
+        .register(&listener, Token(0), Interests::READABLE)?;
+
+    let socket = Socket::new(Domain::ipv4(), Type::stream(), None)?;
+    socket.set_nodelay(true)?;
+    match socket.connect(&addr) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        Err(e) => return Err(e),
+    }
+    let stream = TcpStream::try_from(socket)?;
+    assert!(stream.nodelay()?);
+    poll.registry()
+        .register(&stream, Token(1), Interests::WRITABLE)?;
+
+    let mut ready = (false, false);
+    while ready != (true, true) {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "connection not reported");
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            match event::token(event) {
+                Token(0) => ready.0 |= is_readable(event),
+                Token(1) => ready.1 |= is_writable(event),
+                other => panic!("unexpected token {:?}", other),
+            }
+        }
+    }
+    listener.accept()?;
+
+    //A datagram socket is no stream
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), None)?;
+    let e = TcpStream::try_from(socket.try_clone()?).err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    assert!(UdpSocket::try_from(socket).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_socket2_source() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use ::socket2::{Domain, SockAddr, Type};
+    use std::net;
+    use std::time::Duration;
+
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), None)?;
+    socket.bind(&SockAddr::from(
+        "127.0.0.1:0".parse::<net::SocketAddr>().unwrap(),
+    ))?;
+    socket.set_nonblocking(true)?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&socket, Token(0), Interests::READABLE)?;
+
+    let addr = socket.local_addr()?.as_std().unwrap();
+    net::UdpSocket::bind("127.0.0.1:0")?.send_to(b"ping", addr)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+
+    let mut buf = [0; 8];
+    assert_eq!(socket.recv_from(&mut buf)?.0, 4);
+    poll.registry().deregister(&socket)
+}
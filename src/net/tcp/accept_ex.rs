@@ -0,0 +1,142 @@
+use crate::event::Event;
+use crate::net::{new_socket, peer_addr, socket_family, TcpStream};
+use crate::readiness::Readiness;
+use crate::token::Token;
+use miow::net::{AcceptAddrsBuf, TcpListenerExt};
+use std::collections::VecDeque;
+use std::io;
+use std::mem::{self, ManuallyDrop};
+use std::net::{self, SocketAddr};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, RawSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use winapi::shared::winerror::ERROR_OPERATION_ABORTED;
+use winapi::um::minwinbase::OVERLAPPED;
+use winapi::um::winsock2::{SOCKET, SOCK_STREAM};
+
+//Completion key of listeners taking AcceptEx completions. AFD polls and
+//wakeups come in with key 0.
+pub(crate) const ACCEPT_KEY: usize = 1;
+
+//State shared by a listener and its AcceptEx operations in flight
+pub(crate) struct AcceptShared {
+    listener: SOCKET,
+    family: i32,
+    token: Token,
+    //select() call the listener last reported in, like `Report::seq`
+    last_seq: AtomicUsize,
+    //set before the listener is closed, stops the reposting. Set and checked
+    //with `ready` locked, see `complete`
+    closed: AtomicBool,
+    ready: Mutex<VecDeque<io::Result<(TcpStream, SocketAddr)>>>,
+}
+
+//Everything the kernel writes to while an AcceptEx is in flight. Boxed and
+//owned by the kernel until the completion comes back: if the port is closed
+//with operations in flight, they are leaked.
+#[repr(C)]
+struct AcceptOp {
+    overlapped: OVERLAPPED,
+    addrs: AcceptAddrsBuf,
+    socket: net::TcpStream,
+    shared: Arc<AcceptShared>,
+}
+
+impl AcceptShared {
+    pub fn new(listener: SOCKET, token: Token) -> io::Result<Arc<AcceptShared>> {
+        Ok(Arc::new(AcceptShared {
+            listener,
+            family: socket_family(listener)?,
+            token,
+            last_seq: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            ready: Mutex::new(VecDeque::new()),
+        }))
+    }
+
+    //The listener is owned by `TcpListener`, never closed from here
+    fn listener(&self) -> ManuallyDrop<net::TcpListener> {
+        ManuallyDrop::new(unsafe { net::TcpListener::from_raw_socket(self.listener as RawSocket) })
+    }
+
+    pub fn pop(&self) -> Option<io::Result<(TcpStream, SocketAddr)>> {
+        self.ready.lock().unwrap().pop_front()
+    }
+
+    //Waits for a completion being handled, which may still use the listener
+    pub fn close(&self) {
+        let _ready = self.ready.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+//Held by the listener, stops the reposting once dropped
+pub(crate) struct AcceptGuard(pub Arc<AcceptShared>);
+
+impl Drop for AcceptGuard {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+//Posts one AcceptEx with a fresh socket. Its completion is queued on the port
+//whether it finishes right away or not.
+pub(crate) fn post(shared: &Arc<AcceptShared>) -> io::Result<()> {
+    let socket = new_socket(shared.family, SOCK_STREAM)?;
+    let op = Box::into_raw(Box::new(AcceptOp {
+        overlapped: unsafe { mem::zeroed() },
+        addrs: AcceptAddrsBuf::new(),
+        socket: unsafe { net::TcpStream::from_raw_socket(socket as RawSocket) },
+        shared: shared.clone(),
+    }));
+
+    let r = unsafe {
+        shared
+            .listener()
+            .accept_overlapped(&(*op).socket, &mut (*op).addrs, &mut (*op).overlapped)
+    };
+    if let Err(e) = r {
+        drop(unsafe { Box::from_raw(op) });
+        return Err(e);
+    }
+    Ok(())
+}
+
+//Handles a completion taken from the port with `ACCEPT_KEY`
+pub(crate) unsafe fn complete(overlapped: *mut OVERLAPPED, seq: usize, events: &mut Vec<Event>) {
+    let mut op = Box::from_raw(overlapped as *mut AcceptOp);
+    let shared = op.shared.clone();
+    //Held until the next AcceptEx is posted: the listener can't be closed in
+    //between, and its SOCKET value taken by another one
+    let mut ready = shared.ready.lock().unwrap();
+    if shared.closed.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let listener = shared.listener();
+    let accepted = listener
+        .result(&mut op.overlapped)
+        .and_then(|_| listener.accept_complete(&op.socket))
+        //Not from the accept buffer: miow byte swaps scope id and flowinfo
+        .and_then(|()| peer_addr(op.socket.as_raw_socket() as SOCKET));
+    let AcceptOp { socket, .. } = *op;
+
+    match accepted {
+        Ok(addr) => {
+            let r = socket.set_nonblocking(true);
+            ready.push_back(r.map(|()| (TcpStream::from_std(socket), addr)));
+        }
+        //The listener was closed under the operation
+        Err(ref e) if e.raw_os_error() == Some(ERROR_OPERATION_ABORTED as i32) => return,
+        //That connection is gone, e.g. reset before it got here: the next
+        //one takes its slot
+        Err(_) => {}
+    }
+    if let Err(e) = post(&shared) {
+        ready.push_back(Err(e));
+    }
+
+    if !ready.is_empty() && shared.last_seq.swap(seq, Ordering::SeqCst) != seq {
+        events.push(Event::new(Readiness::READABLE, shared.token));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::net::{get_opt, last_error};
+use std::io;
+use std::mem;
+use std::ptr::null_mut;
+use std::time::Duration;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{DWORD, ULONG};
+use winapi::shared::mstcpip::{tcp_keepalive, SIO_KEEPALIVE_VALS};
+use winapi::shared::ws2def::IPPROTO_TCP;
+use winapi::um::winsock2::{WSAIoctl, SOCKET, SOCKET_ERROR};
+
+//Readable from Windows 10 1709 on, in seconds. Not in winapi 0.3.
+const TCP_KEEPIDLE: c_int = 3;
+const TCP_KEEPINTVL: c_int = 17;
+
+//What Windows uses when nothing was set
+const DEFAULT_TIME: Duration = Duration::from_secs(2 * 60 * 60);
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keepalive timing, for [`TcpSocket::set_keepalive_params`] and
+/// [`TcpStream::set_keepalive_params`].
+///
+/// Windows takes both values at once: one left out gets the system default,
+/// two hours of idle time and one second between probes.
+///
+/// [`TcpSocket::set_keepalive_params`]: crate::net::TcpSocket::set_keepalive_params
+/// [`TcpStream::set_keepalive_params`]: crate::net::TcpStream::set_keepalive_params
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    time: Option<Duration>,
+    interval: Option<Duration>,
+}
+
+impl TcpKeepalive {
+    pub fn new() -> TcpKeepalive {
+        TcpKeepalive::default()
+    }
+
+    /// How long the connection stays idle before the first probe.
+    pub fn with_time(self, time: Duration) -> TcpKeepalive {
+        TcpKeepalive {
+            time: Some(time),
+            ..self
+        }
+    }
+
+    /// Time between probes when the previous one went unanswered.
+    pub fn with_interval(self, interval: Duration) -> TcpKeepalive {
+        TcpKeepalive {
+            interval: Some(interval),
+            ..self
+        }
+    }
+}
+
+//Whole milliseconds, rounded up so a short duration never turns into zero
+fn to_millis(dur: Duration) -> ULONG {
+    let millis = dur.as_secs() as u128 * 1000 + (dur.subsec_nanos() as u128).div_ceil(1_000_000);
+    millis.min(ULONG::MAX as u128) as ULONG
+}
+
+//Also turns keepalive on
+pub(crate) fn set_keepalive_params(socket: SOCKET, keepalive: &TcpKeepalive) -> io::Result<()> {
+    let time = keepalive.time.unwrap_or(DEFAULT_TIME);
+    let interval = keepalive.interval.unwrap_or(DEFAULT_INTERVAL);
+    if time == Duration::from_secs(0) || interval == Duration::from_secs(0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "keepalive time and interval must not be zero",
+        ));
+    }
+
+    let mut vals = tcp_keepalive {
+        onoff: 1,
+        keepalivetime: to_millis(time),
+        keepaliveinterval: to_millis(interval),
+    };
+    let mut returned: DWORD = 0;
+    let r = unsafe {
+        WSAIoctl(
+            socket,
+            SIO_KEEPALIVE_VALS,
+            &mut vals as *mut _ as *mut _,
+            mem::size_of::<tcp_keepalive>() as DWORD,
+            null_mut(),
+            0,
+            &mut returned,
+            null_mut(),
+            None,
+        )
+    };
+    if r == SOCKET_ERROR {
+        return Err(last_error());
+    }
+    Ok(())
+}
+
+//These read back in whole seconds, whatever precision went in
+pub(crate) fn keepalive_time(socket: SOCKET) -> io::Result<Duration> {
+    get_opt::<DWORD>(socket, IPPROTO_TCP as c_int, TCP_KEEPIDLE)
+        .map(|secs| Duration::from_secs(secs as u64))
+}
+
+pub(crate) fn keepalive_interval(socket: SOCKET) -> io::Result<Duration> {
+    get_opt::<DWORD>(socket, IPPROTO_TCP as c_int, TCP_KEEPINTVL)
+        .map(|secs| Duration::from_secs(secs as u64))
+}
+
+#[test]
+fn test_keepalive_params() -> io::Result<()> {
+    use crate::net::TcpSocket;
+    use std::os::windows::io::AsRawSocket;
+
+    let socket = TcpSocket::new_v4()?;
+    let raw = socket.as_raw_socket() as SOCKET;
+
+    let keepalive = TcpKeepalive::new()
+        .with_time(Duration::from_secs(30))
+        .with_interval(Duration::from_secs(5));
+    socket.set_keepalive_params(&keepalive)?;
+    assert_eq!(keepalive_time(raw)?, Duration::from_secs(30));
+    assert_eq!(keepalive_interval(raw)?, Duration::from_secs(5));
+
+    //Sub-second values are fine too
+    let short = TcpKeepalive::new()
+        .with_time(Duration::from_millis(1500))
+        .with_interval(Duration::from_nanos(1));
+    socket.set_keepalive_params(&short)?;
+    assert_eq!(to_millis(Duration::from_millis(1500)), 1500);
+    assert_eq!(to_millis(Duration::from_nanos(1)), 1);
+
+    let zero_time = TcpKeepalive::new()
+        .with_time(Duration::from_secs(0))
+        .with_interval(Duration::from_secs(1));
+    let e = socket.set_keepalive_params(&zero_time).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
@@ -0,0 +1,439 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::net::tcp::accept_ex::{post, AcceptGuard, AcceptShared, ACCEPT_KEY};
+use crate::net::{local_addr, set_ttl, ttl, OrError, TcpSocket, TcpStream};
+use crate::poll::Registry;
+use crate::sys::raw_source;
+use crate::token::Token;
+use std::fmt;
+use std::io;
+use std::net::{self, SocketAddr};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket, RawSocket};
+use std::sync::Mutex;
+use winapi::um::handleapi::SetHandleInformation;
+use winapi::um::winbase::HANDLE_FLAG_INHERIT;
+use winapi::um::winnt::HANDLE;
+use winapi::um::winsock2::{SOCKET, SOMAXCONN};
+
+/// A non-blocking TCP listener.
+pub struct TcpListener {
+    //declared first to be dropped before the socket is closed
+    accept_ex: Mutex<Option<AcceptGuard>>,
+    inner: net::TcpListener,
+}
+
+impl TcpListener {
+    /// Wraps a listener created with std.
+    ///
+    /// The listener has to be in non-blocking mode already, see
+    /// [`net::TcpListener::set_nonblocking`].
+    pub fn from_std(inner: net::TcpListener) -> TcpListener {
+        TcpListener {
+            accept_ex: Mutex::new(None),
+            inner,
+        }
+    }
+
+    /// Returns the std listener, without closing or duplicating the handle.
+    ///
+    /// A registration is not undone by this: deregister first, or the listener
+    /// keeps producing events with its old token. Connections already taken
+    /// by [`TcpListener::accept_overlapped`] and not accepted yet are lost.
+    pub fn into_std(self) -> net::TcpListener {
+        self.inner
+    }
+
+    /// Binds a listener to `addr`.
+    ///
+    /// Listeners report readable when a connection is waiting to be accepted.
+    pub fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = TcpSocket::for_addr(&addr)?;
+        socket.bind(addr)?;
+        socket.listen(SOMAXCONN as u32)
+    }
+
+    /// Accepts a connection, `WouldBlock` if none is waiting.
+    ///
+    /// The returned stream is non-blocking and not inherited by child
+    /// processes, like the ones this crate creates.
+    ///
+    /// One readable event may stand for several waiting connections: call
+    /// this until `WouldBlock` to take them all. Readiness is level
+    /// triggered, so the next poll reports the listener again if some were
+    /// left in the backlog, and otherwise only once a new one arrives.
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        if let Some(AcceptGuard(ref shared)) = *self.accept_ex.lock().unwrap() {
+            if let Some(accepted) = shared.pop() {
+                return accepted;
+            }
+        }
+
+        let (stream, addr) = self.inner.accept()?;
+        //An accepted socket inherits the listener's modes, but a blocking
+        //stream would stall the whole event loop: don't count on it. Same
+        //for inheritance, which std happens to clear today.
+        let handle = stream.as_raw_socket() as HANDLE;
+        if unsafe { SetHandleInformation(handle, HANDLE_FLAG_INHERIT, 0) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stream.set_nonblocking(true)?;
+        Ok((TcpStream::from_std(stream), addr))
+    }
+
+    /// Keeps `count` AcceptEx operations in flight on the completion port of
+    /// `registry`, each taking the next incoming connection as soon as it
+    /// arrives.
+    ///
+    /// Every completion is reported as a readable event with `token`, merged
+    /// within a `poll` call, and its stream comes out of
+    /// [`TcpListener::accept`] before any connection still in the backlog.
+    /// Unlike a registration this can't be undone: it lasts as long as the
+    /// listener, and only one registry can take a listener's completions.
+    pub fn accept_overlapped(
+        &self,
+        registry: &Registry,
+        token: Token,
+        count: usize,
+    ) -> io::Result<()> {
+        let mut accept_ex = self.accept_ex.lock().unwrap();
+        if accept_ex.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "AcceptEx operations already run on this listener",
+            ));
+        }
+
+        registry
+            .selector()
+            .port()
+            .add_socket(ACCEPT_KEY, &self.inner)?;
+        let shared = AcceptShared::new(self.inner.as_raw_socket() as SOCKET, token)?;
+        *accept_ex = Some(AcceptGuard(shared.clone()));
+        for _ in 0..count {
+            post(&shared)?;
+        }
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        local_addr(self.inner.as_raw_socket() as SOCKET)
+    }
+
+    /// Sets the time-to-live of outgoing packets: `IP_TTL` for IPv4,
+    /// the unicast hop limit for IPv6. Accepted streams start out with the
+    /// listener's.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        set_ttl(self.inner.as_raw_socket() as SOCKET, ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        ttl(self.inner.as_raw_socket() as SOCKET)
+    }
+
+    /// Returns and clears the pending error of the socket (`SO_ERROR`).
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+}
+
+impl fmt::Debug for TcpListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpListener")
+            .field("addr", &OrError(self.local_addr()))
+            .field("socket", &self.inner.as_raw_socket())
+            .field("overlapped", &self.accept_ex.lock().unwrap().is_some())
+            .finish()
+    }
+}
+
+impl AsRawSocket for TcpListener {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+/// The socket has to be in non-blocking mode already. The base socket is only looked up on registration.
+impl FromRawSocket for TcpListener {
+    unsafe fn from_raw_socket(socket: RawSocket) -> TcpListener {
+        TcpListener::from_std(net::TcpListener::from_raw_socket(socket))
+    }
+}
+
+/// Gives up ownership, the socket is not closed.
+impl IntoRawSocket for TcpListener {
+    fn into_raw_socket(self) -> RawSocket {
+        self.into_std().into_raw_socket()
+    }
+}
+
+impl event::Source for TcpListener {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        registry
+            .selector()
+            .register(raw_source(self), token, interests)
+            .map(|_| ())
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        registry
+            .selector()
+            .reregister(raw_source(self), None, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        registry.selector().deregister(raw_source(self), None)
+    }
+}
+
+#[test]
+fn test_tcp_listener_accept() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+    poll.registry()
+        .register(&listener, Token(0), Interests::READABLE)?;
+
+    match listener.accept() {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        other => panic!("accept without a connection: {:?}", other.map(|(_, a)| a)),
+    }
+
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+
+    let (_stream, peer) = listener.accept()?;
+    assert_eq!(peer, client.local_addr()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_listener_ttl_inherited() -> io::Result<()> {
+    use std::thread;
+    use std::time::Duration;
+
+    for bind in &["127.0.0.1:0", "[::1]:0"] {
+        let listener = TcpListener::bind(bind.parse().unwrap())?;
+        listener.set_ttl(42)?;
+        assert_eq!(listener.ttl()?, 42);
+
+        let _client = net::TcpStream::connect(listener.local_addr()?)?;
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        assert_eq!(stream.ttl()?, 42);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_listener_accepted_ready_for_poll() -> io::Result<()> {
+    use std::io::Read;
+    use std::thread;
+    use std::time::Duration;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::handleapi::GetHandleInformation;
+
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+    let _client = net::TcpStream::connect(listener.local_addr()?)?;
+    let mut stream = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    //Nothing was sent: this must not block
+    let e = stream.read(&mut [0; 8]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    let mut flags: DWORD = 0;
+    assert_ne!(
+        unsafe { GetHandleInformation(stream.as_raw_socket() as HANDLE, &mut flags) },
+        0
+    );
+    assert_eq!(flags & HANDLE_FLAG_INHERIT, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_listener_accept_overlapped() -> io::Result<()> {
+    use crate::{Events, Poll};
+    use std::collections::HashSet;
+    use std::thread;
+    use std::time::Duration;
+
+    const CONNECTIONS: usize = 1000;
+
+    //Same connections, same peers, whichever path accepts them
+    fn accept_all(overlapped: bool) -> io::Result<()> {
+        let mut poll = Poll::new()?;
+        let mut events = Events::with_capacity(64);
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+        if overlapped {
+            listener.accept_overlapped(poll.registry(), Token(0), 16)?;
+            let e = listener
+                .accept_overlapped(poll.registry(), Token(0), 16)
+                .unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        } else {
+            poll.registry()
+                .register(&listener, Token(0), Interests::READABLE)?;
+        }
+
+        let addr = listener.local_addr()?;
+        let clients = thread::spawn(move || -> io::Result<_> {
+            let mut clients = Vec::new();
+            for _ in 0..CONNECTIONS {
+                clients.push(net::TcpStream::connect(addr)?);
+            }
+            Ok(clients)
+        });
+
+        let mut peers = HashSet::new();
+        let mut streams = Vec::new();
+        while streams.len() < CONNECTIONS {
+            poll.poll(&mut events, Some(Duration::from_secs(5)))?;
+            assert!(!events.is_empty(), "stuck at {} connections", streams.len());
+            loop {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        assert_eq!(stream.peer_addr()?, peer);
+                        assert!(peers.insert(peer));
+                        streams.push(stream);
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        let clients = clients.join().unwrap()?;
+        let expected = clients
+            .iter()
+            .map(|client| client.local_addr())
+            .collect::<io::Result<HashSet<_>>>()?;
+        assert_eq!(peers, expected);
+        Ok(())
+    }
+
+    accept_all(false)?;
+    accept_all(true)
+}
+
+#[test]
+fn test_tcp_listener_accept_overlapped_closed() -> io::Result<()> {
+    use crate::event;
+    use crate::{Events, Poll};
+    use std::thread;
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+    listener.accept_overlapped(poll.registry(), Token(0), 4)?;
+    //A completion left on the port, then the listener closed under it
+    let _client = net::TcpStream::connect(listener.local_addr()?)?;
+    thread::sleep(Duration::from_millis(100));
+    drop(listener);
+
+    //Likely under the same SOCKET value: nothing posted to it on behalf of
+    //the old one, the connection stays in its backlog
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+    poll.registry()
+        .register(&listener, Token(1), Interests::READABLE)?;
+    let _client = net::TcpStream::connect(listener.local_addr()?)?;
+    //The old operations come back too, reported by nothing
+    let mut tokens = Vec::new();
+    for _ in 0..10 {
+        if !tokens.is_empty() {
+            break;
+        }
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        tokens.extend((0..events.len()).map(|i| event::token(events.get(i).unwrap())));
+    }
+    assert_eq!(tokens, [Token(1)]);
+    listener.accept()?;
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_listener_drain_burst() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    fn drain(listener: &TcpListener) -> io::Result<usize> {
+        let mut accepted = 0;
+        loop {
+            match listener.accept() {
+                Ok(_) => accepted += 1,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(accepted),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+    let addr = listener.local_addr()?;
+    poll.registry()
+        .register(&listener, Token(0), Interests::READABLE)?;
+
+    //A burst is a single event, and draining it leaves nothing to report
+    let clients = (0..10)
+        .map(|_| net::TcpStream::connect(addr))
+        .collect::<io::Result<Vec<_>>>()?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+    let mut accepted = drain(&listener)?;
+    while accepted < clients.len() {
+        //Some connections may still be on their way to the backlog
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        accepted += drain(&listener)?;
+    }
+    assert_eq!(accepted, clients.len());
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    //Left in the backlog, a connection is reported on every poll
+    let _late = net::TcpStream::connect(addr)?;
+    for _ in 0..2 {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        assert!(is_readable(events.get(0).unwrap()));
+    }
+    assert_eq!(drain(&listener)?, 1);
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,12 @@
+mod accept_ex;
+mod keepalive;
+mod listener;
+mod socket;
+mod stream;
+
+pub use self::keepalive::TcpKeepalive;
+pub use self::listener::TcpListener;
+pub use self::socket::TcpSocket;
+pub use self::stream::TcpStream;
+
+pub(crate) use self::accept_ex::{complete as complete_accept, ACCEPT_KEY};
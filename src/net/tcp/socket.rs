@@ -0,0 +1,366 @@
+use crate::net::tcp::keepalive::{keepalive_interval, keepalive_time, set_keepalive_params};
+use crate::net::{
+    family, get_opt, last_error, linger, new_socket, only_v6, recv_buffer_size, send_buffer_size,
+    set_linger, set_only_v6, set_opt, set_recv_buffer_size, set_send_buffer_size, socket_addr,
+};
+use crate::net::{TcpKeepalive, TcpListener, TcpStream};
+use std::io;
+use std::mem;
+use std::net::{self, SocketAddr};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, RawSocket};
+use std::time::Duration;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::winerror::WSAEWOULDBLOCK;
+use winapi::shared::ws2def::{
+    AF_INET, AF_INET6, IPPROTO_TCP, SOL_SOCKET, SO_EXCLUSIVEADDRUSE, SO_KEEPALIVE, SO_REUSEADDR,
+    TCP_NODELAY,
+};
+use winapi::um::winsock2::{bind, closesocket, connect, listen, SOCKET, SOCKET_ERROR, SOCK_STREAM};
+
+/// A TCP socket that is neither connected nor listening yet.
+///
+/// Options that only take effect before `bind` or `connect` are set here,
+/// then [`TcpSocket::connect`] or [`TcpSocket::listen`] turn the socket into
+/// a [`TcpStream`] or a [`TcpListener`].
+pub struct TcpSocket {
+    socket: SOCKET,
+    family: c_int,
+}
+
+impl TcpSocket {
+    /// Creates a non-blocking IPv4 socket.
+    pub fn new_v4() -> io::Result<TcpSocket> {
+        TcpSocket::new(AF_INET)
+    }
+
+    /// Creates a non-blocking IPv6 socket.
+    pub fn new_v6() -> io::Result<TcpSocket> {
+        TcpSocket::new(AF_INET6)
+    }
+
+    pub(crate) fn for_addr(addr: &SocketAddr) -> io::Result<TcpSocket> {
+        TcpSocket::new(family(addr))
+    }
+
+    fn new(family: c_int) -> io::Result<TcpSocket> {
+        new_socket(family, SOCK_STREAM).map(|socket| TcpSocket { socket, family })
+    }
+
+    //Caught here, winsock would only say WSAEFAULT or WSAEAFNOSUPPORT
+    fn check_family(&self, addr: &SocketAddr) -> io::Result<()> {
+        if family(addr) != self.family {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "address family doesn't match the socket's",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Binds the socket to `addr`, port 0 letting the OS pick one.
+    ///
+    /// Before [`TcpSocket::connect`], this picks the source address and port
+    /// of the connection.
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<()> {
+        self.check_family(&addr)?;
+        let (raw_addr, len) = socket_addr(&addr);
+        if unsafe { bind(self.socket, raw_addr.as_ptr(), len) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(())
+    }
+
+    /// Starts connecting to `addr` and returns right away, see
+    /// [`TcpStream::connect`].
+    pub fn connect(self, addr: SocketAddr) -> io::Result<TcpStream> {
+        self.check_family(&addr)?;
+        let (raw_addr, len) = socket_addr(&addr);
+        if unsafe { connect(self.socket, raw_addr.as_ptr(), len) } == SOCKET_ERROR {
+            let e = last_error();
+            if e.raw_os_error() != Some(WSAEWOULDBLOCK as i32) {
+                return Err(e);
+            }
+        }
+
+        let stream = unsafe { net::TcpStream::from_raw_socket(self.into_raw() as RawSocket) };
+        Ok(TcpStream::from_std(stream))
+    }
+
+    /// Starts listening, with at most `backlog` connections waiting to be
+    /// accepted.
+    pub fn listen(self, backlog: u32) -> io::Result<TcpListener> {
+        let backlog = backlog.min(c_int::max_value() as u32) as c_int;
+        if unsafe { listen(self.socket, backlog) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+
+        let listener = unsafe { net::TcpListener::from_raw_socket(self.into_raw() as RawSocket) };
+        Ok(TcpListener::from_std(listener))
+    }
+
+    /// Sets `SO_REUSEADDR`, to bind to an address still in use, e.g. by
+    /// connections waiting in `TIME_WAIT`.
+    ///
+    /// Unlike on Unix, Windows also lets such a socket bind to a port some
+    /// other socket is actively listening on, and take its traffic over.
+    /// [`TcpSocket::set_exclusiveaddruse`] guards a socket against that.
+    pub fn set_reuseaddr(&self, reuseaddr: bool) -> io::Result<()> {
+        set_opt(self.socket, SOL_SOCKET, SO_REUSEADDR, reuseaddr as BOOL)
+    }
+
+    pub fn reuseaddr(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket, SOL_SOCKET, SO_REUSEADDR).map(|value| value != 0)
+    }
+
+    /// Sets `IPV6_V6ONLY` on an IPv6 socket, before `bind`. On by default on
+    /// Windows; turned off, the socket also takes IPv4 traffic, with peers
+    /// showing up as IPv4-mapped addresses (`::ffff:a.b.c.d`).
+    pub fn set_only_v6(&self, only_v6: bool) -> io::Result<()> {
+        set_only_v6(self.socket, only_v6)
+    }
+
+    pub fn only_v6(&self) -> io::Result<bool> {
+        only_v6(self.socket)
+    }
+
+    /// Sets `SO_EXCLUSIVEADDRUSE`, so that no other socket can bind to the
+    /// address this one is bound to, `SO_REUSEADDR` or not. Can't be
+    /// combined with [`TcpSocket::set_reuseaddr`].
+    pub fn set_exclusiveaddruse(&self, exclusive: bool) -> io::Result<()> {
+        set_opt(
+            self.socket,
+            SOL_SOCKET,
+            SO_EXCLUSIVEADDRUSE,
+            exclusive as BOOL,
+        )
+    }
+
+    pub fn exclusiveaddruse(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket, SOL_SOCKET, SO_EXCLUSIVEADDRUSE).map(|value| value != 0)
+    }
+
+    /// Sets `SO_RCVBUF`. The OS may round the size up or clamp it, the
+    /// getter returns what it applied.
+    pub fn set_recv_buffer_size(&self, size: u32) -> io::Result<()> {
+        set_recv_buffer_size(self.socket, size)
+    }
+
+    pub fn recv_buffer_size(&self) -> io::Result<u32> {
+        recv_buffer_size(self.socket)
+    }
+
+    /// Sets `SO_SNDBUF`, rounded like the receive buffer. 0 is allowed:
+    /// sends are then not copied into the socket, which is only worth it
+    /// with overlapped I/O.
+    pub fn set_send_buffer_size(&self, size: u32) -> io::Result<()> {
+        set_send_buffer_size(self.socket, size)
+    }
+
+    pub fn send_buffer_size(&self) -> io::Result<u32> {
+        send_buffer_size(self.socket)
+    }
+
+    /// Sets `SO_LINGER`: with `Some`, closing the socket waits up to that long
+    /// for unsent data. The OS only takes whole seconds, the rest is
+    /// truncated. `Some(Duration::from_secs(0))` resets the connection on
+    /// close.
+    pub fn set_linger(&self, dur: Option<Duration>) -> io::Result<()> {
+        set_linger(self.socket, dur)
+    }
+
+    pub fn linger(&self) -> io::Result<Option<Duration>> {
+        linger(self.socket)
+    }
+
+    pub fn set_keepalive(&self, keepalive: bool) -> io::Result<()> {
+        set_opt(self.socket, SOL_SOCKET, SO_KEEPALIVE, keepalive as BOOL)
+    }
+
+    pub fn keepalive(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket, SOL_SOCKET, SO_KEEPALIVE).map(|value| value != 0)
+    }
+
+    /// Turns keepalive on, with the timing of `keepalive`.
+    pub fn set_keepalive_params(&self, keepalive: &TcpKeepalive) -> io::Result<()> {
+        set_keepalive_params(self.socket, keepalive)
+    }
+
+    /// Idle time before the first probe, in whole seconds. Windows 10 1709
+    /// or later only.
+    pub fn keepalive_time(&self) -> io::Result<Duration> {
+        keepalive_time(self.socket)
+    }
+
+    /// Time between probes, in whole seconds. Windows 10 1709 or later only.
+    pub fn keepalive_interval(&self) -> io::Result<Duration> {
+        keepalive_interval(self.socket)
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        set_opt(
+            self.socket,
+            IPPROTO_TCP as c_int,
+            TCP_NODELAY,
+            nodelay as BOOL,
+        )
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket, IPPROTO_TCP as c_int, TCP_NODELAY).map(|value| value != 0)
+    }
+
+    //Hands the socket over without closing it
+    fn into_raw(self) -> SOCKET {
+        let socket = self.socket;
+        mem::forget(self);
+        socket
+    }
+}
+
+impl AsRawSocket for TcpSocket {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.socket as RawSocket
+    }
+}
+
+impl Drop for TcpSocket {
+    fn drop(&mut self) {
+        unsafe { closesocket(self.socket) };
+    }
+}
+
+#[test]
+fn test_tcp_socket_reuseaddr() -> io::Result<()> {
+    use std::io::Read;
+    use std::thread;
+
+    let socket = TcpSocket::new_v4()?;
+    socket.bind("127.0.0.1:0".parse().unwrap())?;
+    let listener = socket.listen(8)?;
+    let addr = listener.local_addr()?;
+
+    //Close the accepted side first, its address stays in TIME_WAIT
+    let mut client = net::TcpStream::connect(addr)?;
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                drop(stream);
+                break;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    assert_eq!(client.read(&mut [0; 8])?, 0);
+    drop(client);
+    drop(listener);
+
+    let socket = TcpSocket::new_v4()?;
+    socket.set_reuseaddr(true)?;
+    assert!(socket.reuseaddr()?);
+    socket.bind(addr)?;
+    assert_eq!(socket.listen(8)?.local_addr()?, addr);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_socket_options() -> io::Result<()> {
+    let socket = TcpSocket::new_v4()?;
+
+    socket.set_recv_buffer_size(64 * 1024)?;
+    assert!(socket.recv_buffer_size()? >= 64 * 1024);
+    socket.set_send_buffer_size(32 * 1024)?;
+    assert!(socket.send_buffer_size()? >= 32 * 1024);
+    socket.set_send_buffer_size(0)?;
+    assert_eq!(socket.send_buffer_size()?, 0);
+
+    assert_eq!(socket.linger()?, None);
+    socket.set_linger(Some(Duration::from_secs(5)))?;
+    assert_eq!(socket.linger()?, Some(Duration::from_secs(5)));
+
+    socket.set_keepalive(true)?;
+    assert!(socket.keepalive()?);
+    socket.set_nodelay(true)?;
+    assert!(socket.nodelay()?);
+    socket.set_nodelay(false)?;
+    assert!(!socket.nodelay()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_socket_dual_stack() -> io::Result<()> {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::thread;
+
+    let socket = TcpSocket::new_v6()?;
+    assert!(socket.only_v6()?);
+    socket.set_only_v6(false)?;
+    assert!(!socket.only_v6()?);
+    socket.bind("[::]:0".parse().unwrap())?;
+    let listener = socket.listen(8)?;
+    let port = listener.local_addr()?.port();
+
+    let client = net::TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+    let peer = loop {
+        match listener.accept() {
+            Ok((_, peer)) => break peer,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    //The v4 peer comes back mapped into the v6 address space
+    match peer.ip() {
+        IpAddr::V6(ip) => assert_eq!(ip.to_ipv4(), Some(Ipv4Addr::LOCALHOST)),
+        IpAddr::V4(ip) => panic!("unexpected v4 peer {}", ip),
+    }
+    assert_eq!(peer.port(), client.local_addr()?.port());
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_socket_exclusiveaddruse() -> io::Result<()> {
+    let socket = TcpSocket::new_v4()?;
+    assert!(!socket.exclusiveaddruse()?);
+    socket.set_exclusiveaddruse(true)?;
+    assert!(socket.exclusiveaddruse()?);
+    socket.bind("127.0.0.1:0".parse().unwrap())?;
+    let listener = socket.listen(8)?;
+
+    //Not even a reuseaddr socket gets to share the port
+    let other = TcpSocket::new_v4()?;
+    other.set_reuseaddr(true)?;
+    assert!(other.bind(listener.local_addr()?).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_socket_bind_then_connect() -> io::Result<()> {
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let socket = TcpSocket::new_v4()?;
+    socket.bind("127.0.0.1:0".parse().unwrap())?;
+    let stream = socket.connect(listener.local_addr()?)?;
+
+    //The OS picked port is known right away, and it's the one the peer sees
+    let local = stream.local_addr()?;
+    assert_ne!(local.port(), 0);
+    let (_, peer) = listener.accept()?;
+    assert_eq!(peer, local);
+
+    let socket = TcpSocket::new_v4()?;
+    let e = socket.bind("[::1]:0".parse().unwrap()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = socket.connect("[::1]:80".parse().unwrap()).err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
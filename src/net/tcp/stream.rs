@@ -0,0 +1,904 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::io_source::IoSource;
+use crate::net::tcp::keepalive::{keepalive_interval, keepalive_time, set_keepalive_params};
+use crate::net::{
+    get_opt, last_error, linger, local_addr, peer_addr, recv_buffer_size, recv_vectored,
+    send_buffer_size, send_vectored, set_linger, set_opt, set_recv_buffer_size,
+    set_send_buffer_size, set_ttl, ttl, OrError, TcpKeepalive, TcpSocket,
+};
+use crate::poll::Registry;
+use crate::token::Token;
+use std::fmt;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::net::{self, Shutdown, SocketAddr};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket, RawSocket};
+use std::time::Duration;
+use winapi::ctypes::{c_char, c_int};
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::winerror::WSAEINVAL;
+use winapi::shared::ws2def::{IPPROTO_TCP, SOL_SOCKET, SO_OOBINLINE, TCP_NODELAY};
+use winapi::um::winsock2::{recv, send, MSG_OOB, SOCKET, SOCKET_ERROR};
+
+/// A non-blocking TCP stream.
+pub struct TcpStream {
+    inner: IoSource<net::TcpStream>,
+}
+
+impl TcpStream {
+    /// Starts connecting to `addr` and returns right away.
+    ///
+    /// The connection is established once the stream reports writable: register
+    /// it for [`Interests::WRITABLE`] and wait for that event before using it.
+    pub fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+        TcpSocket::for_addr(&addr)?.connect(addr)
+    }
+
+    /// Wraps a stream created with std.
+    ///
+    /// The stream has to be in non-blocking mode already, see
+    /// [`net::TcpStream::set_nonblocking`].
+    pub fn from_std(inner: net::TcpStream) -> TcpStream {
+        TcpStream {
+            inner: IoSource::new(inner),
+        }
+    }
+
+    /// Returns the std stream, without closing or duplicating the handle.
+    ///
+    /// A registration is not undone by this: deregister first, or the socket
+    /// keeps producing events with its old token.
+    pub fn into_std(self) -> net::TcpStream {
+        self.inner.into_inner()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        local_addr(self.socket())
+    }
+
+    /// Address of the peer, `NotConnected` while connecting.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        peer_addr(self.socket())
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        set_opt(
+            self.socket(),
+            IPPROTO_TCP as c_int,
+            TCP_NODELAY,
+            nodelay as BOOL,
+        )
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket(), IPPROTO_TCP as c_int, TCP_NODELAY).map(|value| value != 0)
+    }
+
+    /// Turns keepalive on, with the timing of `keepalive`.
+    pub fn set_keepalive_params(&self, keepalive: &TcpKeepalive) -> io::Result<()> {
+        set_keepalive_params(self.socket(), keepalive)
+    }
+
+    /// Idle time before the first probe, in whole seconds. Windows 10 1709
+    /// or later only.
+    pub fn keepalive_time(&self) -> io::Result<Duration> {
+        keepalive_time(self.socket())
+    }
+
+    /// Time between probes, in whole seconds. Windows 10 1709 or later only.
+    pub fn keepalive_interval(&self) -> io::Result<Duration> {
+        keepalive_interval(self.socket())
+    }
+
+    /// Sets `SO_RCVBUF`. The OS may round the size up or clamp it, the
+    /// getter returns what it applied.
+    pub fn set_recv_buffer_size(&self, size: u32) -> io::Result<()> {
+        set_recv_buffer_size(self.socket(), size)
+    }
+
+    pub fn recv_buffer_size(&self) -> io::Result<u32> {
+        recv_buffer_size(self.socket())
+    }
+
+    /// Sets `SO_SNDBUF`, rounded like the receive buffer. 0 is allowed:
+    /// sends are then not copied into the socket, which is only worth it
+    /// with overlapped I/O.
+    pub fn set_send_buffer_size(&self, size: u32) -> io::Result<()> {
+        set_send_buffer_size(self.socket(), size)
+    }
+
+    pub fn send_buffer_size(&self) -> io::Result<u32> {
+        send_buffer_size(self.socket())
+    }
+
+    /// Sets `SO_LINGER`: with `Some`, closing the socket waits up to that long
+    /// for unsent data. The OS only takes whole seconds, the rest is
+    /// truncated. `Some(Duration::from_secs(0))` resets the connection on
+    /// close.
+    pub fn set_linger(&self, dur: Option<Duration>) -> io::Result<()> {
+        set_linger(self.socket(), dur)
+    }
+
+    pub fn linger(&self) -> io::Result<Option<Duration>> {
+        linger(self.socket())
+    }
+
+    /// Sets the time-to-live of outgoing packets: `IP_TTL` for IPv4,
+    /// the unicast hop limit for IPv6.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        set_ttl(self.socket(), ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        ttl(self.socket())
+    }
+
+    /// Returns and clears the pending error of the socket (`SO_ERROR`).
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+
+    /// Shuts down the read half, the write half, or both.
+    ///
+    /// After [`Shutdown::Write`] the peer sees read-closed, writes fail and
+    /// readable events carry on. Shutting down a half twice is not an error.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    /// Reads into `buf` without removing the data from the queue,
+    /// `WouldBlock` if nothing was received.
+    ///
+    /// The stream keeps reporting readable until the data is actually read.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.peek(buf)
+    }
+
+    /// Sends `byte` as urgent data (`MSG_OOB`), reported to the peer as
+    /// [`Interests::PRIORITY`] readiness.
+    pub fn send_oob(&self, byte: u8) -> io::Result<()> {
+        self.inner.do_io(|inner| {
+            let socket = inner.as_raw_socket() as SOCKET;
+            if unsafe { send(socket, &byte as *const u8 as *const c_char, 1, MSG_OOB) }
+                == SOCKET_ERROR
+            {
+                return Err(last_error());
+            }
+            Ok(())
+        })
+    }
+
+    /// Reads urgent data, `WouldBlock` if none is pending.
+    ///
+    /// With `SO_OOBINLINE` set, urgent data is read in band instead and this
+    /// fails with `InvalidInput`.
+    pub fn recv_oob(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(c_int::max_value() as usize) as c_int;
+        let n = unsafe { recv(self.socket(), buf.as_mut_ptr() as *mut c_char, len, MSG_OOB) };
+        if n != SOCKET_ERROR {
+            return Ok(n as usize);
+        }
+
+        //Nothing to read can also come back as WSAEINVAL, not WSAEWOULDBLOCK
+        let e = last_error();
+        if e.raw_os_error() != Some(WSAEINVAL as i32) {
+            return Err(e);
+        }
+        if self.oobinline()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "urgent data is read in band with SO_OOBINLINE",
+            ));
+        }
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    /// Sets `SO_OOBINLINE`: urgent data is then read like the rest of the
+    /// stream.
+    pub fn set_oobinline(&self, oobinline: bool) -> io::Result<()> {
+        set_opt(self.socket(), SOL_SOCKET, SO_OOBINLINE, oobinline as BOOL)
+    }
+
+    pub fn oobinline(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket(), SOL_SOCKET, SO_OOBINLINE).map(|value| value != 0)
+    }
+
+    fn socket(&self) -> SOCKET {
+        self.inner.as_raw_socket() as SOCKET
+    }
+}
+
+//Winsock errors keep their std mapping: WSAEWOULDBLOCK is `WouldBlock`,
+//WSAECONNRESET is `ConnectionReset`. Reads return Ok(0) once the peer has
+//closed, and writes may be short. Nothing is buffered, `flush` is a no-op.
+impl Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl Read for &TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self.inner).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        recv_vectored(self.socket(), bufs)
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.do_io(|mut inner| inner.write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.inner
+            .do_io(|inner| send_vectored(inner.as_raw_socket() as SOCKET, bufs))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpStream")
+            .field("addr", &OrError(self.local_addr()))
+            .field("peer", &OrError(self.peer_addr()))
+            .field("socket", &self.socket())
+            .field("registered", &self.inner.is_registered())
+            .finish()
+    }
+}
+
+impl AsRawSocket for TcpStream {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+/// The socket has to be in non-blocking mode already. The base socket is only looked up on registration.
+impl FromRawSocket for TcpStream {
+    unsafe fn from_raw_socket(socket: RawSocket) -> TcpStream {
+        TcpStream::from_std(net::TcpStream::from_raw_socket(socket))
+    }
+}
+
+/// Gives up ownership, the socket is not closed.
+impl IntoRawSocket for TcpStream {
+    fn into_raw_socket(self) -> RawSocket {
+        self.into_std().into_raw_socket()
+    }
+}
+
+impl event::Source for TcpStream {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+#[test]
+fn test_tcp_stream_echo() -> io::Result<()> {
+    echo(&mut crate::Poll::new()?)
+}
+
+//Same as over AFD, with the WSAPoll() thread standing in
+#[cfg(feature = "wsapoll")]
+#[test]
+fn test_tcp_stream_echo_wsapoll() -> io::Result<()> {
+    let mut poll = crate::Poll::builder().deny_afd().build()?;
+    echo(&mut poll)?;
+    assert!(poll.registry().selector().uses_wsapoll());
+    Ok(())
+}
+
+#[cfg(test)]
+fn echo(poll: &mut crate::Poll) -> io::Result<()> {
+    use crate::event::{is_readable, is_writable};
+    use crate::Events;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let echo = thread::spawn(move || -> io::Result<()> {
+        let (mut peer, _) = listener.accept()?;
+        let mut buf = [0; 5];
+        peer.read_exact(&mut buf)?;
+        peer.write_all(&buf)
+    });
+
+    let mut events = Events::with_capacity(8);
+    let mut stream = TcpStream::connect(addr)?;
+    poll.registry()
+        .register(&stream, Token(0), Interests::READABLE | Interests::WRITABLE)?;
+
+    //Connected once writable
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_writable(events.get(0).unwrap()));
+    stream.write_all(b"hello")?;
+
+    let mut buf = [0; 5];
+    let mut read = 0;
+    while read < buf.len() {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "no echo");
+        if is_readable(events.get(0).unwrap()) {
+            match stream.read(&mut buf[read..]) {
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    assert_eq!(&buf, b"hello");
+
+    echo.join().unwrap()
+}
+
+#[test]
+fn test_tcp_stream_std_round_trip() -> io::Result<()> {
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    let raw = client.as_raw_socket();
+
+    client.set_nonblocking(true)?;
+    let mut stream = TcpStream::from_std(client);
+    assert_eq!(stream.as_raw_socket(), raw);
+    stream.write_all(b"wrapped ")?;
+
+    let mut client = stream.into_std();
+    assert_eq!(client.as_raw_socket(), raw);
+    client.write_all(b"std")?;
+    drop(client);
+
+    let mut received = Vec::new();
+    peer.read_to_end(&mut received)?;
+    assert_eq!(received, b"wrapped std");
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_error_mapping() -> io::Result<()> {
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    client.set_nonblocking(true)?;
+    let stream = TcpStream::from_std(client);
+    let (peer, _) = listener.accept()?;
+
+    //Reader and writer halves share the stream
+    let (mut reader, mut writer) = (&stream, &stream);
+    let mut buf = [0; 64 * 1024];
+    assert_eq!(
+        reader.read(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    //The peer doesn't read, the send buffer fills up
+    let mut sent = 0;
+    loop {
+        match writer.write(&buf) {
+            Ok(n) => sent += n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+        assert!(sent < 1 << 30, "send buffer never filled up");
+    }
+    writer.flush()?;
+
+    //A zero linger resets the connection on close
+    let peer = TcpStream::from_std(peer);
+    peer.set_linger(Some(Duration::from_secs(0)))?;
+    drop(peer);
+    let e = loop {
+        match reader.read(&mut buf) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            other => break other.unwrap_err(),
+        }
+    };
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_read_after_close() -> io::Result<()> {
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    client.set_nonblocking(true)?;
+    let mut stream = TcpStream::from_std(client);
+    let (mut peer, _) = listener.accept()?;
+    peer.write_all(b"bye")?;
+    drop(peer);
+
+    let mut received = Vec::new();
+    let mut buf = [0; 8];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    assert_eq!(received, b"bye");
+    //Stays at Ok(0)
+    assert_eq!(stream.read(&mut buf)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_vectored() -> io::Result<()> {
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    let (peer, _) = listener.accept()?;
+    client.set_nonblocking(true)?;
+    let mut stream = TcpStream::from_std(client);
+    peer.set_nonblocking(true)?;
+    let mut peer = TcpStream::from_std(peer);
+
+    assert_eq!(stream.write_vectored(&[])?, 0);
+    assert_eq!(stream.write_vectored(&[IoSlice::new(b"")])?, 0);
+    let bufs = [
+        IoSlice::new(b"frame"),
+        IoSlice::new(b""),
+        IoSlice::new(b" body"),
+    ];
+    assert_eq!(stream.write_vectored(&bufs)?, 10);
+    stream.write_all(b"frame body")?;
+
+    //Byte for byte what the plain write sent, spread over the buffers
+    let (mut a, mut b) = ([0; 3], [0; 7]);
+    let mut received = Vec::new();
+    while received.len() < 20 {
+        let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        match peer.read_vectored(&mut bufs) {
+            Ok(n) => {
+                let scattered: Vec<u8> = a.iter().chain(b.iter()).cloned().collect();
+                received.extend_from_slice(&scattered[..n]);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    assert_eq!(received, b"frame bodyframe body");
+    assert_eq!(peer.read_vectored(&mut [])?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_peek_stays_readable() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    client.set_nonblocking(true)?;
+    let mut stream = TcpStream::from_std(client);
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&stream, Token(0), Interests::READABLE)?;
+
+    let mut buf = [0; 8];
+    assert_eq!(
+        stream.peek(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    //A peek doesn't count as a read, the next poll reports readable again
+    peer.write_all(b"\x16\x03\x01")?;
+    for _ in 0..2 {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        assert!(is_readable(events.get(0).unwrap()));
+        let n = stream.peek(&mut buf)?;
+        assert_eq!(&buf[..n], b"\x16\x03\x01");
+    }
+
+    let mut read = [0; 8];
+    let n = stream.read(&mut read)?;
+    assert_eq!(&read[..n], b"\x16\x03\x01");
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_half_close() -> io::Result<()> {
+    use crate::event::{self, Event};
+    use crate::{Events, Poll, Readiness};
+    use std::time::Duration;
+
+    //First event of `token`, skipping the others
+    fn next_event(poll: &mut Poll, events: &mut Events, token: Token) -> io::Result<Event> {
+        for _ in 0..10 {
+            poll.poll(events, Some(Duration::from_millis(100)))?;
+            for i in 0..events.len() {
+                let event = events.get(i).unwrap();
+                if event::token(event) == token {
+                    return Ok(event.clone());
+                }
+            }
+        }
+        panic!("no event for {:?}", token)
+    }
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    let (peer, _) = listener.accept()?;
+    client.set_nonblocking(true)?;
+    peer.set_nonblocking(true)?;
+    let (mut a, mut b) = (TcpStream::from_std(client), TcpStream::from_std(peer));
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let interests = Interests::READABLE | Interests::READ_CLOSED;
+    poll.registry().register(&a, Token(0), interests)?;
+    poll.registry().register(&b, Token(1), interests)?;
+
+    //a stops writing: b sees the FIN, a alone sees nothing
+    a.shutdown(Shutdown::Write)?;
+    a.shutdown(Shutdown::Write)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    let event = events.get(0).unwrap();
+    assert_eq!(event::token(event), Token(1));
+    assert_eq!(
+        event.readiness(),
+        Readiness::READABLE | Readiness::READ_CLOSED
+    );
+    assert_eq!(b.read(&mut [0; 8])?, 0);
+    assert!(a.write(b"late").is_err());
+
+    //b still writes, a still reads. The FIN stays readable on b, so b only
+    //watches for writable from here on.
+    poll.registry()
+        .reregister(&b, Token(1), Interests::WRITABLE)?;
+    b.write_all(b"ack")?;
+    let event = next_event(&mut poll, &mut events, Token(0))?;
+    assert_eq!(event.readiness(), Readiness::READABLE);
+    let mut buf = [0; 8];
+    let n = a.read(&mut buf)?;
+    assert_eq!(&buf[..n], b"ack");
+
+    //b closes both halves: a gets its FIN in turn
+    b.shutdown(Shutdown::Both)?;
+    b.shutdown(Shutdown::Both)?;
+    let event = next_event(&mut poll, &mut events, Token(0))?;
+    assert!(event.readiness().is_read_closed());
+    assert_eq!(a.read(&mut buf)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_refused_take_error() -> io::Result<()> {
+    use crate::event::is_error;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    //Nothing listens there anymore
+    let addr = net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let stream = TcpStream::connect(addr)?;
+    poll.registry()
+        .register(&stream, Token(0), Interests::WRITABLE)?;
+
+    //Windows retries a refused SYN for a while before giving up
+    poll.poll(&mut events, Some(Duration::from_secs(5)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_error(events.get(0).unwrap()));
+
+    let e = stream.take_error()?.expect("no connect error");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    assert!(stream.take_error()?.is_none());
+
+    //Still failed on the next poll, the error isn't stored again
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert!(stream.take_error()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_refused_is_writable() -> io::Result<()> {
+    use crate::event::{is_error, is_writable};
+    use crate::{Events, Poll};
+    use std::time::{Duration, Instant};
+
+    let addr = net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let stream = TcpStream::connect(addr)?;
+    //Not even asking for writable
+    poll.registry()
+        .register(&stream, Token(0), Interests::READABLE)?;
+
+    //Bounded by the SYN retries of the local stack, not by our timeout
+    let start = Instant::now();
+    poll.poll(&mut events, Some(Duration::from_secs(10)))?;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(events.len(), 1);
+    let event = events.get(0).unwrap();
+    assert!(is_error(event));
+    assert!(is_writable(event));
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_addrs() -> io::Result<()> {
+    use std::thread;
+    use std::time::Duration;
+
+    for bind in &["127.0.0.1:0", "[::1]:0"] {
+        let listener = net::TcpListener::bind(bind)?;
+        let addr = listener.local_addr()?;
+        let stream = TcpStream::connect(addr)?;
+
+        //Not before the handshake is done
+        loop {
+            match stream.peer_addr() {
+                Ok(peer) => {
+                    assert_eq!(peer, addr);
+                    break;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::NotConnected => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let (_, from) = listener.accept()?;
+        assert_eq!(stream.local_addr()?, from);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_nodelay() -> io::Result<()> {
+    use crate::net::tcp_pair;
+
+    let (client, accepted) = tcp_pair()?;
+    //Right after accept, and on the connecting side
+    for stream in &[accepted, client] {
+        stream.set_nodelay(true)?;
+        assert!(stream.nodelay()?);
+        stream.set_nodelay(false)?;
+        assert!(!stream.nodelay()?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_linger() -> io::Result<()> {
+    use crate::event::{is_error, is_hup};
+    use crate::{Events, Poll};
+
+    let (stream, peer) = crate::net::tcp_pair()?;
+    assert_eq!(stream.linger()?, None);
+    stream.set_linger(Some(Duration::from_millis(2999)))?;
+    assert_eq!(stream.linger()?, Some(Duration::from_secs(2)));
+    stream.set_linger(None)?;
+    assert_eq!(stream.linger()?, None);
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&peer, Token(0), Interests::READABLE)?;
+
+    //Closing with a zero linger aborts the connection
+    stream.set_linger(Some(Duration::from_secs(0)))?;
+    drop(stream);
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    let event = events.get(0).unwrap();
+    assert!(is_hup(event) || is_error(event));
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_raw_round_trip() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+
+    //Still open after giving it up, and usable once taken back
+    let raw = client.into_raw_socket();
+    let mut client = unsafe { TcpStream::from_raw_socket(raw) };
+    assert_eq!(client.as_raw_socket(), raw);
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&client, Token(0), Interests::READABLE)?;
+    peer.write_all(b"raw")?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+
+    let mut buf = [0; 3];
+    client.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"raw");
+    client.write_all(b"back")?;
+    let mut buf = [0; 4];
+    peer.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"back");
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_oob() -> io::Result<()> {
+    use crate::event::{is_priority, is_readable};
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let sender = TcpStream::from_std(net::TcpStream::connect(listener.local_addr()?)?);
+    let (receiver, _) = listener.accept()?;
+    receiver.set_nonblocking(true)?;
+    let mut receiver = TcpStream::from_std(receiver);
+    poll.registry().register(
+        &receiver,
+        Token(0),
+        Interests::READABLE | Interests::PRIORITY,
+    )?;
+
+    let mut buf = [0; 8];
+    let e = receiver.recv_oob(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    sender.send_oob(b'!')?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_priority(events.get(0).unwrap()));
+    assert_eq!(receiver.recv_oob(&mut buf)?, 1);
+    assert_eq!(buf[0], b'!');
+
+    //In-band data still comes through the normal path
+    (&sender).write_all(b"data")?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+    assert_eq!(receiver.read(&mut buf)?, 4);
+    assert_eq!(&buf[..4], b"data");
+
+    receiver.set_oobinline(true)?;
+    assert!(receiver.oobinline()?);
+    let e = receiver.recv_oob(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_stream_buffer_sizes() -> io::Result<()> {
+    for bind in &["127.0.0.1:0", "[::1]:0"] {
+        let listener = net::TcpListener::bind(bind)?;
+        let stream = TcpStream::connect(listener.local_addr()?)?;
+
+        //Rounded or clamped by the OS, never below what was asked for here
+        stream.set_recv_buffer_size(128 * 1024)?;
+        assert!(stream.recv_buffer_size()? >= 128 * 1024);
+        stream.set_send_buffer_size(96 * 1024)?;
+        assert!(stream.send_buffer_size()? >= 96 * 1024);
+
+        stream.set_send_buffer_size(0)?;
+        assert_eq!(stream.send_buffer_size()?, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tcp_debug() -> io::Result<()> {
+    use crate::net::TcpListener;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+    let addr = listener.local_addr()?;
+    let client = TcpStream::connect(addr)?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&client, Token(0), Interests::WRITABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+
+    let debug = format!("{:?}", client);
+    assert!(debug.starts_with("TcpStream { "), "{}", debug);
+    assert!(
+        debug.contains(&format!("addr: {:?}", client.local_addr()?)),
+        "{}",
+        debug
+    );
+    assert!(debug.contains(&format!("peer: {:?}", addr)), "{}", debug);
+    assert!(
+        debug.contains(&format!("socket: {}", client.as_raw_socket())),
+        "{}",
+        debug
+    );
+    assert!(debug.contains("registered: true"), "{}", debug);
+
+    poll.registry().deregister(&client)?;
+    assert!(format!("{:?}", client).contains("registered: false"));
+
+    let debug = format!("{:?}", listener);
+    assert!(debug.contains(&format!("addr: {:?}", addr)), "{}", debug);
+    assert!(
+        debug.contains(&format!("socket: {}", listener.as_raw_socket())),
+        "{}",
+        debug
+    );
+
+    //A failed lookup is printed, not propagated
+    let failed: io::Result<SocketAddr> = Err(io::ErrorKind::NotConnected.into());
+    assert_eq!(format!("{:?}", OrError(failed)), "<error>");
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use crate::net::{
+    family, get_opt, last_error, new_socket, only_v6, set_only_v6, set_opt, socket_addr, UdpSocket,
+};
+use std::io;
+use std::mem;
+use std::net::{self, SocketAddr};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, RawSocket};
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::ws2def::{
+    AF_INET, AF_INET6, SOL_SOCKET, SO_BROADCAST, SO_EXCLUSIVEADDRUSE, SO_REUSEADDR,
+};
+use winapi::um::winsock2::{bind, closesocket, SOCKET, SOCKET_ERROR, SOCK_DGRAM};
+
+/// A UDP socket that is not bound yet.
+///
+/// Options that only take effect before `bind` are set here, then
+/// [`UdpBuilder::bind`] turns the socket into a [`UdpSocket`].
+pub struct UdpBuilder {
+    socket: SOCKET,
+}
+
+impl UdpBuilder {
+    /// Creates a non-blocking IPv4 socket.
+    pub fn new_v4() -> io::Result<UdpBuilder> {
+        UdpBuilder::new(AF_INET)
+    }
+
+    /// Creates a non-blocking IPv6 socket.
+    pub fn new_v6() -> io::Result<UdpBuilder> {
+        UdpBuilder::new(AF_INET6)
+    }
+
+    pub(crate) fn for_addr(addr: &SocketAddr) -> io::Result<UdpBuilder> {
+        UdpBuilder::new(family(addr))
+    }
+
+    fn new(family: c_int) -> io::Result<UdpBuilder> {
+        new_socket(family, SOCK_DGRAM).map(|socket| UdpBuilder { socket })
+    }
+
+    pub fn bind(self, addr: SocketAddr) -> io::Result<UdpSocket> {
+        let (raw_addr, len) = socket_addr(&addr);
+        if unsafe { bind(self.socket, raw_addr.as_ptr(), len) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+
+        let socket = unsafe { net::UdpSocket::from_raw_socket(self.into_raw() as RawSocket) };
+        Ok(UdpSocket::from_std(socket))
+    }
+
+    /// Sets `IPV6_V6ONLY` on an IPv6 socket. On by default on Windows; turned
+    /// off, the socket also takes IPv4 traffic, with senders showing up as
+    /// IPv4-mapped addresses (`::ffff:a.b.c.d`).
+    pub fn set_only_v6(&self, only_v6: bool) -> io::Result<()> {
+        set_only_v6(self.socket, only_v6)
+    }
+
+    pub fn only_v6(&self) -> io::Result<bool> {
+        only_v6(self.socket)
+    }
+
+    /// Sets `SO_REUSEADDR`, to share the port with other sockets that set it
+    /// too, e.g. several listeners of the same broadcast or multicast traffic.
+    ///
+    /// Windows also lets such a socket bind to a port some other socket is
+    /// actively using, and take its traffic over.
+    /// [`UdpBuilder::set_exclusiveaddruse`] guards a socket against that.
+    pub fn set_reuseaddr(&self, reuseaddr: bool) -> io::Result<()> {
+        set_opt(self.socket, SOL_SOCKET, SO_REUSEADDR, reuseaddr as BOOL)
+    }
+
+    pub fn reuseaddr(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket, SOL_SOCKET, SO_REUSEADDR).map(|value| value != 0)
+    }
+
+    /// Sets `SO_EXCLUSIVEADDRUSE`, so that no other socket can bind to the
+    /// address this one is bound to, `SO_REUSEADDR` or not. Can't be
+    /// combined with [`UdpBuilder::set_reuseaddr`].
+    pub fn set_exclusiveaddruse(&self, exclusive: bool) -> io::Result<()> {
+        set_opt(
+            self.socket,
+            SOL_SOCKET,
+            SO_EXCLUSIVEADDRUSE,
+            exclusive as BOOL,
+        )
+    }
+
+    pub fn exclusiveaddruse(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket, SOL_SOCKET, SO_EXCLUSIVEADDRUSE).map(|value| value != 0)
+    }
+
+    /// See [`UdpSocket::set_broadcast`].
+    pub fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        set_opt(self.socket, SOL_SOCKET, SO_BROADCAST, broadcast as BOOL)
+    }
+
+    pub fn broadcast(&self) -> io::Result<bool> {
+        get_opt::<BOOL>(self.socket, SOL_SOCKET, SO_BROADCAST).map(|value| value != 0)
+    }
+
+    fn into_raw(self) -> SOCKET {
+        let socket = self.socket;
+        mem::forget(self);
+        socket
+    }
+}
+
+impl AsRawSocket for UdpBuilder {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.socket as RawSocket
+    }
+}
+
+impl Drop for UdpBuilder {
+    fn drop(&mut self) {
+        unsafe { closesocket(self.socket) };
+    }
+}
+
+#[test]
+fn test_udp_builder_dual_stack() -> io::Result<()> {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    let builder = UdpBuilder::new_v6()?;
+    assert!(builder.only_v6()?);
+    builder.set_only_v6(false)?;
+    assert!(!builder.only_v6()?);
+    let socket = builder.bind("[::]:0".parse().unwrap())?;
+    let port = socket.local_addr()?.port();
+
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    sender.send_to(b"v4", (Ipv4Addr::LOCALHOST, port))?;
+
+    let mut buf = [0; 8];
+    let (n, from) = loop {
+        match socket.recv_from(&mut buf) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            r => break r?,
+        }
+    };
+    assert_eq!(&buf[..n], b"v4");
+    match from.ip() {
+        IpAddr::V6(ip) => assert_eq!(ip.to_ipv4(), Some(Ipv4Addr::LOCALHOST)),
+        IpAddr::V4(ip) => panic!("unexpected v4 sender {}", ip),
+    }
+    assert_eq!(from.port(), sender.local_addr()?.port());
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_builder_reuseaddr() -> io::Result<()> {
+    let socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let addr = socket.local_addr()?;
+    drop(socket);
+
+    //Rebinding right away, and sharing the port with a second socket
+    let first = UdpBuilder::new_v4()?;
+    first.set_reuseaddr(true)?;
+    assert!(first.reuseaddr()?);
+    let first = first.bind(addr)?;
+    let second = UdpBuilder::new_v4()?;
+    second.set_reuseaddr(true)?;
+    let second = second.bind(addr)?;
+    assert_eq!(first.local_addr()?, second.local_addr()?);
+
+    //Once exclusive, the port can't be shared any more
+    let exclusive = UdpBuilder::new_v4()?;
+    exclusive.set_exclusiveaddruse(true)?;
+    assert!(exclusive.exclusiveaddruse()?);
+    let exclusive = exclusive.bind("127.0.0.1:0".parse().unwrap())?;
+    let other = UdpBuilder::new_v4()?;
+    other.set_reuseaddr(true)?;
+    assert!(other.bind(exclusive.local_addr()?).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,7 @@
+mod builder;
+mod msg;
+mod socket;
+
+pub use self::builder::UdpBuilder;
+pub use self::msg::RecvMsg;
+pub use self::socket::UdpSocket;
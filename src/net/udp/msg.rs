@@ -0,0 +1,178 @@
+use crate::net::{last_error, set_opt, socket_family, to_socket_addr};
+use std::io::{self, IoSliceMut};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{BOOL, DWORD};
+use winapi::shared::winerror::WSAEMSGSIZE;
+use winapi::shared::ws2def::{
+    AF_INET6, IPPROTO_IP, IPPROTO_IPV6, SIO_GET_EXTENSION_FUNCTION_POINTER, SOCKADDR,
+    SOCKADDR_STORAGE, WSABUF, WSACMSGHDR, WSAMSG,
+};
+use winapi::shared::ws2ipdef::{IN6_PKTINFO, IN_PKTINFO, IPV6_PKTINFO, IP_PKTINFO};
+use winapi::um::mswsock::{LPFN_WSARECVMSG, WSAID_WSARECVMSG};
+use winapi::um::winsock2::{WSAIoctl, SOCKET, SOCKET_ERROR};
+
+/// A datagram received by [`UdpSocket::recv_msg`], with where it was sent to.
+///
+/// [`UdpSocket::recv_msg`]: crate::net::UdpSocket::recv_msg
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RecvMsg {
+    len: usize,
+    source: SocketAddr,
+    destination: Option<IpAddr>,
+    interface: Option<u32>,
+}
+
+impl RecvMsg {
+    /// Bytes written to the buffer, `buf.len()` for a truncated datagram.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// True for an empty datagram, which UDP allows.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// Local address the datagram was sent to, the one to reply from.
+    /// `None` if the OS didn't say.
+    pub fn destination(&self) -> Option<IpAddr> {
+        self.destination
+    }
+
+    /// Index of the interface the datagram came in on.
+    pub fn interface_index(&self) -> Option<u32> {
+        self.interface
+    }
+}
+
+//WSARecvMsg is only reachable through the extension function pointer, the
+//same for every socket of the process
+static WSARECVMSG: AtomicUsize = AtomicUsize::new(0);
+
+fn wsa_recv_msg(socket: SOCKET) -> io::Result<usize> {
+    let cached = WSARECVMSG.load(Ordering::Relaxed);
+    if cached != 0 {
+        return Ok(cached);
+    }
+
+    let mut guid = WSAID_WSARECVMSG;
+    let mut ptr: usize = 0;
+    let mut returned: DWORD = 0;
+    let r = unsafe {
+        WSAIoctl(
+            socket,
+            SIO_GET_EXTENSION_FUNCTION_POINTER,
+            &mut guid as *mut _ as *mut _,
+            mem::size_of_val(&guid) as DWORD,
+            &mut ptr as *mut _ as *mut _,
+            mem::size_of::<usize>() as DWORD,
+            &mut returned,
+            null_mut(),
+            None,
+        )
+    };
+    if r == SOCKET_ERROR {
+        return Err(last_error());
+    }
+    WSARECVMSG.store(ptr, Ordering::Relaxed);
+    Ok(ptr)
+}
+
+//Asks for the pktinfo control messages. An IPv6 socket gets IPv4 ones too,
+//for mapped traffic: that fails harmlessly on a v6 only socket.
+pub(crate) fn enable_pktinfo(socket: SOCKET) -> io::Result<()> {
+    if socket_family(socket)? == AF_INET6 {
+        set_opt(socket, IPPROTO_IPV6 as c_int, IPV6_PKTINFO, 1 as BOOL)?;
+        let _ = set_opt(socket, IPPROTO_IP, IP_PKTINFO, 1 as BOOL);
+        Ok(())
+    } else {
+        set_opt(socket, IPPROTO_IP, IP_PKTINFO, 1 as BOOL)
+    }
+}
+
+//Aligned like WSA_CMSGHDR_ALIGN and WSA_CMSGDATA_ALIGN
+fn cmsg_align(len: usize) -> usize {
+    let align = mem::align_of::<WSACMSGHDR>();
+    (len + align - 1) & !(align - 1)
+}
+
+pub(crate) fn recv_msg(socket: SOCKET, buf: &mut [u8]) -> io::Result<RecvMsg> {
+    let recv_msg: LPFN_WSARECVMSG = unsafe { mem::transmute(wsa_recv_msg(socket)?) };
+    let recv_msg = recv_msg.expect("WSARecvMsg missing");
+
+    let mut storage: SOCKADDR_STORAGE = unsafe { mem::zeroed() };
+    //Room for both pktinfo messages, aligned for their headers
+    let mut control = [0u64; 16];
+    let mut bufs = [IoSliceMut::new(buf)];
+    let mut msg = WSAMSG {
+        name: &mut storage as *mut _ as *mut SOCKADDR,
+        namelen: mem::size_of::<SOCKADDR_STORAGE>() as c_int,
+        lpBuffers: bufs.as_mut_ptr() as *mut WSABUF,
+        dwBufferCount: 1,
+        Control: WSABUF {
+            len: mem::size_of_val(&control) as u32,
+            buf: control.as_mut_ptr() as *mut _,
+        },
+        dwFlags: 0,
+    };
+
+    let mut received: DWORD = 0;
+    let mut len =
+        if unsafe { recv_msg(socket, &mut msg, &mut received, null_mut(), None) } == SOCKET_ERROR {
+            let e = last_error();
+            //Like `recv_from`: a truncated datagram fills the buffer
+            if e.raw_os_error() != Some(WSAEMSGSIZE as i32) {
+                return Err(e);
+            }
+            bufs[0].len()
+        } else {
+            received as usize
+        };
+    len = len.min(bufs[0].len());
+
+    let mut recv = RecvMsg {
+        len,
+        source: to_socket_addr(&storage)?,
+        destination: None,
+        interface: None,
+    };
+
+    let control = unsafe {
+        std::slice::from_raw_parts(control.as_ptr() as *const u8, msg.Control.len as usize)
+    };
+    let header_len = mem::size_of::<WSACMSGHDR>();
+    let mut offset = 0;
+    while offset + header_len <= control.len() {
+        let header = unsafe { &*(control[offset..].as_ptr() as *const WSACMSGHDR) };
+        let data = unsafe { control[offset..].as_ptr().add(cmsg_align(header_len)) };
+        match (header.cmsg_level, header.cmsg_type) {
+            (IPPROTO_IP, IP_PKTINFO) => {
+                let info = unsafe { &*(data as *const IN_PKTINFO) };
+                let ip = unsafe { *info.ipi_addr.S_un.S_addr() }.to_ne_bytes();
+                recv.destination = Some(Ipv4Addr::from(ip).into());
+                recv.interface = Some(info.ipi_ifindex);
+            }
+            (level, IPV6_PKTINFO) if level == IPPROTO_IPV6 as c_int => {
+                let info = unsafe { &*(data as *const IN6_PKTINFO) };
+                let ip = *unsafe { info.ipi6_addr.u.Byte() };
+                recv.destination = Some(Ipv6Addr::from(ip).into());
+                recv.interface = Some(info.ipi6_ifindex);
+            }
+            _ => {}
+        }
+        if header.cmsg_len == 0 {
+            break;
+        }
+        offset += cmsg_align(header.cmsg_len);
+    }
+
+    Ok(recv)
+}
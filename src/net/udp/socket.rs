@@ -0,0 +1,798 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::io_source::IoSource;
+use crate::net::udp::msg::{enable_pktinfo, recv_msg};
+use crate::net::{
+    local_addr, recv_buffer_size, recv_from_vectored, send_buffer_size, send_to_vectored,
+    set_recv_buffer_size, set_send_buffer_size, set_ttl, socket_family, ttl, OrError, RecvMsg,
+    UdpBuilder,
+};
+use crate::poll::Registry;
+use crate::token::Token;
+use std::fmt;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::net::{self, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket, RawSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use winapi::ctypes::c_int;
+use winapi::shared::ws2def::{AF_INET, AF_INET6};
+use winapi::um::winsock2::{MSG_PEEK, SOCKET};
+
+/// A non-blocking UDP socket.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::net::UdpSocket;
+/// use iocp_wrapper::{Events, Interests, Poll, Token};
+/// use std::time::Duration;
+///
+/// let mut poll = Poll::new()?;
+/// let mut events = Events::with_capacity(8);
+///
+/// let sender = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+/// let receiver = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+/// poll.registry()
+///     .register(&receiver, Token(0), Interests::READABLE)?;
+///
+/// sender.send_to(b"ping", receiver.local_addr()?)?;
+/// poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+/// assert!(events.get(0).unwrap().readiness().is_readable());
+///
+/// let mut buf = [0; 16];
+/// let (n, from) = receiver.recv_from(&mut buf)?;
+/// assert_eq!(&buf[..n], b"ping");
+/// assert_eq!(from, sender.local_addr()?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct UdpSocket {
+    inner: IoSource<net::UdpSocket>,
+    //pktinfo options set, done by the first `recv_msg`
+    pktinfo: AtomicBool,
+}
+
+impl UdpSocket {
+    /// Wraps a socket created with std.
+    ///
+    /// The socket has to be in non-blocking mode already, see
+    /// [`net::UdpSocket::set_nonblocking`].
+    pub fn from_std(inner: net::UdpSocket) -> UdpSocket {
+        UdpSocket {
+            inner: IoSource::new(inner),
+            pktinfo: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the std socket, without closing or duplicating the handle.
+    ///
+    /// A registration is not undone by this: deregister first, or the socket
+    /// keeps producing events with its old token.
+    pub fn into_std(self) -> net::UdpSocket {
+        self.inner.into_inner()
+    }
+
+    /// Binds a socket to `addr`. Options that have to be set before binding
+    /// go through [`UdpBuilder`].
+    pub fn bind(addr: SocketAddr) -> io::Result<UdpSocket> {
+        UdpBuilder::for_addr(&addr)?.bind(addr)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        local_addr(self.socket())
+    }
+
+    /// Sets the time-to-live of outgoing packets: `IP_TTL` for IPv4,
+    /// the unicast hop limit for IPv6.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        set_ttl(self.socket(), ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        ttl(self.socket())
+    }
+
+    /// Sets `SO_BROADCAST`, without which sending to a broadcast address fails
+    /// with `WSAEACCES`.
+    pub fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        self.inner.set_broadcast(broadcast)
+    }
+
+    pub fn broadcast(&self) -> io::Result<bool> {
+        self.inner.broadcast()
+    }
+
+    /// Joins the IPv4 multicast group `multiaddr` on the interface with
+    /// address `interface`, `0.0.0.0` letting the OS pick.
+    pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> io::Result<()> {
+        self.check_family(AF_INET)?;
+        self.inner.join_multicast_v4(multiaddr, interface)
+    }
+
+    pub fn leave_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> io::Result<()> {
+        self.check_family(AF_INET)?;
+        self.inner.leave_multicast_v4(multiaddr, interface)
+    }
+
+    /// Joins the IPv6 multicast group `multiaddr` on the interface with index
+    /// `interface`, 0 letting the OS pick.
+    pub fn join_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        self.check_family(AF_INET6)?;
+        self.inner.join_multicast_v6(multiaddr, interface)
+    }
+
+    pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        self.check_family(AF_INET6)?;
+        self.inner.leave_multicast_v6(multiaddr, interface)
+    }
+
+    /// Whether datagrams sent to a group this socket joined are received by it
+    /// too.
+    pub fn set_multicast_loop_v4(&self, multicast_loop: bool) -> io::Result<()> {
+        self.check_family(AF_INET)?;
+        self.inner.set_multicast_loop_v4(multicast_loop)
+    }
+
+    pub fn multicast_loop_v4(&self) -> io::Result<bool> {
+        self.check_family(AF_INET)?;
+        self.inner.multicast_loop_v4()
+    }
+
+    pub fn set_multicast_loop_v6(&self, multicast_loop: bool) -> io::Result<()> {
+        self.check_family(AF_INET6)?;
+        self.inner.set_multicast_loop_v6(multicast_loop)
+    }
+
+    pub fn multicast_loop_v6(&self) -> io::Result<bool> {
+        self.check_family(AF_INET6)?;
+        self.inner.multicast_loop_v6()
+    }
+
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> io::Result<()> {
+        self.check_family(AF_INET)?;
+        self.inner.set_multicast_ttl_v4(ttl)
+    }
+
+    pub fn multicast_ttl_v4(&self) -> io::Result<u32> {
+        self.check_family(AF_INET)?;
+        self.inner.multicast_ttl_v4()
+    }
+
+    /// Sets `SO_RCVBUF`. The OS may round the size up or clamp it, the
+    /// getter returns what it applied.
+    pub fn set_recv_buffer_size(&self, size: u32) -> io::Result<()> {
+        set_recv_buffer_size(self.socket(), size)
+    }
+
+    pub fn recv_buffer_size(&self) -> io::Result<u32> {
+        recv_buffer_size(self.socket())
+    }
+
+    /// Sets `SO_SNDBUF`, rounded like the receive buffer. 0 is allowed:
+    /// sends are then not copied into the socket, which is only worth it
+    /// with overlapped I/O.
+    pub fn set_send_buffer_size(&self, size: u32) -> io::Result<()> {
+        set_send_buffer_size(self.socket(), size)
+    }
+
+    pub fn send_buffer_size(&self) -> io::Result<u32> {
+        send_buffer_size(self.socket())
+    }
+
+    /// Returns and clears the pending error of the socket (`SO_ERROR`).
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+
+    /// Sends `buf` to `target`, `WouldBlock` if the send buffer is full.
+    ///
+    /// `buf` may be empty, that sends a zero-length datagram.
+    pub fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        self.send_to_vectored(&[IoSlice::new(buf)], target)
+    }
+
+    /// Receives one datagram, `WouldBlock` if none is waiting.
+    ///
+    /// A datagram larger than `buf` is truncated to `buf.len()` bytes, the
+    /// rest of it is lost: the returned count is `buf.len()`, not an error.
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.recv_from_with_flags(buf, 0)
+    }
+
+    /// Like [`UdpSocket::recv_from`], truncation included, but the datagram
+    /// stays queued: the next receive returns it again.
+    ///
+    /// The socket keeps reporting readable until the datagram is received.
+    pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.recv_from_with_flags(buf, MSG_PEEK)
+    }
+
+    /// Like [`UdpSocket::recv_from`], truncation and `WouldBlock` included,
+    /// but also tells the local address the datagram was sent to and the
+    /// interface it arrived on.
+    ///
+    /// For a socket bound to a wildcard address that is the address to reply
+    /// from. The first call turns on `IP_PKTINFO` (and `IPV6_PKTINFO`), so
+    /// datagrams queued before it may come without a destination.
+    pub fn recv_msg(&self, buf: &mut [u8]) -> io::Result<RecvMsg> {
+        if !self.pktinfo.load(Ordering::Relaxed) {
+            enable_pktinfo(self.socket())?;
+            self.pktinfo.store(true, Ordering::Relaxed);
+        }
+        recv_msg(self.socket(), buf)
+    }
+
+    /// Connects the socket to `addr`: [`UdpSocket::send`] goes there, and only
+    /// datagrams from there are received.
+    ///
+    /// Errors from ICMP messages the peer's host sends back, like a closed
+    /// port, come out of the next receive, e.g. `ConnectionReset`.
+    pub fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    /// Sends `buf` to the connected address.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.do_io(|inner| inner.send(buf))
+    }
+
+    /// Receives one datagram from the connected address, truncating like
+    /// [`UdpSocket::recv_from`].
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv_from_with_flags(buf, 0).map(|(n, _)| n)
+    }
+
+    /// Like [`UdpSocket::recv`], but the datagram stays queued.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv_from_with_flags(buf, MSG_PEEK).map(|(n, _)| n)
+    }
+
+    /// Sends the concatenation of `bufs` as one datagram to `target`.
+    pub fn send_to_vectored(&self, bufs: &[IoSlice<'_>], target: SocketAddr) -> io::Result<usize> {
+        self.inner
+            .do_io(|inner| send_to_vectored(inner.as_raw_socket() as SOCKET, bufs, Some(&target)))
+    }
+
+    /// Receives one datagram into `bufs`, filling them in order.
+    ///
+    /// Truncates like [`UdpSocket::recv_from`].
+    pub fn recv_from_vectored(
+        &self,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> io::Result<(usize, SocketAddr)> {
+        recv_from_vectored(self.socket(), bufs, 0)
+    }
+
+    //std reports truncated datagrams as errors
+    fn recv_from_with_flags(
+        &self,
+        buf: &mut [u8],
+        flags: c_int,
+    ) -> io::Result<(usize, SocketAddr)> {
+        recv_from_vectored(self.socket(), &mut [IoSliceMut::new(buf)], flags)
+    }
+
+    fn socket(&self) -> SOCKET {
+        self.inner.as_raw_socket() as SOCKET
+    }
+
+    //Winsock answers a multicast option of the other family with WSAEINVAL
+    fn check_family(&self, family: c_int) -> io::Result<()> {
+        if socket_family(self.socket())? == family {
+            return Ok(());
+        }
+        let msg = match family {
+            AF_INET => "IPv4 multicast option on an IPv6 socket",
+            _ => "IPv6 multicast option on an IPv4 socket",
+        };
+        Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+    }
+}
+
+impl fmt::Debug for UdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpSocket")
+            .field("addr", &OrError(self.local_addr()))
+            .field("socket", &self.socket())
+            .field("registered", &self.inner.is_registered())
+            .finish()
+    }
+}
+
+impl AsRawSocket for UdpSocket {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.inner.as_raw_socket()
+    }
+}
+
+/// The socket has to be in non-blocking mode already. The base socket is only looked up on registration.
+impl FromRawSocket for UdpSocket {
+    unsafe fn from_raw_socket(socket: RawSocket) -> UdpSocket {
+        UdpSocket::from_std(net::UdpSocket::from_raw_socket(socket))
+    }
+}
+
+/// Gives up ownership, the socket is not closed.
+impl IntoRawSocket for UdpSocket {
+    fn into_raw_socket(self) -> RawSocket {
+        self.into_std().into_raw_socket()
+    }
+}
+
+impl event::Source for UdpSocket {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+#[test]
+fn test_udp_truncated_datagram() -> io::Result<()> {
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let sender = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let receiver = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    poll.registry()
+        .register(&receiver, Token(0), Interests::READABLE)?;
+
+    let mut buf = [0; 8];
+    match receiver.recv_from(&mut buf) {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        other => panic!("recv_from without a datagram: {:?}", other),
+    }
+
+    let datagram: Vec<u8> = (0..64).collect();
+    sender.send_to(&datagram, receiver.local_addr()?)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+
+    assert_eq!(receiver.peek_from(&mut buf)?, (8, sender.local_addr()?));
+    assert_eq!(receiver.recv_from(&mut buf)?, (8, sender.local_addr()?));
+    assert_eq!(&buf, &datagram[..8]);
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_std_round_trip() -> io::Result<()> {
+    let receiver = net::UdpSocket::bind("127.0.0.1:0")?;
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    let raw = sender.as_raw_socket();
+
+    sender.set_nonblocking(true)?;
+    let sender = UdpSocket::from_std(sender);
+    assert_eq!(sender.as_raw_socket(), raw);
+    sender.send_to(b"wrapped", receiver.local_addr()?)?;
+
+    let sender = sender.into_std();
+    assert_eq!(sender.as_raw_socket(), raw);
+    sender.send_to(b"std", receiver.local_addr()?)?;
+
+    let mut buf = [0; 16];
+    let (n, _) = receiver.recv_from(&mut buf)?;
+    assert_eq!(&buf[..n], b"wrapped");
+    let (n, _) = receiver.recv_from(&mut buf)?;
+    assert_eq!(&buf[..n], b"std");
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_vectored() -> io::Result<()> {
+    let sender = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let receiver = net::UdpSocket::bind("127.0.0.1:0")?;
+    let target = receiver.local_addr()?;
+
+    let bufs = [
+        IoSlice::new(b"head"),
+        IoSlice::new(b""),
+        IoSlice::new(b"tail"),
+    ];
+    assert_eq!(sender.send_to_vectored(&bufs, target)?, 8);
+    sender.send_to(b"headtail", target)?;
+
+    //Same datagram both ways
+    let mut flat = [0; 16];
+    let (n, _) = receiver.recv_from(&mut flat)?;
+    assert_eq!(&flat[..n], b"headtail");
+    let (n, _) = receiver.recv_from(&mut flat)?;
+    assert_eq!(&flat[..n], b"headtail");
+
+    //Scattered, and truncated past the last buffer
+    receiver.set_nonblocking(true)?;
+    let receiver = UdpSocket::from_std(receiver);
+    net::UdpSocket::bind("127.0.0.1:0")?.send_to(b"0123456789", target)?;
+    let (mut a, mut b) = ([0; 3], [0; 4]);
+    loop {
+        let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        match receiver.recv_from_vectored(&mut bufs) {
+            Ok((n, _)) => {
+                assert_eq!(n, 7);
+                break;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    assert_eq!((&a, &b), (b"012", b"3456"));
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_ttl() -> io::Result<()> {
+    for bind in &["127.0.0.1:0", "[::1]:0"] {
+        let socket = UdpSocket::bind(bind.parse().unwrap())?;
+        socket.set_ttl(7)?;
+        assert_eq!(socket.ttl()?, 7);
+        socket.set_ttl(200)?;
+        assert_eq!(socket.ttl()?, 200);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_send_recv_addrs() -> io::Result<()> {
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    for (i, bind) in ["127.0.0.1:0", "[::1]:0"].iter().enumerate() {
+        let sender = UdpSocket::bind(bind.parse().unwrap())?;
+        let receiver = UdpSocket::bind(bind.parse().unwrap())?;
+        poll.registry()
+            .register(&receiver, Token(i), Interests::READABLE)?;
+
+        let mut buf = [0; 16];
+        for datagram in &[&b"port byte order"[..], &b""[..]] {
+            assert_eq!(
+                sender.send_to(datagram, receiver.local_addr()?)?,
+                datagram.len()
+            );
+            poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+            assert_eq!(events.len(), 1);
+
+            let (n, from) = receiver.recv_from(&mut buf)?;
+            assert_eq!(&buf[..n], *datagram);
+            assert_eq!(from, sender.local_addr()?);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_connected() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::thread;
+    use std::time::Duration;
+
+    let (a, b) = crate::net::udp_pair()?;
+    let stranger = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&a, Token(0), Interests::READABLE)?;
+
+    //Filtered out by the OS, never shows up
+    stranger.send_to(b"stranger", a.local_addr()?)?;
+    b.send(b"ping")?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+
+    let mut buf = [0; 16];
+    assert_eq!(a.peek(&mut buf)?, 4);
+    assert_eq!(a.recv(&mut buf)?, 4);
+    assert_eq!(&buf[..4], b"ping");
+    a.send(b"pong")?;
+
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(
+        a.recv(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+    assert_eq!(b.recv(&mut buf)?, 4);
+    assert_eq!(&buf[..4], b"pong");
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_multicast_loop() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let group = Ipv4Addr::new(224, 0, 0, 251);
+    let socket = UdpSocket::bind("0.0.0.0:0".parse().unwrap())?;
+    socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    assert!(socket.multicast_loop_v4()?);
+    socket.set_multicast_ttl_v4(1)?;
+    assert_eq!(socket.multicast_ttl_v4()?, 1);
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&socket, Token(0), Interests::READABLE)?;
+
+    let port = socket.local_addr()?.port();
+    socket.send_to(b"query", SocketAddr::from((group, port)))?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+    let mut buf = [0; 16];
+    let (n, _) = socket.recv_from(&mut buf)?;
+    assert_eq!(&buf[..n], b"query");
+
+    socket.leave_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?;
+
+    //The other family's options are refused up front
+    let e = socket
+        .join_multicast_v6(&"ff02::fb".parse().unwrap(), 0)
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_broadcast() -> io::Result<()> {
+    use crate::{Events, Poll};
+    use std::time::Duration;
+    use winapi::shared::winerror::WSAEACCES;
+
+    //Receiver on the wildcard address, sharing the port with anyone else
+    //listening for broadcasts
+    let any: SocketAddr = "0.0.0.0:0".parse().unwrap();
+    let builder = UdpBuilder::new_v4()?;
+    builder.set_reuseaddr(true)?;
+    let receiver = builder.bind(any)?;
+    let target = SocketAddr::from((Ipv4Addr::BROADCAST, receiver.local_addr()?.port()));
+
+    let sender = UdpSocket::bind(any)?;
+    assert!(!sender.broadcast()?);
+    let e = sender.send_to(b"hello", target).unwrap_err();
+    assert_eq!(e.raw_os_error(), Some(WSAEACCES as i32));
+
+    sender.set_broadcast(true)?;
+    assert!(sender.broadcast()?);
+    assert_eq!(sender.send_to(b"hello", target)?, 5);
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    poll.registry()
+        .register(&receiver, Token(0), Interests::READABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+
+    let mut buf = [0; 16];
+    let (n, from) = receiver.recv_from(&mut buf)?;
+    assert_eq!(&buf[..n], b"hello");
+    assert_eq!(from.port(), sender.local_addr()?.port());
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_raw_round_trip() -> io::Result<()> {
+    let socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let addr = socket.local_addr()?;
+
+    let raw = socket.into_raw_socket();
+    let socket = unsafe { UdpSocket::from_raw_socket(raw) };
+    assert_eq!(socket.local_addr()?, addr);
+
+    net::UdpSocket::bind("127.0.0.1:0")?.send_to(b"raw", addr)?;
+    let mut buf = [0; 8];
+    let n = loop {
+        match socket.recv(&mut buf) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            r => break r?,
+        }
+    };
+    assert_eq!(&buf[..n], b"raw");
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_peek_from() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let sender = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let receiver = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    poll.registry()
+        .register(&receiver, Token(0), Interests::READABLE)?;
+
+    let mut buf = [0; 4];
+    let e = receiver.peek_from(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    sender.send_to(b"first datagram", receiver.local_addr()?)?;
+    sender.send_to(b"second", receiver.local_addr()?)?;
+    let from = sender.local_addr()?;
+
+    //Peeking neither consumes the datagram nor the readiness
+    for _ in 0..2 {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        assert!(is_readable(events.get(0).unwrap()));
+        assert_eq!(receiver.peek_from(&mut buf)?, (4, from));
+        assert_eq!(&buf, b"firs");
+    }
+
+    let mut full = [0; 32];
+    let (n, addr) = receiver.recv_from(&mut full)?;
+    assert_eq!((&full[..n], addr), (&b"first datagram"[..], from));
+    assert_eq!(receiver.peek_from(&mut full)?, (6, from));
+    assert_eq!(receiver.recv_from(&mut full)?, (6, from));
+    assert_eq!(&full[..6], b"second");
+
+    let e = receiver.peek_from(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_buffer_sizes() -> io::Result<()> {
+    for bind in &["127.0.0.1:0", "[::1]:0"] {
+        let socket = UdpSocket::bind(bind.parse().unwrap())?;
+
+        socket.set_recv_buffer_size(256 * 1024)?;
+        assert!(socket.recv_buffer_size()? >= 256 * 1024);
+        socket.set_send_buffer_size(64 * 1024)?;
+        assert!(socket.send_buffer_size()? >= 64 * 1024);
+
+        socket.set_send_buffer_size(0)?;
+        assert_eq!(socket.send_buffer_size()?, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_recv_msg_destination() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let receiver = UdpSocket::bind("0.0.0.0:0".parse().unwrap())?;
+    poll.registry()
+        .register(&receiver, Token(0), Interests::READABLE)?;
+
+    //Turns the pktinfo options on before anything is queued
+    let mut buf = [0; 32];
+    let e = receiver.recv_msg(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    let port = receiver.local_addr()?.port();
+    sender.send_to(b"to loopback", (Ipv4Addr::LOCALHOST, port))?;
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(is_readable(events.get(0).unwrap()));
+
+    let msg = receiver.recv_msg(&mut buf)?;
+    assert!(!msg.is_empty());
+    assert_eq!(&buf[..msg.len()], b"to loopback");
+    assert_eq!(msg.source(), sender.local_addr()?);
+    assert_eq!(msg.destination(), Some(Ipv4Addr::LOCALHOST.into()));
+    assert!(msg.interface_index().is_some());
+
+    let e = receiver.recv_msg(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_debug() -> io::Result<()> {
+    let socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+
+    let debug = format!("{:?}", socket);
+    assert!(debug.starts_with("UdpSocket { "), "{}", debug);
+    assert!(
+        debug.contains(&format!("addr: {:?}", socket.local_addr()?)),
+        "{}",
+        debug
+    );
+    assert!(
+        debug.contains(&format!("socket: {}", socket.as_raw_socket())),
+        "{}",
+        debug
+    );
+    assert!(debug.contains("registered: false"), "{}", debug);
+
+    Ok(())
+}
+
+#[test]
+fn test_udp_switch_directions() -> io::Result<()> {
+    use crate::event::{is_readable, is_writable};
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    let socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let addr = socket.local_addr()?;
+    poll.registry()
+        .register(&socket, Token(0), Interests::READABLE)?;
+
+    for _ in 0..3 {
+        //Waiting for a request: readable only
+        sender.send_to(b"request", addr)?;
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        assert!(is_readable(events.get(0).unwrap()));
+        assert!(!is_writable(events.get(0).unwrap()));
+
+        //Flushing a response: datagrams keep coming, and stay unread
+        poll.registry()
+            .reregister(&socket, Token(0), Interests::WRITABLE)?;
+        sender.send_to(b"more", addr)?;
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        assert!(is_writable(events.get(0).unwrap()));
+        assert!(!is_readable(events.get(0).unwrap()));
+        for _ in 0..3 {
+            sender.send_to(b"more", addr)?;
+            poll.poll(&mut events, Some(Duration::from_millis(50)))?;
+            for i in 0..events.len() {
+                assert!(!is_readable(events.get(i).unwrap()));
+            }
+        }
+
+        //Back to requests, what was queued meanwhile is reported right away
+        poll.registry()
+            .reregister(&socket, Token(0), Interests::READABLE)?;
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        assert!(is_readable(events.get(0).unwrap()));
+        assert!(!is_writable(events.get(0).unwrap()));
+    }
+
+    let mut buf = [0; 16];
+    let mut received = 0;
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok(_) => received += 1,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    assert_eq!(received, 3 * 5);
+
+    Ok(())
+}
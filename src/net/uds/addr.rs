@@ -0,0 +1,110 @@
+use crate::net::last_error;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use winapi::ctypes::{c_char, c_int};
+use winapi::shared::ws2def::{ADDRESS_FAMILY, AF_UNIX, SOCKADDR};
+use winapi::um::winsock2::{getpeername, getsockname, SOCKET, SOCKET_ERROR};
+
+//Not in winapi yet, see afunix.h
+#[repr(C)]
+pub(crate) struct SOCKADDR_UN {
+    sun_family: ADDRESS_FAMILY,
+    sun_path: [c_char; 108],
+}
+
+impl SOCKADDR_UN {
+    pub(crate) fn as_ptr(&self) -> *const SOCKADDR {
+        self as *const _ as *const SOCKADDR
+    }
+}
+
+//UTF-8 path, NUL terminated inside `sun_path`. An empty path or one starting
+//with NUL would be an abstract name, which Windows doesn't support.
+pub(crate) fn sockaddr_un(path: &Path) -> io::Result<(SOCKADDR_UN, c_int)> {
+    let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+
+    let bytes = match path.to_str() {
+        Some(path) => path.as_bytes(),
+        None => return invalid("socket path is not valid UTF-8"),
+    };
+    if bytes.is_empty() || bytes[0] == 0 {
+        return invalid("abstract socket names are not supported");
+    }
+    if bytes.contains(&0) {
+        return invalid("socket path contains a NUL byte");
+    }
+
+    let mut addr = SOCKADDR_UN {
+        sun_family: AF_UNIX as ADDRESS_FAMILY,
+        sun_path: [0; 108],
+    };
+    //The terminating NUL has to fit too
+    if bytes.len() >= addr.sun_path.len() {
+        return invalid("socket path is too long");
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(bytes) {
+        *dst = *src as c_char;
+    }
+
+    let len = mem::size_of::<ADDRESS_FAMILY>() + bytes.len() + 1;
+    Ok((addr, len as c_int))
+}
+
+//Path a socket is bound or connected to, empty if unnamed
+pub(crate) struct SocketPath(PathBuf);
+
+impl fmt::Debug for SocketPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.as_os_str().is_empty() {
+            f.write_str("(unnamed)")
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+pub(crate) fn local_path(socket: SOCKET) -> io::Result<SocketPath> {
+    query_path(socket, getsockname)
+}
+
+pub(crate) fn peer_path(socket: SOCKET) -> io::Result<SocketPath> {
+    query_path(socket, getpeername)
+}
+
+fn query_path(
+    socket: SOCKET,
+    query: unsafe extern "system" fn(SOCKET, *mut SOCKADDR, *mut c_int) -> c_int,
+) -> io::Result<SocketPath> {
+    let mut addr: SOCKADDR_UN = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<SOCKADDR_UN>() as c_int;
+    if unsafe { query(socket, &mut addr as *mut _ as *mut SOCKADDR, &mut len) } == SOCKET_ERROR {
+        return Err(last_error());
+    }
+
+    let len = (len as usize).saturating_sub(mem::size_of::<ADDRESS_FAMILY>());
+    let bytes: Vec<u8> = addr.sun_path[..len.min(addr.sun_path.len())]
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    Ok(SocketPath(PathBuf::from(
+        String::from_utf8_lossy(&bytes).into_owned(),
+    )))
+}
+
+#[test]
+fn test_sockaddr_un_limits() {
+    let (_, len) = sockaddr_un(Path::new(r"C:\tmp\a.sock")).unwrap();
+    assert_eq!(len as usize, 2 + 13 + 1);
+
+    let long = "a".repeat(107);
+    assert!(sockaddr_un(Path::new(&long)).is_err());
+    assert!(sockaddr_un(Path::new(&long[1..])).is_ok());
+
+    for path in &["", "\0abstract", "a\0b"] {
+        let e = sockaddr_un(Path::new(path)).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::net::uds::addr::{local_path, sockaddr_un};
+use crate::net::uds::new_unix_socket;
+use crate::net::{last_error, OrError, UnixStream};
+use crate::poll::Registry;
+use crate::sys::raw_source;
+use crate::token::Token;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket, RawSocket};
+use std::path::Path;
+use std::ptr::null_mut;
+use winapi::um::handleapi::SetHandleInformation;
+use winapi::um::winbase::HANDLE_FLAG_INHERIT;
+use winapi::um::winnt::HANDLE;
+use winapi::um::winsock2::{
+    accept, bind, closesocket, ioctlsocket, listen, FIONBIO, INVALID_SOCKET, SOCKET, SOCKET_ERROR,
+    SOMAXCONN,
+};
+
+/// A non-blocking `AF_UNIX` listener, Windows 10 1803 or later.
+pub struct UnixListener {
+    socket: SOCKET,
+}
+
+impl UnixListener {
+    /// Binds a listener to `path`, creating the socket file.
+    ///
+    /// The file outlives the listener: remove it once done, binding fails
+    /// while it exists.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixListener> {
+        let (addr, len) = sockaddr_un(path.as_ref())?;
+        let listener = UnixListener {
+            socket: new_unix_socket()?,
+        };
+
+        if unsafe { bind(listener.socket, addr.as_ptr(), len) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        if unsafe { listen(listener.socket, SOMAXCONN) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(listener)
+    }
+
+    /// Accepts a connection, `WouldBlock` if none is waiting.
+    ///
+    /// Like with [`TcpListener::accept`], the stream is non-blocking and not
+    /// inherited by child processes.
+    ///
+    /// [`TcpListener::accept`]: crate::net::TcpListener::accept
+    pub fn accept(&self) -> io::Result<UnixStream> {
+        let socket = unsafe { accept(self.socket, null_mut(), null_mut()) };
+        if socket == INVALID_SOCKET {
+            return Err(last_error());
+        }
+        let stream = UnixStream::from_socket(socket);
+
+        if unsafe { SetHandleInformation(socket as HANDLE, HANDLE_FLAG_INHERIT, 0) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut nonblocking = 1;
+        if unsafe { ioctlsocket(socket, FIONBIO, &mut nonblocking) } != 0 {
+            return Err(last_error());
+        }
+        Ok(stream)
+    }
+}
+
+impl fmt::Debug for UnixListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnixListener")
+            .field("local", &OrError(local_path(self.socket)))
+            .field("socket", &self.socket)
+            .finish()
+    }
+}
+
+impl AsRawSocket for UnixListener {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.socket as RawSocket
+    }
+}
+
+/// The socket has to be in non-blocking mode already. The base socket is only looked up on registration.
+impl FromRawSocket for UnixListener {
+    unsafe fn from_raw_socket(socket: RawSocket) -> UnixListener {
+        UnixListener {
+            socket: socket as SOCKET,
+        }
+    }
+}
+
+/// Gives up ownership, the socket is not closed.
+impl IntoRawSocket for UnixListener {
+    fn into_raw_socket(self) -> RawSocket {
+        let socket = self.socket;
+        mem::forget(self);
+        socket as RawSocket
+    }
+}
+
+impl event::Source for UnixListener {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        registry
+            .selector()
+            .register(raw_source(self), token, interests)
+            .map(|_| ())
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        registry
+            .selector()
+            .reregister(raw_source(self), None, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        registry.selector().deregister(raw_source(self), None)
+    }
+}
+
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        unsafe { closesocket(self.socket) };
+    }
+}
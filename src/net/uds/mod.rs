@@ -0,0 +1,26 @@
+mod addr;
+mod listener;
+mod stream;
+
+pub use self::listener::UnixListener;
+pub use self::stream::UnixStream;
+
+use crate::net::new_socket;
+use std::io;
+use winapi::shared::winerror::WSAEAFNOSUPPORT;
+use winapi::shared::ws2def::AF_UNIX;
+use winapi::um::winsock2::{SOCKET, SOCK_STREAM};
+
+//Before Windows 10 1803 the family is simply unknown to winsock
+fn new_unix_socket() -> io::Result<SOCKET> {
+    new_socket(AF_UNIX, SOCK_STREAM).map_err(|e| {
+        if e.raw_os_error() == Some(WSAEAFNOSUPPORT as i32) {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "AF_UNIX sockets need Windows 10 1803 or later",
+            )
+        } else {
+            e
+        }
+    })
+}
@@ -0,0 +1,273 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::net::uds::addr::{local_path, peer_path, sockaddr_un};
+use crate::net::uds::new_unix_socket;
+use crate::net::{get_opt, last_error, recv_vectored, send_vectored, OrError};
+use crate::poll::Registry;
+use crate::sys::raw_source;
+use crate::token::Token;
+use std::fmt;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::mem;
+use std::net::Shutdown;
+use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket, RawSocket};
+use std::path::Path;
+use winapi::ctypes::c_int;
+use winapi::shared::winerror::WSAEWOULDBLOCK;
+use winapi::shared::ws2def::{SOL_SOCKET, SO_ERROR};
+use winapi::um::winsock2::{
+    closesocket, connect, shutdown, SD_BOTH, SD_RECEIVE, SD_SEND, SOCKET, SOCKET_ERROR,
+};
+
+/// A non-blocking `AF_UNIX` stream, Windows 10 1803 or later.
+pub struct UnixStream {
+    socket: SOCKET,
+}
+
+impl UnixStream {
+    /// Starts connecting to the socket bound at `path` and returns right away.
+    ///
+    /// Like for [`TcpStream::connect`], the connection is established once
+    /// the stream reports writable.
+    ///
+    /// [`TcpStream::connect`]: crate::net::TcpStream::connect
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<UnixStream> {
+        let (addr, len) = sockaddr_un(path.as_ref())?;
+        let stream = UnixStream::from_socket(new_unix_socket()?);
+
+        if unsafe { connect(stream.socket, addr.as_ptr(), len) } == SOCKET_ERROR {
+            let e = last_error();
+            if e.raw_os_error() != Some(WSAEWOULDBLOCK as i32) {
+                return Err(e);
+            }
+        }
+        Ok(stream)
+    }
+
+    pub(crate) fn from_socket(socket: SOCKET) -> UnixStream {
+        UnixStream { socket }
+    }
+
+    /// Returns and clears the pending error of the socket (`SO_ERROR`).
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        match get_opt::<c_int>(self.socket, SOL_SOCKET, SO_ERROR)? {
+            0 => Ok(None),
+            code => Ok(Some(io::Error::from_raw_os_error(code))),
+        }
+    }
+
+    /// Shuts down the read half, the write half, or both.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let how = match how {
+            Shutdown::Read => SD_RECEIVE,
+            Shutdown::Write => SD_SEND,
+            Shutdown::Both => SD_BOTH,
+        };
+        if unsafe { shutdown(self.socket, how) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(())
+    }
+}
+
+//Same behavior as for `TcpStream`
+impl Read for UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl<'a> Read for &'a UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        recv_vectored(self.socket, &mut [IoSliceMut::new(buf)])
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        recv_vectored(self.socket, bufs)
+    }
+}
+
+impl Write for UnixStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> Write for &'a UnixStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        send_vectored(self.socket, &[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        send_vectored(self.socket, bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for UnixStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnixStream")
+            .field("local", &OrError(local_path(self.socket)))
+            .field("peer", &OrError(peer_path(self.socket)))
+            .field("socket", &self.socket)
+            .finish()
+    }
+}
+
+impl AsRawSocket for UnixStream {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.socket as RawSocket
+    }
+}
+
+/// The socket has to be in non-blocking mode already. The base socket is only looked up on registration.
+impl FromRawSocket for UnixStream {
+    unsafe fn from_raw_socket(socket: RawSocket) -> UnixStream {
+        UnixStream::from_socket(socket as SOCKET)
+    }
+}
+
+/// Gives up ownership, the socket is not closed.
+impl IntoRawSocket for UnixStream {
+    fn into_raw_socket(self) -> RawSocket {
+        let socket = self.socket;
+        mem::forget(self);
+        socket as RawSocket
+    }
+}
+
+impl event::Source for UnixStream {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        registry
+            .selector()
+            .register(raw_source(self), token, interests)
+            .map(|_| ())
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        registry
+            .selector()
+            .reregister(raw_source(self), None, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        registry.selector().deregister(raw_source(self), None)
+    }
+}
+
+impl Drop for UnixStream {
+    fn drop(&mut self) {
+        unsafe { closesocket(self.socket) };
+    }
+}
+
+#[test]
+fn test_unix_stream_echo() -> io::Result<()> {
+    use crate::event::{is_readable, is_writable, token};
+    use crate::net::UnixListener;
+    use crate::{Events, Poll};
+    use std::fs;
+    use std::process;
+    use std::time::Duration;
+
+    let path = std::env::temp_dir().join(format!("iocp-wrapper-{}.sock", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = UnixListener::bind(&path)?;
+    poll.registry()
+        .register(&listener, Token(0), Interests::READABLE)?;
+    let mut client = UnixStream::connect(&path)?;
+    poll.registry()
+        .register(&client, Token(1), Interests::WRITABLE)?;
+
+    //Both the pending connection and the connected client
+    let mut ready = (false, false);
+    while ready != (true, true) {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "connection not reported");
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            match token(event) {
+                Token(0) => ready.0 |= is_readable(event),
+                Token(1) => ready.1 |= is_writable(event),
+                other => panic!("unexpected token {:?}", other),
+            }
+        }
+    }
+    let mut server = listener.accept()?;
+    poll.registry()
+        .register(&server, Token(2), Interests::READABLE)?;
+
+    client.write_all(b"hello")?;
+    let mut buf = [0; 5];
+    let mut read = 0;
+    while read < buf.len() {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "data not reported");
+        match server.read(&mut buf[read..]) {
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    assert_eq!(&buf, b"hello");
+
+    drop((client, server, listener));
+    fs::remove_file(&path)
+}
+
+#[test]
+fn test_unix_debug() -> io::Result<()> {
+    use crate::net::UnixListener;
+    use std::fs;
+    use std::process;
+
+    let path = std::env::temp_dir().join(format!("iocp-wrapper-debug-{}.sock", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)?;
+    let client = UnixStream::connect(&path)?;
+
+    let debug = format!("{:?}", listener);
+    assert!(debug.starts_with("UnixListener { "), "{}", debug);
+    assert!(debug.contains(&format!("local: {:?}", path)), "{}", debug);
+    assert!(
+        debug.contains(&format!("socket: {}", listener.as_raw_socket())),
+        "{}",
+        debug
+    );
+
+    //The client side is never bound to a path
+    let debug = format!("{:?}", client);
+    assert!(debug.contains("local: (unnamed)"), "{}", debug);
+    assert!(
+        debug.contains(&format!("socket: {}", client.as_raw_socket())),
+        "{}",
+        debug
+    );
+
+    drop((client, listener));
+    fs::remove_file(&path)
+}
@@ -0,0 +1,530 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event::{self, Event};
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::readiness::Readiness;
+use crate::sys::Selector;
+use crate::token::Token;
+use miow::iocp::CompletionStatus;
+use miow::Overlapped;
+use ntapi::ntrtl::RtlNtStatusToDosError;
+use std::ffi::OsString;
+use std::fs::File;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+use std::sync::{Arc, Mutex};
+use std::{fmt, io, mem, ops};
+use winapi::shared::ntdef::NTSTATUS;
+use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::ioapiset::CancelIoEx;
+use winapi::um::minwinbase::OVERLAPPED;
+use winapi::um::winbase::{
+    ReadDirectoryChangesW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OVERLAPPED,
+};
+use winapi::um::winnt::{
+    FILE_ACTION_ADDED, FILE_ACTION_MODIFIED, FILE_ACTION_REMOVED, FILE_ACTION_RENAMED_NEW_NAME,
+    FILE_ACTION_RENAMED_OLD_NAME, FILE_LIST_DIRECTORY, FILE_NOTIFY_CHANGE_ATTRIBUTES,
+    FILE_NOTIFY_CHANGE_CREATION, FILE_NOTIFY_CHANGE_DIR_NAME, FILE_NOTIFY_CHANGE_FILE_NAME,
+    FILE_NOTIFY_CHANGE_LAST_WRITE, FILE_NOTIFY_CHANGE_SECURITY, FILE_NOTIFY_CHANGE_SIZE,
+    FILE_NOTIFY_INFORMATION, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, HANDLE,
+};
+
+//Completion key of directory watchers, see `ACCEPT_KEY`
+pub(crate) const WATCH_KEY: usize = 5;
+
+//Size of the change buffer, in u64s to keep the records aligned. Changes
+//past it are lost, reported as a rescan.
+const BUFFER_LEN: usize = 8 * 1024;
+
+/// What changed in a watched directory, see [`DirectoryWatcher`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Added,
+    Removed,
+    Modified,
+    /// The old name of a renamed entry, followed by `RenamedTo`.
+    RenamedFrom,
+    RenamedTo,
+    /// Changes were lost, the buffer having overflowed: the directory has to
+    /// be looked at again. Comes with an empty path.
+    Rescan,
+}
+
+/// The kinds of changes a [`DirectoryWatcher`] reports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WatchFilter(u32);
+
+impl WatchFilter {
+    /// Files created, removed or renamed.
+    pub const FILE_NAME: WatchFilter = WatchFilter(FILE_NOTIFY_CHANGE_FILE_NAME);
+    /// Directories created, removed or renamed.
+    pub const DIR_NAME: WatchFilter = WatchFilter(FILE_NOTIFY_CHANGE_DIR_NAME);
+    pub const ATTRIBUTES: WatchFilter = WatchFilter(FILE_NOTIFY_CHANGE_ATTRIBUTES);
+    pub const SIZE: WatchFilter = WatchFilter(FILE_NOTIFY_CHANGE_SIZE);
+    pub const LAST_WRITE: WatchFilter = WatchFilter(FILE_NOTIFY_CHANGE_LAST_WRITE);
+    pub const CREATION: WatchFilter = WatchFilter(FILE_NOTIFY_CHANGE_CREATION);
+    pub const SECURITY: WatchFilter = WatchFilter(FILE_NOTIFY_CHANGE_SECURITY);
+}
+
+impl ops::BitOr for WatchFilter {
+    type Output = WatchFilter;
+
+    fn bitor(self, other: WatchFilter) -> WatchFilter {
+        WatchFilter(self.0 | other.0)
+    }
+}
+
+/// Watches a directory for changes.
+///
+/// Like [`NamedPipe`], the watcher runs an overlapped operation: a
+/// `ReadDirectoryChangesW` is kept in flight while registered. Each time it
+/// completes a readable event follows, `take_changes` hands out what it
+/// reported, the paths being relative to the watched directory.
+///
+/// [`NamedPipe`]: crate::net::NamedPipe
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::net::{DirectoryWatcher, WatchFilter};
+/// use iocp_wrapper::{Events, Interests, Poll, Token};
+///
+/// let mut poll = Poll::new()?;
+/// let watcher = DirectoryWatcher::new(r"C:\logs", true, WatchFilter::FILE_NAME)?;
+/// poll.registry()
+///     .register(&watcher, Token(0), Interests::READABLE)?;
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// for (action, path) in watcher.take_changes() {
+///     println!("{:?} {}", action, path.display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct DirectoryWatcher {
+    inner: Arc<Inner>,
+}
+
+//Shared with the read in flight, which keeps it alive
+struct Inner {
+    dir: File,
+    recursive: bool,
+    filter: WatchFilter,
+    io: Mutex<Io>,
+}
+
+struct Io {
+    //set on the first registration, the handle can't leave its port
+    selector: Option<Selector>,
+    registration: Option<Token>,
+    pending: bool,
+    changes: Vec<(Action, PathBuf)>,
+    error: Option<io::Error>,
+    //select() call and index of the last event reported, like `Report`
+    report: Option<(usize, usize)>,
+}
+
+//Like `AcceptOp`: owned by the kernel until its completion is taken from the
+//port, leaked if the port is closed first.
+#[repr(C)]
+struct WatchOp {
+    overlapped: OVERLAPPED,
+    //no I/O, posted to report what the state already has
+    wake: bool,
+    buf: Vec<u64>,
+    inner: Arc<Inner>,
+}
+
+impl WatchOp {
+    fn new(inner: &Arc<Inner>, wake: bool) -> *mut WatchOp {
+        let buf = if wake {
+            Vec::new()
+        } else {
+            vec![0; BUFFER_LEN]
+        };
+        Box::into_raw(Box::new(WatchOp {
+            overlapped: unsafe { mem::zeroed() },
+            wake,
+            buf,
+            inner: inner.clone(),
+        }))
+    }
+}
+
+impl DirectoryWatcher {
+    /// Opens the directory at `path`, watched with its subdirectories if
+    /// `recursive`. Nothing is watched before the watcher is registered.
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        recursive: bool,
+        filter: WatchFilter,
+    ) -> io::Result<DirectoryWatcher> {
+        let path: Vec<u16> = path
+            .as_ref()
+            .as_os_str()
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+        let handle = unsafe {
+            CreateFileW(
+                path.as_ptr(),
+                FILE_LIST_DIRECTORY,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                null_mut(),
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OVERLAPPED,
+                null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(DirectoryWatcher {
+            inner: Arc::new(Inner {
+                dir: unsafe { File::from_raw_handle(handle as RawHandle) },
+                recursive,
+                filter,
+                io: Mutex::new(Io {
+                    selector: None,
+                    registration: None,
+                    pending: false,
+                    changes: Vec::new(),
+                    error: None,
+                    report: None,
+                }),
+            }),
+        })
+    }
+
+    /// Hands out the changes reported so far, oldest first.
+    pub fn take_changes(&self) -> Vec<(Action, PathBuf)> {
+        mem::take(&mut self.inner.io.lock().unwrap().changes)
+    }
+
+    /// Why watching stopped, if it did. Taking the error resumes it, what
+    /// changed in between is lost.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        let mut io = self.inner.io.lock().unwrap();
+        let error = io.error.take();
+        start_read(&self.inner, &mut io);
+        Ok(error)
+    }
+}
+
+//Keeps one read in flight while registered
+fn start_read(inner: &Arc<Inner>, io: &mut Io) {
+    if io.selector.is_none() || io.registration.is_none() || io.pending || io.error.is_some() {
+        return;
+    }
+
+    let op = WatchOp::new(inner, false);
+    let r = unsafe {
+        ReadDirectoryChangesW(
+            inner.dir.as_raw_handle() as HANDLE,
+            (*op).buf.as_mut_ptr() as *mut _,
+            (BUFFER_LEN * mem::size_of::<u64>()) as u32,
+            inner.recursive as i32,
+            inner.filter.0,
+            null_mut(),
+            &mut (*op).overlapped,
+            None,
+        )
+    };
+    if r == 0 {
+        drop(unsafe { Box::from_raw(op) });
+        io.error = Some(io::Error::last_os_error());
+        //Nothing completes for it, the failure still has to be reported
+        let _ = wake(inner, io);
+    } else {
+        io.pending = true;
+    }
+}
+
+fn wake(inner: &Arc<Inner>, io: &Io) -> io::Result<()> {
+    let selector = match io.selector {
+        Some(ref selector) => selector,
+        None => return Ok(()),
+    };
+
+    let op = WatchOp::new(inner, true);
+    let status = CompletionStatus::new(0, WATCH_KEY, op as *mut Overlapped);
+    if let Err(e) = selector.port().post(status) {
+        drop(unsafe { Box::from_raw(op) });
+        return Err(e);
+    }
+    Ok(())
+}
+
+//Parses the FILE_NOTIFY_INFORMATION records the read got
+fn parse(buf: &[u64], len: usize, changes: &mut Vec<(Action, PathBuf)>) {
+    let base = buf.as_ptr() as *const u8;
+    let mut offset = 0;
+    loop {
+        let record = unsafe { &*(base.add(offset) as *const FILE_NOTIFY_INFORMATION) };
+        let name_len = record.FileNameLength as usize / 2;
+        let name = unsafe { std::slice::from_raw_parts(record.FileName.as_ptr(), name_len) };
+        let action = match record.Action {
+            FILE_ACTION_ADDED => Some(Action::Added),
+            FILE_ACTION_REMOVED => Some(Action::Removed),
+            FILE_ACTION_MODIFIED => Some(Action::Modified),
+            FILE_ACTION_RENAMED_OLD_NAME => Some(Action::RenamedFrom),
+            FILE_ACTION_RENAMED_NEW_NAME => Some(Action::RenamedTo),
+            _ => None,
+        };
+        if let Some(action) = action {
+            changes.push((action, PathBuf::from(OsString::from_wide(name))));
+        }
+
+        if record.NextEntryOffset == 0 {
+            break;
+        }
+        offset += record.NextEntryOffset as usize;
+        if offset >= len {
+            break;
+        }
+    }
+}
+
+impl Io {
+    //Like `SockState::report`, one event per select() call
+    fn report(&mut self, seq: usize, events: &mut Vec<Event>) {
+        let token = match self.registration {
+            Some(token) => token,
+            None => return,
+        };
+        let readiness = match self.error {
+            Some(_) => Readiness::READABLE | Readiness::ERROR,
+            None if !self.changes.is_empty() => Readiness::READABLE,
+            None => return,
+        };
+
+        match self.report {
+            Some((last, index)) if last == seq => events[index].add_readiness(readiness),
+            _ => {
+                self.report = Some((seq, events.len()));
+                events.push(Event::new(readiness, token));
+            }
+        }
+    }
+}
+
+//Handles a completion taken from the port with `WATCH_KEY`
+pub(crate) unsafe fn complete(overlapped: *mut OVERLAPPED, seq: usize, events: &mut Vec<Event>) {
+    let op = Box::from_raw(overlapped as *mut WatchOp);
+    let WatchOp {
+        overlapped,
+        wake,
+        buf,
+        inner,
+    } = *op;
+
+    let mut io = inner.io.lock().unwrap();
+    match overlapped.Internal as NTSTATUS {
+        _ if wake => {}
+        status if status < 0 => {
+            let error = RtlNtStatusToDosError(status);
+            io.error = Some(io::Error::from_raw_os_error(error as i32));
+        }
+        //Success with nothing in the buffer: it overflowed
+        _ if overlapped.InternalHigh == 0 => io.changes.push((Action::Rescan, PathBuf::new())),
+        _ => parse(&buf, overlapped.InternalHigh, &mut io.changes),
+    }
+    if !wake {
+        io.pending = false;
+    }
+
+    start_read(&inner, &mut io);
+    io.report(seq, events);
+}
+
+impl fmt::Debug for DirectoryWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let io = self.inner.io.lock().unwrap();
+        f.debug_struct("DirectoryWatcher")
+            .field("handle", &self.inner.dir.as_raw_handle())
+            .field("recursive", &self.inner.recursive)
+            .field("registered", &io.registration.is_some())
+            .finish()
+    }
+}
+
+impl AsRawHandle for DirectoryWatcher {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.inner.dir.as_raw_handle()
+    }
+}
+
+impl event::Source for DirectoryWatcher {
+    fn register(&self, registry: &Registry, token: Token, _interests: Interests) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        if io.registration.is_some() {
+            return Err(misuse(
+                ErrorKind::AlreadyRegistered,
+                Some(token),
+                "directory watcher is already registered",
+            ));
+        }
+
+        let port = registry.selector().port();
+        match io.selector {
+            Some(ref selector) if selector.port().as_raw_handle() != port.as_raw_handle() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "directory watcher is tied to another registry",
+                ));
+            }
+            Some(_) => {}
+            None => {
+                port.add_handle(WATCH_KEY, &self.inner.dir)?;
+                io.selector = Some(registry.selector().clone());
+            }
+        }
+
+        io.registration = Some(token);
+        io.error = None;
+        start_read(&self.inner, &mut io);
+        wake(&self.inner, &io)
+    }
+
+    fn reregister(
+        &self,
+        _registry: &Registry,
+        token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        if io.registration.is_none() {
+            return Err(misuse(
+                ErrorKind::NotRegistered,
+                Some(token),
+                "directory watcher is not registered",
+            ));
+        }
+
+        io.registration = Some(token);
+        wake(&self.inner, &io)
+    }
+
+    //The read in flight goes on, its completion is no longer reported
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        let mut io = self.inner.io.lock().unwrap();
+        if io.registration.take().is_none() {
+            return Err(misuse(
+                ErrorKind::NotRegistered,
+                None,
+                "directory watcher is not registered",
+            ));
+        }
+        Ok(())
+    }
+}
+
+//The directory itself is closed once the cancelled read has completed
+impl Drop for DirectoryWatcher {
+    fn drop(&mut self) {
+        self.inner.io.lock().unwrap().registration = None;
+        unsafe { CancelIoEx(self.inner.dir.as_raw_handle() as HANDLE, null_mut()) };
+    }
+}
+
+#[test]
+fn test_directory_watcher_actions() -> io::Result<()> {
+    use crate::event::{is_readable, token};
+    use crate::{Events, Poll};
+    use std::fs;
+    use std::process;
+    use std::time::{Duration, Instant};
+
+    let dir = std::env::temp_dir().join(format!("iocp-wrapper-watch-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir)?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let watcher = DirectoryWatcher::new(&dir, false, WatchFilter::FILE_NAME)?;
+    poll.registry()
+        .register(&watcher, Token(2), Interests::READABLE)?;
+    //Nothing changed yet
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    fs::write(dir.join("a.txt"), b"a")?;
+    fs::rename(dir.join("a.txt"), dir.join("b.txt"))?;
+    fs::remove_file(dir.join("b.txt"))?;
+
+    let expected = vec![
+        (Action::Added, PathBuf::from("a.txt")),
+        (Action::RenamedFrom, PathBuf::from("a.txt")),
+        (Action::RenamedTo, PathBuf::from("b.txt")),
+        (Action::Removed, PathBuf::from("b.txt")),
+    ];
+    let mut changes = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while changes.len() < expected.len() {
+        assert!(Instant::now() < deadline, "{:?}", changes);
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            assert_eq!(token(event), Token(2));
+            assert!(is_readable(event));
+        }
+        changes.extend(watcher.take_changes());
+    }
+    assert_eq!(changes, expected);
+    assert!(watcher.take_error()?.is_none());
+
+    //The read is posted again after each completion
+    fs::write(dir.join("c.txt"), b"c")?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        watcher.take_changes(),
+        [(Action::Added, PathBuf::from("c.txt"))]
+    );
+
+    drop(watcher);
+    fs::remove_dir_all(&dir)
+}
+
+#[test]
+fn test_parse_notify_records() {
+    //Laid out like the kernel does: three u32s, the name, padding to 8 bytes
+    fn record(buf: &mut Vec<u8>, action: u32, name: &str, last: bool) {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let len = (12 + name.len() * 2 + 7) / 8 * 8;
+        let next = if last { 0 } else { len as u32 };
+        buf.extend_from_slice(&next.to_le_bytes());
+        buf.extend_from_slice(&action.to_le_bytes());
+        buf.extend_from_slice(&(name.len() as u32 * 2).to_le_bytes());
+        for unit in name {
+            buf.extend_from_slice(&unit.to_le_bytes());
+        }
+        buf.resize((buf.len() + 7) / 8 * 8, 0);
+    }
+
+    let mut bytes = Vec::new();
+    record(&mut bytes, FILE_ACTION_ADDED, "new.txt", false);
+    record(&mut bytes, FILE_ACTION_RENAMED_OLD_NAME, r"sub\old", true);
+    let mut buf = vec![0u64; BUFFER_LEN];
+    for (i, chunk) in bytes.chunks(8).enumerate() {
+        let mut word = [0; 8];
+        word.copy_from_slice(chunk);
+        buf[i] = u64::from_le_bytes(word);
+    }
+
+    let mut changes = Vec::new();
+    parse(&buf, bytes.len(), &mut changes);
+    assert_eq!(
+        changes,
+        [
+            (Action::Added, PathBuf::from("new.txt")),
+            (Action::RenamedFrom, PathBuf::from(r"sub\old")),
+        ]
+    );
+}
@@ -0,0 +1,343 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event;
+use crate::interests::Interests;
+#[cfg(feature = "os-poll")]
+use crate::sys::SelectorConfig;
+use crate::sys::{self, raw_source, AsRawSource, Events, Selector};
+use crate::token::Token;
+use std::io;
+use std::time::Duration;
+
+/// Polls registered sockets for readiness events.
+///
+/// On Windows, sockets are polled through the AFD device. Where opening it is
+/// denied, as in AppContainer sandboxes, registering fails with the system's
+/// error, carried by a `SocketError`; with the `wsapoll` feature a helper
+/// thread polls them with `WSAPoll()` instead, events being the same. Before
+/// Windows 10 2004, `WSAPoll()` may not report a failed connect.
+///
+/// On Unix, file descriptors are polled with epoll on Linux and kqueue on
+/// macOS, iOS, FreeBSD and DragonFly. Events are those of Windows: only what
+/// the interests ask for is reported, along with errors and hang-ups,
+/// readiness is level-triggered and writable readiness is cached until
+/// [`Registry::clear_writable`]. Each descriptor makes at most one event per
+/// call to [`Poll::poll`].
+///
+/// Elsewhere, as on wasm32, and on Unix with the `shell` feature, the crate
+/// still builds but there is nothing to poll with: [`Poll::new`] fails with
+/// `Unsupported`. Without the `os-poll` feature there is no way to create a
+/// `Poll` at all: [`Registry`] and the rest are left for [`event::Source`]
+/// implementations to name.
+///
+/// With the `trace` feature, every step of the poller's work is logged
+/// through the `log` crate, under the module it happens in: registrations,
+/// deregistrations and those left when the `Poll` is dropped at debug level;
+/// kernel polls submitted and cancelled, completions with their raw AFD flags
+/// and the readiness made of them, freed registrations and each call to
+/// [`Poll::poll`] at trace level. Steps of a socket start with `token=N`.
+/// There are no kernel polls to follow with epoll or kqueue.
+pub struct Poll {
+    registry: Registry,
+}
+
+/// Registers sockets with a [`Poll`].
+pub struct Registry {
+    selector: Selector,
+}
+
+impl Poll {
+    /// Creates a `Poll` with the default configuration, see [`PollBuilder`].
+    #[cfg(feature = "os-poll")]
+    pub fn new() -> io::Result<Poll> {
+        PollBuilder::new().build()
+    }
+
+    /// Returns a builder to configure a `Poll` before creating it.
+    #[cfg(feature = "os-poll")]
+    pub fn builder() -> PollBuilder {
+        PollBuilder::new()
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Waits for readiness events, blocking at most `timeout`.
+    ///
+    /// A zero timeout never blocks. On Windows it takes every event already
+    /// queued up to the capacity of `events`, not just the first batch.
+    ///
+    /// A timeout with a fraction of a millisecond is kept to 100ns on Windows
+    /// rather than rounded down, though the wait still ends on a tick of the
+    /// system timer. With epoll it is rounded up to the next millisecond, and
+    /// a signal ends the wait early, with no events.
+    pub fn poll(&mut self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        self.registry.selector.select(events, timeout)
+    }
+
+    /// Returns how many sockets are registered right now, wakers aside.
+    ///
+    /// Sockets still registered when the `Poll` is dropped are deregistered
+    /// then, which is usually a teardown missing a step: checking this is
+    /// zero right before the drop finds those. With the `trace` feature,
+    /// the drop also logs each of them.
+    ///
+    /// On Unix, a descriptor closed while registered is still counted: the
+    /// kernel forgets about it, this `Poll` only once it is deregistered or
+    /// its number is registered again.
+    pub fn registered_count(&self) -> usize {
+        self.registry.selector.registered_count()
+    }
+}
+
+//The kernel writes to a registration's memory until its poll completed, so
+//the polls are cancelled and waited for, not just dropped
+impl Drop for Poll {
+    fn drop(&mut self) {
+        self.registry.selector.close();
+    }
+}
+
+impl Registry {
+    /// Registers `source` for `interests`, events carry `token`.
+    ///
+    /// Events only carry the readiness `interests` ask for, whatever the kernel
+    /// reports. The exception are errors and hang-ups, which are always
+    /// delivered: they affect every direction. A failed connect is reported as
+    /// both error and writable, and as a hang-up too on Unix.
+    ///
+    /// Writable readiness is cached: once a writable event has been delivered
+    /// the socket is not polled for writability again, and no further writable
+    /// event is reported, until [`Registry::clear_writable`] is called or
+    /// writable interest is dropped and then asked for again.
+    pub fn register<S>(&self, source: &S, token: Token, interests: Interests) -> io::Result<()>
+    where
+        S: event::Source + ?Sized,
+    {
+        source.register(self, token, interests)
+    }
+
+    /// Like [`Registry::register`], but the returned guard deregisters `sock`
+    /// when dropped.
+    ///
+    /// The guard doesn't borrow the `Registry`: it can be stored next to the
+    /// socket and moved to other threads.
+    pub fn register_guarded<S>(
+        &self,
+        sock: &S,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<Registration>
+    where
+        S: AsRawSource + ?Sized,
+    {
+        let raw = raw_source(sock);
+        self.selector
+            .register(raw, token, interests)
+            .map(|key| Registration {
+                selector: Some(self.selector.clone()),
+                raw,
+                key,
+            })
+    }
+
+    /// Changes the token and interests of a registered socket.
+    ///
+    /// This is how to narrow a live registration, e.g. with
+    /// [`Interests::remove`]. Events for the removed interests stop right
+    /// away, even if the kernel already reported them.
+    pub fn reregister<S>(&self, source: &S, token: Token, interests: Interests) -> io::Result<()>
+    where
+        S: event::Source + ?Sized,
+    {
+        source.reregister(self, token, interests)
+    }
+
+    /// Adds `interests` to those `sock` is registered for, and sets `token`.
+    ///
+    /// Nothing is resubmitted to the kernel if `interests` were already there.
+    pub fn add_interests<S>(&self, sock: &S, token: Token, interests: Interests) -> io::Result<()>
+    where
+        S: AsRawSource + ?Sized,
+    {
+        self.selector
+            .modify_interests(raw_source(sock), token, |current| {
+                Ok(current.map_or(interests, |current| current | interests))
+            })
+    }
+
+    /// Removes `interests` from those `sock` is registered for, and sets
+    /// `token`.
+    ///
+    /// Fails with `InvalidInput` if nothing would be left: use
+    /// [`Registry::deregister`] for that.
+    pub fn remove_interests<S>(
+        &self,
+        sock: &S,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()>
+    where
+        S: AsRawSource + ?Sized,
+    {
+        self.selector
+            .modify_interests(raw_source(sock), token, |current| {
+                current
+                    .and_then(|current| current.remove(interests))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "removing every interest, deregister instead",
+                        )
+                    })
+            })
+    }
+
+    /// Stops delivering events for `source`.
+    pub fn deregister<S>(&self, source: &S) -> io::Result<()>
+    where
+        S: event::Source + ?Sized,
+    {
+        source.deregister(self)
+    }
+
+    /// Drops the cached writable readiness of `sock`.
+    ///
+    /// Call this when a write to `sock` returned `WouldBlock`: the next
+    /// writable event is then delivered once the send buffer has room again.
+    /// Writes through [`IoSource::do_io`], and so through the crate's own
+    /// sockets, take care of it.
+    ///
+    /// [`IoSource::do_io`]: crate::IoSource::do_io
+    pub fn clear_writable<S>(&self, sock: &S) -> io::Result<()>
+    where
+        S: AsRawSource + ?Sized,
+    {
+        self.selector.clear_writable(raw_source(sock))
+    }
+
+    //For `event::Source` implementations
+    pub(crate) fn selector(&self) -> &Selector {
+        &self.selector
+    }
+
+    //Another handle on the same poller, for the reactor of `Async` to keep
+    //while its thread has the `Poll`, and for `mio_compat` next to its own
+    #[cfg(any(feature = "async-io", feature = "mio-compat"))]
+    pub(crate) fn duplicate(&self) -> Registry {
+        Registry {
+            selector: self.selector.clone(),
+        }
+    }
+}
+
+/// A registration that ends when dropped, see [`Registry::register_guarded`].
+///
+/// Deregistering on drop is best-effort, errors are ignored: use
+/// [`Registration::deregister`] to see them. Drop the guard before closing the
+/// socket, a guard only ever touches the registration it was created for.
+///
+/// A guard outliving its [`Poll`] is left with nothing to do: its calls fail
+/// with [`ErrorKind::PollDropped`](crate::ErrorKind::PollDropped).
+pub struct Registration {
+    //None once forgotten or deregistered
+    selector: Option<Selector>,
+    raw: sys::RawSource,
+    //tells this registration apart from later ones of the same handle value
+    key: sys::Key,
+}
+
+impl Registration {
+    /// Changes the token and interests of the registration.
+    pub fn reregister(&self, token: Token, interests: Interests) -> io::Result<()> {
+        match self.selector {
+            Some(ref selector) => selector.reregister(self.raw, Some(self.key), token, interests),
+            None => Err(misuse(
+                ErrorKind::NotRegistered,
+                Some(token),
+                "socket is not registered",
+            )),
+        }
+    }
+
+    /// Deregisters the socket now, reporting errors.
+    pub fn deregister(mut self) -> io::Result<()> {
+        match self.selector.take() {
+            Some(selector) => selector.deregister(self.raw, Some(self.key)),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the guard and leaves the socket registered.
+    ///
+    /// It then has to be deregistered through [`Registry::deregister`].
+    pub fn forget(mut self) {
+        self.selector = None;
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(selector) = self.selector.take() {
+            let _ = selector.deregister(self.raw, Some(self.key));
+        }
+    }
+}
+
+/// Configures and creates a [`Poll`].
+#[cfg(feature = "os-poll")]
+pub struct PollBuilder {
+    //backends may have settings of their own
+    pub(crate) config: SelectorConfig,
+}
+
+#[cfg(feature = "os-poll")]
+impl PollBuilder {
+    pub fn new() -> PollBuilder {
+        PollBuilder {
+            config: SelectorConfig::default(),
+        }
+    }
+
+    /// Sets how many events a single socket may contribute to one call to
+    /// [`Poll::poll`], at least 1.
+    ///
+    /// The default is 1: everything a socket reports during a call is merged
+    /// into a single event, readiness flags OR-ed together. Raising it keeps
+    /// separate completions as separate events, for users who want raw
+    /// fidelity. Readiness beyond the cap is still merged, never dropped.
+    ///
+    /// With epoll and kqueue each descriptor makes one event per call anyway,
+    /// at least 1 is all that's checked.
+    pub fn max_events_per_socket(mut self, max: usize) -> PollBuilder {
+        assert!(max > 0, "a socket must be able to report at least one event");
+        self.config.set_max_events_per_socket(max);
+        self
+    }
+
+    /// Sets how many parts the registration table is split into, each under
+    /// its own lock, at least 1.
+    ///
+    /// Threads registering, reregistering or deregistering sockets only wait
+    /// for each other when their sockets fall in the same part. The default
+    /// is the number of CPUs. With epoll and kqueue there is a single table,
+    /// at least 1 is all that's checked.
+    pub fn shards(mut self, shards: usize) -> PollBuilder {
+        assert!(shards > 0, "the registration table needs at least one shard");
+        self.config.set_shards(shards);
+        self
+    }
+
+    pub fn build(self) -> io::Result<Poll> {
+        Selector::with_config(self.config).map(|selector| Poll {
+            registry: Registry { selector },
+        })
+    }
+}
+
+#[cfg(feature = "os-poll")]
+impl Default for PollBuilder {
+    fn default() -> PollBuilder {
+        PollBuilder::new()
+    }
+}
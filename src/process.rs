@@ -0,0 +1,185 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::token::Token;
+use crate::wait::ThreadPoolWait;
+use std::io;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, IntoRawHandle, RawHandle};
+use std::process::Child;
+use winapi::shared::minwindef::DWORD;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::GetExitCodeProcess;
+use winapi::um::synchapi::WaitForSingleObject;
+use winapi::um::winbase::WAIT_OBJECT_0;
+use winapi::um::winnt::HANDLE;
+
+/// Reports the exit of a child process as an event.
+///
+/// Registering starts a thread pool wait on the process handle: once the
+/// process exits a readable event with the registration's token follows,
+/// once. A process that already exited is reported right away, and so again
+/// after a `reregister`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::{ChildWatcher, Events, Interests, Poll, Token};
+/// use std::process::Command;
+///
+/// let mut poll = Poll::new()?;
+/// let child = ChildWatcher::new(Command::new("cmd").args(&["/c", "exit 3"]).spawn()?);
+/// poll.registry()
+///     .register(&child, Token(0), Interests::READABLE)?;
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// assert_eq!(child.exit_code()?, Some(3));
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChildWatcher {
+    process: HANDLE,
+    wait: ThreadPoolWait,
+}
+
+//The process handle is only touched through thread safe APIs
+unsafe impl Send for ChildWatcher {}
+unsafe impl Sync for ChildWatcher {}
+
+impl ChildWatcher {
+    /// Watches `child`, which is consumed: take its stdio handles out of it
+    /// first, what's left of them is closed.
+    pub fn new(child: Child) -> ChildWatcher {
+        unsafe { ChildWatcher::from_raw_handle(child.into_raw_handle()) }
+    }
+
+    /// The exit code, `None` while the process is still running.
+    pub fn exit_code(&self) -> io::Result<Option<u32>> {
+        //STILL_ACTIVE is a valid exit code too, only the wait tells
+        if unsafe { WaitForSingleObject(self.process, 0) } != WAIT_OBJECT_0 {
+            return Ok(None);
+        }
+
+        let mut code: DWORD = 0;
+        if unsafe { GetExitCodeProcess(self.process, &mut code) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(code))
+    }
+}
+
+impl AsRawHandle for ChildWatcher {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.process as RawHandle
+    }
+}
+
+/// Takes ownership of a process handle, which needs `SYNCHRONIZE` and
+/// `PROCESS_QUERY_LIMITED_INFORMATION` access.
+impl FromRawHandle for ChildWatcher {
+    unsafe fn from_raw_handle(handle: RawHandle) -> ChildWatcher {
+        ChildWatcher {
+            process: handle as HANDLE,
+            wait: ThreadPoolWait::new(),
+        }
+    }
+}
+
+impl event::Source for ChildWatcher {
+    //A process exits once, the wait doesn't need to outlive that
+    fn register(&self, registry: &Registry, token: Token, _interests: Interests) -> io::Result<()> {
+        self.wait.register(self.process, registry, token, true)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        self.wait.reregister(self.process, registry, token, true)
+    }
+
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        self.wait.deregister()
+    }
+}
+
+impl Drop for ChildWatcher {
+    fn drop(&mut self) {
+        self.wait.stop();
+        unsafe { CloseHandle(self.process) };
+    }
+}
+
+#[test]
+fn test_child_watcher_exit_code() -> io::Result<()> {
+    use crate::event::{is_readable, token};
+    use crate::{Events, Poll};
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let child = ChildWatcher::new(Command::new("cmd").args(&["/c", "exit 3"]).spawn()?);
+    poll.registry()
+        .register(&child, Token(3), Interests::READABLE)?;
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while events.is_empty() {
+        assert!(Instant::now() < deadline, "exit not reported");
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    }
+    assert_eq!(events.len(), 1);
+    assert_eq!(token(events.get(0).unwrap()), Token(3));
+    assert!(is_readable(events.get(0).unwrap()));
+    assert_eq!(child.exit_code()?, Some(3));
+
+    //Reported once: the wait is done
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    //An exited process is reported again right away, with the new token
+    poll.registry()
+        .reregister(&child, Token(4), Interests::READABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(token(events.get(0).unwrap()), Token(4));
+
+    poll.registry().deregister(&child)?;
+    assert_eq!(
+        poll.registry().deregister(&child).unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_child_watcher_deregister_running() -> io::Result<()> {
+    use crate::{Events, Poll};
+    use std::process::Command;
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let child = Command::new("cmd")
+        .args(&["/c", "ping -n 3 127.0.0.1 >NUL"])
+        .spawn()?;
+    let child = ChildWatcher::new(child);
+    assert_eq!(child.exit_code()?, None);
+    poll.registry()
+        .register(&child, Token(0), Interests::READABLE)?;
+    poll.registry().deregister(&child)?;
+
+    //The wait is gone, the exit goes unreported
+    for _ in 0..30 {
+        poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+        assert!(events.is_empty());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use std::{fmt, ops};
+
+use crate::interests::Interests;
+#[cfg(any(windows, target_os = "linux", target_os = "android"))]
+use crate::{
+    EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLPRI, EPOLLRDBAND, EPOLLRDHUP, EPOLLRDNORM,
+    EPOLLWRBAND, EPOLLWRNORM,
+};
+
+/// What happened to a socket, as reported by an event.
+///
+/// Unlike [`Interests`], which is what a registration asks for, a readiness
+/// set may be empty, or only hold conditions that can't be asked for such as
+/// an error or a hang-up.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Readiness(u8);
+
+const EMPTY: u8 = 0b0_000_000;
+const READABLE: u8 = 0b0_000_001;
+const WRITABLE: u8 = 0b0_000_010;
+// The following are not available on all platforms.
+const ERROR: u8 = 0b0_000_100;
+const HUP: u8 = 0b0_001_000;
+const PRIORITY: u8 = 0b0_010_000;
+const AIO: u8 = 0b0_100_000;
+const LIO: u8 = 0b1_000_000;
+const READ_CLOSED: u8 = 0b10_000_000;
+
+impl Readiness {
+    /// Returns an empty `Readiness` set.
+    pub const EMPTY: Readiness = Readiness(EMPTY);
+
+    /// Returns a `Readiness` set representing readable readiness.
+    pub const READABLE: Readiness = Readiness(READABLE);
+
+    /// Returns a `Readiness` set representing writable readiness.
+    pub const WRITABLE: Readiness = Readiness(WRITABLE);
+
+    /// Returns a `Readiness` set representing error readiness.
+    #[cfg(any(unix, windows))]
+    pub const ERROR: Readiness = Readiness(ERROR);
+
+    /// Returns a `Readiness` set representing HUP readiness.
+    #[cfg(any(unix, windows))]
+    pub const HUP: Readiness = Readiness(HUP);
+
+    /// Returns a `Readiness` set representing priority readiness.
+    #[cfg(any(
+        windows,
+        target_os = "linux",
+        target_os = "android",
+        target_os = "solaris"
+    ))]
+    pub const PRIORITY: Readiness = Readiness(PRIORITY);
+
+    /// Returns a `Readiness` set representing read closed readiness.
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    pub const READ_CLOSED: Readiness = Readiness(READ_CLOSED);
+
+    /// Returns a `Readiness` set representing AIO completion readiness.
+    #[cfg(any(
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "macos"
+    ))]
+    pub const AIO: Readiness = Readiness(AIO);
+
+    /// Returns a `Readiness` set representing LIO completion readiness.
+    #[cfg(target_os = "freebsd")]
+    pub const LIO: Readiness = Readiness(LIO);
+
+    /// Returns true if the `Readiness` set is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0 == EMPTY
+    }
+
+    /// Returns true if the `Readiness` set contains readable readiness.
+    #[inline]
+    pub fn is_readable(&self) -> bool {
+        self.contains(Readiness::READABLE)
+    }
+
+    /// Returns true if the `Readiness` set contains writable readiness.
+    #[inline]
+    pub fn is_writable(&self) -> bool {
+        self.contains(Readiness::WRITABLE)
+    }
+
+    /// Returns true if the `Readiness` set contains error readiness.
+    ///
+    /// Error events occur when the socket enters an error state. In this case,
+    /// the socket will also receive a readable or writable event. Reading or
+    /// writing to the socket will result in an error.
+    ///
+    /// # Notes
+    ///
+    /// Method is available on all platforms, but not all platforms (can) use
+    /// this indicator.
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.contains(Readiness(ERROR))
+    }
+
+    /// Returns true if the `Readiness` set contains HUP readiness.
+    ///
+    /// HUP events occur when the remote end of a socket hangs up. In the TCP
+    /// case, this occurs when the remote end of a TCP socket shuts down writes.
+    ///
+    /// It is also unclear if HUP readiness will remain in 0.7. See
+    /// [here](https://github.com/tokio-rs/mio/issues/941)
+    ///
+    /// # Notes
+    ///
+    /// Method is available on all platforms, but not all platforms (can) use
+    /// this indicator.
+    #[inline]
+    pub fn is_hup(&self) -> bool {
+        self.contains(Readiness(HUP))
+    }
+
+    /// Returns true if the `Readiness` set contains priority readiness.
+    ///
+    /// # Notes
+    ///
+    /// Method is available on all platforms, but not all platforms (can) use
+    /// this indicator.
+    #[inline]
+    pub fn is_priority(&self) -> bool {
+        self.contains(Readiness(PRIORITY))
+    }
+
+    /// Returns true if the `Readiness` set contains read closed readiness.
+    ///
+    /// Set once the peer shut down its write side, only for registrations
+    /// with read closed interests.
+    #[inline]
+    pub fn is_read_closed(&self) -> bool {
+        self.contains(Readiness(READ_CLOSED))
+    }
+
+    /// Returns true if the `Readiness` set contains AIO readiness.
+    ///
+    /// # Notes
+    ///
+    /// Method is available on all platforms, but not all platforms (can) use
+    /// this indicator.
+    #[inline]
+    pub fn is_aio(&self) -> bool {
+        self.contains(Readiness(AIO))
+    }
+
+    /// Returns true if the `Readiness` set contains LIO readiness.
+    ///
+    /// # Notes
+    ///
+    /// Method is available on all platforms, but not all platforms (can) use
+    /// this indicator.
+    #[inline]
+    pub fn is_lio(&self) -> bool {
+        self.contains(Readiness(LIO))
+    }
+
+    /// Returns true if `self` is a superset of `other`.
+    ///
+    /// The `other` set may represent more than one readiness operations, in
+    /// which case the function only returns true if `self` contains **all**
+    /// readiness specified in `other`.
+    #[inline]
+    pub fn contains(&self, other: Readiness) -> bool {
+        (self.0 & other.0) == other.0
+    }
+
+    /// Returns true if `self` holds any readiness `interests` ask for.
+    ///
+    /// Errors and hang-ups are never asked for, they don't count here.
+    #[inline]
+    pub fn intersects(&self, interests: Interests) -> bool {
+        !(*self & Readiness::from(interests)).is_empty()
+    }
+
+    /// Translates `EPOLL*` flags, as derived from AFD poll events.
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    pub(crate) fn from_epoll_events(epoll_events: u32) -> Readiness {
+        let mut ready = EMPTY;
+
+        if epoll_events & (EPOLLIN | EPOLLRDNORM) != 0 {
+            ready |= READABLE;
+        }
+        if epoll_events & (EPOLLOUT | EPOLLWRNORM | EPOLLWRBAND) != 0 {
+            ready |= WRITABLE;
+        }
+        if epoll_events & EPOLLERR != 0 {
+            ready |= ERROR;
+        }
+        if epoll_events & EPOLLHUP != 0 {
+            ready |= HUP;
+        }
+        if epoll_events & EPOLLRDHUP != 0 {
+            ready |= READ_CLOSED;
+        }
+        if epoll_events & (EPOLLPRI | EPOLLRDBAND) != 0 {
+            ready |= PRIORITY;
+        }
+
+        Readiness(ready)
+    }
+}
+
+impl From<Interests> for Readiness {
+    /// The readiness each of `interests` asks for.
+    fn from(interests: Interests) -> Readiness {
+        let mut ready = EMPTY;
+
+        //Through the methods, the constants of some are missing on kqueue
+        for &(asked, readiness) in &[
+            (interests.is_readable(), READABLE),
+            (interests.is_writable(), WRITABLE),
+            (interests.is_priority(), PRIORITY),
+            (interests.is_read_closed(), READ_CLOSED),
+        ] {
+            if asked {
+                ready |= readiness;
+            }
+        }
+
+        Readiness(ready)
+    }
+}
+
+impl ops::BitOr for Readiness {
+    type Output = Readiness;
+
+    #[inline]
+    fn bitor(self, other: Readiness) -> Readiness {
+        Readiness(self.0 | other.0)
+    }
+}
+
+impl ops::BitAnd for Readiness {
+    type Output = Readiness;
+
+    #[inline]
+    fn bitand(self, other: Readiness) -> Readiness {
+        Readiness(self.0 & other.0)
+    }
+}
+
+impl ops::Sub for Readiness {
+    type Output = Readiness;
+
+    #[inline]
+    fn sub(self, other: Readiness) -> Readiness {
+        Readiness(self.0 & !other.0)
+    }
+}
+
+impl fmt::Debug for Readiness {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut one = false;
+        let flags = [
+            (Readiness(READABLE), "Readable"),
+            (Readiness(WRITABLE), "Writable"),
+            (Readiness(ERROR), "Error"),
+            (Readiness(HUP), "Hup"),
+            (Readiness(PRIORITY), "Priority"),
+            (Readiness(AIO), "AIO"),
+            (Readiness(LIO), "LIO"),
+            (Readiness(READ_CLOSED), "ReadClosed"),
+        ];
+
+        for &(flag, msg) in &flags {
+            if self.contains(flag) {
+                if one {
+                    write!(fmt, " | ")?
+                }
+                write!(fmt, "{}", msg)?;
+
+                one = true
+            }
+        }
+
+        if !one {
+            fmt.write_str("(empty)")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_readiness_without_interests() {
+    let error = Readiness::ERROR;
+    assert!(!error.is_empty() && error.is_error());
+    assert!(!error.intersects(Interests::all()));
+
+    let hup = Readiness::HUP;
+    assert!(!hup.is_empty() && hup.is_hup());
+    assert!(!hup.intersects(Interests::all()));
+
+    #[cfg(any(windows, target_os = "linux", target_os = "android"))]
+    {
+        let closed = Readiness::READ_CLOSED | Readiness::HUP;
+        assert!(!closed.intersects(Interests::READABLE));
+        assert!(closed.intersects(Interests::READ_CLOSED));
+        assert!(Readiness::from_epoll_events(0).is_empty());
+    }
+
+    assert_eq!(
+        Readiness::from(Interests::READABLE | Interests::WRITABLE),
+        Readiness::READABLE | Readiness::WRITABLE
+    );
+}
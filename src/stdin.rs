@@ -0,0 +1,400 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event::{self, Event};
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::readiness::Readiness;
+use crate::sys::Selector;
+use crate::token::Token;
+use miow::iocp::CompletionStatus;
+use miow::Overlapped;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::{cmp, fmt, thread};
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::ERROR_BROKEN_PIPE;
+use winapi::um::fileapi::{GetFileType, ReadFile};
+use winapi::um::winbase::FILE_TYPE_DISK;
+use winapi::um::winnt::HANDLE;
+
+//Completion key of stdin, see `ACCEPT_KEY`
+pub(crate) const STDIN_KEY: usize = 4;
+
+//The reader stops reading ahead past this much
+const BUFFER_SIZE: usize = 64 * 1024;
+
+//Stdin is one per process, and so is its reader
+lazy_static! {
+    static ref SHARED: Shared = Shared {
+        state: Mutex::new(State {
+            data: VecDeque::new(),
+            closed: None,
+            registration: None,
+            generation: 0,
+            reader: false,
+        }),
+        cond: Condvar::new(),
+    };
+}
+
+static NEXT_OWNER: AtomicUsize = AtomicUsize::new(0);
+
+struct Shared {
+    state: Mutex<State>,
+    //signaled when the reader may go on
+    cond: Condvar,
+}
+
+struct State {
+    data: VecDeque<u8>,
+    //set once the reader saw the end of input, or failed
+    closed: Option<io::Result<()>>,
+    registration: Option<Registration>,
+    //numbers registrations, a packet posted for an earlier one is dropped
+    generation: u32,
+    //whether the reader thread was started
+    reader: bool,
+}
+
+struct Registration {
+    selector: Selector,
+    token: Token,
+    //the `Stdin` it was made through
+    owner: usize,
+}
+
+/// The standard input of the process as a non-blocking source.
+///
+/// Consoles and anonymous pipes can't be polled or read overlapped, so a
+/// reader thread blocks on them instead: started with the first registration,
+/// it reads ahead into a buffer shared by all `Stdin`s and posts a readable
+/// event for each chunk it got. A console hands out a line at a time. While
+/// nothing is registered, or the buffer is full, the reader stays parked.
+/// The thread is one per process and never exits before the input ends.
+///
+/// Stdin redirected from a file is read directly, with no thread: it is
+/// readable right after registering, until the end of the file.
+///
+/// After a readable event read until `WouldBlock`, the end of input is a
+/// `read` returning 0 and comes with a `read_closed` event. Don't read
+/// `std::io::stdin()` alongside, what it buffers isn't seen here.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::{Events, Interests, Poll, Stdin, Token};
+/// use std::io::Read;
+///
+/// let mut poll = Poll::new()?;
+/// let mut stdin = Stdin::new();
+/// poll.registry()
+///     .register(&stdin, Token(0), Interests::READABLE)?;
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// let mut line = [0; 256];
+/// let n = stdin.read(&mut line)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Stdin {
+    owner: usize,
+}
+
+impl Stdin {
+    pub fn new() -> Stdin {
+        Stdin {
+            owner: NEXT_OWNER.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Stdin {
+    fn default() -> Stdin {
+        Stdin::new()
+    }
+}
+
+fn handle() -> HANDLE {
+    io::stdin().as_raw_handle() as HANDLE
+}
+
+//Whether reads never block, and are done right in `read`
+fn is_file() -> bool {
+    unsafe { GetFileType(handle()) == FILE_TYPE_DISK }
+}
+
+//A blocking read of stdin, 0 standing for the end of input
+fn read_handle(buf: &mut [u8]) -> io::Result<usize> {
+    let len = cmp::min(buf.len(), DWORD::max_value() as usize) as DWORD;
+    let mut read = 0;
+    let r = unsafe {
+        ReadFile(
+            handle(),
+            buf.as_mut_ptr() as *mut _,
+            len,
+            &mut read,
+            null_mut(),
+        )
+    };
+    if r == 0 {
+        let e = io::Error::last_os_error();
+        //The writing end of a pipe went away
+        if e.raw_os_error() == Some(ERROR_BROKEN_PIPE as i32) {
+            return Ok(0);
+        }
+        return Err(e);
+    }
+    Ok(read as usize)
+}
+
+impl State {
+    //Tells the registration about new data, or the end of it
+    fn notify(&self) {
+        if let Some(ref registration) = self.registration {
+            let status = CompletionStatus::new(
+                self.generation,
+                STDIN_KEY,
+                &*SHARED as *const Shared as *mut Overlapped,
+            );
+            //The port is gone with its poll: nobody left to tell
+            let _ = registration.selector.port().post(status);
+        }
+    }
+}
+
+fn reader() {
+    let mut buf = vec![0; 4096];
+    loop {
+        {
+            let mut state = SHARED.state.lock().unwrap();
+            while state.registration.is_none() || state.data.len() >= BUFFER_SIZE {
+                state = SHARED.cond.wait(state).unwrap();
+            }
+        }
+
+        let r = read_handle(&mut buf);
+        let mut state = SHARED.state.lock().unwrap();
+        match r {
+            Ok(0) => state.closed = Some(Ok(())),
+            Ok(n) => state.data.extend(&buf[..n]),
+            Err(e) => state.closed = Some(Err(e)),
+        }
+        state.notify();
+        if state.closed.is_some() {
+            return;
+        }
+    }
+}
+
+//Handles a completion taken from the port with `STDIN_KEY`
+pub(crate) fn complete(status: &CompletionStatus, events: &mut Vec<Event>) {
+    let state = SHARED.state.lock().unwrap();
+    let registration = match state.registration {
+        Some(ref registration) if state.generation == status.bytes_transferred() => registration,
+        _ => return,
+    };
+
+    let mut readiness = Readiness::READABLE;
+    if state.closed.is_some() {
+        readiness = readiness | Readiness::READ_CLOSED;
+    }
+    events.push(Event::new(readiness, registration.token));
+}
+
+impl Read for Stdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl<'a> Read for &'a Stdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if is_file() {
+            return read_handle(buf);
+        }
+
+        let mut state = SHARED.state.lock().unwrap();
+        if state.data.is_empty() {
+            return match state.closed {
+                None => Err(io::ErrorKind::WouldBlock.into()),
+                Some(Ok(())) => Ok(0),
+                Some(Err(ref e)) => Err(io::Error::new(e.kind(), e.to_string())),
+            };
+        }
+
+        let n = cmp::min(buf.len(), state.data.len());
+        for (dst, src) in buf.iter_mut().zip(state.data.drain(..n)) {
+            *dst = src;
+        }
+        if state.data.len() < BUFFER_SIZE {
+            SHARED.cond.notify_one();
+        }
+        Ok(n)
+    }
+}
+
+impl event::Source for Stdin {
+    fn register(&self, registry: &Registry, token: Token, _interests: Interests) -> io::Result<()> {
+        let mut state = SHARED.state.lock().unwrap();
+        if state.registration.is_some() {
+            return Err(misuse(
+                ErrorKind::AlreadyRegistered,
+                Some(token),
+                "stdin is already registered",
+            ));
+        }
+        self.start(&mut state, registry, token)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        let mut state = SHARED.state.lock().unwrap();
+        match state.registration {
+            Some(ref registration) if registration.owner == self.owner => {}
+            _ => return Err(not_registered(Some(token))),
+        }
+        self.start(&mut state, registry, token)
+    }
+
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        let mut state = SHARED.state.lock().unwrap();
+        match state.registration {
+            Some(ref registration) if registration.owner == self.owner => {}
+            _ => return Err(not_registered(None)),
+        }
+        //Parks the reader once it got its current read
+        state.registration = None;
+        Ok(())
+    }
+}
+
+impl Stdin {
+    fn start(&self, state: &mut State, registry: &Registry, token: Token) -> io::Result<()> {
+        let file = is_file();
+        if !file && !state.reader {
+            thread::Builder::new()
+                .name("iocp-wrapper stdin".to_string())
+                .spawn(reader)?;
+            state.reader = true;
+        }
+
+        state.generation = state.generation.wrapping_add(1);
+        state.registration = Some(Registration {
+            selector: registry.selector().clone(),
+            token,
+            owner: self.owner,
+        });
+        //What's already there, or a file, is readable right away
+        if file || !state.data.is_empty() || state.closed.is_some() {
+            state.notify();
+        }
+        SHARED.cond.notify_one();
+        Ok(())
+    }
+}
+
+fn not_registered(token: Option<Token>) -> io::Error {
+    misuse(ErrorKind::NotRegistered, token, "stdin is not registered")
+}
+
+impl AsRawHandle for Stdin {
+    fn as_raw_handle(&self) -> RawHandle {
+        handle() as RawHandle
+    }
+}
+
+impl fmt::Debug for Stdin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = SHARED.state.lock().unwrap();
+        f.debug_struct("Stdin")
+            .field("buffered", &state.data.len())
+            .field("closed", &state.closed.is_some())
+            .finish()
+    }
+}
+
+impl Drop for Stdin {
+    fn drop(&mut self) {
+        let mut state = SHARED.state.lock().unwrap();
+        let owned = match state.registration {
+            Some(ref registration) => registration.owner == self.owner,
+            None => false,
+        };
+        if owned {
+            state.registration = None;
+        }
+    }
+}
+
+//Runs in a child with its stdin piped, see `test_stdin_pipe`
+#[test]
+fn test_stdin_pipe_child() -> io::Result<()> {
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    if std::env::var_os("IOCP_WRAPPER_STDIN_CHILD").is_none() {
+        return Ok(());
+    }
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let mut stdin = Stdin::new();
+    poll.registry()
+        .register(&stdin, Token(9), Interests::READABLE)?;
+
+    let mut input = Vec::new();
+    let mut buf = [0; 64];
+    'outer: loop {
+        poll.poll(&mut events, Some(Duration::from_secs(10)))?;
+        assert!(!events.is_empty(), "no stdin event");
+        assert_eq!(event::token(events.get(0).unwrap()), Token(9));
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) => break 'outer,
+                Ok(n) => input.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    println!("stdin: {:?}", String::from_utf8_lossy(&input));
+    Ok(())
+}
+
+#[test]
+fn test_stdin_pipe() -> io::Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(&["--exact", "stdin::test_stdin_pipe_child", "--nocapture"])
+        .env("IOCP_WRAPPER_STDIN_CHILD", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    {
+        let mut input = child.stdin.take().unwrap();
+        input.write_all(b"hello\n")?;
+        //Apart, so the child most likely sees two events
+        thread::sleep(Duration::from_millis(100));
+        input.write_all(b"world\n")?;
+    }
+
+    let mut output = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut output)?;
+    assert!(child.wait()?.success(), "{}", output);
+    assert!(output.contains(r#"stdin: "hello\nworld\n""#), "{}", output);
+
+    Ok(())
+}
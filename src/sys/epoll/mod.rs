@@ -0,0 +1,9 @@
+//The epoll backend of Linux and Android. `sys::unix` has what it shares with
+//the kqueue one.
+pub(crate) mod selector;
+pub(crate) mod waker;
+
+pub(crate) use super::unix::event;
+pub use self::selector::Events;
+pub(crate) use self::selector::{Key, Selector, SelectorConfig};
+pub(crate) use self::waker::Waker;
@@ -0,0 +1,471 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event::Event;
+use crate::interests::Interests;
+use crate::readiness::Readiness;
+use crate::token::Token;
+#[cfg(feature = "trace")]
+use crate::trace::Trace;
+#[cfg(test)]
+use crate::EPOLLERR;
+use crate::{EPOLLIN, EPOLLOUT, EPOLLPRI, EPOLLRDHUP};
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{cmp, fmt, io};
+
+#[derive(Clone, Default)]
+pub(crate) struct SelectorConfig {}
+
+//There is a single table, and one event per descriptor and call anyway
+impl SelectorConfig {
+    pub(crate) fn set_max_events_per_socket(&mut self, _max: usize) {}
+
+    pub(crate) fn set_shards(&mut self, _shards: usize) {}
+}
+
+//Tells a registration apart from later ones of the same descriptor
+pub(crate) type Key = u32;
+
+//What a registered descriptor asked for
+struct FdState {
+    token: Token,
+    interests: Interests,
+    //A writable event was delivered: EPOLLOUT is left out until cleared, as
+    //the AFD backend stops polling for writability
+    known_writable: bool,
+    //The eventfd of a `Waker`, edge-triggered and never narrowed
+    waker: bool,
+    //tells this registration apart from later ones of the same descriptor
+    key: u32,
+}
+
+impl FdState {
+    fn epoll_events(&self) -> u32 {
+        if self.waker {
+            return EPOLLIN | libc::EPOLLET as u32;
+        }
+
+        let mut events = 0;
+        //Level-triggered, as AFD polls are submitted again after each event
+        if self.interests.is_readable() {
+            events |= EPOLLIN | EPOLLRDHUP;
+        }
+        if self.interests.is_writable() && !self.known_writable {
+            events |= EPOLLOUT;
+        }
+        if self.interests.is_priority() {
+            events |= EPOLLPRI;
+        }
+        if self.interests.is_read_closed() {
+            events |= EPOLLRDHUP;
+        }
+        events
+    }
+
+    //Only what the interests ask for is reported, errors and hang-ups always
+    fn readiness(&self, epoll_events: u32) -> Readiness {
+        let reported = Readiness::from(self.interests) | Readiness::ERROR | Readiness::HUP;
+        Readiness::from_epoll_events(epoll_events) & reported
+    }
+
+    //The registration's key rides in the upper half, to drop events of a
+    //descriptor registered again since they were reported
+    fn epoll_data(&self, fd: RawFd) -> u64 {
+        u64::from(self.key) << 32 | u64::from(fd as u32)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Selector {
+    inner: Arc<SelectorInner>,
+}
+
+struct SelectorInner {
+    ep: RawFd,
+    fds: Mutex<HashMap<RawFd, FdState>>,
+    next_key: AtomicU32,
+    //Set once the `Poll` is dropped, see `check_open`
+    closed: AtomicBool,
+}
+
+impl SelectorInner {
+    fn check_open(&self, token: Option<Token>) -> io::Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            Err(misuse(ErrorKind::PollDropped, token, "poll was dropped"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn ctl(&self, op: libc::c_int, fd: RawFd, state: &FdState) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: state.epoll_events(),
+            u64: state.epoll_data(fd),
+        };
+        cvt(unsafe { libc::epoll_ctl(self.ep, op, fd, &mut event) }).map(|_| ())
+    }
+
+    //Tells the kernel what `state` now asks for. A descriptor closed while
+    //registered was dropped by the kernel already, its entry goes too.
+    fn update(&self, fds: &mut HashMap<RawFd, FdState>, fd: RawFd) -> io::Result<()> {
+        let r = match fds.get(&fd) {
+            Some(state) => self.ctl(libc::EPOLL_CTL_MOD, fd, state),
+            None => return Ok(()),
+        };
+        match r {
+            Err(ref e) if is_gone(e) => {
+                let token = fds.remove(&fd).map(|state| state.token);
+                Err(not_registered(token))
+            }
+            r => r,
+        }
+    }
+}
+
+impl Drop for SelectorInner {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.ep) };
+    }
+}
+
+//The descriptor was closed, or its number reused, while registered
+fn is_gone(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENOENT) || e.raw_os_error() == Some(libc::EBADF)
+}
+
+fn not_registered(token: Option<Token>) -> io::Error {
+    misuse(ErrorKind::NotRegistered, token, "socket is not registered")
+}
+
+//The entry of `fd`, if it is the registration `key` is of
+fn find(
+    fds: &mut HashMap<RawFd, FdState>,
+    fd: RawFd,
+    key: Option<u32>,
+    token: Option<Token>,
+) -> io::Result<&mut FdState> {
+    match fds.get_mut(&fd) {
+        Some(state) if key.is_none_or(|key| key == state.key) => Ok(state),
+        _ => Err(not_registered(token)),
+    }
+}
+
+//Bits without a constant on this platform are refused, as on Windows
+fn check_interests(interests: Interests, token: Token) -> io::Result<()> {
+    if interests.is_supported() {
+        Ok(())
+    } else {
+        Err(misuse(
+            ErrorKind::UnsupportedInterests,
+            Some(token),
+            "unsupported interests",
+        ))
+    }
+}
+
+fn cvt(r: libc::c_int) -> io::Result<libc::c_int> {
+    if r < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(r)
+    }
+}
+
+impl Selector {
+    pub(crate) fn with_config(_config: SelectorConfig) -> io::Result<Selector> {
+        let ep = cvt(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        Ok(Selector {
+            inner: Arc::new(SelectorInner {
+                ep,
+                fds: Mutex::new(HashMap::new()),
+                next_key: AtomicU32::new(0),
+                closed: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    //Registers `fd`, returns the key of the registration.
+    pub(crate) fn register(
+        &self,
+        fd: RawFd,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<u32> {
+        self.inner.check_open(Some(token))?;
+        self.add(fd, token, interests, false)
+    }
+
+    //The eventfd of a `Waker`, see `FdState::waker`
+    pub(crate) fn register_waker(&self, fd: RawFd, token: Token) -> io::Result<u32> {
+        self.inner.check_open(Some(token))?;
+        self.add(fd, token, Interests::READABLE, true)
+    }
+
+    fn add(&self, fd: RawFd, token: Token, interests: Interests, waker: bool) -> io::Result<u32> {
+        check_interests(interests, token)?;
+        let mut fds = self.inner.fds.lock().unwrap();
+        let state = FdState {
+            token,
+            interests,
+            known_writable: false,
+            waker,
+            key: self.inner.next_key.fetch_add(1, Ordering::Relaxed),
+        };
+        //The kernel has the last word: an entry left by a descriptor closed
+        //while registered is replaced
+        match self.inner.ctl(libc::EPOLL_CTL_ADD, fd, &state) {
+            Err(ref e) if e.raw_os_error() == Some(libc::EEXIST) => {
+                return Err(misuse(
+                    ErrorKind::AlreadyRegistered,
+                    Some(token),
+                    "socket is already registered",
+                ));
+            }
+            Err(e) => return Err(e),
+            Ok(()) => {}
+        }
+        trace!(Trace::Register { token, interests });
+        let key = state.key;
+        fds.insert(fd, state);
+        Ok(key)
+    }
+
+    //Changes the token and interests of `fd`, registered as `key` if given.
+    //Dropping writable interest forgets that the descriptor is known to be
+    //writable.
+    pub(crate) fn reregister(
+        &self,
+        fd: RawFd,
+        key: Option<u32>,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.inner.check_open(Some(token))?;
+        self.modify(fd, key, token, |_| Ok(interests))
+    }
+
+    pub(crate) fn modify_interests<F>(&self, fd: RawFd, token: Token, f: F) -> io::Result<()>
+    where
+        F: FnOnce(Option<Interests>) -> io::Result<Interests>,
+    {
+        self.inner.check_open(Some(token))?;
+        self.modify(fd, None, token, |current| f(Some(current)))
+    }
+
+    fn modify<F>(&self, fd: RawFd, key: Option<u32>, token: Token, f: F) -> io::Result<()>
+    where
+        F: FnOnce(Interests) -> io::Result<Interests>,
+    {
+        let mut fds = self.inner.fds.lock().unwrap();
+        let state = find(&mut fds, fd, key, Some(token))?;
+        let interests = f(state.interests)?;
+        check_interests(interests, token)?;
+        trace!(Trace::Reregister { token, interests });
+        state.token = token;
+        state.interests = interests;
+        if !interests.is_writable() {
+            state.known_writable = false;
+        }
+        self.inner.update(&mut fds, fd)
+    }
+
+    //Deregisters `fd`, registered as `key` if given.
+    pub(crate) fn deregister(&self, fd: RawFd, key: Option<u32>) -> io::Result<()> {
+        self.inner.check_open(None)?;
+        self.remove(fd, key)
+    }
+
+    //Left to the kernel if the descriptor was closed already
+    pub(crate) fn remove(&self, fd: RawFd, key: Option<u32>) -> io::Result<()> {
+        let mut fds = self.inner.fds.lock().unwrap();
+        find(&mut fds, fd, key, None)?;
+        let _state = fds.remove(&fd).unwrap();
+        trace!(Trace::Deregister {
+            token: _state.token,
+        });
+        let mut event = libc::epoll_event { events: 0, u64: 0 };
+        match cvt(unsafe { libc::epoll_ctl(self.inner.ep, libc::EPOLL_CTL_DEL, fd, &mut event) }) {
+            Err(ref e) if is_gone(e) => Ok(()),
+            r => r.map(|_| ()),
+        }
+    }
+
+    //Polls `fd` for writability again, see `FdState::known_writable`.
+    pub(crate) fn clear_writable(&self, fd: RawFd) -> io::Result<()> {
+        self.inner.check_open(None)?;
+        let mut fds = self.inner.fds.lock().unwrap();
+        let state = find(&mut fds, fd, None, None)?;
+        if !state.known_writable {
+            return Ok(());
+        }
+        state.known_writable = false;
+        self.inner.update(&mut fds, fd)
+    }
+
+    pub(crate) fn select(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        trace!(Trace::PollEnter { timeout });
+        let r = self.select_events(events, timeout);
+        trace!(Trace::PollExit {
+            events: events.len()
+        });
+        r
+    }
+
+    fn select_events(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        events.clear();
+
+        //Rounded up: cut down to whole milliseconds, a wait would end early
+        //and be called again right away
+        let timeout = timeout.map_or(-1, |timeout| {
+            let millis = timeout.as_millis() + (timeout.subsec_nanos() % 1_000_000 != 0) as u128;
+            cmp::min(millis, libc::c_int::MAX as u128) as libc::c_int
+        });
+        let n = unsafe {
+            libc::epoll_wait(
+                self.inner.ep,
+                events.raw.as_mut_ptr(),
+                cmp::max(events.capacity, 1) as libc::c_int,
+                timeout,
+            )
+        };
+        let n = match cvt(n) {
+            Ok(n) => n as usize,
+            //A signal cut the wait short, as a timeout would have
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => 0,
+            Err(e) => return Err(e),
+        };
+        unsafe { events.raw.set_len(n) };
+
+        let mut fds = self.inner.fds.lock().unwrap();
+        for i in 0..n {
+            //Copied out, the struct is packed
+            let (epoll_events, data) = (events.raw[i].events, events.raw[i].u64);
+            let fd = data as u32 as RawFd;
+            let state = match fds.get_mut(&fd) {
+                Some(state) if state.key == (data >> 32) as u32 => state,
+                //Deregistered since
+                _ => continue,
+            };
+            let readiness = state.readiness(epoll_events);
+            if readiness.is_empty() {
+                continue;
+            }
+            events.events.push(Event::new(readiness, state.token));
+
+            if readiness.is_writable() && !state.waker {
+                state.known_writable = true;
+                match self.inner.update(&mut fds, fd) {
+                    //Closed while registered, the event is all that is left
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                    r => r?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn registered_count(&self) -> usize {
+        let fds = self.inner.fds.lock().unwrap();
+        fds.values().filter(|state| !state.waker).count()
+    }
+
+    //Deregisters what is still registered, returns how many were left
+    //behind. The epoll instance is closed with the last `SelectorInner`.
+    pub(crate) fn close(&self) -> usize {
+        self.inner.closed.store(true, Ordering::Release);
+        let mut fds = self.inner.fds.lock().unwrap();
+        let mut leaked = 0;
+        for (fd, state) in fds.drain() {
+            let mut event = libc::epoll_event { events: 0, u64: 0 };
+            unsafe { libc::epoll_ctl(self.inner.ep, libc::EPOLL_CTL_DEL, fd, &mut event) };
+            if !state.waker {
+                trace!(Trace::Leak { token: state.token });
+                leaked += 1;
+            }
+        }
+        leaked
+    }
+}
+
+pub struct Events {
+    //Filled in by epoll_wait(), up to its capacity, and translated into
+    //`events`
+    raw: Vec<libc::epoll_event>,
+    events: Vec<Event>,
+    //as asked for, the vectors may have been given more
+    capacity: usize,
+}
+
+impl Events {
+    pub fn with_capacity(cap: usize) -> Events {
+        //epoll_wait() won't be asked for no events at all
+        Events {
+            raw: Vec::with_capacity(cmp::max(cap, 1)),
+            events: Vec::with_capacity(cap),
+            capacity: cap,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Event> {
+        self.events.get(idx)
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.truncate(0);
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Events")
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+#[test]
+fn test_epoll_events() {
+    let state = |interests, known_writable| FdState {
+        token: Token(0),
+        interests,
+        known_writable,
+        waker: false,
+        key: 0,
+    };
+
+    let both = state(Interests::READABLE | Interests::WRITABLE, false);
+    assert_eq!(both.epoll_events(), EPOLLIN | EPOLLRDHUP | EPOLLOUT);
+    //Known writable, no longer polled for it
+    let both = state(Interests::READABLE | Interests::WRITABLE, true);
+    assert_eq!(both.epoll_events(), EPOLLIN | EPOLLRDHUP);
+    assert_eq!(
+        state(Interests::READ_CLOSED, false).epoll_events(),
+        EPOLLRDHUP
+    );
+
+    //A peer's shutdown is readable, but only read closed if asked for
+    let readable = state(Interests::READABLE, false);
+    let shutdown = readable.readiness(EPOLLIN | EPOLLRDHUP);
+    assert!(shutdown.is_readable() && !shutdown.is_read_closed());
+    let failed = state(Interests::WRITABLE, false).readiness(EPOLLIN | EPOLLOUT | EPOLLERR);
+    assert!(failed.is_writable() && failed.is_error() && !failed.is_readable());
+}
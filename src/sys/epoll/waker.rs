@@ -0,0 +1,53 @@
+use super::selector::Selector;
+use crate::token::Token;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+//An eventfd registered for readable with the waker's token
+pub(crate) struct Waker {
+    selector: Selector,
+    eventfd: File,
+    key: u32,
+}
+
+impl Waker {
+    pub(crate) fn new(selector: &Selector, token: Token) -> io::Result<Waker> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let eventfd = unsafe { File::from_raw_fd(fd) };
+        let key = selector.register_waker(fd, token)?;
+        Ok(Waker {
+            selector: selector.clone(),
+            eventfd,
+            key,
+        })
+    }
+
+    pub(crate) fn wake(&self) -> io::Result<()> {
+        match (&self.eventfd).write(&1u64.to_ne_bytes()) {
+            Ok(_) => Ok(()),
+            //The counter is full: reset it, the next write is an edge again
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                match (&self.eventfd).read(&mut [0; 8]) {
+                    Ok(_) => {}
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e),
+                }
+                self.wake()
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        //Gone already if the `Poll` was dropped first
+        let _ = self
+            .selector
+            .remove(self.eventfd.as_raw_fd(), Some(self.key));
+    }
+}
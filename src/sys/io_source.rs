@@ -0,0 +1,86 @@
+use super::{raw_source, AsRawSource, Selector};
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::token::Token;
+use std::io;
+use std::sync::Mutex;
+
+//The registration side of an `IoSource`: the selector it is registered with,
+//for `do_io` to drop the cached writable readiness through. Written against
+//the facade only, every backend shares it.
+pub(crate) struct IoSourceState {
+    //set while registered
+    selector: Mutex<Option<Selector>>,
+}
+
+impl IoSourceState {
+    pub(crate) fn new() -> IoSourceState {
+        IoSourceState {
+            selector: Mutex::new(None),
+        }
+    }
+
+    #[cfg(all(windows, feature = "net"))]
+    pub(crate) fn is_registered(&self) -> bool {
+        self.selector.lock().unwrap().is_some()
+    }
+
+    pub(crate) fn register<S>(
+        &self,
+        registry: &Registry,
+        io: &S,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()>
+    where
+        S: AsRawSource + ?Sized,
+    {
+        registry
+            .selector()
+            .register(raw_source(io), token, interests)?;
+        *self.selector.lock().unwrap() = Some(registry.selector().clone());
+        Ok(())
+    }
+
+    pub(crate) fn reregister<S>(
+        &self,
+        registry: &Registry,
+        io: &S,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()>
+    where
+        S: AsRawSource + ?Sized,
+    {
+        registry
+            .selector()
+            .reregister(raw_source(io), None, token, interests)
+    }
+
+    pub(crate) fn deregister<S>(&self, registry: &Registry, io: &S) -> io::Result<()>
+    where
+        S: AsRawSource + ?Sized,
+    {
+        registry.selector().deregister(raw_source(io), None)?;
+        *self.selector.lock().unwrap() = None;
+        Ok(())
+    }
+
+    pub(crate) fn do_io<S, F, R>(&self, io: &S, f: F) -> io::Result<R>
+    where
+        S: AsRawSource,
+        F: FnOnce(&S) -> io::Result<R>,
+    {
+        let result = f(io);
+        if let Err(ref e) = result {
+            if e.kind() == io::ErrorKind::WouldBlock {
+                if let Some(ref selector) = *self.selector.lock().unwrap() {
+                    //The caller is told `WouldBlock` either way; failing here
+                    //only leaves the cached readiness to be polled again
+                    let _ = selector.clear_writable(raw_source(io));
+                }
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,11 @@
+//The kqueue backend of macOS, iOS, FreeBSD and DragonFly. `sys::unix` has
+//what it shares with the epoll one.
+#[cfg(target_os = "freebsd")]
+mod poll;
+pub(crate) mod selector;
+pub(crate) mod waker;
+
+pub(crate) use super::unix::event;
+pub use self::selector::Events;
+pub(crate) use self::selector::{Key, Selector, SelectorConfig};
+pub(crate) use self::waker::Waker;
@@ -0,0 +1,22 @@
+//What `Registry` has on top with the kqueue of FreeBSD
+use crate::poll::Registry;
+use crate::token::Token;
+use std::io;
+
+impl Registry {
+    /// Returns a `sigevent` that reports the completion of an AIO request to
+    /// this `Poll`, as an event with `token`.
+    ///
+    /// Put it in the `aio_sigevent` of the `aiocb` given to `aio_read()` or
+    /// `aio_write()`, whose completion is then an [`AIO`] event, or pass it to
+    /// `lio_listio()`, which reports the whole list as one [`LIO`] event. Each
+    /// request makes a single event, as a completion does on Windows, and
+    /// nothing is registered: it doesn't count in [`Poll::registered_count`].
+    ///
+    /// [`AIO`]: crate::Readiness::AIO
+    /// [`LIO`]: crate::Readiness::LIO
+    /// [`Poll::registered_count`]: crate::Poll::registered_count
+    pub fn aio_sigevent(&self, token: Token) -> io::Result<libc::sigevent> {
+        self.selector().aio_sigevent(token)
+    }
+}
@@ -0,0 +1,676 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event::Event;
+use crate::interests::Interests;
+use crate::readiness::Readiness;
+use crate::token::Token;
+#[cfg(feature = "trace")]
+use crate::trace::Trace;
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{cmp, fmt, io, mem, ptr};
+
+#[derive(Clone, Default)]
+pub(crate) struct SelectorConfig {}
+
+//There is a single table, and one event per descriptor and call anyway
+impl SelectorConfig {
+    pub(crate) fn set_max_events_per_socket(&mut self, _max: usize) {}
+
+    pub(crate) fn set_shards(&mut self, _shards: usize) {}
+}
+
+//Tells a registration apart from later ones of the same descriptor
+pub(crate) type Key = u32;
+
+//What a registered descriptor asked for
+struct FdState {
+    token: Token,
+    interests: Interests,
+    //A writable event was delivered: EVFILT_WRITE is oneshot and gone until
+    //cleared, as the AFD backend stops polling for writability
+    known_writable: bool,
+    //tells this registration apart from later ones of the same descriptor,
+    //as the udata of its filters
+    key: u32,
+}
+
+impl FdState {
+    //EVFILT_READ stays for the whole registration, disabled without readable
+    //interest: the kernel dropping it tells the descriptor was closed
+    fn read_flags(&self) -> u16 {
+        if self.interests.is_readable() {
+            libc::EV_ENABLE
+        } else {
+            libc::EV_DISABLE
+        }
+    }
+
+    fn watches_writable(&self) -> bool {
+        self.interests.is_writable() && !self.known_writable
+    }
+
+    //Only what the interests ask for is reported, errors and hang-ups always
+    fn readiness(&self, filter: i16, flags: u16, fflags: u32) -> Readiness {
+        let mut readiness = match filter {
+            libc::EVFILT_READ => Readiness::READABLE,
+            libc::EVFILT_WRITE => Readiness::WRITABLE,
+            _ => Readiness::EMPTY,
+        };
+        //The peer is gone for writes: a reset, or a failed connect
+        if filter == libc::EVFILT_WRITE && flags & libc::EV_EOF != 0 {
+            readiness = readiness | Readiness::HUP;
+        }
+        //With EV_EOF, fflags holds the socket error if there is one
+        if flags & libc::EV_ERROR != 0 || (flags & libc::EV_EOF != 0 && fflags != 0) {
+            readiness = readiness | Readiness::ERROR;
+        }
+        let reported = Readiness::from(self.interests) | Readiness::ERROR | Readiness::HUP;
+        readiness & reported
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Selector {
+    inner: Arc<SelectorInner>,
+}
+
+struct SelectorInner {
+    kq: RawFd,
+    fds: Mutex<HashMap<RawFd, FdState>>,
+    //Shared by descriptors and wakers, the latter use it as their ident
+    next_key: AtomicU32,
+    //Set once the `Poll` is dropped, see `check_open`
+    closed: AtomicBool,
+}
+
+impl SelectorInner {
+    fn check_open(&self, token: Option<Token>) -> io::Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            Err(misuse(ErrorKind::PollDropped, token, "poll was dropped"))
+        } else {
+            Ok(())
+        }
+    }
+
+    //Applies a single change, whose error is that of the call
+    fn ctl(&self, change: &libc::kevent) -> io::Result<()> {
+        let r = unsafe { libc::kevent(self.kq, change, 1, ptr::null_mut(), 0, ptr::null()) };
+        cvt(r).map(|_| ())
+    }
+
+    //Tells the kernel what `state` now asks for. EVFILT_READ goes first and
+    //without EV_ADD, which fails for a descriptor closed while registered:
+    //the kernel dropped its filters already, its entry goes too.
+    fn update(&self, fds: &mut HashMap<RawFd, FdState>, fd: RawFd) -> io::Result<()> {
+        let (read_flags, watches_writable, key) = match fds.get(&fd) {
+            Some(state) => (state.read_flags(), state.watches_writable(), state.key),
+            None => return Ok(()),
+        };
+        match self.ctl(&kevent(
+            fd as usize,
+            libc::EVFILT_READ,
+            read_flags,
+            key as usize,
+        )) {
+            Err(ref e) if is_gone(e) => {
+                let token = fds.remove(&fd).map(|state| state.token);
+                return Err(not_registered(token));
+            }
+            r => r?,
+        }
+        if watches_writable {
+            let flags = libc::EV_ADD | libc::EV_ONESHOT;
+            self.ctl(&kevent(
+                fd as usize,
+                libc::EVFILT_WRITE,
+                flags,
+                key as usize,
+            ))
+        } else {
+            //Gone already if it fired
+            match self.ctl(&kevent(fd as usize, libc::EVFILT_WRITE, libc::EV_DELETE, 0)) {
+                Err(ref e) if is_gone(e) => Ok(()),
+                r => r,
+            }
+        }
+    }
+
+    //Drops both filters of `fd`, those the kernel doesn't know anymore aside
+    fn delete(&self, fd: RawFd) -> io::Result<()> {
+        for &filter in &[libc::EVFILT_READ, libc::EVFILT_WRITE] {
+            match self.ctl(&kevent(fd as usize, filter, libc::EV_DELETE, 0)) {
+                Err(ref e) if is_gone(e) => {}
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SelectorInner {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.kq) };
+    }
+}
+
+fn kevent(ident: usize, filter: i16, flags: u16, udata: usize) -> libc::kevent {
+    //Zeroed first, the fields after `udata` differ between releases
+    let mut kevent: libc::kevent = unsafe { mem::zeroed() };
+    kevent.ident = ident as libc::uintptr_t;
+    kevent.filter = filter;
+    kevent.flags = flags;
+    kevent.udata = udata as *mut libc::c_void;
+    kevent
+}
+
+//The descriptor was closed, or its number reused, while registered
+fn is_gone(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENOENT) || e.raw_os_error() == Some(libc::EBADF)
+}
+
+fn not_registered(token: Option<Token>) -> io::Error {
+    misuse(ErrorKind::NotRegistered, token, "socket is not registered")
+}
+
+//The entry of `fd`, if it is the registration `key` is of
+fn find(
+    fds: &mut HashMap<RawFd, FdState>,
+    fd: RawFd,
+    key: Option<u32>,
+    token: Option<Token>,
+) -> io::Result<&mut FdState> {
+    match fds.get_mut(&fd) {
+        Some(state) if key.is_none_or(|key| key == state.key) => Ok(state),
+        _ => Err(not_registered(token)),
+    }
+}
+
+//Bits without a constant on this platform are refused, as on Windows
+fn check_interests(interests: Interests, token: Token) -> io::Result<()> {
+    if interests.is_supported() {
+        Ok(())
+    } else {
+        Err(misuse(
+            ErrorKind::UnsupportedInterests,
+            Some(token),
+            "unsupported interests",
+        ))
+    }
+}
+
+fn cvt(r: libc::c_int) -> io::Result<libc::c_int> {
+    if r < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(r)
+    }
+}
+
+impl Selector {
+    pub(crate) fn with_config(_config: SelectorConfig) -> io::Result<Selector> {
+        let kq = cvt(unsafe { libc::kqueue() })?;
+        let inner = SelectorInner {
+            kq,
+            fds: Mutex::new(HashMap::new()),
+            next_key: AtomicU32::new(0),
+            closed: AtomicBool::new(false),
+        };
+        //No kqueue1() everywhere, closed on exec all the same
+        cvt(unsafe { libc::fcntl(kq, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        Ok(Selector {
+            inner: Arc::new(inner),
+        })
+    }
+
+    //Registers `fd`, returns the key of the registration. `AIO` and `LIO`
+    //ask for nothing here: a descriptor has no such filter, see
+    //`aio_sigevent`.
+    pub(crate) fn register(
+        &self,
+        fd: RawFd,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<u32> {
+        self.inner.check_open(Some(token))?;
+        check_interests(interests, token)?;
+        let mut fds = self.inner.fds.lock().unwrap();
+        //The kernel has the last word: an entry left by a descriptor closed
+        //while registered is replaced. Its EVFILT_READ is set as it is.
+        if let Some(state) = fds.get(&fd) {
+            let probe = kevent(
+                fd as usize,
+                libc::EVFILT_READ,
+                state.read_flags(),
+                state.key as usize,
+            );
+            match self.inner.ctl(&probe) {
+                Err(ref e) if is_gone(e) => {}
+                Err(e) => return Err(e),
+                Ok(()) => {
+                    return Err(misuse(
+                        ErrorKind::AlreadyRegistered,
+                        Some(token),
+                        "socket is already registered",
+                    ));
+                }
+            }
+        }
+        let state = FdState {
+            token,
+            interests,
+            known_writable: false,
+            key: self.inner.next_key.fetch_add(1, Ordering::Relaxed),
+        };
+        let flags = libc::EV_ADD | state.read_flags();
+        let read = kevent(fd as usize, libc::EVFILT_READ, flags, state.key as usize);
+        self.inner.ctl(&read)?;
+        if state.watches_writable() {
+            let flags = libc::EV_ADD | libc::EV_ONESHOT;
+            let write = kevent(fd as usize, libc::EVFILT_WRITE, flags, state.key as usize);
+            if let Err(e) = self.inner.ctl(&write) {
+                let _ = self.inner.delete(fd);
+                return Err(e);
+            }
+        }
+        trace!(Trace::Register { token, interests });
+        let key = state.key;
+        fds.insert(fd, state);
+        Ok(key)
+    }
+
+    //Changes the token and interests of `fd`, registered as `key` if given.
+    //Dropping writable interest forgets that the descriptor is known to be
+    //writable.
+    pub(crate) fn reregister(
+        &self,
+        fd: RawFd,
+        key: Option<u32>,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        self.inner.check_open(Some(token))?;
+        self.modify(fd, key, token, |_| Ok(interests))
+    }
+
+    pub(crate) fn modify_interests<F>(&self, fd: RawFd, token: Token, f: F) -> io::Result<()>
+    where
+        F: FnOnce(Option<Interests>) -> io::Result<Interests>,
+    {
+        self.inner.check_open(Some(token))?;
+        self.modify(fd, None, token, |current| f(Some(current)))
+    }
+
+    fn modify<F>(&self, fd: RawFd, key: Option<u32>, token: Token, f: F) -> io::Result<()>
+    where
+        F: FnOnce(Interests) -> io::Result<Interests>,
+    {
+        let mut fds = self.inner.fds.lock().unwrap();
+        let state = find(&mut fds, fd, key, Some(token))?;
+        let interests = f(state.interests)?;
+        check_interests(interests, token)?;
+        trace!(Trace::Reregister { token, interests });
+        state.token = token;
+        state.interests = interests;
+        if !interests.is_writable() {
+            state.known_writable = false;
+        }
+        self.inner.update(&mut fds, fd)
+    }
+
+    //Deregisters `fd`, registered as `key` if given.
+    pub(crate) fn deregister(&self, fd: RawFd, key: Option<u32>) -> io::Result<()> {
+        self.inner.check_open(None)?;
+        let mut fds = self.inner.fds.lock().unwrap();
+        find(&mut fds, fd, key, None)?;
+        let _state = fds.remove(&fd).unwrap();
+        trace!(Trace::Deregister {
+            token: _state.token,
+        });
+        self.inner.delete(fd)
+    }
+
+    //Polls `fd` for writability again, see `FdState::known_writable`.
+    pub(crate) fn clear_writable(&self, fd: RawFd) -> io::Result<()> {
+        self.inner.check_open(None)?;
+        let mut fds = self.inner.fds.lock().unwrap();
+        let state = find(&mut fds, fd, None, None)?;
+        if !state.known_writable {
+            return Ok(());
+        }
+        state.known_writable = false;
+        self.inner.update(&mut fds, fd)
+    }
+
+    //An EVFILT_USER filter of its own, `key` as ident and `token` as udata.
+    //EV_CLEAR, as the eventfd of the epoll backend is edge-triggered: wake-ups
+    //before the next poll are merged into one event.
+    pub(crate) fn register_waker(&self, token: Token) -> io::Result<u32> {
+        self.inner.check_open(Some(token))?;
+        let key = self.inner.next_key.fetch_add(1, Ordering::Relaxed);
+        let flags = libc::EV_ADD | libc::EV_CLEAR;
+        self.inner.ctl(&kevent(
+            key as usize,
+            libc::EVFILT_USER,
+            flags,
+            usize::from(token),
+        ))?;
+        Ok(key)
+    }
+
+    pub(crate) fn wake(&self, key: u32, token: Token) -> io::Result<()> {
+        //The udata is set again by any change, the token goes along
+        let mut trigger = kevent(key as usize, libc::EVFILT_USER, 0, usize::from(token));
+        trigger.fflags = libc::NOTE_TRIGGER;
+        self.inner.ctl(&trigger)
+    }
+
+    //Gone already if the kqueue was closed
+    pub(crate) fn remove_waker(&self, key: u32) -> io::Result<()> {
+        self.inner
+            .ctl(&kevent(key as usize, libc::EVFILT_USER, libc::EV_DELETE, 0))
+    }
+
+    //A sigevent reporting an AIO request to this kqueue, `token` as udata.
+    //sigev_signo stands for sigev_notify_kqueue.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn aio_sigevent(&self, token: Token) -> io::Result<libc::sigevent> {
+        self.inner.check_open(Some(token))?;
+        let mut sigevent: libc::sigevent = unsafe { mem::zeroed() };
+        sigevent.sigev_notify = libc::SIGEV_KEVENT;
+        sigevent.sigev_signo = self.inner.kq;
+        sigevent.sigev_value = libc::sigval {
+            sival_ptr: usize::from(token) as *mut libc::c_void,
+        };
+        //sigev_notify_kevent_flags, at the start of the union libc only
+        //shows the thread id of. EV_ONESHOT: one event per completed request,
+        //as an IOCP completion is.
+        unsafe {
+            let flags = &mut sigevent.sigev_notify_thread_id as *mut libc::lwpid_t;
+            *(flags as *mut libc::c_ushort) = libc::EV_ONESHOT;
+        }
+        Ok(sigevent)
+    }
+
+    pub(crate) fn select(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        trace!(Trace::PollEnter { timeout });
+        let r = self.select_events(events, timeout);
+        trace!(Trace::PollExit {
+            events: events.len()
+        });
+        r
+    }
+
+    fn select_events(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        events.clear();
+        events.merged.clear();
+
+        let timeout = timeout.map(|timeout| libc::timespec {
+            tv_sec: cmp::min(timeout.as_secs(), libc::time_t::MAX as u64) as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        });
+        let n = unsafe {
+            libc::kevent(
+                self.inner.kq,
+                ptr::null(),
+                0,
+                events.raw.as_mut_ptr(),
+                cmp::max(events.capacity, 1) as libc::c_int,
+                timeout.as_ref().map_or(ptr::null(), |timeout| timeout),
+            )
+        };
+        let n = match cvt(n) {
+            Ok(n) => n as usize,
+            //A signal cut the wait short, as a timeout would have
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => 0,
+            Err(e) => return Err(e),
+        };
+        unsafe { events.raw.set_len(n) };
+
+        let mut fds = self.inner.fds.lock().unwrap();
+        for i in 0..n {
+            let kevent = events.raw[i];
+            let udata = kevent.udata as usize;
+            //Wakers and AIO requests carry their token, registered descriptors
+            //their key
+            let ready = match kevent.filter {
+                libc::EVFILT_USER => Some(Readiness::READABLE),
+                #[cfg(target_os = "freebsd")]
+                libc::EVFILT_AIO => Some(Readiness::AIO),
+                #[cfg(target_os = "freebsd")]
+                libc::EVFILT_LIO => Some(Readiness::LIO),
+                _ => None,
+            };
+            if let Some(readiness) = ready {
+                events
+                    .events
+                    .push(Event::new(readiness, Token::from(udata)));
+                continue;
+            }
+
+            let fd = kevent.ident as RawFd;
+            let state = match fds.get_mut(&fd) {
+                Some(state) if state.key as usize == udata => state,
+                //Deregistered since
+                _ => continue,
+            };
+            let readiness = state.readiness(kevent.filter, kevent.flags, kevent.fflags);
+            if readiness.is_empty() {
+                continue;
+            }
+            //EVFILT_WRITE went with the event, being oneshot
+            if readiness.is_writable() {
+                state.known_writable = true;
+            }
+            //Both filters of a descriptor come apart, they still make one
+            //event
+            match events.merged.get(&fd) {
+                Some(&idx) => events.events[idx].add_readiness(readiness),
+                None => {
+                    events.merged.insert(fd, events.events.len());
+                    events.events.push(Event::new(readiness, state.token));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn registered_count(&self) -> usize {
+        self.inner.fds.lock().unwrap().len()
+    }
+
+    //Deregisters what is still registered, returns how many were left
+    //behind. The kqueue is closed with the last `SelectorInner`, wakers and
+    //AIO requests with it.
+    pub(crate) fn close(&self) -> usize {
+        self.inner.closed.store(true, Ordering::Release);
+        let mut fds = self.inner.fds.lock().unwrap();
+        let mut leaked = 0;
+        for (fd, _state) in fds.drain() {
+            let _ = self.inner.delete(fd);
+            trace!(Trace::Leak {
+                token: _state.token
+            });
+            leaked += 1;
+        }
+        leaked
+    }
+}
+
+pub struct Events {
+    //Filled in by kevent(), up to its capacity, and translated into `events`
+    raw: Vec<libc::kevent>,
+    events: Vec<Event>,
+    //Where the event of each descriptor is in `events`, for the poll at hand
+    merged: HashMap<RawFd, usize>,
+    //as asked for, the vectors may have been given more
+    capacity: usize,
+}
+
+impl Events {
+    pub fn with_capacity(cap: usize) -> Events {
+        //kevent() won't be asked for no events at all
+        Events {
+            raw: Vec::with_capacity(cmp::max(cap, 1)),
+            events: Vec::with_capacity(cap),
+            merged: HashMap::new(),
+            capacity: cap,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Event> {
+        self.events.get(idx)
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.truncate(0);
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Events")
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+#[test]
+fn test_kevent_readiness() {
+    let state = |interests, known_writable| FdState {
+        token: Token(0),
+        interests,
+        known_writable,
+        key: 0,
+    };
+
+    let both = state(Interests::READABLE | Interests::WRITABLE, false);
+    assert_eq!(both.read_flags(), libc::EV_ENABLE);
+    assert!(both.watches_writable());
+    //Known writable, no longer polled for it
+    assert!(!state(Interests::READABLE | Interests::WRITABLE, true).watches_writable());
+    assert_eq!(
+        state(Interests::WRITABLE, false).read_flags(),
+        libc::EV_DISABLE
+    );
+
+    //A peer's shutdown is readable, and nothing else
+    let readable = state(Interests::READABLE, false);
+    let shutdown = readable.readiness(libc::EVFILT_READ, libc::EV_EOF, 0);
+    assert!(shutdown.is_readable() && !shutdown.is_hup() && !shutdown.is_error());
+    //A failed connect, whose error comes in fflags
+    let writable = state(Interests::WRITABLE, false);
+    let failed = writable.readiness(libc::EVFILT_WRITE, libc::EV_EOF, libc::ECONNREFUSED as u32);
+    assert!(failed.is_writable() && failed.is_hup() && failed.is_error());
+    //Not asked for
+    assert!(writable.readiness(libc::EVFILT_READ, 0, 0).is_empty());
+}
+
+#[test]
+fn test_aio_on_descriptor() -> io::Result<()> {
+    use crate::event;
+    use crate::{Events, Poll, SourceFd};
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let (a, mut b) = UnixStream::pair()?;
+    a.set_nonblocking(true)?;
+    b.write_all(b"unread")?;
+
+    //Registered, but a descriptor has no AIO filter to report through
+    let fd = a.as_raw_fd();
+    poll.registry()
+        .register(&SourceFd(&fd), Token(0), Interests::AIO)?;
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+    assert_eq!(poll.registered_count(), 1);
+
+    poll.registry().reregister(
+        &SourceFd(&fd),
+        Token(1),
+        Interests::AIO | Interests::READABLE,
+    )?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    let e = events.get(0).unwrap();
+    assert!(event::is_readable(e) && !event::is_aio(e));
+
+    Ok(())
+}
+
+#[cfg(target_os = "freebsd")]
+#[test]
+fn test_aio_sigevent() -> io::Result<()> {
+    use crate::event;
+    use crate::{Events, Poll};
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let path = std::env::temp_dir().join(format!("iocp-wrapper-aio-{}", std::process::id()));
+    File::create(&path)?.write_all(b"aio")?;
+    let file = File::open(&path)?;
+
+    let mut buf = [0u8; 8];
+    let mut aiocb: libc::aiocb = unsafe { mem::zeroed() };
+    aiocb.aio_fildes = file.as_raw_fd();
+    aiocb.aio_buf = buf.as_mut_ptr() as *mut libc::c_void;
+    aiocb.aio_nbytes = buf.len();
+    aiocb.aio_sigevent = poll.registry().aio_sigevent(Token(5))?;
+    cvt(unsafe { libc::aio_read(&mut aiocb) })?;
+
+    poll.poll(&mut events, Some(Duration::from_secs(5)))?;
+    assert_eq!(events.len(), 1);
+    let e = events.get(0).unwrap();
+    assert_eq!(event::token(e), Token(5));
+    assert!(event::is_aio(e) && !event::is_readable(e));
+    assert_eq!(unsafe { libc::aio_return(&mut aiocb) }, 3);
+    assert_eq!(&buf[..3], b"aio");
+    //One event per request, and nothing left registered
+    poll.poll(&mut events, Some(Duration::from_millis(50)))?;
+    assert!(events.is_empty());
+    assert_eq!(poll.registered_count(), 0);
+
+    //A list is reported as a whole, its own requests don't notify
+    let mut read: libc::aiocb = unsafe { mem::zeroed() };
+    read.aio_fildes = file.as_raw_fd();
+    read.aio_buf = buf.as_mut_ptr() as *mut libc::c_void;
+    read.aio_nbytes = buf.len();
+    read.aio_lio_opcode = libc::LIO_READ;
+    let list = [&mut read as *mut libc::aiocb];
+    let mut sigevent = poll.registry().aio_sigevent(Token(6))?;
+    cvt(unsafe { libc::lio_listio(libc::LIO_NOWAIT, list.as_ptr(), 1, &mut sigevent) })?;
+
+    poll.poll(&mut events, Some(Duration::from_secs(5)))?;
+    assert_eq!(events.len(), 1);
+    let e = events.get(0).unwrap();
+    assert_eq!(event::token(e), Token(6));
+    assert!(event::is_lio(e) && !event::is_aio(e));
+    assert_eq!(unsafe { libc::aio_return(&mut read) }, 3);
+
+    fs::remove_file(&path)
+}
@@ -0,0 +1,31 @@
+use super::selector::Selector;
+use crate::token::Token;
+use std::io;
+
+//An `EVFILT_USER` filter of the poll's kqueue, triggered to wake
+pub(crate) struct Waker {
+    selector: Selector,
+    token: Token,
+    key: u32,
+}
+
+impl Waker {
+    pub(crate) fn new(selector: &Selector, token: Token) -> io::Result<Waker> {
+        let key = selector.register_waker(token)?;
+        Ok(Waker {
+            selector: selector.clone(),
+            token,
+            key,
+        })
+    }
+
+    pub(crate) fn wake(&self) -> io::Result<()> {
+        self.selector.wake(self.key, self.token)
+    }
+}
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        let _ = self.selector.remove_waker(self.key);
+    }
+}
@@ -0,0 +1,489 @@
+//The backends, and the facade the rest of the crate polls through. `poll`,
+//`event`, `waker` and `io_source` only ever use what is re-exported here,
+//whichever backend the target picks:
+//
+//- `Selector` is the poller, cloned for each thing that keeps it, like a
+//  `Waker` or a `Registration`. `register` takes the raw socket with its
+//  token and interests, fails with `AlreadyRegistered` if it is registered
+//  already and with `UnsupportedInterests` for interests the platform can't
+//  watch, and returns the `Key` of the registration. `reregister` and
+//  `deregister` fail with `NotRegistered` unless the socket is registered, as
+//  `key` if given: a key never matches a later registration of the same
+//  socket. Once `close` was called, everything fails with `PollDropped`.
+//- `select` fills `Events` with `crate::event::Event`s, waiting at most the
+//  timeout. Their readiness is what the interests ask for, plus errors and
+//  hang-ups, level-triggered but for writable, which is reported until
+//  `clear_writable`. That is all the public accessors of `event` read.
+//- `Event` is what an event carries besides its token and readiness, kept
+//  in `crate::event::Event`. The backend's `event` module has what is public
+//  about it, `crate::event` re-exports all of it.
+//- `Waker` posts a readable event with its token from any thread.
+//- `SelectorConfig` has a setter for each `PollBuilder` setting. Settings a
+//  backend has no use for are ignored.
+//- `IoSourceState` is shared by every backend, as are the raw sources of the
+//  platform: `RawSource`, what `AsRawSource` gives through `raw_source`.
+//
+//Backends can have more, like the handles of Windows: the modules of the
+//crate root that are Windows-only use its selector for them, which is why the
+//`shell` feature, the backend that polls nothing, leaves Windows alone.
+//Without the `os-poll` feature that backend is the only one, everywhere. The
+//tests below go through the facade only, and pass on every backend that
+//polls.
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    feature = "os-poll",
+    not(feature = "shell")
+))]
+mod epoll;
+mod io_source;
+#[cfg(all(
+    any(
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "macos"
+    ),
+    feature = "os-poll",
+    not(feature = "shell")
+))]
+mod kqueue;
+#[cfg(not(any(
+    all(
+        any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos"
+        ),
+        feature = "os-poll",
+        not(feature = "shell")
+    ),
+    all(windows, feature = "os-poll")
+)))]
+mod shell;
+#[cfg(unix)]
+pub(crate) mod unix;
+#[cfg(windows)]
+pub(crate) mod windows;
+
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    feature = "os-poll",
+    not(feature = "shell")
+))]
+use self::epoll as backend;
+#[cfg(all(
+    any(
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "macos"
+    ),
+    feature = "os-poll",
+    not(feature = "shell")
+))]
+use self::kqueue as backend;
+#[cfg(not(any(
+    all(
+        any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos"
+        ),
+        feature = "os-poll",
+        not(feature = "shell")
+    ),
+    all(windows, feature = "os-poll")
+)))]
+use self::shell as backend;
+#[cfg(not(any(unix, windows)))]
+use self::shell as platform;
+#[cfg(unix)]
+use self::unix as platform;
+#[cfg(all(windows, feature = "os-poll"))]
+use self::windows as backend;
+#[cfg(windows)]
+use self::windows as platform;
+
+pub use self::backend::Events;
+#[cfg(feature = "os-poll")]
+pub(crate) use self::backend::SelectorConfig;
+pub(crate) use self::backend::{event, Key, Selector, Waker};
+pub(crate) use self::event::Event;
+pub(crate) use self::io_source::IoSourceState;
+pub(crate) use self::platform::{raw_source, AsRawSource, RawSource};
+
+#[cfg(all(test, feature = "os-poll", any(windows, not(feature = "shell"))))]
+use crate::{Interests, IoSource, Poll, Token};
+#[cfg(all(test, feature = "os-poll", any(windows, not(feature = "shell"))))]
+use std::time::Duration;
+#[cfg(all(test, feature = "os-poll", any(windows, not(feature = "shell"))))]
+use std::io;
+
+//Two TCP streams connected to each other over loopback, both blocking, the
+//connecting one first. What `net::tcp_pair` is made of, and the tests here
+//use it where that isn't built: they register the first and only ever write
+//and read what fits.
+#[cfg(any(test, all(windows, feature = "net")))]
+pub(crate) fn tcp_pair() -> std::io::Result<(std::net::TcpStream, std::net::TcpStream)> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    //Loopback, the handshake is done by the time this returns
+    let connected = std::net::TcpStream::connect(listener.local_addr()?)?;
+    let local = connected.local_addr()?;
+    let accepted = loop {
+        let (stream, from) = listener.accept()?;
+        if from == local {
+            break stream;
+        }
+    };
+    Ok((connected, accepted))
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_writable_cached_until_would_block() -> io::Result<()> {
+    use crate::event;
+    use std::io::{Read, Write};
+    use std::net;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut server, _) = listener.accept()?;
+    let mut writer = client.try_clone()?;
+    writer.set_nonblocking(true)?;
+
+    let stream = IoSource::new(client);
+    poll.registry()
+        .register(&stream, Token(0), Interests::WRITABLE)?;
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_writable(events.get(0).unwrap()));
+
+    //Known writable now, nothing to report until a write would block
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    let buf = [0; 64 * 1024];
+    let mut written = 0;
+    loop {
+        match writer.write(&buf) {
+            Ok(n) => written += n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    poll.registry().clear_writable(&stream)?;
+
+    //The peer hasn't read anything, the buffer is still full
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    let mut rbuf = vec![0; 64 * 1024];
+    let mut read = 0;
+    while read < written {
+        read += server.read(&mut rbuf)?;
+    }
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_writable(events.get(0).unwrap()));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_downgraded_interests_stop_readable() -> io::Result<()> {
+    use crate::event;
+    use std::io::Write;
+    use std::net;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let client = net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut server, _) = listener.accept()?;
+
+    //Unread data stays queued for the whole test
+    server.write_all(b"unread")?;
+
+    let stream = IoSource::new(client);
+    poll.registry()
+        .register(&stream, Token(0), Interests::READABLE | Interests::WRITABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_readable(events.get(0).unwrap()));
+
+    poll.registry()
+        .reregister(&stream, Token(0), Interests::WRITABLE)?;
+    for _ in 0..5 {
+        poll.registry().clear_writable(&stream)?;
+        poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+        for i in 0..events.len() {
+            assert!(!event::is_readable(events.get(i).unwrap()));
+        }
+    }
+
+    poll.registry()
+        .reregister(&stream, Token(0), Interests::READABLE | Interests::WRITABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_readable(events.get(0).unwrap()));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_registration_guard() -> io::Result<()> {
+    use std::net;
+    use std::thread;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let stream = IoSource::new(net::TcpStream::connect(addr)?);
+    let guard = poll
+        .registry()
+        .register_guarded(&stream, Token(0), Interests::WRITABLE)?;
+    //Send, no borrow of the registry
+    let guard = thread::spawn(move || guard).join().unwrap();
+    guard.reregister(Token(1), Interests::WRITABLE)?;
+    drop(guard);
+
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+    assert_eq!(
+        poll.registry().deregister(&stream).unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+
+    let stream = IoSource::new(net::TcpStream::connect(addr)?);
+    poll.registry()
+        .register_guarded(&stream, Token(2), Interests::WRITABLE)?
+        .forget();
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(crate::event::token(events.get(0).unwrap()), Token(2));
+    poll.registry().deregister(&stream)?;
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_removed_interests_stop_events() -> io::Result<()> {
+    use crate::event;
+    use std::net;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let stream = IoSource::new(net::TcpStream::connect(listener.local_addr()?)?);
+
+    let interests = Interests::READABLE | Interests::WRITABLE;
+    poll.registry().register(&stream, Token(0), interests)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_writable(events.get(0).unwrap()));
+
+    let interests = interests.remove(Interests::WRITABLE).unwrap();
+    poll.registry().reregister(&stream, Token(0), interests)?;
+    //Would report writable again right away if it were still watched for
+    poll.registry().clear_writable(&stream)?;
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "os-poll",
+    any(
+        windows,
+        all(
+            any(target_os = "linux", target_os = "android"),
+            not(feature = "shell")
+        )
+    )
+))]
+#[test]
+fn test_read_closed_only() -> io::Result<()> {
+    use crate::event;
+    use std::io::Write;
+    use std::net::Shutdown;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let (stream, mut peer) = tcp_pair()?;
+    let stream = IoSource::new(stream);
+
+    poll.registry()
+        .register(&stream, Token(0), Interests::READ_CLOSED)?;
+
+    //Ordinary data isn't reported
+    peer.write_all(b"data")?;
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    peer.shutdown(Shutdown::Write)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    let e = events.get(0).unwrap();
+    assert!(event::is_read_closed(e));
+    assert!(!event::is_readable(e));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_register_all_interests() -> io::Result<()> {
+    use crate::event;
+    use std::net;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let stream = IoSource::new(net::TcpStream::connect(listener.local_addr()?)?);
+
+    poll.registry()
+        .register(&stream, Token(0), Interests::all())?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_writable(events.get(0).unwrap()));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_writable_only_never_readable() -> io::Result<()> {
+    use crate::event;
+    use std::io::Write;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let (stream, mut peer) = tcp_pair()?;
+    let stream = IoSource::new(stream);
+    peer.write_all(b"pending")?;
+
+    poll.registry()
+        .register(&stream, Token(0), Interests::WRITABLE)?;
+    for _ in 0..3 {
+        poll.registry().clear_writable(&stream)?;
+        poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+        assert_eq!(events.len(), 1);
+        assert!(!event::is_readable(events.get(0).unwrap()));
+    }
+
+    poll.registry()
+        .reregister(&stream, Token(0), Interests::READABLE | Interests::WRITABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_readable(events.get(0).unwrap()));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_add_remove_interests() -> io::Result<()> {
+    use std::net;
+
+    let poll = Poll::new()?;
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let stream = IoSource::new(net::TcpStream::connect(listener.local_addr()?)?);
+    let registry = poll.registry();
+
+    assert_eq!(
+        registry
+            .add_interests(&stream, Token(0), Interests::READABLE)
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::NotFound
+    );
+
+    registry.register(&stream, Token(0), Interests::READABLE)?;
+    registry.add_interests(&stream, Token(0), Interests::WRITABLE)?;
+    registry.remove_interests(&stream, Token(0), Interests::READABLE)?;
+    assert_eq!(
+        registry
+            .remove_interests(&stream, Token(0), Interests::WRITABLE)
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidInput
+    );
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_waker() -> io::Result<()> {
+    use crate::{event, Waker};
+    use std::sync::Arc;
+    use std::thread;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    //Token 0, whose wake-up on Windows passes a null pointer just like those
+    //the poller sends itself
+    let waker = Arc::new(Waker::new(poll.registry(), Token(0))?);
+
+    let remote = waker.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        remote.wake()
+    });
+    poll.poll(&mut events, Some(Duration::from_secs(5)))?;
+    handle.join().unwrap()?;
+    assert_eq!(events.len(), 1);
+    let e = events.get(0).unwrap();
+    assert_eq!(event::token(e), Token(0));
+    assert!(event::is_readable(e));
+
+    poll.poll(&mut events, Some(Duration::from_millis(50)))?;
+    assert!(events.is_empty());
+    assert_eq!(poll.registered_count(), 0);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "os-poll", any(windows, not(feature = "shell"))))]
+#[test]
+fn test_events_capacity() -> io::Result<()> {
+    use crate::Waker;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(3);
+    assert_eq!(events.capacity(), 3);
+    let wakers = (0..5)
+        .map(|i| Waker::new(poll.registry(), Token(i)))
+        .collect::<io::Result<Vec<_>>>()?;
+    for waker in &wakers {
+        waker.wake()?;
+    }
+
+    //The capacity asked for, whatever the allocation holds
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 3);
+    assert_eq!(events.capacity(), 3);
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 2);
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//A backend that polls nothing, for targets without epoll, kqueue or AFD, like
+//wasm32 or other Unix platforms, on Unix with the `shell` feature, and
+//anywhere without the `os-poll` one. It is the facade filled in and no more:
+//building a `Poll` fails with `Unsupported`, if it can be built at all, so
+//there is never a `Selector` to call and its methods can't be reached. A new
+//backend can start from here.
+use crate::event::Event;
+use crate::interests::Interests;
+use crate::token::Token;
+use std::time::Duration;
+use std::{fmt, io};
+
+#[cfg(unix)]
+pub(crate) use super::unix::event;
+#[cfg(unix)]
+use super::unix::RawSource;
+#[cfg(windows)]
+use super::windows::RawSource;
+
+//Events are readiness only, as with epoll and kqueue
+#[cfg(not(unix))]
+pub(crate) mod event {
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct Event;
+}
+
+//Neither Unix nor Windows, no raw sources either: the trait is implemented
+//by nothing, so nothing can be registered
+#[cfg(not(any(unix, windows)))]
+pub(crate) type RawSource = usize;
+
+#[cfg(not(any(unix, windows)))]
+pub trait AsRawSource {
+    fn as_raw_source(&self) -> RawSource;
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn raw_source<S: AsRawSource + ?Sized>(source: &S) -> RawSource {
+    source.as_raw_source()
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct SelectorConfig {}
+
+impl SelectorConfig {
+    pub(crate) fn set_max_events_per_socket(&mut self, _max: usize) {}
+
+    pub(crate) fn set_shards(&mut self, _shards: usize) {}
+}
+
+pub(crate) type Key = u32;
+
+#[derive(Clone)]
+enum Never {}
+
+#[derive(Clone)]
+pub(crate) struct Selector {
+    never: Never,
+}
+
+impl Selector {
+    pub(crate) fn with_config(_config: SelectorConfig) -> io::Result<Selector> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no poller on this platform",
+        ))
+    }
+
+    pub(crate) fn register(
+        &self,
+        _fd: RawSource,
+        _token: Token,
+        _interests: Interests,
+    ) -> io::Result<Key> {
+        match self.never {}
+    }
+
+    pub(crate) fn reregister(
+        &self,
+        _fd: RawSource,
+        _key: Option<Key>,
+        _token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        match self.never {}
+    }
+
+    pub(crate) fn modify_interests<F>(&self, _fd: RawSource, _token: Token, _f: F) -> io::Result<()>
+    where
+        F: FnOnce(Option<Interests>) -> io::Result<Interests>,
+    {
+        match self.never {}
+    }
+
+    pub(crate) fn deregister(&self, _fd: RawSource, _key: Option<Key>) -> io::Result<()> {
+        match self.never {}
+    }
+
+    pub(crate) fn clear_writable(&self, _fd: RawSource) -> io::Result<()> {
+        match self.never {}
+    }
+
+    pub(crate) fn select(
+        &self,
+        _events: &mut Events,
+        _timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        match self.never {}
+    }
+
+    pub(crate) fn registered_count(&self) -> usize {
+        match self.never {}
+    }
+
+    pub(crate) fn close(&self) -> usize {
+        match self.never {}
+    }
+}
+
+pub(crate) struct Waker {
+    never: Never,
+}
+
+impl Waker {
+    pub(crate) fn new(selector: &Selector, _token: Token) -> io::Result<Waker> {
+        match selector.never {}
+    }
+
+    pub(crate) fn wake(&self) -> io::Result<()> {
+        match self.never {}
+    }
+}
+
+pub struct Events {
+    events: Vec<Event>,
+    //as asked for, `events` may have been given more
+    capacity: usize,
+}
+
+impl Events {
+    pub fn with_capacity(cap: usize) -> Events {
+        Events {
+            events: Vec::with_capacity(cap),
+            capacity: cap,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Event> {
+        self.events.get(idx)
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.truncate(0);
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Events")
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+#[cfg(feature = "os-poll")]
+#[test]
+fn test_poll_unsupported() -> io::Result<()> {
+    use crate::{Events, Poll};
+
+    let err = Poll::new().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    let err = Poll::builder().shards(2).build().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+    //Events need no poller
+    let events = Events::with_capacity(8);
+    assert!(events.is_empty());
+    assert_eq!(events.capacity(), 8);
+
+    Ok(())
+}
@@ -0,0 +1,3 @@
+//epoll and kqueue events are all readiness, there is nothing else to keep
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Event;
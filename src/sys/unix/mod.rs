@@ -0,0 +1,11 @@
+//What the epoll and kqueue backends share, and what any backend needs on Unix
+//for the facade: descriptors as the sources to register.
+pub(crate) mod event;
+mod source_fd;
+
+pub use self::source_fd::SourceFd;
+pub(crate) use std::os::unix::io::{AsRawFd as AsRawSource, RawFd as RawSource};
+
+pub(crate) fn raw_source<S: AsRawSource + ?Sized>(source: &S) -> RawSource {
+    source.as_raw_fd()
+}
@@ -0,0 +1,104 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::token::Token;
+use crate::Registry;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Registers a raw file descriptor, such as that of a std socket.
+///
+/// The descriptor is only borrowed, and has to be in non-blocking mode
+/// already. Deregister it before closing it: the kernel forgets a closed
+/// descriptor by itself, the `Poll` only once it is deregistered. Use an
+/// [`IoSource`](crate::IoSource) to keep the cached writable readiness
+/// honest on writes.
+///
+/// # Examples
+///
+#[cfg_attr(feature = "os-poll", doc = "```no_run")]
+#[cfg_attr(not(feature = "os-poll"), doc = "```ignore")]
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::{Interests, Poll, SourceFd, Token};
+/// use std::net;
+/// use std::os::unix::io::AsRawFd;
+///
+/// let listener = net::TcpListener::bind("127.0.0.1:0")?;
+/// listener.set_nonblocking(true)?;
+///
+/// let poll = Poll::new()?;
+/// poll.registry().register(
+///     &SourceFd(&listener.as_raw_fd()),
+///     Token(0),
+///     Interests::READABLE,
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SourceFd<'a>(pub &'a RawFd);
+
+impl AsRawFd for SourceFd<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        *self.0
+    }
+}
+
+impl event::Source for SourceFd<'_> {
+    fn register(&self, registry: &Registry, token: Token, interests: Interests) -> io::Result<()> {
+        registry
+            .selector()
+            .register(*self.0, token, interests)
+            .map(|_| ())
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        registry
+            .selector()
+            .reregister(*self.0, None, token, interests)
+    }
+
+    fn deregister(&self, registry: &Registry) -> io::Result<()> {
+        registry.selector().deregister(*self.0, None)
+    }
+}
+
+#[cfg(all(feature = "os-poll", not(feature = "shell")))]
+#[test]
+fn test_source_fd() -> io::Result<()> {
+    use crate::event::is_readable;
+    use crate::{Events, Poll};
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let (a, mut b) = UnixStream::pair()?;
+    a.set_nonblocking(true)?;
+
+    let fd = a.as_raw_fd();
+    poll.registry()
+        .register(&SourceFd(&fd), Token(3), Interests::READABLE)?;
+    b.write_all(b"ping")?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(event::token(events.get(0).unwrap()), Token(3));
+    assert!(is_readable(events.get(0).unwrap()));
+
+    //Another socket behind the same number, the first one closed while
+    //registered: the kernel forgot it, and so does registering the number
+    //again
+    let (c, _d) = UnixStream::pair()?;
+    assert_eq!(unsafe { libc::dup2(c.as_raw_fd(), fd) }, fd);
+    poll.registry()
+        .register(&SourceFd(&fd), Token(4), Interests::READABLE)?;
+    poll.registry().deregister(&SourceFd(&fd))?;
+    assert_eq!(poll.registered_count(), 0);
+
+    Ok(())
+}
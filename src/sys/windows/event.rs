@@ -0,0 +1,110 @@
+use crate::ctrl::CtrlSignal;
+use crate::event::Event as PublicEvent;
+use crate::job::JobEvent;
+use crate::readiness::Readiness;
+use crate::token::Token;
+use std::io;
+use winapi::um::minwinbase::OVERLAPPED;
+
+//What the completions of handles, jobs and console signals carry. At most one
+//of them is set, readiness events have none.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Event {
+    completion: Option<IoCompletion>,
+    job: Option<JobEvent>,
+    ctrl: Option<CtrlSignal>,
+}
+
+/// An overlapped operation on a handle of [`Registry::register_handle`] that
+/// completed.
+///
+/// [`Registry::register_handle`]: crate::Registry::register_handle
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IoCompletion {
+    bytes: u32,
+    //the caller's pointer, only handed back
+    overlapped: usize,
+    //0 for success, a Win32 error code otherwise
+    error: u32,
+}
+
+impl IoCompletion {
+    pub(crate) fn new(bytes: u32, overlapped: *mut OVERLAPPED, error: u32) -> IoCompletion {
+        IoCompletion {
+            bytes,
+            overlapped: overlapped as usize,
+            error,
+        }
+    }
+
+    pub fn bytes_transferred(&self) -> usize {
+        self.bytes as usize
+    }
+
+    /// The `OVERLAPPED` the operation was started with, null for a packet
+    /// posted without one.
+    pub fn overlapped(&self) -> *mut OVERLAPPED {
+        self.overlapped as *mut OVERLAPPED
+    }
+
+    /// The bytes transferred, or why the operation failed.
+    pub fn result(&self) -> io::Result<usize> {
+        match self.error {
+            0 => Ok(self.bytes as usize),
+            error => Err(io::Error::from_raw_os_error(error as i32)),
+        }
+    }
+}
+
+impl PublicEvent {
+    pub(crate) fn from_completion(completion: IoCompletion, token: Token) -> PublicEvent {
+        let sys = Event {
+            completion: Some(completion),
+            ..Event::default()
+        };
+        PublicEvent::with_sys(Readiness::EMPTY, token, sys)
+    }
+
+    pub(crate) fn from_job(job: JobEvent, token: Token) -> PublicEvent {
+        let sys = Event {
+            job: Some(job),
+            ..Event::default()
+        };
+        PublicEvent::with_sys(Readiness::EMPTY, token, sys)
+    }
+
+    pub(crate) fn from_ctrl(readiness: Readiness, signal: CtrlSignal, token: Token) -> PublicEvent {
+        let sys = Event {
+            ctrl: Some(signal),
+            ..Event::default()
+        };
+        PublicEvent::with_sys(readiness, token, sys)
+    }
+}
+
+/// Whether `event` is the completion of an operation on a handle of
+/// [`Registry::register_handle`]. Those carry no readiness.
+///
+/// [`Registry::register_handle`]: crate::Registry::register_handle
+pub fn is_io_completion(event: &PublicEvent) -> bool {
+    event.sys().completion.is_some()
+}
+
+pub fn io_completion(event: &PublicEvent) -> Option<IoCompletion> {
+    event.sys().completion
+}
+
+/// The message of an event of a [`JobObject`], those carry no readiness
+/// either.
+///
+/// [`JobObject`]: crate::JobObject
+pub fn job_event(event: &PublicEvent) -> Option<JobEvent> {
+    event.sys().job
+}
+
+/// The signal of an event of a [`CtrlC`].
+///
+/// [`CtrlC`]: crate::CtrlC
+pub fn ctrl_signal(event: &PublicEvent) -> Option<CtrlSignal> {
+    event.sys().ctrl
+}
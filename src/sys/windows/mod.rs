@@ -0,0 +1,42 @@
+//The AFD backend: sockets polled through the AFD device as wepoll does, on
+//an IO completion port that the crate's handle types complete to as well.
+//The Windows-only modules of the crate root reach in here for the port, see
+//`Selector::port`. Without the `os-poll` feature there is only what the
+//facade needs of the platform, the raw sockets.
+#[cfg(feature = "os-poll")]
+pub(crate) mod afd;
+#[cfg(feature = "os-poll")]
+pub(crate) mod event;
+#[cfg(feature = "os-poll")]
+pub(crate) mod net;
+#[cfg(feature = "os-poll")]
+mod poll;
+#[cfg(feature = "os-poll")]
+mod queue;
+#[cfg(feature = "os-poll")]
+pub(crate) mod selector;
+#[cfg(feature = "os-poll")]
+mod slab;
+#[cfg(feature = "os-poll")]
+pub(crate) mod waker;
+#[cfg(feature = "wsapoll")]
+mod wsa_poll;
+
+#[cfg(feature = "os-poll")]
+pub use self::selector::Events;
+#[cfg(feature = "os-poll")]
+pub use self::selector::PollStats;
+#[cfg(feature = "os-poll")]
+pub(crate) use self::selector::{Key, Selector, SelectorConfig};
+#[cfg(feature = "debug-stats")]
+pub use self::selector::{PayloadStats, RegistrationInfo};
+#[cfg(feature = "os-poll")]
+pub(crate) use self::waker::Waker;
+pub(crate) use std::os::windows::io::AsRawSocket as AsRawSource;
+use winapi::um::winsock2::SOCKET;
+
+pub(crate) type RawSource = SOCKET;
+
+pub(crate) fn raw_source<S: AsRawSource + ?Sized>(source: &S) -> RawSource {
+    source.as_raw_socket() as SOCKET
+}
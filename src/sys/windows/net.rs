@@ -0,0 +1,48 @@
+//Socket options and winsock errors, for the backend and the sockets of `net`
+//alike.
+use std::io;
+use std::mem;
+use winapi::ctypes::{c_char, c_int};
+#[cfg(any(feature = "net", feature = "wsapoll"))]
+use winapi::um::winsock2::getsockopt;
+use winapi::um::winsock2::{setsockopt, WSAGetLastError, SOCKET, SOCKET_ERROR};
+
+pub(crate) fn set_opt<T>(socket: SOCKET, level: c_int, name: c_int, value: T) -> io::Result<()> {
+    let r = unsafe {
+        setsockopt(
+            socket,
+            level,
+            name,
+            &value as *const T as *const c_char,
+            mem::size_of::<T>() as c_int,
+        )
+    };
+    if r == SOCKET_ERROR {
+        return Err(last_error());
+    }
+    Ok(())
+}
+
+//Some options are shorter than `T` (BOOLEAN for a BOOL), the rest stays zero
+#[cfg(any(feature = "net", feature = "wsapoll"))]
+pub(crate) fn get_opt<T: Copy>(socket: SOCKET, level: c_int, name: c_int) -> io::Result<T> {
+    let mut value: T = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<T>() as c_int;
+    let r = unsafe {
+        getsockopt(
+            socket,
+            level,
+            name,
+            &mut value as *mut T as *mut c_char,
+            &mut len,
+        )
+    };
+    if r == SOCKET_ERROR {
+        return Err(last_error());
+    }
+    Ok(value)
+}
+
+pub(crate) fn last_error() -> io::Error {
+    io::Error::from_raw_os_error(unsafe { WSAGetLastError() })
+}
@@ -0,0 +1,809 @@
+//What `Poll`, `Registry` and `PollBuilder` have on top on Windows
+#[cfg(all(test, feature = "net"))]
+use super::raw_source;
+use super::selector::PollStats;
+#[cfg(feature = "debug-stats")]
+use super::selector::{PayloadStats, RegistrationInfo};
+#[cfg(all(test, feature = "net"))]
+use crate::error::ErrorKind;
+#[cfg(all(test, feature = "net"))]
+use crate::interests::Interests;
+use crate::poll::{Poll, PollBuilder, Registry};
+use crate::token::Token;
+#[cfg(test)]
+use crate::Events;
+use std::io;
+use std::os::windows::io::AsRawHandle;
+#[cfg(test)]
+use std::time::Duration;
+
+impl Poll {
+    /// Number of registrations the internal storage has room for.
+    ///
+    /// It grows with the number of sockets registered at the same time and
+    /// only comes down again through [`Poll::compact`].
+    pub fn capacity(&self) -> usize {
+        self.registry().selector().capacity()
+    }
+
+    /// Releases internal storage left over from past registrations.
+    ///
+    /// Useful after a burst of short-lived sockets: storage stays sized for
+    /// the high-water mark otherwise. Live registrations, and polls still in
+    /// flight in the kernel, are not affected.
+    pub fn compact(&self) {
+        self.registry().selector().compact()
+    }
+
+    /// Returns what this `Poll` did so far: polls, completions, kernel polls
+    /// submitted and cancelled, and how many sockets are registered.
+    ///
+    /// The counters are cheap enough to be always on. They are approximate
+    /// while other threads use the `Poll`, see [`PollStats`].
+    pub fn stats(&self) -> PollStats {
+        self.registry().selector().stats()
+    }
+
+    /// Sets the counters of [`Poll::stats`] back to zero, the high-water mark
+    /// to the current length of the update queue.
+    pub fn reset_stats(&self) {
+        self.registry().selector().reset_stats()
+    }
+}
+
+impl Registry {
+    /// Ties `handle` to the poll's completion port, for overlapped operations
+    /// the caller starts on it.
+    ///
+    /// Each completion comes back as an event carrying `token` and no
+    /// readiness, see [`event::io_completion`]. The `OVERLAPPED` of an
+    /// operation has to stay put until its completion was returned by `poll`.
+    /// Handles are not polled, and the association can't be undone: it lasts
+    /// as long as the handle. Tokens above `usize::MAX / 2 - 16` are refused,
+    /// the upper half of the range and a few below it: their completion key
+    /// would collide with the crate's own.
+    ///
+    /// [`event::io_completion`]: crate::event::io_completion
+    pub fn register_handle<H>(&self, handle: &H, token: Token) -> io::Result<()>
+    where
+        H: AsRawHandle + ?Sized,
+    {
+        self.selector().register_handle(handle, token)
+    }
+
+    /// Returns the internal state of the registration using `token`.
+    ///
+    /// Meant for debugging a socket that stopped producing events: when its
+    /// poll was last submitted and with what, whether a completion came back,
+    /// whether it waits in the update queue. `None` if no registration uses
+    /// `token`.
+    #[cfg(feature = "debug-stats")]
+    pub fn registration_info(&self, token: Token) -> Option<RegistrationInfo> {
+        self.selector().registration_info(token)
+    }
+
+    /// Returns how often sockets registered so far reused the registration of
+    /// one deregistered before, kernel poll state included, instead of
+    /// allocating it.
+    #[cfg(feature = "debug-stats")]
+    pub fn payload_stats(&self) -> PayloadStats {
+        self.selector().payload_stats()
+    }
+}
+
+impl PollBuilder {
+    //Polls as if the AFD device couldn't be opened
+    #[cfg(all(test, feature = "net", feature = "wsapoll"))]
+    pub(crate) fn deny_afd(mut self) -> PollBuilder {
+        self.config.deny_afd = true;
+        self
+    }
+}
+
+#[test]
+fn test_poll_builder_max_events_per_socket() -> io::Result<()> {
+    let poll = Poll::new()?;
+    assert_eq!(poll.registry().selector().config().max_events_per_socket, 1);
+
+    let poll = Poll::builder().max_events_per_socket(4).build()?;
+    assert_eq!(poll.registry().selector().config().max_events_per_socket, 4);
+
+    let poll = Poll::builder().shards(3).build()?;
+    assert_eq!(poll.registry().selector().config().shards, 3);
+
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_compact_after_churn() -> io::Result<()> {
+    use std::net;
+    use std::os::windows::io::FromRawSocket;
+    use winapi::shared::ws2def::{AF_INET, IPPROTO_TCP};
+    use winapi::um::winsock2::{socket, INVALID_SOCKET, SOCK_STREAM};
+
+    const SOCKETS: usize = 50_000;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(64);
+
+    //Unconnected sockets, 50k connections would run out of ephemeral ports
+    let mut streams = Vec::with_capacity(SOCKETS);
+    for i in 0..SOCKETS {
+        let sock = unsafe { socket(AF_INET, SOCK_STREAM, IPPROTO_TCP as _) };
+        if sock == INVALID_SOCKET {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = unsafe { net::TcpStream::from_raw_socket(sock as _) };
+        let stream = crate::net::TcpStream::from_std(stream);
+        poll.registry()
+            .register(&stream, Token(i), Interests::READABLE)?;
+        streams.push(stream);
+    }
+    assert!(poll.capacity() >= SOCKETS);
+
+    for stream in &streams {
+        poll.registry().deregister(stream)?;
+    }
+    poll.poll(&mut events, Some(Duration::from_millis(0)))?;
+    assert!(poll.capacity() >= SOCKETS);
+
+    poll.compact();
+    assert!(poll.capacity() < SOCKETS / 100, "{}", poll.capacity());
+
+    //Still usable after compacting
+    poll.registry()
+        .register(&streams[0], Token(0), Interests::READABLE)?;
+    assert!(poll.capacity() >= 1);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "net", feature = "debug-stats"))]
+#[test]
+fn test_registration_info() -> io::Result<()> {
+    use std::net;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let stream = crate::net::TcpStream::from_std(net::TcpStream::connect(listener.local_addr()?)?);
+
+    poll.registry()
+        .register(&stream, Token(0), Interests::WRITABLE)?;
+    let info = poll.registry().registration_info(Token(0)).unwrap();
+    assert_eq!(info.interests, Some(Interests::WRITABLE));
+    assert!(info.update_enqueued && !info.poll_pending);
+    assert_eq!((info.submit_count, info.completion_count), (0, 0));
+    assert!(info.last_submit.is_none());
+
+    //Submitted, completed right away, and queued again for the rearm
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    let info = poll.registry().registration_info(Token(0)).unwrap();
+    assert_eq!((info.submit_count, info.completion_count), (1, 1));
+    assert_eq!(info.submitted_interests, Some(Interests::WRITABLE));
+    assert!(info.last_completion.unwrap() >= info.last_submit.unwrap());
+    assert!(info.update_enqueued);
+
+    poll.registry()
+        .reregister(&stream, Token(1), Interests::READABLE | Interests::WRITABLE)?;
+    assert!(poll.registry().registration_info(Token(0)).is_none());
+    let info = poll.registry().registration_info(Token(1)).unwrap();
+    assert_eq!(info.interests, Some(Interests::READABLE | Interests::WRITABLE));
+
+    //Writable is cached, so the new poll only watches for readable
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+    let info = poll.registry().registration_info(Token(1)).unwrap();
+    assert_eq!((info.submit_count, info.completion_count), (2, 1));
+    assert_eq!(info.submitted_interests, Some(Interests::READABLE));
+    assert!(info.poll_pending && !info.update_enqueued);
+
+    Ok(())
+}
+
+//Connections come and go, each echoing a bit: past the first ones they all
+//get the payloads of those closed before
+#[cfg(all(feature = "net", feature = "debug-stats"))]
+#[test]
+fn test_payload_reuse() -> io::Result<()> {
+    use crate::event::{is_readable, token};
+    use std::io::{Read, Write};
+
+    const CONNECTIONS: usize = 200;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let mut buf = [0; 16];
+    for _ in 0..CONNECTIONS {
+        let (mut client, mut server) = crate::net::tcp_pair()?;
+        poll.registry()
+            .register(&client, Token(0), Interests::READABLE)?;
+        poll.registry()
+            .register(&server, Token(1), Interests::READABLE)?;
+
+        assert_eq!(client.write(b"ping")?, 4);
+        let mut echoed = false;
+        while !echoed {
+            poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+            assert!(!events.is_empty(), "echo stalled");
+            for i in 0..events.len() {
+                let event = events.get(i).unwrap();
+                if !is_readable(event) {
+                    continue;
+                }
+                if token(event) == Token(1) {
+                    let n = server.read(&mut buf)?;
+                    assert_eq!(server.write(&buf[..n])?, n);
+                } else {
+                    assert_eq!(client.read(&mut buf)?, 4);
+                    echoed = true;
+                }
+            }
+        }
+
+        poll.registry().deregister(&client)?;
+        poll.registry().deregister(&server)?;
+    }
+    //Those of a deregistration are back once the cancelled poll completed,
+    //that's during the next connection's echo
+    let stats = poll.registry().payload_stats();
+    assert_eq!(stats.reused + stats.allocated, 2 * CONNECTIONS);
+    assert!(stats.reuse_rate() > 0.95, "{:?}", stats);
+
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_priority_out_of_band() -> io::Result<()> {
+    use crate::event;
+    use std::net;
+    use std::os::windows::io::AsRawSocket;
+    use winapi::um::winsock2::{send, SOCKET_ERROR, MSG_OOB};
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let priority = crate::net::TcpStream::from_std(net::TcpStream::connect(addr)?);
+    let (priority_peer, _) = listener.accept()?;
+    let readable = crate::net::TcpStream::from_std(net::TcpStream::connect(addr)?);
+    let (readable_peer, _) = listener.accept()?;
+    poll.registry()
+        .register(&priority, Token(0), Interests::PRIORITY)?;
+    poll.registry()
+        .register(&readable, Token(1), Interests::READABLE)?;
+
+    for peer in &[&priority_peer, &readable_peer] {
+        let n = unsafe { send(peer.as_raw_socket() as _, b"!".as_ptr() as _, 1, MSG_OOB) };
+        if n == SOCKET_ERROR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    let mut seen_priority = false;
+    for _ in 0..10 {
+        poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+        for i in 0..events.len() {
+            let e = events.get(i).unwrap();
+            if event::token(e) == Token(0) {
+                assert!(event::is_priority(e) && !event::is_readable(e));
+                seen_priority = true;
+            } else {
+                //Never asked for it
+                assert!(!event::is_priority(e));
+            }
+        }
+    }
+    assert!(seen_priority);
+
+    Ok(())
+}
+
+#[test]
+fn test_register_handle_completions() -> io::Result<()> {
+    use crate::event;
+    use miow::pipe::NamedPipe;
+    use std::fs::{self, File, OpenOptions};
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::{env, mem, process};
+    use winapi::shared::winerror::{ERROR_IO_PENDING, ERROR_OPERATION_ABORTED};
+    use winapi::um::fileapi::ReadFile;
+    use winapi::um::ioapiset::CancelIoEx;
+    use winapi::um::minwinbase::OVERLAPPED;
+    use winapi::um::winbase::FILE_FLAG_OVERLAPPED;
+    use winapi::um::winnt::HANDLE;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    let path = env::temp_dir().join(format!("iocp-wrapper-read-{}.txt", process::id()));
+    fs::write(&path, b"hello overlapped")?;
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_OVERLAPPED)
+        .open(&path)?;
+    poll.registry().register_handle(&file, Token(7))?;
+
+    let mut overlapped: Box<OVERLAPPED> = Box::new(unsafe { mem::zeroed() });
+    let mut buf = [0u8; 64];
+    let r = unsafe {
+        ReadFile(
+            file.as_raw_handle() as HANDLE,
+            buf.as_mut_ptr() as *mut _,
+            buf.len() as u32,
+            std::ptr::null_mut(),
+            &mut *overlapped,
+        )
+    };
+    if r == 0 {
+        let e = io::Error::last_os_error();
+        assert_eq!(e.raw_os_error(), Some(ERROR_IO_PENDING as i32));
+    }
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    let event = events.get(0).unwrap();
+    assert_eq!(event::token(event), Token(7));
+    assert!(event::is_io_completion(event));
+    assert!(event.readiness().is_empty());
+    let completion = event::io_completion(event).unwrap();
+    assert_eq!(completion.overlapped(), &mut *overlapped as *mut OVERLAPPED);
+    assert_eq!(completion.result()?, 16);
+    assert_eq!(&buf[..16], b"hello overlapped");
+    drop(file);
+    fs::remove_file(&path)?;
+
+    //Failed operations come back too: a read no data ever arrives for,
+    //cancelled
+    let name = format!(r"\\.\pipe\iocp-wrapper-handle-{}", process::id());
+    let pipe = NamedPipe::new(&name)?;
+    let _client = File::open(&name)?;
+    pipe.connect()?;
+    poll.registry().register_handle(&pipe, Token(8))?;
+
+    let mut overlapped: Box<OVERLAPPED> = Box::new(unsafe { mem::zeroed() });
+    assert_eq!(unsafe { pipe.read_overlapped(&mut buf, &mut *overlapped)? }, None);
+    unsafe { CancelIoEx(pipe.as_raw_handle() as HANDLE, &mut *overlapped) };
+
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    let event = events.get(0).unwrap();
+    assert_eq!(event::token(event), Token(8));
+    let completion = event::io_completion(event).unwrap();
+    assert_eq!(completion.bytes_transferred(), 0);
+    let e = completion.result().unwrap_err();
+    assert_eq!(e.raw_os_error(), Some(ERROR_OPERATION_ABORTED as i32));
+
+    Ok(())
+}
+
+#[test]
+fn test_register_handle_token_range() -> io::Result<()> {
+    use std::fs::{self, File};
+    use std::{env, process};
+
+    let poll = Poll::new()?;
+    let path = env::temp_dir().join(format!("iocp-wrapper-range-{}.txt", process::id()));
+    let file = File::create(&path)?;
+
+    for &token in [usize::MAX / 2 - 15, usize::MAX / 2 + 1, usize::MAX - 1].iter() {
+        let err = poll
+            .registry()
+            .register_handle(&file, Token(token))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:#x}", token);
+    }
+    //The largest one left, refusals didn't associate the handle
+    poll.registry()
+        .register_handle(&file, Token(usize::MAX / 2 - 16))?;
+    drop(file);
+    fs::remove_file(&path)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_sub_millisecond_timeout() -> io::Result<()> {
+    use ntapi::ntexapi::NtSetTimerResolution;
+    use std::time::Instant;
+    use winapi::shared::ntdef::{FALSE, TRUE};
+
+    const ROUNDS: u32 = 200;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+
+    //Waits end on a clock tick, 15.6ms apart by default: asks for 0.5ms
+    let mut actual = 0;
+    unsafe { NtSetTimerResolution(5_000, TRUE, &mut actual) };
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        poll.poll(&mut events, Some(Duration::from_micros(250)))?;
+        assert!(events.is_empty());
+    }
+    let average = started.elapsed() / ROUNDS;
+    unsafe { NtSetTimerResolution(5_000, FALSE, &mut actual) };
+
+    //Rounded down to whole milliseconds, each would have returned right away
+    assert!(average >= Duration::from_micros(250), "{:?}", average);
+    assert!(average < Duration::from_micros(625), "{:?}", average);
+
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_poll_stats() -> io::Result<()> {
+    use crate::event;
+    use std::net;
+    use std::time::Instant;
+
+    const SOCKETS: usize = 10;
+    const ROUNDS: usize = 5;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(64);
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    let mut sockets = Vec::new();
+    for i in 0..SOCKETS {
+        let socket = crate::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+        poll.registry()
+            .register(&socket, Token(i), Interests::READABLE)?;
+        sockets.push(socket);
+    }
+    let stats = poll.stats();
+    assert_eq!(stats.registered, SOCKETS as u64);
+    //Nothing submitted before the first poll
+    assert_eq!(stats.update_queue_high_water, SOCKETS as u64);
+    assert_eq!(stats.submissions, 0);
+
+    let mut polls = 0;
+    let mut buf = [0; 8];
+    for _ in 0..ROUNDS {
+        for socket in &sockets {
+            assert_eq!(sender.send_to(b"ping", socket.local_addr()?)?, 4);
+        }
+        let mut readable = 0;
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while readable < SOCKETS {
+            assert!(Instant::now() < deadline, "datagrams went missing");
+            poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+            polls += 1;
+            for i in 0..events.len() {
+                let event = events.get(i).unwrap();
+                assert!(event::is_readable(event));
+                let socket = &sockets[usize::from(event::token(event))];
+                while socket.recv_from(&mut buf).is_ok() {
+                    readable += 1;
+                }
+            }
+        }
+    }
+    //Submits the rearms of the last round
+    poll.poll(&mut events, Some(Duration::from_millis(0)))?;
+    polls += 1;
+
+    let stats = poll.stats();
+    let datagrams = (SOCKETS * ROUNDS) as u64;
+    assert_eq!(stats.polls, polls);
+    assert!(stats.completions >= datagrams, "{:?}", stats);
+    assert!(stats.completions <= datagrams + polls, "{:?}", stats);
+    //A first poll each, then one per event
+    assert!(stats.submissions >= SOCKETS as u64 + datagrams, "{:?}", stats);
+    assert!(stats.submissions <= 2 * (SOCKETS as u64 + datagrams), "{:?}", stats);
+    assert_eq!(stats.cancellations, 0);
+
+    poll.reset_stats();
+    let stats = poll.stats();
+    assert_eq!(stats.polls, 0);
+    assert_eq!(stats.completions, 0);
+    assert_eq!(stats.update_queue_high_water, 0);
+    assert_eq!(stats.registered, SOCKETS as u64);
+
+    //Polls are all pending, deregistering cancels each
+    for socket in &sockets {
+        poll.registry().deregister(socket)?;
+    }
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while poll.stats().registered > 0 {
+        assert!(Instant::now() < deadline, "cancelled polls never completed");
+        poll.poll(&mut events, Some(Duration::from_millis(10)))?;
+    }
+    let stats = poll.stats();
+    assert_eq!(stats.cancellations, SOCKETS as u64);
+    assert_eq!(stats.submissions, 0);
+    assert!(stats.completions >= SOCKETS as u64, "{:?}", stats);
+
+    Ok(())
+}
+
+//`cargo test -- --ignored scale_100k`, takes a while
+#[cfg(feature = "net")]
+#[test]
+#[ignore]
+fn test_scale_100k() -> io::Result<()> {
+    use crate::event;
+    use socket2::{Domain, Socket, Type};
+    use std::collections::HashSet;
+    use std::mem;
+    use std::net;
+    use winapi::um::processthreadsapi::GetCurrentProcess;
+    use winapi::um::psapi::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS_EX};
+
+    const SOCKETS: usize = 100_000;
+    const AWAKE: usize = SOCKETS / 100;
+    const IDLE: usize = SOCKETS - AWAKE;
+    //Private memory a registration may take, the socket itself aside
+    const CEILING: usize = 1024;
+
+    fn private_usage() -> io::Result<usize> {
+        let mut counters: PROCESS_MEMORY_COUNTERS_EX = unsafe { mem::zeroed() };
+        let size = mem::size_of::<PROCESS_MEMORY_COUNTERS_EX>() as u32;
+        let ok = unsafe {
+            GetProcessMemoryInfo(GetCurrentProcess(), &mut counters as *mut _ as *mut _, size)
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(counters.PrivateUsage)
+    }
+
+    //Only the sockets woken up are bound, ephemeral ports wouldn't go round.
+    //The others are polled all the same, and never get anything.
+    let idle = (0..IDLE)
+        .map(|_| Socket::new(Domain::ipv4(), Type::dgram(), None))
+        .collect::<io::Result<Vec<_>>>()?;
+    let awake = (0..AWAKE)
+        .map(|_| crate::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap()))
+        .collect::<io::Result<Vec<_>>>()?;
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(1024);
+    let before = private_usage()?;
+    for (i, socket) in idle.iter().enumerate() {
+        poll.registry()
+            .selector()
+            .register(raw_source(socket), Token(i), Interests::READABLE)?;
+    }
+    for (i, socket) in awake.iter().enumerate() {
+        poll.registry()
+            .register(socket, Token(IDLE + i), Interests::READABLE)?;
+    }
+    //Submits every poll
+    poll.poll(&mut events, Some(Duration::from_millis(0)))?;
+    assert!(events.is_empty());
+    assert_eq!(poll.stats().registered, SOCKETS as u64);
+    assert_eq!(poll.stats().submissions, SOCKETS as u64);
+
+    let per_socket = private_usage()?.saturating_sub(before) / SOCKETS;
+    assert!(per_socket < CEILING, "{} bytes per registration", per_socket);
+
+    //A poll doesn't look at the sockets that have nothing to report, none is
+    //submitted again
+    for _ in 0..100 {
+        poll.poll(&mut events, Some(Duration::from_millis(0)))?;
+        assert!(events.is_empty());
+    }
+    assert_eq!(poll.stats().submissions, SOCKETS as u64);
+
+    for socket in &awake {
+        assert_eq!(sender.send_to(b"wake", socket.local_addr()?)?, 4);
+    }
+    let mut woken = HashSet::new();
+    for _ in 0..50 {
+        if woken.len() == AWAKE {
+            break;
+        }
+        poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+        for i in 0..events.len() {
+            let token = usize::from(event::token(events.get(i).unwrap()));
+            assert!(token >= IDLE, "idle socket {} woken", token);
+            woken.insert(token);
+        }
+    }
+    assert_eq!(woken.len(), AWAKE);
+    //Nor does waking some of them up take more per registration
+    let per_socket = private_usage()?.saturating_sub(before) / SOCKETS;
+    assert!(per_socket < CEILING, "{} bytes per registration", per_socket);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "net", feature = "trace"))]
+#[test]
+fn test_trace_registration_cycle() -> io::Result<()> {
+    use crate::trace;
+    use std::net;
+
+    let (r, lines) = trace::capture(|| -> io::Result<()> {
+        let mut poll = Poll::new()?;
+        let mut events = Events::with_capacity(8);
+        let socket = crate::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+        let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+        poll.registry()
+            .register(&socket, Token(3), Interests::READABLE)?;
+        poll.poll(&mut events, Some(Duration::from_millis(10)))?;
+        assert!(events.is_empty());
+
+        assert_eq!(sender.send_to(b"ping", socket.local_addr()?)?, 4);
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        let mut buf = [0; 4];
+        assert_eq!(socket.recv_from(&mut buf)?.0, 4);
+        //The poll is submitted again, then cancelled by the deregistration
+        poll.poll(&mut events, Some(Duration::from_millis(0)))?;
+        poll.registry().deregister(&socket)?;
+        poll.poll(&mut events, Some(Duration::from_millis(100)))
+    });
+    r?;
+
+    let mut steps = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix("token=3 "));
+    let mut next = || steps.next().expect("trace missing");
+    assert_eq!(next(), "register READABLE");
+    let submit = next();
+    assert!(submit.starts_with("submit ") && !submit.contains("error="));
+    let completion = next();
+    assert!(completion.starts_with("completion status=0x0 "), "{}", completion);
+    assert!(completion.contains("readiness=Some(Readable"), "{}", completion);
+    let submit = next();
+    assert!(submit.starts_with("submit ") && !submit.contains("error="));
+    assert_eq!(next(), "deregister");
+    assert_eq!(next(), "cancel");
+    let completion = next();
+    assert!(completion.ends_with("readiness=None"), "{}", completion);
+    assert_eq!(next(), "reclaim");
+    assert_eq!(steps.next(), None);
+
+    //Each poll is traced on the way in and out
+    let enters = lines.iter().filter(|line| line.starts_with("poll timeout="));
+    assert_eq!(enters.count(), 4);
+    assert!(lines.iter().any(|line| line == "poll returned events=1"));
+
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_drop_with_registrations() -> io::Result<()> {
+    use crate::event;
+    use std::net;
+
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    let sockets = (0..3)
+        .map(|_| crate::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    for (i, socket) in sockets.iter().enumerate() {
+        poll.registry()
+            .register(socket, Token(i), Interests::READABLE)?;
+    }
+    assert_eq!(poll.registered_count(), 3);
+    poll.poll(&mut events, Some(Duration::from_millis(10)))?;
+    poll.registry().deregister(&sockets[0])?;
+    //Deregistered right away, whatever the kernel still has to say
+    assert_eq!(poll.registered_count(), 2);
+    drop(poll);
+
+    //Nothing of the old one is left in the way
+    let mut poll = Poll::new()?;
+    poll.registry()
+        .register(&sockets[1], Token(7), Interests::READABLE)?;
+    assert_eq!(sender.send_to(b"ping", sockets[1].local_addr()?)?, 4);
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(event::token(events.get(0).unwrap()), Token(7));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "net", feature = "trace"))]
+#[test]
+fn test_trace_leaks() -> io::Result<()> {
+    use crate::trace;
+
+    let leaks = |lines: &[String]| -> Vec<String> {
+        let leaks = lines.iter().filter(|line| line.ends_with(" leak"));
+        leaks.cloned().collect()
+    };
+    let socket = crate::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let other = crate::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+
+    //Clean teardown
+    let (r, lines) = trace::capture(|| -> io::Result<()> {
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&socket, Token(1), Interests::READABLE)?;
+        poll.registry().deregister(&socket)?;
+        assert_eq!(poll.registered_count(), 0);
+        Ok(())
+    });
+    r?;
+    assert!(leaks(&lines).is_empty());
+
+    //One left registered
+    let (r, lines) = trace::capture(|| -> io::Result<()> {
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&socket, Token(1), Interests::READABLE)?;
+        poll.registry()
+            .register(&other, Token(2), Interests::READABLE)?;
+        poll.registry().deregister(&other)?;
+        assert_eq!(poll.registered_count(), 1);
+        Ok(())
+    });
+    r?;
+    assert_eq!(leaks(&lines), ["token=1 leak"]);
+    //And it was deregistered, its poll waited for
+    assert!(lines.iter().any(|line| line == "token=1 deregister"));
+    assert!(lines.iter().any(|line| line == "token=1 reclaim"));
+
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_misuse_errors() -> io::Result<()> {
+    use crate::error::Error;
+    use std::mem;
+
+    let misuse = |error: io::Error| {
+        let inner = Error::of(&error).expect("not a misuse");
+        (inner.kind(), inner.token())
+    };
+
+    let poll = Poll::new()?;
+    let socket = crate::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap())?;
+    let err = poll
+        .registry()
+        .reregister(&socket, Token(1), Interests::READABLE)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(misuse(err), (ErrorKind::NotRegistered, Some(Token(1))));
+    let err = poll.registry().deregister(&socket).unwrap_err();
+    assert_eq!(misuse(err), (ErrorKind::NotRegistered, None));
+
+    poll.registry()
+        .register(&socket, Token(2), Interests::READABLE)?;
+    let err = poll
+        .registry()
+        .register(&socket, Token(3), Interests::READABLE)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(misuse(err), (ErrorKind::AlreadyRegistered, Some(Token(3))));
+
+    //No constant has the high bit, only unsafe code gets there
+    let unsupported = unsafe { mem::transmute::<u8, Interests>(0x80) };
+    let err = poll
+        .registry()
+        .reregister(&socket, Token(4), unsupported)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        misuse(err),
+        (ErrorKind::UnsupportedInterests, Some(Token(4)))
+    );
+    poll.registry().deregister(&socket)?;
+
+    let registration = poll
+        .registry()
+        .register_guarded(&socket, Token(5), Interests::READABLE)?;
+    drop(poll);
+    let err = registration
+        .reregister(Token(6), Interests::WRITABLE)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert_eq!(misuse(err), (ErrorKind::PollDropped, Some(Token(6))));
+    let err = registration.deregister().unwrap_err();
+    assert_eq!(misuse(err), (ErrorKind::PollDropped, None));
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Arc;
+
+//Multi-producer single-consumer queue, Dmitry Vyukov's intrusive node based
+//MPSC. The link is part of the queued value, so a push allocates nothing: it
+//is one swap plus one store, and never waits on other producers nor on the
+//consumer. A value is only ever in the queue once at a time. Popping is only
+//allowed through `drain()`, which makes sure there is a single consumer at any
+//time.
+pub(crate) struct MpscQueue<T: Linked> {
+    //most recently pushed node, producers swap themselves in here
+    head: AtomicPtr<Link>,
+    //oldest node, or the stub in front of it. Consumer only
+    tail: UnsafeCell<*mut Link>,
+    //put back in whenever the queue would run out of nodes otherwise
+    stub: Box<Link>,
+    //held by the thread currently draining
+    consuming: AtomicBool,
+    //the queue owns a reference to each value in it
+    marker: PhantomData<Arc<T>>,
+}
+
+//What a queued value is linked through
+pub(crate) struct Link {
+    next: AtomicPtr<Link>,
+}
+
+impl Link {
+    pub fn new() -> Link {
+        Link {
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+/// Implemented by `#[repr(C)]` types having a `Link` as their first field, a
+/// pointer to the value is one to its link.
+///
+/// # Safety
+///
+/// The queue writes through the `Link` of a value pushed, until it pops it.
+/// The implementing type must start with its `Link`, and the callers of
+/// `push` keep a value where it is and push it once only until it was popped.
+pub(crate) unsafe trait Linked {}
+
+unsafe impl<T: Linked + Send + Sync> Send for MpscQueue<T> {}
+unsafe impl<T: Linked + Send + Sync> Sync for MpscQueue<T> {}
+
+impl<T: Linked> MpscQueue<T> {
+    pub fn new() -> MpscQueue<T> {
+        let mut stub = Box::new(Link::new());
+        let stub_ptr: *mut Link = &mut *stub;
+        MpscQueue {
+            head: AtomicPtr::new(stub_ptr),
+            tail: UnsafeCell::new(stub_ptr),
+            stub,
+            consuming: AtomicBool::new(false),
+            marker: PhantomData,
+        }
+    }
+
+    //`value` must not be in the queue already
+    pub fn push(&self, value: Arc<T>) {
+        unsafe { self.push_link(Arc::into_raw(value) as *mut Link) };
+    }
+
+    unsafe fn push_link(&self, node: *mut Link) {
+        (*node).next.store(ptr::null_mut(), Ordering::SeqCst);
+        let prev = self.head.swap(node, Ordering::SeqCst);
+        //Until this store lands the consumer sees the queue as ending at `prev`
+        (*prev).next.store(node, Ordering::SeqCst);
+    }
+
+    //Returns None if another thread is already draining the queue
+    pub fn drain(&self) -> Option<Drain<'_, T>> {
+        match self
+            .consuming
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => Some(Drain { queue: self }),
+            Err(_) => None,
+        }
+    }
+
+    fn stub(&self) -> *mut Link {
+        &*self.stub as *const Link as *mut Link
+    }
+
+    //Caller must be the single consumer
+    unsafe fn pop(&self) -> Option<Arc<T>> {
+        let stub = self.stub();
+        let mut tail = *self.tail.get();
+        let mut next = (*tail).next.load(Ordering::SeqCst);
+
+        if tail == stub {
+            if next.is_null() {
+                return None;
+            }
+            *self.tail.get() = next;
+            tail = next;
+            next = (*next).next.load(Ordering::SeqCst);
+        }
+
+        if !next.is_null() {
+            *self.tail.get() = next;
+            return Some(Arc::from_raw(tail as *const T));
+        }
+
+        //Either `tail` is the last node, or a producer is between its swap and
+        //its store. In the latter case the rest is picked up by the next drain.
+        if tail != self.head.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        //The last node can only go once something is behind it
+        self.push_link(stub);
+        next = (*tail).next.load(Ordering::SeqCst);
+        if !next.is_null() {
+            *self.tail.get() = next;
+            return Some(Arc::from_raw(tail as *const T));
+        }
+
+        None
+    }
+}
+
+impl<T: Linked> Drop for MpscQueue<T> {
+    fn drop(&mut self) {
+        unsafe { while self.pop().is_some() {} }
+    }
+}
+
+pub(crate) struct Drain<'a, T: Linked> {
+    queue: &'a MpscQueue<T>,
+}
+
+impl<'a, T: Linked> Iterator for Drain<'a, T> {
+    type Item = Arc<T>;
+
+    fn next(&mut self) -> Option<Arc<T>> {
+        unsafe { self.queue.pop() }
+    }
+}
+
+impl<'a, T: Linked> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        self.queue.consuming.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+#[repr(C)]
+struct TestNode<T> {
+    link: Link,
+    value: T,
+}
+
+#[cfg(test)]
+unsafe impl<T> Linked for TestNode<T> {}
+
+#[cfg(test)]
+fn node<T>(value: T) -> Arc<TestNode<T>> {
+    Arc::new(TestNode {
+        link: Link::new(),
+        value,
+    })
+}
+
+#[test]
+fn test_mpsc_queue_stress() {
+    use std::thread;
+
+    const PRODUCERS: usize = 8;
+    const PER_PRODUCER: usize = 10_000;
+
+    let queue = Arc::new(MpscQueue::new());
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|p| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for i in 0..PER_PRODUCER {
+                    queue.push(node((p, i)));
+                }
+            })
+        })
+        .collect();
+
+    //Single consumer draining while producers are still running
+    let mut next_expected = [0; PRODUCERS];
+    let mut received = 0;
+    while received < PRODUCERS * PER_PRODUCER {
+        let drain = queue.drain().unwrap();
+        for (p, i) in drain.map(|node| node.value) {
+            //nothing lost, nothing duplicated, per producer order kept
+            assert_eq!(i, next_expected[p]);
+            next_expected[p] += 1;
+            received += 1;
+        }
+        thread::yield_now();
+    }
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    assert!(queue.drain().unwrap().next().is_none());
+}
+
+#[test]
+fn test_mpsc_queue_single_consumer() {
+    let queue = MpscQueue::new();
+    queue.push(node(1));
+
+    let drain = queue.drain().unwrap();
+    assert!(queue.drain().is_none());
+    drop(drain);
+
+    let values: Vec<_> = queue.drain().unwrap().map(|node| node.value).collect();
+    assert_eq!(values, vec![1]);
+}
+
+#[test]
+fn test_mpsc_queue_push_again() {
+    let queue = MpscQueue::new();
+    let (a, b) = (node('a'), node('b'));
+
+    //Popped values can go back in, in any order, the last one included
+    for _ in 0..3 {
+        queue.push(a.clone());
+        queue.push(b.clone());
+        let drained: Vec<_> = queue.drain().unwrap().map(|node| node.value).collect();
+        assert_eq!(drained, vec!['a', 'b']);
+
+        queue.push(b.clone());
+        let drained: Vec<_> = queue.drain().unwrap().map(|node| node.value).collect();
+        assert_eq!(drained, vec!['b']);
+    }
+    //Nothing left holding on to them
+    assert_eq!((Arc::strong_count(&a), Arc::strong_count(&b)), (1, 1));
+}
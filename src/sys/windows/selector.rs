@@ -0,0 +1,3171 @@
+use super::afd::{
+    afd_cancel, afd_create_helper_handle, afd_poll, epoll_to_interests, init, interests_to_epoll,
+    sock_afd_events_to_epoll_events, sock_epoll_events_to_afd_events, ws_get_base_socket,
+    HasOverlappedIoCompleted, AFD_POLL_CONNECT_FAIL, AFD_POLL_HANDLE_INFO, AFD_POLL_INFO,
+    AFD_POLL_LOCAL_CLOSE, SOCK_KNOWN_EPOLL_EVENTS,
+};
+use super::event::IoCompletion;
+use super::net::set_opt;
+use super::queue::{Link, Linked, MpscQueue};
+#[cfg(test)]
+use super::raw_source;
+use super::slab::{Slab, SlabKey};
+use super::waker::{complete as complete_waker, WAKER_KEY};
+#[cfg(feature = "wsapoll")]
+use super::wsa_poll::WsaPoll;
+use crate::ctrl::{complete as complete_ctrl, CTRL_KEY};
+use crate::error::{misuse, with_socket, ErrorKind};
+use crate::event::Event;
+use crate::interests::Interests;
+use crate::job::JobEvent;
+#[cfg(feature = "net")]
+use crate::net::{complete_accept, complete_pipe, complete_watch, ACCEPT_KEY, PIPE_KEY, WATCH_KEY};
+use crate::readiness::Readiness;
+use crate::stdin::{complete as complete_stdin, STDIN_KEY};
+use crate::token::Token;
+#[cfg(feature = "trace")]
+use crate::trace::Trace;
+use crate::wait::{complete as complete_wait, WAIT_KEY};
+use crate::{EPOLLERR, EPOLLHUP, EPOLLONESHOT, EPOLLOUT, EPOLLWRBAND, EPOLLWRNORM};
+use miow::iocp::{CompletionPort, CompletionStatus};
+use miow::Overlapped;
+use ntapi::ntioapi::{NtRemoveIoCompletionEx, FILE_IO_COMPLETION_INFORMATION};
+use ntapi::ntrtl::RtlNtStatusToDosError;
+use std::collections::hash_map::Entry;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem;
+use std::ops::Deref;
+use std::os::windows::io::AsRawHandle;
+#[cfg(test)]
+use std::os::windows::io::AsRawSocket;
+use std::ptr::{self, null_mut};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{DWORD, FALSE, LPVOID, ULONG};
+use winapi::shared::ntdef::{self, BOOLEAN, NTSTATUS, NULL, PVOID};
+use winapi::shared::ntstatus::{
+    STATUS_CANCELLED, STATUS_CONNECTION_ABORTED, STATUS_CONNECTION_REFUSED,
+    STATUS_CONNECTION_RESET, STATUS_HOST_UNREACHABLE, STATUS_IO_TIMEOUT,
+    STATUS_NETWORK_UNREACHABLE, STATUS_PORT_UNREACHABLE, STATUS_SUCCESS, STATUS_TIMEOUT,
+};
+use winapi::shared::winerror::{
+    ERROR_INVALID_HANDLE, ERROR_IO_PENDING, WAIT_TIMEOUT, WSAECONNABORTED, WSAECONNREFUSED,
+    WSAECONNRESET, WSAEHOSTUNREACH, WSAENETUNREACH, WSAETIMEDOUT,
+};
+#[cfg(any(test, feature = "wsapoll"))]
+use winapi::shared::winerror::ERROR_ACCESS_DENIED;
+use winapi::shared::ws2def::{SOL_SOCKET, SO_ERROR};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::jobapi2::SetInformationJobObject;
+use winapi::um::minwinbase::OVERLAPPED;
+use winapi::um::winnt::{
+    JobObjectAssociateCompletionPortInformation, HANDLE, JOBOBJECT_ASSOCIATE_COMPLETION_PORT,
+    LARGE_INTEGER,
+};
+use winapi::um::winsock2::SOCKET;
+
+static MAX_SOCKET_PER_POLL_GROUP: i32 = 32;
+
+#[derive(Clone)]
+pub struct PollGroup {
+    pub group_size: i32,
+    //one PollGroup can have at most 32 socket associated
+    pub afd_helper_handle: HANDLE,
+    //the thread standing in for AFD, in place of a helper handle, see
+    //`PollGroupQueue::acquire`
+    #[cfg(feature = "wsapoll")]
+    pub fallback: Option<Arc<WsaPoll>>,
+}
+
+impl PollGroup {
+    pub fn new(iocp: &HANDLE) -> io::Result<PollGroup> {
+        afd_create_helper_handle(iocp).map(|achh| PollGroup {
+            group_size: 0,
+            afd_helper_handle: achh,
+            #[cfg(feature = "wsapoll")]
+            fallback: None,
+        })
+    }
+
+    fn cancel(&self, overlapped: &mut OVERLAPPED) -> io::Result<()> {
+        #[cfg(feature = "wsapoll")]
+        {
+            if let Some(ref fallback) = self.fallback {
+                fallback.cancel(overlapped);
+                return Ok(());
+            }
+        }
+        afd_cancel(self.afd_helper_handle, overlapped)
+    }
+}
+
+//The groups of a port, by helper handle value. With many sockets there are
+//thousands of them: taking one with room and giving a slot back are both O(1).
+pub struct PollGroupQueue {
+    groups: HashMap<usize, PollGroup>,
+    //groups with room left, in no particular order. The last is the one the
+    //next socket goes to, a group that gets room again is put back in.
+    with_room: Vec<HANDLE>,
+    iocp: HANDLE,
+    //set once the AFD device turned out to be off limits, every socket then
+    //goes to the one thread
+    #[cfg(feature = "wsapoll")]
+    fallback: Option<Arc<WsaPoll>>,
+    //makes opening the AFD device fail as in a sandbox
+    #[cfg(test)]
+    deny_afd: bool,
+}
+
+unsafe impl Send for PollGroupQueue {}
+
+impl PollGroupQueue {
+    pub fn new(completion_port: &CompletionPort) -> PollGroupQueue {
+        PollGroupQueue {
+            groups: HashMap::new(),
+            with_room: Vec::new(),
+            iocp: completion_port.as_raw_handle(),
+            #[cfg(feature = "wsapoll")]
+            fallback: None,
+            #[cfg(test)]
+            deny_afd: false,
+        }
+    }
+
+    //Where opening the AFD device is denied, as in AppContainer sandboxes,
+    //and with the `wsapoll` feature, sockets are polled with WSAPoll() from
+    //a helper thread instead. For good: AFD isn't tried again.
+    pub fn acquire(&mut self) -> io::Result<PollGroup> {
+        #[cfg(feature = "wsapoll")]
+        {
+            if let Some(ref fallback) = self.fallback {
+                return Ok(PollGroup {
+                    group_size: 0,
+                    afd_helper_handle: NULL,
+                    fallback: Some(fallback.clone()),
+                });
+            }
+        }
+
+        let handle = match self.with_room.last() {
+            Some(&handle) => handle,
+            None => {
+                let pg = match self.create_group() {
+                    Ok(pg) => pg,
+                    #[cfg(feature = "wsapoll")]
+                    Err(ref e) if e.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) => {
+                        self.fallback = Some(Arc::new(WsaPoll::new(self.iocp)?));
+                        return self.acquire();
+                    }
+                    Err(e) => return Err(e),
+                };
+                let handle = pg.afd_helper_handle;
+                self.groups.insert(handle as usize, pg);
+                self.with_room.push(handle);
+                handle
+            }
+        };
+
+        let pg = self.groups.get_mut(&(handle as usize)).unwrap();
+        pg.group_size += 1;
+        if pg.group_size >= MAX_SOCKET_PER_POLL_GROUP {
+            self.with_room.pop();
+        }
+        Ok(pg.clone())
+    }
+
+    fn create_group(&self) -> io::Result<PollGroup> {
+        #[cfg(test)]
+        {
+            if self.deny_afd {
+                return Err(io::Error::from_raw_os_error(ERROR_ACCESS_DENIED as i32));
+            }
+        }
+        PollGroup::new(&self.iocp)
+    }
+
+    //Slots freed by deregistrations are used again before any new group is
+    //created, so churn doesn't leave a trail of half empty groups behind.
+    pub fn release(&mut self, poll_group: &PollGroup) {
+        let handle = poll_group.afd_helper_handle;
+        if let Some(pg) = self.groups.get_mut(&(handle as usize)) {
+            pg.group_size -= 1;
+            if pg.group_size == MAX_SOCKET_PER_POLL_GROUP - 1 {
+                self.with_room.push(handle);
+            }
+        }
+    }
+
+    //Closes the helper handles no socket uses anymore. A group only gets
+    //empty once none of its sockets has a poll in flight.
+    pub fn compact(&mut self) {
+        self.groups.retain(|_, pg| {
+            if pg.group_size == 0 {
+                unsafe { CloseHandle(pg.afd_helper_handle) };
+                false
+            } else {
+                true
+            }
+        });
+        let groups = &self.groups;
+        self.with_room
+            .retain(|&handle| groups.contains_key(&(handle as usize)));
+        self.groups.shrink_to_fit();
+        self.with_room.shrink_to_fit();
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.groups.len()
+    }
+}
+
+#[derive(PartialEq)]
+pub(crate) enum SockPollState {
+    Idle,
+    Pending,
+    Cancelled,
+}
+
+//Everything the kernel writes to while an AFD poll is in flight.
+//It lives in the registration's `SockEntry`, which never moves, and the
+//overlapped pointer handed back by the completion port points at its start.
+#[repr(C)]
+pub(crate) struct PollPayload {
+    pub overlapped: OVERLAPPED,
+    pub poll_info: PollInfo,
+    //shard of the registration table the owning SockState is in, and its
+    //slot there. Never change: completions go pointer -> payload -> key ->
+    //state without any hashing, taking the lock of that shard only.
+    pub shard: usize,
+    pub key: SlabKey,
+}
+
+impl PollPayload {
+    fn new(shard: usize, key: SlabKey) -> PollPayload {
+        PollPayload {
+            overlapped: OVERLAPPED::default(),
+            poll_info: PollInfo::single(NULL, 0),
+            shard,
+            key,
+        }
+    }
+}
+
+//Most handles a batched poll takes, as many as sockets in a poll group
+const MAX_POLL_BATCH: usize = 32;
+
+//What is handed to the kernel for a poll. A registration polls its own socket
+//only, that is kept inline with no allocation. Polls of several handles at
+//once spill to a block of their own.
+pub(crate) enum PollInfo {
+    Single(AFD_POLL_INFO),
+    Batch(Box<AFD_POLL_INFO<MAX_POLL_BATCH>>),
+}
+
+impl PollInfo {
+    //Never times out, the same goes for batches: a poll ends with an event or
+    //when cancelled
+    fn single(handle: HANDLE, events: DWORD) -> PollInfo {
+        let mut info = AFD_POLL_INFO {
+            Timeout: LARGE_INTEGER::default(),
+            NumberOfHandles: 1,
+            Exclusive: 0,
+            Handles: [AFD_POLL_HANDLE_INFO {
+                Handle: handle,
+                Events: events,
+                Status: 0,
+            }],
+        };
+        unsafe { *info.Timeout.QuadPart_mut() = i64::MAX };
+        PollInfo::Single(info)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn batch(handles: &[(HANDLE, DWORD)]) -> PollInfo {
+        assert!(handles.len() <= MAX_POLL_BATCH, "too many handles for one poll");
+        let mut info = Box::new(AFD_POLL_INFO {
+            Timeout: LARGE_INTEGER::default(),
+            NumberOfHandles: handles.len() as ULONG,
+            Exclusive: 0,
+            Handles: unsafe { mem::zeroed() },
+        });
+        unsafe { *info.Timeout.QuadPart_mut() = i64::MAX };
+        for (slot, &(handle, events)) in info.Handles.iter_mut().zip(handles) {
+            slot.Handle = handle;
+            slot.Events = events;
+        }
+        PollInfo::Batch(info)
+    }
+
+    //Once the poll completed, the handles that got events and what they got
+    fn handles(&self) -> &[AFD_POLL_HANDLE_INFO] {
+        let (handles, n) = match *self {
+            PollInfo::Single(ref info) => (&info.Handles[..], info.NumberOfHandles),
+            PollInfo::Batch(ref info) => (&info.Handles[..], info.NumberOfHandles),
+        };
+        &handles[..cmp::min(n as usize, handles.len())]
+    }
+
+    fn submit(&mut self, poll_group: &PollGroup, overlapped: &mut OVERLAPPED) -> io::Result<()> {
+        #[cfg(feature = "wsapoll")]
+        {
+            if let Some(ref fallback) = poll_group.fallback {
+                return match *self {
+                    PollInfo::Single(ref mut info) => fallback.submit(info, overlapped),
+                    PollInfo::Batch(ref mut info) => fallback.submit(&mut **info, overlapped),
+                };
+            }
+        }
+        let afd_helper_handle = poll_group.afd_helper_handle;
+        match *self {
+            PollInfo::Single(ref mut info) => afd_poll(afd_helper_handle, info, overlapped),
+            PollInfo::Batch(ref mut info) => afd_poll(afd_helper_handle, &mut **info, overlapped),
+        }
+    }
+}
+
+//Retired registrations kept for new ones, past that they are freed
+const PAYLOAD_POOL_SIZE: usize = 4096;
+
+//Blocks of registrations that went away, handed to new ones instead of
+//allocating. Only a block the kernel is done with gets in: it is put back by
+//`retire`, once its poll completed.
+pub(crate) struct PayloadPool {
+    free: Mutex<Vec<Arc<SockEntry>>>,
+    #[cfg(feature = "debug-stats")]
+    reused: AtomicUsize,
+    #[cfg(feature = "debug-stats")]
+    allocated: AtomicUsize,
+}
+
+impl PayloadPool {
+    fn new() -> PayloadPool {
+        PayloadPool {
+            free: Mutex::new(Vec::new()),
+            #[cfg(feature = "debug-stats")]
+            reused: AtomicUsize::new(0),
+            #[cfg(feature = "debug-stats")]
+            allocated: AtomicUsize::new(0),
+        }
+    }
+
+    fn take(&self, state: SockState) -> Arc<SockEntry> {
+        let mut free = self.free.lock().unwrap();
+        while let Some(mut entry) = free.pop() {
+            //Still referred to, from the update queue most likely: left to
+            //whoever holds it last
+            if let Some(Ok(slot)) = Arc::get_mut(&mut entry).map(|e| e.state.get_mut()) {
+                *slot = state;
+                #[cfg(feature = "debug-stats")]
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                return entry;
+            }
+        }
+        drop(free);
+
+        #[cfg(feature = "debug-stats")]
+        self.allocated.fetch_add(1, Ordering::Relaxed);
+        Arc::new(SockEntry {
+            link: Link::new(),
+            state: Mutex::new(state),
+        })
+    }
+
+    fn put(&self, entry: Arc<SockEntry>) {
+        let mut free = self.free.lock().unwrap();
+        if free.len() < PAYLOAD_POOL_SIZE {
+            free.push(entry);
+        }
+    }
+}
+
+//A registration, in one allocation: its state with the payload inline, and
+//the link queueing it for updates. Shared by the table, the update queue and
+//whoever is working on it.
+#[repr(C)]
+pub(crate) struct SockEntry {
+    link: Link,
+    state: Mutex<SockState>,
+}
+
+//The link comes first
+unsafe impl Linked for SockEntry {}
+
+impl Deref for SockEntry {
+    type Target = Mutex<SockState>;
+
+    fn deref(&self) -> &Mutex<SockState> {
+        &self.state
+    }
+}
+
+//Which select() call a socket last reported in, so later completions of the
+//same call can be merged instead of taking extra slots in `Events`.
+pub(crate) struct Report {
+    pub seq: usize,
+    pub count: usize,
+    pub index: usize,
+    //every readiness flag already handed out during call `seq`
+    pub readiness: Readiness,
+}
+
+impl Report {
+    fn new(seq: usize) -> Report {
+        Report {
+            seq,
+            count: 0,
+            index: 0,
+            readiness: Readiness::EMPTY,
+        }
+    }
+}
+
+//What happened to a registration so far, to look into one that went quiet
+#[cfg(feature = "debug-stats")]
+#[derive(Default)]
+pub(crate) struct SockStats {
+    pub last_submit: Option<Instant>,
+    pub submit_count: usize,
+    //mask of the last submitted poll
+    pub submitted_events: u32,
+    pub last_completion: Option<Instant>,
+    pub completion_count: usize,
+}
+
+pub(crate) struct SockState {
+    pub payload: PollPayload,
+    pub sock: SOCKET,
+    pub base_sock: SOCKET,
+    pub poll_group: PollGroup,
+    //None once a oneshot registration fired
+    pub interests: Option<Interests>,
+    pub user_events: u32,
+    pub pending_events: u32,
+    pub user_data: u64,
+    pub update_enqueued: bool, //to note if this socket is in selector's update_queue
+    pub delete_pending: bool,
+    pub poll_state: SockPollState,
+    //number of polls cancelled over the lifetime of this registration
+    pub cancel_count: usize,
+    pub report: Report,
+    //a writable event went out and no write hit WouldBlock since: polling
+    //for writability again would just complete right away
+    pub known_writable: bool,
+    //the kernel reported the socket closed under us
+    pub closed: bool,
+    //SO_ERROR got what made the connect fail, see `store_connect_error`
+    pub connect_error_stored: bool,
+    #[cfg(feature = "debug-stats")]
+    pub stats: SockStats,
+    //makes handling the next completion fail, for tests of what a failure
+    //leaves behind
+    #[cfg(test)]
+    pub fail_completion: bool,
+    //same for the next cancel
+    #[cfg(test)]
+    pub fail_cancel: bool,
+}
+
+//Raw handles inside are only touched with the socket's lock held.
+unsafe impl Send for SockState {}
+
+impl SockState {
+    fn new(
+        shard: usize,
+        key: SlabKey,
+        sock: SOCKET,
+        base_sock: SOCKET,
+        poll_group: PollGroup,
+    ) -> SockState {
+        SockState {
+            payload: PollPayload::new(shard, key),
+            sock,
+            base_sock,
+            poll_group,
+            interests: None,
+            user_events: 0,
+            pending_events: 0,
+            user_data: 0,
+            update_enqueued: false,
+            delete_pending: false,
+            poll_state: SockPollState::Idle,
+            cancel_count: 0,
+            report: Report::new(0),
+            known_writable: false,
+            closed: false,
+            connect_error_stored: false,
+            #[cfg(feature = "debug-stats")]
+            stats: SockStats::default(),
+            #[cfg(test)]
+            fail_completion: false,
+            #[cfg(test)]
+            fail_cancel: false,
+        }
+    }
+
+    //What the next AFD poll has to watch for
+    fn poll_events(&self) -> u32 {
+        if self.known_writable {
+            self.user_events & !(EPOLLOUT | EPOLLWRNORM | EPOLLWRBAND)
+        } else {
+            self.user_events
+        }
+    }
+
+    fn needs_update(&self) -> bool {
+        let poll_events = self.poll_events() & *SOCK_KNOWN_EPOLL_EVENTS;
+        let pending_events = self.pending_events & *SOCK_KNOWN_EPOLL_EVENTS;
+
+        //Either there is something new to watch for, or the poll in flight
+        //still watches for something that was dropped from the interests.
+        //Completions are filtered through `user_events` anyway, cancelling
+        //just keeps the kernel from waking us up for nothing.
+        0 != (poll_events & !pending_events) || 0 != (pending_events & !poll_events)
+    }
+
+    //Returns true if the socket has to go through the update queue
+    fn clear_writable(&mut self) -> bool {
+        self.known_writable = false;
+        self.needs_update()
+    }
+
+    //Returns true if the socket has to go through the update queue
+    fn set_events(&mut self, interests: Interests, token: Token) -> bool {
+        debug_assert!(interests.is_supported());
+        let user_events = interests_to_epoll(interests) | EPOLLERR | EPOLLHUP;
+        //The token is only read when a completion is translated, nothing in
+        //flight carries it. If that's all that changed, the kernel poll stays.
+        self.user_data = usize::from(token) as u64;
+        if user_events == self.user_events {
+            return false;
+        }
+        //Writable asked for again after it was dropped: the cached readiness
+        //went out for the earlier request, this one gets its own event
+        if user_events & EPOLLOUT != 0 && self.user_events & EPOLLOUT == 0 {
+            self.known_writable = false;
+        }
+        self.interests = Some(interests);
+        self.user_events = user_events;
+
+        self.needs_update()
+    }
+
+    //Once this holds, nothing in the kernel refers to the payload anymore
+    fn can_free(&self) -> bool {
+        self.delete_pending && self.poll_state == SockPollState::Idle
+    }
+
+    fn cancel_poll(&mut self) -> io::Result<()> {
+        assert!(self.poll_state == SockPollState::Pending);
+
+        #[cfg(test)]
+        {
+            if mem::replace(&mut self.fail_cancel, false) {
+                return Err(io::Error::from_raw_os_error(ERROR_INVALID_HANDLE as i32));
+            }
+        }
+        if !HasOverlappedIoCompleted(&self.payload.overlapped) {
+            self.poll_group.cancel(&mut self.payload.overlapped)?;
+        }
+
+        self.poll_state = SockPollState::Cancelled;
+        self.pending_events = 0;
+        self.cancel_count += 1;
+        Ok(())
+    }
+
+    //Only marks the state: with a poll in flight the kernel still owns the
+    //payload, so reclamation is left to the completion of that poll.
+    //If the socket still sits in the update queue, the drain skips it.
+    fn delete(&mut self) -> io::Result<()> {
+        if !self.delete_pending {
+            if self.poll_state == SockPollState::Pending {
+                self.cancel_poll()?;
+            }
+
+            self.delete_pending = true;
+        }
+
+        Ok(())
+    }
+
+    fn update(&mut self) -> io::Result<()> {
+        assert!(!self.delete_pending);
+
+        match self.poll_state {
+            SockPollState::Pending => {
+                if self.needs_update() {
+                    self.cancel_poll()
+                } else {
+                    Ok(())
+                }
+            }
+            SockPollState::Cancelled => Ok(()),
+            SockPollState::Idle => {
+                //Start a new poll operation
+                let poll_events = self.poll_events();
+                let payload = &mut self.payload;
+                payload.overlapped = OVERLAPPED::default();
+                payload.poll_info = PollInfo::single(
+                    self.base_sock as HANDLE,
+                    sock_epoll_events_to_afd_events(poll_events),
+                );
+
+                let r = payload
+                    .poll_info
+                    .submit(&self.poll_group, &mut payload.overlapped);
+
+                match r {
+                    Ok(()) => {}
+                    Err(ref e) if e.raw_os_error() == Some(ERROR_IO_PENDING as _) => {}
+                    Err(ref e) if e.raw_os_error() == Some(ERROR_INVALID_HANDLE as _) => {
+                        return self.delete();
+                    }
+                    Err(e) => return Err(e),
+                }
+
+                self.poll_state = SockPollState::Pending;
+                self.pending_events = poll_events;
+                #[cfg(feature = "debug-stats")]
+                {
+                    self.stats.last_submit = Some(Instant::now());
+                    self.stats.submit_count += 1;
+                    self.stats.submitted_events = poll_events;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    //Adds `event` to the ones collected by select() call `seq`.
+    //A readiness flag is handed out at most once per call, whatever the number
+    //of completions carrying it. Past `max` events from this socket in the
+    //same call, new flags are folded into the last one it reported.
+    fn report(&mut self, seq: usize, max: usize, events: &mut Vec<Event>, event: Event) {
+        if self.report.seq != seq {
+            self.report = Report::new(seq);
+        }
+
+        let readiness = event.readiness() - self.report.readiness;
+        if readiness.is_empty() {
+            return;
+        }
+        self.report.readiness = self.report.readiness | readiness;
+
+        if self.report.count >= max {
+            events[self.report.index].add_readiness(readiness);
+        } else {
+            self.report.count += 1;
+            self.report.index = events.len();
+            events.push(Event::new(readiness, crate::event::token(&event)));
+        }
+    }
+
+    fn feed_event(&mut self) -> io::Result<Option<Event>> {
+        let mut epoll_events: u32 = 0;
+        let mut connect_failed = false;
+
+        //What the completed poll was armed with. Only those can be reported,
+        //until the next poll armed the same way completes too.
+        let armed_events = self.pending_events;
+
+        self.poll_state = SockPollState::Idle;
+        self.pending_events = 0;
+        #[cfg(feature = "debug-stats")]
+        {
+            self.stats.last_completion = Some(Instant::now());
+            self.stats.completion_count += 1;
+        }
+        #[cfg(test)]
+        {
+            if mem::replace(&mut self.fail_completion, false) {
+                return Err(io::Error::from_raw_os_error(ERROR_INVALID_HANDLE as i32));
+            }
+        }
+
+        let status = self.payload.overlapped.Internal as NTSTATUS;
+        let handles = self.payload.poll_info.handles();
+
+        if self.delete_pending {
+            return Ok(None);
+        } else if status == STATUS_CANCELLED {
+        } else if status < 0 {
+            epoll_events = EPOLLERR;
+        } else if handles.is_empty() {
+        } else if handles[0].Events & AFD_POLL_LOCAL_CLOSE != 0 {
+            self.closed = true;
+            self.delete()?;
+            return Ok(None);
+        } else {
+            let handle = &handles[0];
+            connect_failed = handle.Events & AFD_POLL_CONNECT_FAIL != 0;
+            //Once only: polls keep reporting the failure, an error taken
+            //with `take_error` must not come back.
+            if connect_failed && !self.connect_error_stored {
+                self.connect_error_stored = true;
+                store_connect_error(self.sock, handle.Status);
+            }
+            epoll_events = sock_afd_events_to_epoll_events(&handle.Events);
+        }
+
+        //Only what the completed poll watched for can be reported. Of that, only
+        //`interests` as of now, not as of the submission: a direction dropped
+        //meanwhile is never reported. Errors and hang-ups always go through.
+        let readiness = Readiness::from_epoll_events(epoll_events & armed_events);
+        let watched = match (self.interests, epoll_to_interests(armed_events)) {
+            (Some(interests), Some(armed)) => interests & armed,
+            _ => None,
+        };
+        let mut wanted = Readiness::ERROR | Readiness::HUP;
+        if let Some(watched) = watched {
+            wanted = wanted | Readiness::from(watched);
+        }
+        let mut readiness = readiness & wanted;
+        //Whatever the interests: code waiting for a connect waits for
+        //writable, then looks at `take_error`
+        if connect_failed {
+            readiness = readiness | Readiness::ERROR | Readiness::WRITABLE;
+        }
+
+        if readiness.intersects(Interests::WRITABLE) {
+            self.known_writable = true;
+        }
+
+        if readiness.is_empty() {
+            return Ok(None);
+        }
+
+        if self.user_events & EPOLLONESHOT != 0 {
+            self.interests = None;
+            self.user_events = 0;
+        }
+
+        Ok(Some(Event::new(readiness, self.token())))
+    }
+
+    fn token(&self) -> Token {
+        Token::from(self.user_data as usize)
+    }
+}
+
+//AFD tells why a connect failed to the poll only, winsock doesn't know and
+//SO_ERROR stays empty. Put it there for `take_error`, as a winsock code.
+fn store_connect_error(sock: SOCKET, status: NTSTATUS) {
+    let code = match status {
+        STATUS_CONNECTION_REFUSED | STATUS_PORT_UNREACHABLE => WSAECONNREFUSED,
+        STATUS_IO_TIMEOUT => WSAETIMEDOUT,
+        STATUS_NETWORK_UNREACHABLE => WSAENETUNREACH,
+        STATUS_HOST_UNREACHABLE => WSAEHOSTUNREACH,
+        STATUS_CONNECTION_RESET => WSAECONNRESET,
+        STATUS_CONNECTION_ABORTED => WSAECONNABORTED,
+        _ => unsafe { RtlNtStatusToDosError(status) },
+    };
+    //The socket may be on its way out, nobody is left to take the error then
+    let _ = set_opt(sock, SOL_SOCKET, SO_ERROR, code as c_int);
+}
+
+//Completion keys from here on are handles of `register_handle`, the key being
+//the token plus this. The ones below are the crate's own, with room to spare.
+pub(crate) const HANDLE_KEY: usize = 16;
+
+//Set in the keys of job objects, the rest being the job's id: a job posts
+//with the key of its association, and it can't be associated again.
+pub(crate) const JOB_KEY: usize = !(usize::MAX >> 1);
+
+//The handle and its OVERLAPPED are the caller's: only the status is read
+fn handle_completion(status: &CompletionStatus) -> IoCompletion {
+    let overlapped = status.overlapped();
+    let error = if overlapped.is_null() {
+        0
+    } else {
+        match unsafe { (*overlapped).Internal } as NTSTATUS {
+            status if status >= 0 => 0,
+            status => unsafe { RtlNtStatusToDosError(status) },
+        }
+    };
+    IoCompletion::new(status.bytes_transferred(), overlapped, error)
+}
+
+//Interests all come from the same per platform table, anything outside of it
+//was made up through unsafe code.
+fn check_interests(interests: Interests, token: Token) -> io::Result<()> {
+    if interests.is_supported() {
+        Ok(())
+    } else {
+        Err(misuse(
+            ErrorKind::UnsupportedInterests,
+            Some(token),
+            "unsupported interests",
+        ))
+    }
+}
+
+//NtRemoveIoCompletionEx() fills the buffer GetQueuedCompletionStatusEx() gets
+const _: () = assert!(
+    mem::size_of::<FILE_IO_COMPLETION_INFORMATION>() == mem::size_of::<CompletionStatus>()
+);
+
+//NT timeouts are in 100ns units, negative for one relative to now. Rounded up
+//so the wait is never cut short, and one too long to represent is as good as
+//forever.
+fn relative_time(timeout: Duration) -> i64 {
+    let units = timeout
+        .as_secs()
+        .checked_mul(10_000_000)
+        .and_then(|units| units.checked_add(u64::from(timeout.subsec_nanos()).div_ceil(100)));
+    match units {
+        Some(units) if units <= i64::MAX as u64 => -(units as i64),
+        _ => -i64::MAX,
+    }
+}
+
+#[derive(Clone)]
+pub struct Selector {
+    inner: Arc<SelectorInner>,
+}
+
+pub(crate) struct SelectorConfig {
+    //events a single socket may contribute to one select() call
+    pub max_events_per_socket: usize,
+    //locks the registration table is split under
+    pub shards: usize,
+    //polls as if the AFD device couldn't be opened, see `PollGroupQueue`
+    #[cfg(test)]
+    pub deny_afd: bool,
+}
+
+impl Default for SelectorConfig {
+    fn default() -> SelectorConfig {
+        SelectorConfig {
+            max_events_per_socket: 1,
+            shards: thread::available_parallelism().map_or(1, |n| n.get()),
+            #[cfg(test)]
+            deny_afd: false,
+        }
+    }
+}
+
+impl SelectorConfig {
+    pub(crate) fn set_max_events_per_socket(&mut self, max: usize) {
+        self.max_events_per_socket = max;
+    }
+
+    pub(crate) fn set_shards(&mut self, shards: usize) {
+        self.shards = shards;
+    }
+}
+
+//Tells a registration apart from later ones of the same handle value
+pub(crate) type Key = SlabKey;
+
+//Completions resolve their socket through `slab`, straight from the key kept
+//in the payload. `by_socket` is only looked at on registration.
+struct SockTable {
+    slab: Slab<Arc<SockEntry>>,
+    by_socket: HashMap<SOCKET, SlabKey>,
+    //sockets retired because they were closed while registered. Only kept to
+    //explain the next lookup that misses, or until the handle value is reused.
+    closed: HashSet<SOCKET>,
+}
+
+impl SockTable {
+    //Error for a lookup of `sock` that found nothing
+    fn not_registered(&mut self, sock: SOCKET, token: Option<Token>) -> io::Error {
+        let message = if self.closed.remove(&sock) {
+            "socket is not registered; it was closed"
+        } else {
+            "socket is not registered"
+        };
+        misuse(ErrorKind::NotRegistered, token, message)
+    }
+}
+
+//Always maintained, see `PollStats`. Only relaxed operations: each counter is
+//exact on its own, but they are not updated together.
+#[derive(Default)]
+struct Counters {
+    polls: AtomicU64,
+    completions: AtomicU64,
+    submissions: AtomicU64,
+    cancellations: AtomicU64,
+    //sockets in the update queue right now, and the most there were at once
+    queued: AtomicU64,
+    queued_high_water: AtomicU64,
+}
+
+//Lock order: a socket's own lock may be held while taking a `sock_tables`
+//shard or `poll_group_queue`, never the other way round. No two shards are
+//ever held at the same time.
+//The update queue takes no lock at all: any thread may push to it, only a
+//polling thread drains it.
+struct SelectorInner {
+    port: CompletionPort,
+    config: SelectorConfig,
+    //numbers select() calls, starting from 1
+    poll_seq: AtomicUsize,
+    //act as poll_group in wepoll, to manage limited use of afd_helper_handle
+    poll_group_queue: Mutex<PollGroupQueue>,
+    //to note the number of thread who is polling on this iocp port
+    poll_count: AtomicUsize,
+    //set while a wakeup packet is queued on the port and not yet dequeued
+    wake_pending: AtomicBool,
+    //the error of a completion that failed in a call having events to return
+    //as well, for the next call to return. The flag spares polls the lock.
+    deferred_error: Mutex<Option<io::Error>>,
+    has_deferred_error: AtomicBool,
+    //all sockets registered on this port, sharded by handle value so threads
+    //registering different sockets don't wait on each other.
+    //Read-mostly: only register and retire take the write lock.
+    sock_tables: Box<[RwLock<SockTable>]>,
+    //sockets whose poll operation needs to be submitted or cancelled
+    update_queue: MpscQueue<SockEntry>,
+    //token of each registered job object, by id. Dropped when deregistered,
+    //a job's messages are then discarded.
+    jobs: Mutex<HashMap<usize, Token>>,
+    next_job: AtomicUsize,
+    payload_pool: Arc<PayloadPool>,
+    counters: Counters,
+    //set once the `Poll` is dropped, only registrations outliving it are left
+    closed: AtomicBool,
+    //calls to GetQueuedCompletionStatusEx(), for tests to count
+    #[cfg(test)]
+    dequeues: AtomicUsize,
+}
+
+impl SelectorInner {
+    fn new(port: CompletionPort, config: SelectorConfig) -> SelectorInner {
+        let shards = cmp::max(config.shards, 1);
+        #[allow(unused_mut)]
+        let mut poll_group_queue = PollGroupQueue::new(&port);
+        #[cfg(test)]
+        {
+            poll_group_queue.deny_afd = config.deny_afd;
+        }
+        SelectorInner {
+            poll_group_queue: Mutex::new(poll_group_queue),
+            port,
+            config,
+            poll_seq: AtomicUsize::new(0),
+            poll_count: AtomicUsize::new(0),
+            wake_pending: AtomicBool::new(false),
+            deferred_error: Mutex::new(None),
+            has_deferred_error: AtomicBool::new(false),
+            sock_tables: (0..shards)
+                .map(|_| {
+                    RwLock::new(SockTable {
+                        slab: Slab::new(),
+                        by_socket: HashMap::new(),
+                        closed: HashSet::new(),
+                    })
+                })
+                .collect(),
+            update_queue: MpscQueue::new(),
+            jobs: Mutex::new(HashMap::new()),
+            next_job: AtomicUsize::new(0),
+            payload_pool: Arc::new(PayloadPool::new()),
+            counters: Counters::default(),
+            closed: AtomicBool::new(false),
+            #[cfg(test)]
+            dequeues: AtomicUsize::new(0),
+        }
+    }
+
+    //Shard of the table the registration of `sock` goes to. The handle value
+    //it was registered with: its base handle would take a syscall to find on
+    //every lookup. Handle values are multiples of 4.
+    fn shard(&self, sock: SOCKET) -> usize {
+        (sock >> 2) % self.sock_tables.len()
+    }
+
+    fn table(&self, sock: SOCKET) -> &RwLock<SockTable> {
+        &self.sock_tables[self.shard(sock)]
+    }
+
+    fn registered(&self) -> usize {
+        let tables = self.sock_tables.iter();
+        tables.map(|table| table.read().unwrap().slab.len()).sum()
+    }
+
+    fn check_open(&self, token: Option<Token>) -> io::Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            Err(misuse(ErrorKind::PollDropped, token, "poll was dropped"))
+        } else {
+            Ok(())
+        }
+    }
+
+    //Takes as many completions as `statuses` holds in one call, waiting for
+    //the first one until `timeout`. Failed operations are dequeued like the
+    //others, their status is in their OVERLAPPED. 0 once the timeout expired.
+    fn dequeue(
+        &self,
+        statuses: &mut [CompletionStatus],
+        timeout: Option<Duration>,
+    ) -> io::Result<usize> {
+        #[cfg(test)]
+        self.dequeues.fetch_add(1, Ordering::Relaxed);
+
+        //Not alertable: nothing here queues APCs, a user's would be run too
+        match self.port.get_many(statuses, timeout) {
+            Ok(statuses) => Ok(statuses.len()),
+            Err(ref e) if e.raw_os_error() == Some(WAIT_TIMEOUT as i32) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    //Same as `dequeue`, with the timeout kept to 100ns instead of cut down to
+    //whole milliseconds. GetQueuedCompletionStatusEx() is built on this call.
+    fn dequeue_precise(
+        &self,
+        statuses: &mut [CompletionStatus],
+        timeout: Option<Duration>,
+    ) -> io::Result<usize> {
+        #[cfg(test)]
+        self.dequeues.fetch_add(1, Ordering::Relaxed);
+
+        //Not the one of winnt the AFD structs use
+        let mut relative = ntdef::LARGE_INTEGER::default();
+        let timeout = match timeout {
+            Some(timeout) => {
+                unsafe { *relative.QuadPart_mut() = relative_time(timeout) };
+                &mut relative as *mut ntdef::LARGE_INTEGER
+            }
+            None => null_mut(),
+        };
+        let len = cmp::min(statuses.len(), ULONG::MAX as usize) as ULONG;
+        let mut removed: ULONG = 0;
+        let status = unsafe {
+            NtRemoveIoCompletionEx(
+                self.port.as_raw_handle(),
+                statuses.as_mut_ptr() as *mut FILE_IO_COMPLETION_INFORMATION,
+                len,
+                &mut removed,
+                timeout,
+                FALSE as BOOLEAN,
+            )
+        };
+
+        match status {
+            //A success code too, with nothing removed
+            STATUS_TIMEOUT => Ok(0),
+            STATUS_SUCCESS => {
+                let removed = &mut statuses[..removed as usize];
+                for status in removed.iter_mut() {
+                    let info = unsafe {
+                        ptr::read(status as *const _ as *const FILE_IO_COMPLETION_INFORMATION)
+                    };
+                    *status = CompletionStatus::new(
+                        info.IoStatusBlock.Information as u32,
+                        info.KeyContext as usize,
+                        info.ApcContext as *mut Overlapped,
+                    );
+                }
+                Ok(removed.len())
+            }
+            _ => unsafe {
+                Err(io::Error::from_raw_os_error(
+                    RtlNtStatusToDosError(status) as _,
+                ))
+            },
+        }
+    }
+
+    //Never blocks: the queue push is wait-free, and the socket's own lock is
+    //already held by the caller.
+    fn request_update(
+        &self,
+        sock_state: &Arc<SockEntry>,
+        state: &mut SockState,
+    ) -> io::Result<()> {
+        if !state.update_enqueued {
+            state.update_enqueued = true;
+            //Counted in first, the drain counts it out once it got it
+            let queued = self.counters.queued.fetch_add(1, Ordering::Relaxed) + 1;
+            self.counters
+                .queued_high_water
+                .fetch_max(queued, Ordering::Relaxed);
+            self.update_queue.push(sock_state.clone());
+            self.wake_poller()
+                .map_err(|e| with_socket(e, state.token(), state.sock))?;
+        }
+
+        Ok(())
+    }
+
+    //`before` is the cancel count of `state` before it was worked on
+    fn count_cancels(&self, before: usize, state: &SockState) {
+        let cancels = state.cancel_count - before;
+        if cancels > 0 {
+            self.counters
+                .cancellations
+                .fetch_add(cancels as u64, Ordering::Relaxed);
+            trace!(Trace::Cancel { token: state.token() });
+        }
+    }
+
+    //Updates are only ever drained by a polling thread, so a thread blocked in
+    //select() has to be kicked to pick up what was queued after it went to sleep.
+    fn wake_poller(&self) -> io::Result<()> {
+        if self.poll_count.load(Ordering::SeqCst) > 0
+            && !self.wake_pending.swap(true, Ordering::SeqCst)
+        {
+            self.port.post(CompletionStatus::new(0, 0, null_mut()))?;
+        }
+
+        Ok(())
+    }
+
+    fn update_events(&self) -> io::Result<()> {
+        let queue = match self.update_queue.drain() {
+            Some(queue) => queue,
+            //Another poller is draining right now, it will see our updates
+            None => return Ok(()),
+        };
+        let mut result = Ok(());
+
+        for sock_state in queue {
+            self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+            let mut state = sock_state.lock().unwrap();
+            //Linked through the entry itself: queued only while the flag is set
+            debug_assert!(state.update_enqueued);
+            state.update_enqueued = false;
+            if state.delete_pending {
+                continue;
+            }
+
+            let (idle, cancels) = (
+                state.poll_state == SockPollState::Idle,
+                state.cancel_count,
+            );
+            let r = state.update();
+            let submitted = state.poll_state == SockPollState::Pending;
+            if idle && submitted {
+                self.counters.submissions.fetch_add(1, Ordering::Relaxed);
+            }
+            if idle && (submitted || r.is_err()) {
+                trace!(Trace::Submit {
+                    token: state.token(),
+                    afd_events: state.payload.poll_info.handles()[0].Events,
+                    error: r.as_ref().err().and_then(|e| e.raw_os_error()),
+                });
+            }
+            self.count_cancels(cancels, &state);
+            let r = r.map_err(|e| with_socket(e, state.token(), state.sock));
+            if state.can_free() {
+                let (key, sock) = (state.payload.key, state.sock);
+                drop(state);
+                self.retire(key, sock);
+            }
+
+            if result.is_ok() {
+                result = r;
+            }
+        }
+
+        result
+    }
+
+    fn feed_event(
+        &self,
+        payload: *const PollPayload,
+        seq: usize,
+        events: &mut Vec<Event>,
+    ) -> io::Result<()> {
+        if payload.is_null() {
+            return Ok(());
+        }
+
+        //The payload can't be freed under us: its SockState stays in the table
+        //until the completion we are handling right now has been processed.
+        let (shard, key) = unsafe { ((*payload).shard, (*payload).key) };
+        let sock_state = match self.sock_tables[shard].read().unwrap().slab.get(key) {
+            Some(sock_state) => sock_state.clone(),
+            None => return Ok(()),
+        };
+
+        let mut state = sock_state.lock().unwrap();
+        #[cfg(feature = "trace")]
+        let (status, afd_events) = (
+            state.payload.overlapped.Internal as NTSTATUS,
+            state.payload.poll_info.handles().first().map_or(0, |handle| handle.Events),
+        );
+        let event = state.feed_event();
+        trace!(Trace::Completion {
+            token: state.token(),
+            status,
+            afd_events,
+            readiness: event
+                .as_ref()
+                .ok()
+                .and_then(|event| event.as_ref())
+                .map(|event| event.readiness()),
+        });
+        //A completion that failed is done with all the same: the socket is
+        //idle now, and rearmed or retired like any other
+        let result = match event {
+            Ok(Some(event)) => {
+                state.report(seq, self.config.max_events_per_socket, events, event);
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            Err(e) => Err(with_socket(e, state.token(), state.sock)),
+        };
+
+        if state.can_free() {
+            let sock = state.sock;
+            drop(state);
+            self.retire(key, sock);
+            result
+        } else {
+            //Not resubmitted right here: the rearm goes through the queue and
+            //lands on the port behind completions already waiting there.
+            let r = self.request_update(&sock_state, &mut state);
+            result.and(r)
+        }
+    }
+
+    fn find(&self, sock: SOCKET, token: Option<Token>) -> io::Result<Arc<SockEntry>> {
+        {
+            let table = self.table(sock).read().unwrap();
+            let key = table.by_socket.get(&sock);
+            if let Some(sock_state) = key.and_then(|key| table.slab.get(*key)) {
+                return Ok(sock_state.clone());
+            }
+        }
+
+        Err(self.table(sock).write().unwrap().not_registered(sock, token))
+    }
+
+    fn compact(&self) {
+        for table in self.sock_tables.iter() {
+            let mut table = table.write().unwrap();
+            table.slab.shrink_to_fit();
+            table.by_socket.shrink_to_fit();
+            table.closed.shrink_to_fit();
+        }
+        self.poll_group_queue.lock().unwrap().compact();
+    }
+
+    fn retire(&self, key: SlabKey, sock: SOCKET) {
+        let removed = {
+            let mut table = self.table(sock).write().unwrap();
+            let removed = table.slab.remove(key);
+            //deregister may already have handed the socket to a new registration
+            if removed.is_some() && table.by_socket.get(&sock) == Some(&key) {
+                table.by_socket.remove(&sock);
+            }
+            removed
+        };
+        if let Some(sock_state) = removed {
+            let state = sock_state.lock().unwrap();
+            if state.closed {
+                let mut table = self.table(sock).write().unwrap();
+                if !table.by_socket.contains_key(&sock) {
+                    table.closed.insert(sock);
+                }
+            }
+            self.poll_group_queue
+                .lock()
+                .unwrap()
+                .release(&state.poll_group);
+            trace!(Trace::Reclaim { token: state.token() });
+            drop(state);
+            //Only retired once its poll is done with, see `can_free`
+            self.payload_pool.put(sock_state);
+        }
+    }
+}
+
+//The fallback's thread posts to `port`, it has to be done before the port is
+//closed. Registrations that still refer to it only go after.
+#[cfg(feature = "wsapoll")]
+impl Drop for SelectorInner {
+    fn drop(&mut self) {
+        if let Some(ref fallback) = self.poll_group_queue.get_mut().unwrap().fallback {
+            fallback.stop();
+        }
+    }
+}
+
+impl Selector {
+    #[cfg(test)]
+    pub fn new() -> io::Result<Selector> {
+        Selector::with_config(SelectorConfig::default())
+    }
+
+    pub(crate) fn with_config(config: SelectorConfig) -> io::Result<Selector> {
+        //Equal to epoll_create, which create port_state representing iocp port
+        init()?;
+
+        CompletionPort::new(1).map(|port| Selector {
+            inner: Arc::new(SelectorInner::new(port, config)),
+        })
+    }
+
+    /// Waits for readiness events, filling at most `events.capacity()` of them.
+    ///
+    /// Delivery is fair across sockets: if `ready` sockets stay ready, each of
+    /// them is reported at least once within `ceil(ready / capacity)` calls.
+    /// Completions are taken from the port in FIFO order, and a socket that was
+    /// just reported is only rearmed on the next call, so its next completion
+    /// queues up behind those of every socket still waiting for a slot.
+    ///
+    /// With a zero timeout it never blocks, and takes every completion already
+    /// waiting on the port, as far as `events` has room.
+    ///
+    /// Should handling a completion fail, the events taken along with it are
+    /// returned all the same, and the error by the next call.
+    pub fn select(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        trace!(Trace::PollEnter { timeout });
+        let r = self.select_events(events, timeout);
+        trace!(Trace::PollExit { events: events.len() });
+        r
+    }
+
+    fn select_events(&self, events: &mut Events, timeout: Option<Duration>) -> io::Result<()> {
+        //init() appear in four functions in epoll
+        //They are just four critical functions, epoll_*
+        init()?;
+
+        events.clear();
+
+        //Left by the call before, which returned its events instead
+        if self.inner.has_deferred_error.swap(false, Ordering::SeqCst) {
+            if let Some(e) = self.inner.deferred_error.lock().unwrap().take() {
+                return Err(e);
+            }
+        }
+
+        let seq = self.inner.poll_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        self.inner.counters.polls.fetch_add(1, Ordering::Relaxed);
+        //A poll that doesn't wait has no deadline to keep. With no updates
+        //queued it is then a single GetQueuedCompletionStatusEx(), nothing
+        //allocated and no lock taken.
+        let nonblocking = timeout == Some(Duration::from_secs(0));
+        let deadline = match timeout {
+            Some(timeout) if !nonblocking => Some(Instant::now() + timeout),
+            _ => None,
+        };
+        //Set while a non-blocking poll takes what else is waiting on the port
+        let mut draining = false;
+        //Only a timeout finer than a millisecond takes the Nt call, the
+        //Win32 one would round it down
+        let precise = timeout.is_some_and(|timeout| timeout.subsec_nanos() % 1_000_000 != 0);
+
+        loop {
+            //Count ourselves in before draining, so an update pushed after the
+            //drain sees us polling and wakes us up.
+            self.inner.poll_count.fetch_add(1, Ordering::SeqCst);
+
+            //GetQueuedCompletionStatusEx() called here, without holding any lock.
+            //No updates while draining: what was just reported is rearmed by
+            //the next call, as with a single dequeue.
+            let updated = if draining {
+                Ok(())
+            } else {
+                self.inner.update_events()
+            };
+            let r = updated.and_then(|()| {
+                let timeout = match deadline {
+                    Some(deadline) => Some(deadline.saturating_duration_since(Instant::now())),
+                    None if nonblocking => Some(Duration::from_secs(0)),
+                    None => None,
+                };
+                let statuses = events.statuses();
+                let asked = statuses.len();
+                let n = if precise {
+                    self.inner.dequeue_precise(statuses, timeout)
+                } else {
+                    self.inner.dequeue(statuses, timeout)
+                };
+                n.map(|n| (asked, n))
+            });
+
+            self.inner.poll_count.fetch_sub(1, Ordering::SeqCst);
+            let (asked, n) = r?;
+            events.adapt(asked, n);
+            self.inner
+                .counters
+                .completions
+                .fetch_add(n as u64, Ordering::Relaxed);
+
+            let mut woken = false;
+            //Every completion dequeued is handled, whatever happens to the
+            //others: one left out would keep its socket pending for good. The
+            //first error is returned once the batch is done, or by the next
+            //call if there are events to return: their readiness was taken
+            //from the port, it wouldn't be reported again.
+            let mut result = Ok(());
+            for status in events.statuses[..n].iter() {
+                if status.token() & JOB_KEY != 0 {
+                    let id = status.token() & !JOB_KEY;
+                    if let Some(&token) = self.inner.jobs.lock().unwrap().get(&id) {
+                        //The overlapped pointer holds the message's value
+                        let job = JobEvent::new(
+                            status.bytes_transferred(),
+                            status.overlapped() as usize as u32,
+                        );
+                        events.events.push(Event::from_job(job, token));
+                    }
+                    continue;
+                }
+                if status.token() >= HANDLE_KEY {
+                    let token = Token::from(status.token() - HANDLE_KEY);
+                    let completion = handle_completion(status);
+                    events.events.push(Event::from_completion(completion, token));
+                    continue;
+                }
+
+                //Before the wakeups of our own, token 0 comes with a null
+                //overlapped pointer too
+                if status.token() == WAKER_KEY {
+                    complete_waker(status, &mut events.events);
+                    continue;
+                }
+                if status.overlapped().is_null() {
+                    //Cleared before the next drain, so nothing pushed after
+                    //this point can be left without a wakeup.
+                    self.inner.wake_pending.swap(false, Ordering::SeqCst);
+                    woken = true;
+                    continue;
+                }
+
+                #[cfg(feature = "net")]
+                if status.token() == ACCEPT_KEY {
+                    unsafe { complete_accept(status.overlapped(), seq, &mut events.events) };
+                    continue;
+                }
+                #[cfg(feature = "net")]
+                if status.token() == PIPE_KEY {
+                    unsafe { complete_pipe(status.overlapped(), seq, &mut events.events) };
+                    continue;
+                }
+                if status.token() == WAIT_KEY {
+                    unsafe { complete_wait(status.overlapped(), &mut events.events) };
+                    continue;
+                }
+                #[cfg(feature = "net")]
+                if status.token() == WATCH_KEY {
+                    unsafe { complete_watch(status.overlapped(), seq, &mut events.events) };
+                    continue;
+                }
+                if status.token() == STDIN_KEY {
+                    complete_stdin(status, &mut events.events);
+                    continue;
+                }
+                if status.token() == CTRL_KEY {
+                    complete_ctrl(status, &mut events.events);
+                    continue;
+                }
+
+                let r = self.inner.feed_event(
+                    status.overlapped() as *const PollPayload,
+                    seq,
+                    &mut events.events,
+                );
+                if result.is_ok() {
+                    result = r;
+                }
+            }
+            if let Err(e) = result {
+                //What the batch queued for a rearm is submitted before giving
+                //up, rather than on the next call only
+                let _ = self.inner.update_events();
+                if events.is_empty() {
+                    return Err(e);
+                }
+                *self.inner.deferred_error.lock().unwrap() = Some(e);
+                self.inner.has_deferred_error.store(true, Ordering::SeqCst);
+                return Ok(());
+            }
+
+            //Only woken up to submit new updates: go back to waiting
+            let expired = match deadline {
+                Some(deadline) => Instant::now() >= deadline,
+                None => nonblocking,
+            };
+            //The port came back full and doesn't wait: there may be more,
+            //taken in this same call while `events` has room. Completions of
+            //a socket already reported are merged into its event, as seq
+            //stays the same.
+            if nonblocking && n == asked && events.has_room() {
+                draining = true;
+                continue;
+            }
+            if !woken || !events.is_empty() || expired {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn port(&self) -> &CompletionPort {
+        &self.inner.port
+    }
+
+    #[cfg(test)]
+    pub(crate) fn config(&self) -> &SelectorConfig {
+        &self.inner.config
+    }
+
+    //Registrations the table has room for without growing
+    pub(crate) fn capacity(&self) -> usize {
+        let tables = self.inner.sock_tables.iter();
+        tables
+            .map(|table| table.read().unwrap().slab.capacity())
+            .sum()
+    }
+
+    pub(crate) fn compact(&self) {
+        self.inner.compact()
+    }
+
+    pub(crate) fn stats(&self) -> PollStats {
+        let counters = &self.inner.counters;
+        PollStats {
+            polls: counters.polls.load(Ordering::Relaxed),
+            completions: counters.completions.load(Ordering::Relaxed),
+            submissions: counters.submissions.load(Ordering::Relaxed),
+            cancellations: counters.cancellations.load(Ordering::Relaxed),
+            registered: self.inner.registered() as u64,
+            update_queue_high_water: counters.queued_high_water.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset_stats(&self) {
+        let counters = &self.inner.counters;
+        counters.polls.store(0, Ordering::Relaxed);
+        counters.completions.store(0, Ordering::Relaxed);
+        counters.submissions.store(0, Ordering::Relaxed);
+        counters.cancellations.store(0, Ordering::Relaxed);
+        //What is queued now is as much as there was since
+        let queued = counters.queued.load(Ordering::Relaxed);
+        counters.queued_high_water.store(queued, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "debug-stats")]
+    pub(crate) fn registration_info(&self, token: Token) -> Option<RegistrationInfo> {
+        //Socket locks can't be taken under the table lock
+        let mut sock_states = Vec::new();
+        for table in self.inner.sock_tables.iter() {
+            let table = table.read().unwrap();
+            let registered = table.by_socket.values();
+            sock_states.extend(registered.filter_map(|key| table.slab.get(*key).cloned()));
+        }
+
+        sock_states.iter().find_map(|sock_state| {
+            let state = sock_state.lock().unwrap();
+            if state.delete_pending || state.user_data != usize::from(token) as u64 {
+                return None;
+            }
+
+            Some(RegistrationInfo {
+                token,
+                interests: state.interests,
+                poll_pending: state.poll_state == SockPollState::Pending,
+                update_enqueued: state.update_enqueued,
+                last_submit: state.stats.last_submit,
+                submit_count: state.stats.submit_count,
+                submitted_interests: epoll_to_interests(state.stats.submitted_events),
+                last_completion: state.stats.last_completion,
+                completion_count: state.stats.completion_count,
+            })
+        })
+    }
+
+    #[cfg(feature = "debug-stats")]
+    pub(crate) fn payload_stats(&self) -> PayloadStats {
+        let pool = &self.inner.payload_pool;
+        PayloadStats {
+            reused: pool.reused.load(Ordering::Relaxed),
+            allocated: pool.allocated.load(Ordering::Relaxed),
+        }
+    }
+
+    //The returned key tells this registration apart from later ones of the
+    //same handle value, see `reregister` and `deregister`.
+    pub(crate) fn register(
+        &self,
+        socket: SOCKET,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<SlabKey> {
+        //embed register on selector by now
+        //maybe move to struct which construct TcpStream in future pr
+        init()?;
+        self.inner.check_open(Some(token))?;
+        check_interests(interests, token)?;
+
+        let base_sock = ws_get_base_socket(&socket).map_err(|e| with_socket(e, token, socket))?;
+
+        let poll_group = self.inner.poll_group_queue.lock().unwrap().acquire();
+        let poll_group = poll_group.map_err(|e| with_socket(e, token, socket))?;
+
+        let shard = self.inner.shard(socket);
+        let inserted = {
+            let mut table = self.inner.sock_tables[shard].write().unwrap();
+            let table = &mut *table;
+            match table.by_socket.entry(socket) {
+                Entry::Occupied(_) => None,
+                Entry::Vacant(entry) => {
+                    //The handle value got reused, it's a different socket now
+                    table.closed.remove(&socket);
+                    let key = table.slab.next_key();
+                    let mut state = SockState::new(
+                        shard,
+                        key,
+                        socket,
+                        base_sock,
+                        poll_group.clone(),
+                    );
+                    let needs_update = state.set_events(interests, token);
+                    let sock_state = self.inner.payload_pool.take(state);
+
+                    table.slab.insert(sock_state.clone());
+                    entry.insert(key);
+                    Some((key, sock_state, needs_update))
+                }
+            }
+        };
+
+        let (key, sock_state, needs_update) = match inserted {
+            Some(inserted) => inserted,
+            None => {
+                self.inner
+                    .poll_group_queue
+                    .lock()
+                    .unwrap()
+                    .release(&poll_group);
+                return Err(misuse(
+                    ErrorKind::AlreadyRegistered,
+                    Some(token),
+                    "socket is already registered",
+                ));
+            }
+        };
+
+        trace!(Trace::Register { token, interests });
+        if needs_update {
+            self.inner
+                .request_update(&sock_state, &mut sock_state.lock().unwrap())?;
+        }
+
+        Ok(key)
+    }
+
+    //With `key`, only the registration it was returned for is touched
+    pub(crate) fn reregister(
+        &self,
+        socket: SOCKET,
+        key: Option<SlabKey>,
+        token: Token,
+        interests: Interests,
+    ) -> io::Result<()> {
+        init()?;
+        self.inner.check_open(Some(token))?;
+        check_interests(interests, token)?;
+
+        let sock_state = self.inner.find(socket, Some(token))?;
+        let mut state = sock_state.lock().unwrap();
+        if state.delete_pending || key.is_some_and(|key| key != state.payload.key) {
+            return Err(misuse(
+                ErrorKind::NotRegistered,
+                Some(token),
+                "socket is not registered",
+            ));
+        }
+
+        trace!(Trace::Reregister { token, interests });
+        if state.set_events(interests, token) {
+            self.inner.request_update(&sock_state, &mut state)?;
+        }
+
+        Ok(())
+    }
+
+    //Like reregister, with interests computed from the current ones
+    pub(crate) fn modify_interests<F>(&self, socket: SOCKET, token: Token, f: F) -> io::Result<()>
+    where
+        F: FnOnce(Option<Interests>) -> io::Result<Interests>,
+    {
+        init()?;
+        self.inner.check_open(Some(token))?;
+
+        let sock_state = self.inner.find(socket, Some(token))?;
+        let mut state = sock_state.lock().unwrap();
+        if state.delete_pending {
+            return Err(misuse(
+                ErrorKind::NotRegistered,
+                Some(token),
+                "socket is not registered",
+            ));
+        }
+
+        let interests = f(state.interests)?;
+        check_interests(interests, token)?;
+        trace!(Trace::Reregister { token, interests });
+        if state.set_events(interests, token) {
+            self.inner.request_update(&sock_state, &mut state)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn register_handle<H>(&self, handle: &H, token: Token) -> io::Result<()>
+    where
+        H: AsRawHandle + ?Sized,
+    {
+        let key = usize::from(token)
+            .checked_add(HANDLE_KEY)
+            .filter(|key| key & JOB_KEY == 0)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "token too large for a handle registration",
+                )
+            })?;
+        self.inner.port.add_handle(key, handle)
+    }
+
+    //Associates `job` with the port, returning the job's id
+    pub(crate) fn register_job(&self, job: HANDLE, token: Token) -> io::Result<usize> {
+        let id = self.inner.next_job.fetch_add(1, Ordering::Relaxed) & !JOB_KEY;
+        self.inner.jobs.lock().unwrap().insert(id, token);
+
+        let mut port = JOBOBJECT_ASSOCIATE_COMPLETION_PORT {
+            CompletionKey: (id | JOB_KEY) as PVOID,
+            CompletionPort: self.inner.port.as_raw_handle() as HANDLE,
+        };
+        let r = unsafe {
+            SetInformationJobObject(
+                job,
+                JobObjectAssociateCompletionPortInformation,
+                &mut port as *mut _ as LPVOID,
+                mem::size_of_val(&port) as DWORD,
+            )
+        };
+        if r == 0 {
+            let e = io::Error::last_os_error();
+            self.inner.jobs.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    //Reports the messages of the job `id` with `token` from now on, or
+    //discards them for `None`
+    pub(crate) fn set_job_token(&self, id: usize, token: Option<Token>) {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        match token {
+            Some(token) => jobs.insert(id, token),
+            None => jobs.remove(&id),
+        };
+    }
+
+    #[cfg(all(test, feature = "wsapoll"))]
+    pub(crate) fn uses_wsapoll(&self) -> bool {
+        let queue = self.inner.poll_group_queue.lock().unwrap();
+        queue.fallback.is_some()
+    }
+
+    pub(crate) fn same_port(&self, other: &Selector) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub(crate) fn clear_writable(&self, socket: SOCKET) -> io::Result<()> {
+        self.inner.check_open(None)?;
+        let sock_state = self.inner.find(socket, None)?;
+        let mut state = sock_state.lock().unwrap();
+
+        if !state.delete_pending && state.clear_writable() {
+            self.inner.request_update(&sock_state, &mut state)?;
+        }
+
+        Ok(())
+    }
+
+    //With `key`, only the registration it was returned for is touched
+    pub(crate) fn deregister(&self, socket: SOCKET, key: Option<SlabKey>) -> io::Result<()> {
+        self.inner.check_open(None)?;
+        self.remove_socket(socket, key)
+    }
+
+    fn remove_socket(&self, socket: SOCKET, key: Option<SlabKey>) -> io::Result<()> {
+        init()?;
+
+        //Only looked up for now: should the cancel fail, the socket stays
+        //registered and can be deregistered again
+        let sock_state = {
+            let table = self.inner.table(socket).read().unwrap();
+            match (table.by_socket.get(&socket), key) {
+                (Some(found), Some(key)) if *found != key => None,
+                (found, _) => found.and_then(|&key| table.slab.get(key).cloned()),
+            }
+        };
+        let not_registered = || {
+            let mut table = self.inner.table(socket).write().unwrap();
+            Err(table.not_registered(socket, None))
+        };
+        let sock_state = match sock_state {
+            Some(sock_state) => sock_state,
+            None => return not_registered(),
+        };
+
+        let mut state = sock_state.lock().unwrap();
+        //Deregistered by another thread in between
+        if state.delete_pending {
+            return not_registered();
+        }
+        let cancels = state.cancel_count;
+        state
+            .delete()
+            .map_err(|e| with_socket(e, state.token(), socket))?;
+        //Forgotten once cancelled, so the socket can be registered again. The
+        //slot itself lives on until a pending poll has completed.
+        {
+            let mut table = self.inner.table(socket).write().unwrap();
+            if table.by_socket.get(&socket) == Some(&state.payload.key) {
+                table.by_socket.remove(&socket);
+            }
+        }
+        trace!(Trace::Deregister { token: state.token() });
+        self.inner.count_cancels(cancels, &state);
+
+        if state.can_free() {
+            let (key, sock) = (state.payload.key, state.sock);
+            drop(state);
+            self.inner.retire(key, sock);
+        }
+
+        Ok(())
+    }
+
+    //Sockets registered right now, not counting deregistered ones waiting for
+    //their poll to complete
+    pub(crate) fn registered_count(&self) -> usize {
+        let tables = self.inner.sock_tables.iter();
+        tables.map(|table| table.read().unwrap().by_socket.len()).sum()
+    }
+
+    //Wepoll's port_delete(): deregisters what is still registered, then waits
+    //for the kernel to be done with every poll, payloads can only go after.
+    //Returns how many registrations were left behind.
+    //`port` itself is closed when the last `SelectorInner` goes away.
+    pub(crate) fn close(&self) -> usize {
+        self.inner.closed.store(true, Ordering::Release);
+        let mut leaked = Vec::new();
+        for table in self.inner.sock_tables.iter() {
+            let table = table.read().unwrap();
+            for (&sock, &key) in table.by_socket.iter() {
+                if let Some(sock_state) = table.slab.get(key) {
+                    leaked.push((sock, key, sock_state.clone()));
+                }
+            }
+        }
+        //Socket locks only once the table's is released
+        for &(sock, key, ref _sock_state) in &leaked {
+            trace!(Trace::Leak {
+                token: _sock_state.lock().unwrap().token(),
+            });
+            let _ = self.remove_socket(sock, Some(key));
+        }
+
+        let mut events = Events::with_capacity(256);
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        while self.inner.registered() > 0 && Instant::now() < deadline {
+            if self
+                .select(&mut events, Some(Duration::from_millis(10)))
+                .is_err()
+            {
+                break;
+            }
+        }
+        leaked.len()
+    }
+}
+
+/// Counters of what a [`Poll`](crate::Poll) did since it was created, or
+/// since the last [`Poll::reset_stats`](crate::Poll::reset_stats).
+///
+/// Returned by [`Poll::stats`](crate::Poll::stats). The counters are kept
+/// up to date as things happen, but not together: with other threads
+/// registering or polling meanwhile they don't add up to one point in time.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PollStats {
+    /// Calls to [`Poll::poll`](crate::Poll::poll).
+    pub polls: u64,
+    /// Completions taken from the completion port, wakeups included.
+    pub completions: u64,
+    /// Readiness polls submitted to the kernel, one per registration and
+    /// per rearm after an event.
+    pub submissions: u64,
+    /// Polls in flight cancelled, to change interests or to deregister.
+    pub cancellations: u64,
+    /// Sockets registered right now, counting deregistered ones whose
+    /// cancelled poll didn't complete yet. Not reset.
+    pub registered: u64,
+    /// Most registrations waiting at once for their poll to be submitted or
+    /// cancelled by the next [`Poll::poll`](crate::Poll::poll).
+    pub update_queue_high_water: u64,
+}
+
+/// Snapshot of a registration's internal state, for debugging.
+///
+/// Returned by [`Registry::registration_info`](crate::Registry::registration_info).
+#[cfg(feature = "debug-stats")]
+#[derive(Clone, Debug)]
+pub struct RegistrationInfo {
+    pub token: Token,
+    /// Current interests, `None` once a oneshot registration fired.
+    pub interests: Option<Interests>,
+    /// A poll is submitted to the kernel and hasn't completed yet.
+    pub poll_pending: bool,
+    /// Waiting in the update queue for a polling thread to pick it up.
+    pub update_enqueued: bool,
+    pub last_submit: Option<Instant>,
+    pub submit_count: usize,
+    /// What the last submitted poll watched for. `None` if it only watched
+    /// for errors and hang-ups, e.g. with writable readiness cached.
+    pub submitted_interests: Option<Interests>,
+    pub last_completion: Option<Instant>,
+    pub completion_count: usize,
+}
+
+/// How often registrations got the memory of an earlier one, instead of
+/// allocating theirs.
+///
+/// Returned by [`Registry::payload_stats`](crate::Registry::payload_stats).
+#[cfg(feature = "debug-stats")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PayloadStats {
+    pub reused: usize,
+    pub allocated: usize,
+}
+
+#[cfg(feature = "debug-stats")]
+impl PayloadStats {
+    /// The share of registrations that reused a payload, 0 to 1.
+    pub fn reuse_rate(&self) -> f64 {
+        match self.reused + self.allocated {
+            0 => 0.0,
+            total => self.reused as f64 / total as f64,
+        }
+    }
+}
+
+//How long `close` waits for cancelled polls to complete. They do right away,
+//unless the system is in trouble.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+//Completions a dequeue asks for at the least, capacity permitting
+const DEQUEUE_FLOOR: usize = 16;
+
+#[derive(Debug)]
+pub struct Events {
+    /// Raw I/O event completions are filled in here by the call to `get_many`
+    /// on the completion port above. These are then processed to run callbacks
+    /// which figure out what to do after the event is done.
+    statuses: Vec<CompletionStatus>,
+    //how many completions the next dequeue asks for, between the floor and
+    //`limit`. `statuses` grows to hold that many, and never shrinks.
+    request: usize,
+    //moving average of the completions a dequeue got
+    average: usize,
+    limit: usize,
+
+    /// Literal events returned by `get` to the upwards `EventLoop`. This file
+    /// doesn't really modify this (except for the waker), instead almost all
+    /// events are filled in by the `ReadinessQueue` from the `poll` module.
+    events: Vec<Event>,
+    //as asked for, `events` may have been given more
+    capacity: usize,
+}
+
+impl Events {
+    pub fn with_capacity(cap: usize) -> Events {
+        // Note that it's possible for the output `events` to grow beyond the
+        // capacity as it can also include deferred events, but that's certainly
+        // not the end of the world!
+        //The port won't be asked for no completions at all
+        let limit = cmp::max(cap, 1);
+        let request = cmp::min(DEQUEUE_FLOOR, limit);
+        Events {
+            statuses: vec![CompletionStatus::zero(); request],
+            request,
+            average: request,
+            limit,
+            events: Vec::with_capacity(cap),
+            capacity: cap,
+        }
+    }
+
+    //Room for the next dequeue, no more than the events left to fill
+    fn statuses(&mut self) -> &mut [CompletionStatus] {
+        if self.statuses.len() < self.request {
+            self.statuses.resize(self.request, CompletionStatus::zero());
+        }
+        let room = cmp::max(self.limit.saturating_sub(self.events.len()), 1);
+        &mut self.statuses[..cmp::min(self.request, room)]
+    }
+
+    fn has_room(&self) -> bool {
+        self.events.len() < self.limit
+    }
+
+    //Sizes the next dequeue after one that was `asked` for completions and
+    //got `n`. Mostly a few are waiting, and a large request is a buffer for
+    //the kernel to go through for nothing. One that came back full may have
+    //left many more behind: the request then grows fast, up to the capacity.
+    fn adapt(&mut self, asked: usize, n: usize) {
+        if n >= asked {
+            self.request = cmp::min(self.request * 4, self.limit);
+            self.average = self.request;
+        } else {
+            self.average = (self.average * 3 + n) / 4;
+            let floor = cmp::min(DEQUEUE_FLOOR, self.limit);
+            self.request = cmp::min(cmp::max(self.average * 2, floor), self.limit);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Event> {
+        self.events.get(idx)
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.truncate(0);
+    }
+}
+
+#[test]
+fn test_register_while_polling() -> io::Result<()> {
+    use std::{net, thread, time::Instant};
+
+    let selector = Arc::new(Selector::new()?);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    //Poll thread blocks on the port with nothing registered
+    let poller = {
+        let selector = selector.clone();
+        thread::spawn(move || {
+            let mut events = Events::with_capacity(16);
+            selector.select(&mut events, Some(Duration::from_secs(3)))
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    let mut streams = Vec::new();
+    for i in 0..64 {
+        let stream = net::TcpStream::connect(addr)?;
+        selector.register(raw_source(&stream), Token(i), Interests::READABLE)?;
+        streams.push(stream);
+    }
+    let elapsed = start.elapsed();
+
+    //Registering must not have waited for the blocked poll to time out
+    assert!(elapsed < Duration::from_secs(1), "took {:?}", elapsed);
+    assert_eq!(selector.inner.registered(), 64);
+
+    poller.join().unwrap()
+}
+
+#[test]
+fn test_concurrent_register() -> io::Result<()> {
+    use std::{net, thread};
+
+    let selector = Arc::new(Selector::new()?);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let workers: Vec<_> = (0..8)
+        .map(|t| {
+            let selector = selector.clone();
+            thread::spawn(move || -> io::Result<Vec<net::TcpStream>> {
+                let mut streams = Vec::new();
+                for i in 0..32 {
+                    let stream = net::TcpStream::connect(addr)?;
+                    selector.register(raw_source(&stream), Token(t * 32 + i), Interests::WRITABLE)?;
+                    streams.push(stream);
+                }
+                Ok(streams)
+            })
+        })
+        .collect();
+
+    let mut streams = Vec::new();
+    for worker in workers {
+        streams.extend(worker.join().unwrap()?);
+    }
+    assert_eq!(selector.inner.registered(), 8 * 32);
+
+    //Every registration went through: all of them report writable
+    let mut seen = std::collections::HashSet::new();
+    let mut events = Events::with_capacity(256);
+    while seen.len() < 8 * 32 {
+        selector.select(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(!events.is_empty(), "lost registrations");
+        for i in 0..events.len() {
+            seen.insert(crate::event::token(events.get(i).unwrap()));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_update_queue_stress() -> io::Result<()> {
+    use std::collections::HashSet;
+    use std::{net, thread};
+
+    const PRODUCERS: usize = 8;
+    const PER_PRODUCER: usize = 16;
+
+    let selector = Arc::new(Selector::new()?);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    //Single consumer, polling while the registrations come in
+    let poller = {
+        let selector = selector.clone();
+        thread::spawn(move || -> io::Result<HashSet<Token>> {
+            let mut seen = HashSet::new();
+            let mut events = Events::with_capacity(64);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while seen.len() < PRODUCERS * PER_PRODUCER && Instant::now() < deadline {
+                selector.select(&mut events, Some(Duration::from_millis(100)))?;
+                for i in 0..events.len() {
+                    seen.insert(crate::event::token(events.get(i).unwrap()));
+                }
+            }
+            Ok(seen)
+        })
+    };
+
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|p| {
+            let selector = selector.clone();
+            thread::spawn(move || -> io::Result<Vec<net::TcpStream>> {
+                let mut streams = Vec::new();
+                for i in 0..PER_PRODUCER {
+                    let stream = net::TcpStream::connect(addr)?;
+                    let token = Token(p * PER_PRODUCER + i);
+                    selector.register(raw_source(&stream), token, Interests::WRITABLE)?;
+                    streams.push(stream);
+                }
+                Ok(streams)
+            })
+        })
+        .collect();
+
+    let mut streams = Vec::new();
+    for producer in producers {
+        streams.extend(producer.join().unwrap()?);
+    }
+
+    //No update got lost on the way to the poll thread
+    let seen = poller.join().unwrap()?;
+    assert_eq!(seen.len(), PRODUCERS * PER_PRODUCER);
+
+    Ok(())
+}
+
+#[test]
+fn test_deregister_while_polling() -> io::Result<()> {
+    use std::{net, thread};
+
+    const SOCKETS: usize = 32;
+
+    let selector = Arc::new(Selector::new()?);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let mut streams = Vec::new();
+    for i in 0..SOCKETS {
+        let stream = net::TcpStream::connect(addr)?;
+        selector.register(raw_source(&stream), Token(i), Interests::WRITABLE)?;
+        streams.push(stream);
+    }
+
+    //Writable sockets keep rearming, so the poll thread is busy iterating
+    //over events while the other thread pulls the sockets out.
+    let streams = Arc::new(streams);
+    let done = Arc::new(AtomicBool::new(false));
+    let deregisterer = {
+        let (selector, streams) = (selector.clone(), streams.clone());
+        let done = done.clone();
+        thread::spawn(move || -> io::Result<()> {
+            thread::sleep(Duration::from_millis(50));
+            for stream in streams.iter() {
+                selector.deregister(raw_source(stream), None)?;
+                assert_eq!(
+                    selector.deregister(raw_source(stream), None).unwrap_err().kind(),
+                    io::ErrorKind::NotFound
+                );
+            }
+            done.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+    };
+
+    let mut events = Events::with_capacity(8);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let finished = done.load(Ordering::SeqCst);
+        selector.select(&mut events, Some(Duration::from_millis(50)))?;
+        for i in 0..events.len() {
+            let token = usize::from(crate::event::token(events.get(i).unwrap()));
+            //Fails once the other thread got to it
+            let _ = selector.clear_writable(raw_source(&streams[token]));
+        }
+        if finished {
+            //Anything that started after the last deregister sees nothing
+            assert!(events.is_empty());
+            if selector.inner.registered() == 0 {
+                break;
+            }
+        }
+        assert!(Instant::now() < deadline, "states never reclaimed");
+    }
+
+    deregisterer.join().unwrap()?;
+
+    //Reclaimed slots hand the sockets back for a fresh registration
+    selector.register(raw_source(&streams[0]), Token(0), Interests::WRITABLE)?;
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_reregister_token_only() -> io::Result<()> {
+    use std::{net, thread};
+
+    let selector = Arc::new(Selector::new()?);
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let stream = Arc::new(net::TcpStream::connect(listener.local_addr()?)?);
+    selector.register(raw_source(&*stream), Token(0), Interests::WRITABLE)?;
+
+    //Always writable, and the cache is cleared after every event, so a poll
+    //is in flight nearly all the time
+    let current = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let remapper = {
+        let (selector, stream) = (selector.clone(), stream.clone());
+        let (current, done) = (current.clone(), done.clone());
+        thread::spawn(move || -> io::Result<()> {
+            for token in 1..200 {
+                selector.reregister(raw_source(&*stream), None, Token(token), Interests::WRITABLE)?;
+                current.store(token, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(1));
+            }
+            done.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+    };
+
+    let mut events = Events::with_capacity(8);
+    while !done.load(Ordering::SeqCst) {
+        let before = current.load(Ordering::SeqCst);
+        selector.select(&mut events, Some(Duration::from_millis(100)))?;
+        for i in 0..events.len() {
+            let token = usize::from(crate::event::token(events.get(i).unwrap()));
+            assert!(token >= before, "stale token {} after {}", token, before);
+        }
+        //The poll that just completed is idle, so this doesn't cancel anything
+        if !events.is_empty() {
+            selector.clear_writable(raw_source(&*stream))?;
+        }
+    }
+    remapper.join().unwrap()?;
+
+    let sock_state = selector.inner.find(stream.as_raw_socket() as SOCKET, None)?;
+    assert_eq!(sock_state.lock().unwrap().cancel_count, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_fair_delivery() -> io::Result<()> {
+    use std::collections::HashSet;
+    use std::net;
+    use std::thread;
+
+    const SOCKETS: usize = 128;
+    const CAPACITY: usize = 16;
+
+    let selector = Selector::new()?;
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    let mut sockets = Vec::new();
+    for i in 0..SOCKETS {
+        let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+        selector.register(raw_source(&socket), Token(i), Interests::READABLE)?;
+        sender.send_to(b"ready", socket.local_addr()?)?;
+        sockets.push(socket);
+    }
+    //Every poll submitted and completed before the first call: all of them
+    //readable, and staying so with nothing ever read
+    selector.inner.update_events()?;
+    thread::sleep(Duration::from_millis(100));
+
+    let mut seen = HashSet::new();
+    let mut events = Events::with_capacity(CAPACITY);
+    for _ in 0..(SOCKETS + CAPACITY - 1) / CAPACITY {
+        selector.select(&mut events, Some(Duration::from_secs(1)))?;
+        assert!(events.len() <= CAPACITY);
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            assert!(crate::event::is_readable(event));
+            seen.insert(crate::event::token(event));
+        }
+    }
+
+    //Rearmed ahead of the others, the first sockets would take every slot
+    assert_eq!(seen.len(), SOCKETS);
+
+    Ok(())
+}
+
+//A socket state never submitted, for the tests of `report` below
+#[cfg(test)]
+fn unpolled_state() -> SockState {
+    let key = Slab::<()>::new().next_key();
+    let poll_group = PollGroup {
+        group_size: 0,
+        afd_helper_handle: NULL,
+        #[cfg(feature = "wsapoll")]
+        fallback: None,
+    };
+    SockState::new(0, key, 0, 0, poll_group)
+}
+
+#[test]
+fn test_report_merges_per_socket() {
+    use crate::event;
+
+    let mut state = unpolled_state();
+    let mut events = Vec::new();
+
+    //Readable, then a writable completion for the same socket in the same call
+    state.report(1, 1, &mut events, Event::new(Readiness::READABLE, Token(7)));
+    state.report(1, 1, &mut events, Event::new(Readiness::WRITABLE, Token(7)));
+    assert_eq!(events.len(), 1);
+    assert!(event::is_readable(&events[0]));
+    assert!(event::is_writable(&events[0]));
+
+    //A new call starts over
+    state.report(2, 1, &mut events, Event::new(Readiness::WRITABLE, Token(7)));
+    assert_eq!(events.len(), 2);
+    assert!(!event::is_readable(&events[1]));
+}
+
+#[test]
+fn test_report_raised_cap() {
+    use crate::event;
+
+    let mut state = unpolled_state();
+    let mut events = Vec::new();
+
+    state.report(1, 2, &mut events, Event::new(Readiness::READABLE, Token(7)));
+    state.report(1, 2, &mut events, Event::new(Readiness::WRITABLE, Token(7)));
+    assert_eq!(events.len(), 2);
+    assert!(event::is_readable(&events[0]) && !event::is_writable(&events[0]));
+    assert!(!event::is_readable(&events[1]) && event::is_writable(&events[1]));
+}
+
+#[test]
+fn test_report_each_readiness_once_per_call() {
+    use crate::event;
+
+    let mut state = unpolled_state();
+    let mut events = Vec::new();
+
+    //Even with room for more, readable is only reported once in call 1
+    state.report(1, 4, &mut events, Event::new(Readiness::READABLE, Token(7)));
+    state.report(1, 4, &mut events, Event::new(Readiness::READABLE, Token(7)));
+    state.report(
+        1,
+        4,
+        &mut events,
+        Event::new(Readiness::READABLE | Readiness::WRITABLE, Token(7)),
+    );
+    assert_eq!(events.len(), 2);
+    assert!(event::is_readable(&events[0]) && !event::is_writable(&events[0]));
+    assert!(!event::is_readable(&events[1]) && event::is_writable(&events[1]));
+
+    //Next call may report it again
+    state.report(2, 4, &mut events, Event::new(Readiness::READABLE, Token(7)));
+    assert_eq!(events.len(), 3);
+}
+
+#[test]
+fn test_register_error_context() -> io::Result<()> {
+    use crate::SocketError;
+    use winapi::um::winsock2::INVALID_SOCKET;
+
+    let selector = Selector::new()?;
+    let expected = ws_get_base_socket(&INVALID_SOCKET).unwrap_err();
+    assert!(expected.raw_os_error().is_some());
+
+    let err = selector
+        .register(INVALID_SOCKET, Token(42), Interests::READABLE)
+        .unwrap_err();
+    assert_eq!(err.kind(), expected.kind());
+    let message = err.to_string();
+    assert!(message.starts_with(&expected.to_string()), "{}", message);
+    assert!(message.contains("token=42"), "{}", message);
+    assert!(message.contains(&format!("socket={:#x}", INVALID_SOCKET)), "{}", message);
+    let context = SocketError::of(&err).unwrap();
+    assert_eq!(context.token(), Token(42));
+    assert_eq!(context.socket(), INVALID_SOCKET as u64);
+    assert_eq!(context.raw_os_error(), expected.raw_os_error());
+    let source = std::error::Error::source(context).unwrap();
+    let source = source.downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.raw_os_error(), expected.raw_os_error());
+    assert_eq!(selector.registered_count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_reregister_after_close() -> io::Result<()> {
+    use std::mem;
+    use std::net;
+    use std::os::windows::io::FromRawSocket;
+
+    let selector = Selector::new()?;
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let stream = net::TcpStream::connect(addr)?;
+    let socket = stream.as_raw_socket() as SOCKET;
+    selector.register(raw_source(&stream), Token(0), Interests::READABLE)?;
+    let mut events = Events::with_capacity(8);
+    selector.select(&mut events, Some(Duration::from_millis(50)))?;
+
+    //Closed while the poll is in flight: the kernel reports LOCAL_CLOSE
+    drop(stream);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while selector.inner.registered() > 0 {
+        assert!(Instant::now() < deadline, "closed socket never retired");
+        selector.select(&mut events, Some(Duration::from_millis(50)))?;
+    }
+
+    //Only stands for the handle value, never closed by us
+    let closed = unsafe { net::TcpStream::from_raw_socket(socket as _) };
+    let err = selector
+        .reregister(raw_source(&closed), None, Token(0), Interests::WRITABLE)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(err.to_string(), "socket is not registered; it was closed");
+    //Reported once, then forgotten
+    let err = selector.deregister(raw_source(&closed), None).unwrap_err();
+    assert_eq!(err.to_string(), "socket is not registered");
+    mem::forget(closed);
+
+    //Handle values are recycled quickly, the freed one comes back soon
+    let mut streams = Vec::new();
+    let reused = loop {
+        assert!(streams.len() < 64, "handle value never reused");
+        let stream = net::TcpStream::connect(addr)?;
+        if stream.as_raw_socket() as SOCKET == socket {
+            break stream;
+        }
+        streams.push(stream);
+    };
+    selector.register(raw_source(&reused), Token(1), Interests::WRITABLE)?;
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(crate::event::token(events.get(0).unwrap()), Token(1));
+
+    Ok(())
+}
+
+#[test]
+fn test_modify_interests() -> io::Result<()> {
+    use std::net;
+
+    let selector = Selector::new()?;
+    let listener = net::TcpListener::bind("127.0.0.1:0")?;
+    let stream = net::TcpStream::connect(listener.local_addr()?)?;
+    let add = |interests| {
+        move |current: Option<Interests>| {
+            Ok(current.map_or(interests, |current| current | interests))
+        }
+    };
+
+    selector.register(raw_source(&stream), Token(0), Interests::READABLE)?;
+    let mut events = Events::with_capacity(8);
+    selector.select(&mut events, Some(Duration::from_millis(50)))?;
+    let sock_state = selector.inner.find(stream.as_raw_socket() as SOCKET, None)?;
+    assert!(sock_state.lock().unwrap().poll_state == SockPollState::Pending);
+
+    //Already a subset: the poll in flight stays
+    selector.modify_interests(raw_source(&stream), Token(0), add(Interests::READABLE))?;
+    {
+        let state = sock_state.lock().unwrap();
+        assert!(!state.update_enqueued);
+        assert_eq!(state.cancel_count, 0);
+    }
+
+    selector.modify_interests(raw_source(&stream), Token(0), add(Interests::WRITABLE))?;
+    assert!(sock_state.lock().unwrap().update_enqueued);
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(crate::event::is_writable(events.get(0).unwrap()));
+    assert_eq!(sock_state.lock().unwrap().cancel_count, 1);
+    assert_eq!(
+        sock_state.lock().unwrap().interests,
+        Some(Interests::READABLE | Interests::WRITABLE)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_dequeue() -> io::Result<()> {
+    use std::net;
+
+    const SOCKETS: usize = 1000;
+
+    //What `select` reports until every socket was seen, and how many times
+    //the port was asked for it
+    fn collect(capacity: usize) -> io::Result<(HashMap<Token, Readiness>, usize)> {
+        let selector = Selector::new()?;
+        let mut sockets = Vec::with_capacity(SOCKETS);
+        for i in 0..SOCKETS {
+            let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+            selector.register(raw_source(&socket), Token(i), Interests::WRITABLE)?;
+            sockets.push(socket);
+        }
+
+        let mut seen = HashMap::new();
+        let mut events = Events::with_capacity(capacity);
+        let before = selector.inner.dequeues.load(Ordering::Relaxed);
+        for _ in 0..SOCKETS * 3 {
+            selector.select(&mut events, Some(Duration::from_secs(1)))?;
+            for i in 0..events.len() {
+                let event = events.get(i).unwrap();
+                seen.insert(crate::event::token(event), event.readiness());
+            }
+            if seen.len() == SOCKETS {
+                break;
+            }
+        }
+        let dequeues = selector.inner.dequeues.load(Ordering::Relaxed) - before;
+        Ok((seen, dequeues))
+    }
+
+    let (batched, batched_dequeues) = collect(1024)?;
+    let (single, single_dequeues) = collect(1)?;
+    assert_eq!(batched.len(), SOCKETS);
+    assert_eq!(batched, single);
+    assert!(batched.values().all(|readiness| readiness.is_writable()));
+
+    assert!(single_dequeues >= SOCKETS, "{}", single_dequeues);
+    assert!(batched_dequeues < SOCKETS / 10, "{}", batched_dequeues);
+
+    //An empty buffer still takes one completion at a time
+    let selector = Selector::new()?;
+    let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+    selector.register(raw_source(&socket), Token(0), Interests::WRITABLE)?;
+    let mut events = Events::with_capacity(0);
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+
+    Ok(())
+}
+
+//A completion failing in the middle of a batch: the others are still
+//reported, the error comes with the next call, and none of the three sockets
+//is left pending
+#[test]
+fn test_failed_completion_in_batch() -> io::Result<()> {
+    use std::net;
+
+    let selector = Selector::new()?;
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    let mut sockets = Vec::new();
+    for i in 0..3 {
+        let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+        selector.register(raw_source(&socket), Token(i), Interests::READABLE)?;
+        sender.send_to(b"ping", socket.local_addr()?)?;
+        sockets.push(socket);
+    }
+    //Submitted and completed, the three completions wait on the port
+    selector.inner.update_events()?;
+    thread::sleep(Duration::from_millis(100));
+    let middle = selector.inner.find(raw_source(&sockets[1]), None)?;
+    middle.lock().unwrap().fail_completion = true;
+
+    let mut events = Events::with_capacity(8);
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    let mut tokens: Vec<Token> = (0..events.len())
+        .map(|i| crate::event::token(events.get(i).unwrap()))
+        .collect();
+    tokens.sort();
+    assert_eq!(tokens, [Token(0), Token(2)]);
+
+    let err = selector
+        .select(&mut events, Some(Duration::from_secs(1)))
+        .unwrap_err();
+    let context = crate::SocketError::of(&err).unwrap();
+    assert_eq!(context.raw_os_error(), Some(ERROR_INVALID_HANDLE as i32));
+    assert_eq!(context.token(), Token(1));
+    assert!(events.is_empty());
+
+    //Rearmed, with the datagrams still unread: all three come back
+    let mut seen = HashSet::new();
+    for _ in 0..10 {
+        selector.select(&mut events, Some(Duration::from_secs(1)))?;
+        for i in 0..events.len() {
+            seen.insert(crate::event::token(events.get(i).unwrap()));
+        }
+        if seen.len() == 3 {
+            break;
+        }
+    }
+    assert_eq!(seen.len(), 3);
+
+    for socket in &sockets {
+        selector.deregister(raw_source(socket), None)?;
+    }
+    Ok(())
+}
+
+//A cancel failing on deregistering: the socket stays registered, for the
+//next try to deregister it for good
+#[test]
+fn test_failed_deregister() -> io::Result<()> {
+    use crate::SocketError;
+    use std::net;
+
+    let selector = Selector::new()?;
+    let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+    selector.register(raw_source(&socket), Token(0), Interests::READABLE)?;
+    //Nothing to read, the poll submitted stays pending
+    selector.inner.update_events()?;
+    let state = selector.inner.find(raw_source(&socket), None)?;
+    assert!(state.lock().unwrap().poll_state == SockPollState::Pending);
+    state.lock().unwrap().fail_cancel = true;
+
+    let err = selector.deregister(raw_source(&socket), None).unwrap_err();
+    let context = SocketError::of(&err).unwrap();
+    assert_eq!(context.raw_os_error(), Some(ERROR_INVALID_HANDLE as i32));
+    assert_eq!(selector.registered_count(), 1);
+
+    selector.deregister(raw_source(&socket), None)?;
+    assert_eq!(selector.registered_count(), 0);
+    let err = selector.deregister(raw_source(&socket), None).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    Ok(())
+}
+
+//Counts the allocations of each thread, for `test_empty_poll`
+#[cfg(test)]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    struct Counting;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    pub fn allocations() -> usize {
+        ALLOCATIONS.with(|count| count.get())
+    }
+}
+
+#[test]
+fn test_empty_poll() -> io::Result<()> {
+    use std::net;
+
+    let selector = Selector::new()?;
+    let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+    //Registered, but with nothing to report
+    selector.register(raw_source(&socket), Token(0), Interests::READABLE)?;
+    let mut events = Events::with_capacity(64);
+    selector.select(&mut events, Some(Duration::from_millis(10)))?;
+    assert!(events.is_empty());
+
+    let allocations = counting::allocations();
+    let dequeues = selector.inner.dequeues.load(Ordering::Relaxed);
+    for _ in 0..1000 {
+        selector.select(&mut events, Some(Duration::from_secs(0)))?;
+        assert!(events.is_empty());
+    }
+    assert_eq!(counting::allocations(), allocations);
+    assert_eq!(
+        selector.inner.dequeues.load(Ordering::Relaxed) - dequeues,
+        1000
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_relative_time() {
+    assert_eq!(relative_time(Duration::from_secs(0)), 0);
+    assert_eq!(relative_time(Duration::from_micros(250)), -2_500);
+    assert_eq!(relative_time(Duration::from_secs(1)), -10_000_000);
+    assert_eq!(relative_time(Duration::new(3, 500)), -30_000_005);
+    //Never shorter than asked
+    assert_eq!(relative_time(Duration::from_nanos(1)), -1);
+    assert_eq!(relative_time(Duration::from_nanos(150)), -2);
+    assert_eq!(relative_time(Duration::new(u64::MAX, 999_999_999)), -i64::MAX);
+    assert_eq!(relative_time(Duration::from_secs(i64::MAX as u64 / 10_000_000 + 1)), -i64::MAX);
+}
+
+#[test]
+fn test_dequeue_precise() -> io::Result<()> {
+    let selector = Selector::new()?;
+    let mut statuses = vec![CompletionStatus::zero(); 4];
+    assert_eq!(
+        selector
+            .inner
+            .dequeue_precise(&mut statuses, Some(Duration::from_micros(100)))?,
+        0
+    );
+
+    //Read back as GetQueuedCompletionStatusEx() would have
+    let mut overlapped = Overlapped::zero();
+    let port = selector.port();
+    port.post(CompletionStatus::new(7, HANDLE_KEY + 1, &mut overlapped))?;
+    port.post(CompletionStatus::new(9, HANDLE_KEY + 2, null_mut()))?;
+    let n = selector.inner.dequeue_precise(&mut statuses, None)?;
+    assert_eq!(n, 2);
+    assert_eq!(statuses[0].bytes_transferred(), 7);
+    assert_eq!(statuses[0].token(), HANDLE_KEY + 1);
+    assert_eq!(statuses[0].overlapped(), overlapped.raw());
+    assert_eq!(statuses[1].bytes_transferred(), 9);
+    assert_eq!(statuses[1].token(), HANDLE_KEY + 2);
+    assert!(statuses[1].overlapped().is_null());
+
+    Ok(())
+}
+
+#[test]
+fn test_poll_info_inline() {
+    //One handle takes nothing more than the struct itself and a tag
+    let inline = mem::size_of::<AFD_POLL_INFO>() + mem::size_of::<usize>();
+    assert!(mem::size_of::<PollInfo>() <= inline);
+    let single = PollInfo::single(NULL, AFD_POLL_LOCAL_CLOSE);
+    match single {
+        PollInfo::Single(ref info) => {
+            let info_ptr = info as *const AFD_POLL_INFO as *const u8;
+            assert!(info_ptr >= &single as *const PollInfo as *const u8);
+            assert!(info_ptr < unsafe { (&single as *const PollInfo).add(1) } as *const u8);
+        }
+        PollInfo::Batch(_) => panic!("single handle spilled"),
+    }
+    assert_eq!(single.handles().len(), 1);
+    assert_eq!(single.handles()[0].Events, AFD_POLL_LOCAL_CLOSE);
+}
+
+#[test]
+fn test_poll_batch() -> io::Result<()> {
+    use super::afd::AFD_POLL_SEND;
+    use crate::EPOLLOUT;
+    use std::net;
+
+    let selector = Selector::new()?;
+    let port = selector.port().as_raw_handle();
+    let poll_group = PollGroup::new(&port)?;
+    let sockets = (0..3)
+        .map(|_| net::UdpSocket::bind("127.0.0.1:0"))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut bases = Vec::new();
+    for socket in &sockets {
+        bases.push(ws_get_base_socket(&(socket.as_raw_socket() as SOCKET))? as HANDLE);
+    }
+
+    let polled: Vec<_> = bases
+        .iter()
+        .map(|&base| (base, sock_epoll_events_to_afd_events(EPOLLOUT)))
+        .collect();
+    let mut poll_info = PollInfo::batch(&polled);
+    match poll_info {
+        PollInfo::Batch(_) => {}
+        PollInfo::Single(_) => panic!("batch kept inline"),
+    }
+    let mut overlapped = OVERLAPPED::default();
+    match poll_info.submit(&poll_group, &mut overlapped) {
+        Ok(()) => {}
+        Err(ref e) if e.raw_os_error() == Some(ERROR_IO_PENDING as _) => {}
+        Err(e) => return Err(e),
+    }
+
+    //All writable right away, the kernel hands back which ones it saw
+    let mut statuses = [CompletionStatus::zero(); 1];
+    let n = selector
+        .inner
+        .dequeue(&mut statuses, Some(Duration::from_secs(1)))?;
+    assert_eq!(n, 1);
+    assert_eq!(statuses[0].overlapped(), &mut overlapped as *mut OVERLAPPED);
+    assert_eq!(overlapped.Internal as NTSTATUS, STATUS_SUCCESS);
+    let handles = poll_info.handles();
+    assert!(!handles.is_empty() && handles.len() <= bases.len());
+    for handle in handles {
+        assert!(bases.contains(&handle.Handle));
+        assert!(handle.Events & AFD_POLL_SEND != 0);
+    }
+
+    unsafe { CloseHandle(poll_group.afd_helper_handle) };
+    Ok(())
+}
+
+#[test]
+fn test_poll_group_reuse() -> io::Result<()> {
+    let port = CompletionPort::new(0)?;
+    let mut queue = PollGroupQueue::new(&port);
+    let max = MAX_SOCKET_PER_POLL_GROUP as usize;
+
+    let mut taken = Vec::new();
+    for _ in 0..2 * max {
+        taken.push(queue.acquire()?);
+    }
+    assert_eq!(queue.len(), 2);
+    //Slots given back in the first group are taken before a third is made
+    for pg in taken.drain(..max / 2) {
+        queue.release(&pg);
+    }
+    for _ in 0..max / 2 {
+        taken.push(queue.acquire()?);
+    }
+    assert_eq!(queue.len(), 2);
+    taken.push(queue.acquire()?);
+    assert_eq!(queue.len(), 3);
+
+    for pg in taken.drain(..) {
+        queue.release(&pg);
+    }
+    queue.compact();
+    assert_eq!(queue.len(), 0);
+    assert!(queue.with_room.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_registration_size() {
+    //Per registration besides the table slot and the by-socket entry: the
+    //whole block with its counts, and the state with the kernel's payload
+    let block = mem::size_of::<SockEntry>() + 2 * mem::size_of::<usize>();
+    assert!(block <= 512, "{} bytes", block);
+}
+
+#[test]
+fn test_register_allocates_once() -> io::Result<()> {
+    use std::net;
+
+    let selector = Selector::new()?;
+    let mut events = Events::with_capacity(64);
+    let sockets = (0..32)
+        .map(|_| net::UdpSocket::bind("127.0.0.1:0"))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    //Grows the tables, then leaves the blocks in the pool once the cancelled
+    //polls completed
+    for (i, socket) in sockets[..16].iter().enumerate() {
+        selector.register(raw_source(socket), Token(i), Interests::READABLE)?;
+    }
+    selector.select(&mut events, Some(Duration::from_millis(10)))?;
+    for socket in &sockets[..16] {
+        selector.deregister(raw_source(socket), None)?;
+    }
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while selector.inner.registered() > 0 {
+        assert!(Instant::now() < deadline, "cancelled polls never completed");
+        selector.select(&mut events, Some(Duration::from_millis(10)))?;
+    }
+
+    //Blocks of the earlier registrations are reused
+    let allocations = counting::allocations();
+    for (i, socket) in sockets[16..24].iter().enumerate() {
+        selector.register(raw_source(socket), Token(i), Interests::READABLE)?;
+    }
+    assert_eq!(counting::allocations(), allocations);
+
+    //Otherwise a registration is one block, nothing else
+    selector.inner.payload_pool.free.lock().unwrap().clear();
+    let allocations = counting::allocations();
+    selector.register(raw_source(&sockets[24]), Token(24), Interests::READABLE)?;
+    assert_eq!(counting::allocations(), allocations + 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_sharded_registration_contention() -> io::Result<()> {
+    use std::net;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    const THREADS: usize = 16;
+    const PER_THREAD: usize = 100;
+
+    let selector = Selector::with_config(SelectorConfig {
+        shards: THREADS,
+        ..SelectorConfig::default()
+    })?;
+    let done = Arc::new(AtomicBool::new(false));
+
+    //Keeps completions coming in, each taking a shard's read lock
+    let poller = {
+        let (selector, done) = (selector.clone(), done.clone());
+        thread::spawn(move || -> io::Result<()> {
+            let mut events = Events::with_capacity(256);
+            while !done.load(Ordering::SeqCst) {
+                selector.select(&mut events, Some(Duration::from_millis(10)))?;
+            }
+            Ok(())
+        })
+    };
+
+    let workers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let selector = selector.clone();
+            thread::spawn(move || -> io::Result<Duration> {
+                let mut sockets = Vec::with_capacity(PER_THREAD);
+                let mut slowest = Duration::from_secs(0);
+                for i in 0..PER_THREAD {
+                    let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+                    let started = Instant::now();
+                    let token = Token(t * PER_THREAD + i);
+                    selector.register(raw_source(&socket), token, Interests::WRITABLE)?;
+                    slowest = cmp::max(slowest, started.elapsed());
+                    sockets.push(socket);
+                }
+                for socket in &sockets {
+                    selector.deregister(raw_source(socket), None)?;
+                }
+                Ok(slowest)
+            })
+        })
+        .collect();
+
+    let mut slowest = Duration::from_secs(0);
+    for worker in workers {
+        slowest = cmp::max(slowest, worker.join().unwrap()?);
+    }
+    done.store(true, Ordering::SeqCst);
+    poller.join().unwrap()?;
+
+    //Generous, a machine under load may preempt a thread for a while
+    assert!(slowest < Duration::from_millis(200), "{:?}", slowest);
+
+    Ok(())
+}
+
+#[test]
+fn test_adaptive_dequeue_request() -> io::Result<()> {
+    use std::collections::HashSet;
+    use std::net;
+
+    const SOCKETS: usize = 1000;
+
+    let selector = Selector::new()?;
+    let mut events = Events::with_capacity(1024);
+    assert_eq!(events.request, DEQUEUE_FLOOR);
+
+    //A burst: the request grows to fetch it in few calls, all of it is seen
+    let mut sockets = Vec::with_capacity(SOCKETS);
+    for i in 0..SOCKETS {
+        let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+        selector.register(raw_source(&socket), Token(i), Interests::WRITABLE)?;
+        sockets.push(socket);
+    }
+    let mut seen = HashSet::new();
+    let mut largest = 0;
+    for _ in 0..100 {
+        selector.select(&mut events, Some(Duration::from_secs(1)))?;
+        largest = cmp::max(largest, events.request);
+        for i in 0..events.len() {
+            seen.insert(crate::event::token(events.get(i).unwrap()));
+        }
+        if seen.len() == SOCKETS {
+            break;
+        }
+    }
+    assert_eq!(seen.len(), SOCKETS);
+    assert_eq!(largest, 1024);
+    for socket in &sockets {
+        selector.deregister(raw_source(socket), None)?;
+    }
+    let buffer = events.statuses.len();
+    assert_eq!(buffer, 1024);
+
+    //A trickle: one datagram at a time, each reported, the request shrinks
+    let receiver = net::UdpSocket::bind("127.0.0.1:0")?;
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    receiver.set_nonblocking(true)?;
+    selector.register(raw_source(&receiver), Token(SOCKETS), Interests::READABLE)?;
+    let mut buf = [0; 8];
+    for _ in 0..30 {
+        assert_eq!(sender.send_to(b"tick", receiver.local_addr()?)?, 4);
+        let mut readable = false;
+        while !readable {
+            selector.select(&mut events, Some(Duration::from_secs(1)))?;
+            assert!(!events.is_empty(), "datagram not reported");
+            readable = (0..events.len()).any(|i| {
+                let event = events.get(i).unwrap();
+                crate::event::token(event) == Token(SOCKETS) && crate::event::is_readable(event)
+            });
+        }
+        assert_eq!(receiver.recv(&mut buf)?, 4);
+    }
+    assert_eq!(events.request, DEQUEUE_FLOOR);
+    //The buffer stays as large as it got
+    assert_eq!(events.statuses.len(), buffer);
+
+    Ok(())
+}
+
+#[test]
+fn test_nonblocking_poll_drains_port() -> io::Result<()> {
+    use crate::event;
+    use std::collections::HashSet;
+
+    const PACKETS: usize = 200;
+
+    let selector = Selector::new()?;
+    for i in 0..PACKETS {
+        let status = CompletionStatus::new(i as u32, HANDLE_KEY + i, null_mut());
+        selector.port().post(status)?;
+    }
+
+    //More than a first dequeue asks for, all of it in one call
+    let mut events = Events::with_capacity(256);
+    selector.select(&mut events, Some(Duration::from_secs(0)))?;
+    assert_eq!(events.len(), PACKETS);
+    let tokens: HashSet<_> = (0..events.len())
+        .map(|i| event::token(events.get(i).unwrap()))
+        .collect();
+    assert_eq!(tokens.len(), PACKETS);
+
+    //Never past the capacity, the rest is left for the next call
+    for i in 0..PACKETS {
+        let status = CompletionStatus::new(0, HANDLE_KEY + i, null_mut());
+        selector.port().post(status)?;
+    }
+    let mut events = Events::with_capacity(64);
+    selector.select(&mut events, Some(Duration::from_secs(0)))?;
+    assert_eq!(events.len(), 64);
+    let mut total = events.len();
+    while total < PACKETS {
+        selector.select(&mut events, Some(Duration::from_secs(0)))?;
+        assert!(!events.is_empty() && events.len() <= 64);
+        total += events.len();
+    }
+    assert_eq!(total, PACKETS);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "wsapoll"))]
+#[test]
+fn test_afd_denied() -> io::Result<()> {
+    use std::net;
+
+    let selector = Selector::with_config(SelectorConfig {
+        deny_afd: true,
+        ..SelectorConfig::default()
+    })?;
+    let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+    let err = selector
+        .register(raw_source(&socket), Token(0), Interests::READABLE)
+        .unwrap_err();
+    let err = crate::SocketError::of(&err).unwrap();
+    assert_eq!(err.raw_os_error(), Some(ERROR_ACCESS_DENIED as i32));
+    assert_eq!(selector.registered_count(), 0);
+
+    Ok(())
+}
+
+#[cfg(feature = "wsapoll")]
+#[test]
+fn test_wsapoll_cancel() -> io::Result<()> {
+    use crate::event;
+    use std::net;
+
+    let selector = Selector::with_config(SelectorConfig {
+        deny_afd: true,
+        ..SelectorConfig::default()
+    })?;
+    let mut events = Events::with_capacity(8);
+    let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+    let sender = net::UdpSocket::bind("127.0.0.1:0")?;
+    selector.register(raw_source(&socket), Token(1), Interests::READABLE)?;
+    assert!(selector.uses_wsapoll());
+
+    //Pending on the thread, then changed: the poll is cancelled and armed
+    //again for the new interests
+    selector.select(&mut events, Some(Duration::from_millis(10)))?;
+    assert!(events.is_empty());
+    let interests = Interests::READABLE | Interests::WRITABLE;
+    selector.reregister(raw_source(&socket), None, Token(2), interests)?;
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(event::token(events.get(0).unwrap()), Token(2));
+    assert!(event::is_writable(events.get(0).unwrap()));
+
+    selector.reregister(raw_source(&socket), None, Token(3), Interests::READABLE)?;
+    sender.send_to(b"ping", socket.local_addr()?)?;
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_readable(events.get(0).unwrap()));
+
+    //Level-triggered, as over AFD: the datagram is still there
+    selector.select(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert!(event::is_readable(events.get(0).unwrap()));
+
+    //The poll in flight completes as cancelled, and the slot goes
+    selector.deregister(raw_source(&socket), None)?;
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while selector.inner.registered() > 0 {
+        assert!(Instant::now() < deadline, "cancelled poll never completed");
+        selector.select(&mut events, Some(Duration::from_millis(10)))?;
+        assert!(events.is_empty());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::{cmp, mem};
+
+//Key into a `Slab`. The generation tells apart successive values stored in
+//the same slot, so a key kept around after removal never resolves to the
+//value that reused its slot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct SlabKey {
+    index: u32,
+    generation: u32,
+}
+
+enum Entry<T> {
+    Occupied(T),
+    //index of the next vacant slot, `usize::max_value()` ends the list
+    Vacant(usize),
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Entry<T>,
+}
+
+//Storage with stable indices, O(1) insert/remove, and reuse of freed slots
+pub(crate) struct Slab<T> {
+    slots: Vec<Slot<T>>,
+    next_vacant: usize,
+    len: usize,
+    //generation of slots pushed from now on. Past every generation of the
+    //slots dropped by `shrink_to_fit`, so their old keys stay dead.
+    generation: u32,
+}
+
+const END: usize = usize::max_value();
+
+impl<T> Slab<T> {
+    pub fn new() -> Slab<T> {
+        Slab {
+            slots: Vec::new(),
+            next_vacant: END,
+            len: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    //Key the next `insert` is going to return
+    pub fn next_key(&self) -> SlabKey {
+        match self.next_vacant {
+            END => SlabKey {
+                index: self.slots.len() as u32,
+                generation: self.generation,
+            },
+            index => SlabKey {
+                index: index as u32,
+                generation: self.slots[index].generation,
+            },
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> SlabKey {
+        let key = self.next_key();
+        self.len += 1;
+
+        if self.next_vacant == END {
+            self.slots.push(Slot {
+                generation: self.generation,
+                entry: Entry::Occupied(value),
+            });
+            return key;
+        }
+
+        let slot = &mut self.slots[self.next_vacant];
+        match mem::replace(&mut slot.entry, Entry::Occupied(value)) {
+            Entry::Vacant(next) => self.next_vacant = next,
+            Entry::Occupied(_) => unreachable!(),
+        }
+
+        key
+    }
+
+    pub fn get(&self, key: SlabKey) -> Option<&T> {
+        match self.slots.get(key.index as usize) {
+            Some(Slot {
+                generation,
+                entry: Entry::Occupied(value),
+            }) if *generation == key.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, key: SlabKey) -> Option<T> {
+        self.get(key)?;
+
+        let index = key.index as usize;
+        let slot = &mut self.slots[index];
+        //Outdate every key handed out for this slot so far
+        slot.generation = slot.generation.wrapping_add(1);
+        self.len -= 1;
+
+        match mem::replace(&mut slot.entry, Entry::Vacant(self.next_vacant)) {
+            Entry::Occupied(value) => {
+                self.next_vacant = index;
+                Some(value)
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+    }
+
+    //Drops the vacant slots at the end and gives their memory back.
+    //Occupied slots never move, so every live key stays valid.
+    pub fn shrink_to_fit(&mut self) {
+        while let Some(Slot {
+            generation,
+            entry: Entry::Vacant(_),
+        }) = self.slots.last()
+        {
+            self.generation = cmp::max(self.generation, *generation);
+            self.slots.pop();
+        }
+        self.slots.shrink_to_fit();
+
+        //Relink what's left of the vacant list, lowest index first
+        self.next_vacant = END;
+        for (index, slot) in self.slots.iter_mut().enumerate().rev() {
+            if let Entry::Vacant(ref mut next) = slot.entry {
+                *next = self.next_vacant;
+                self.next_vacant = index;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_slab_churn_stays_bounded() {
+    let mut slab = Slab::new();
+
+    for round in 0..10_000 {
+        let keys: Vec<_> = (0..8).map(|i| slab.insert(round * 8 + i)).collect();
+        for key in keys {
+            assert!(slab.remove(key).is_some());
+        }
+    }
+
+    assert_eq!(slab.len(), 0);
+    assert_eq!(slab.capacity(), 8);
+}
+
+#[test]
+fn test_slab_reused_slot_rejects_old_key() {
+    let mut slab = Slab::new();
+
+    let old = slab.insert("old");
+    assert_eq!(slab.remove(old), Some("old"));
+
+    let next = slab.next_key();
+    let new = slab.insert("new");
+    assert_eq!(new, next);
+    assert_eq!(new.index, old.index);
+    assert_eq!(slab.get(old), None);
+    assert_eq!(slab.remove(old), None);
+    assert_eq!(slab.get(new), Some(&"new"));
+}
+
+#[test]
+fn test_slab_shrink_to_fit() {
+    let mut slab = Slab::new();
+
+    let keys: Vec<_> = (0..1000).map(|i| slab.insert(i)).collect();
+    for key in &keys[1..] {
+        slab.remove(*key);
+    }
+    slab.shrink_to_fit();
+    assert_eq!(slab.capacity(), 1);
+    assert_eq!(slab.get(keys[0]), Some(&0));
+
+    //Keys of the dropped slots don't resolve to whatever takes their place
+    let new = slab.insert(1000);
+    assert_eq!(new.index, keys[1].index);
+    assert_eq!(slab.get(keys[1]), None);
+    assert_eq!(slab.get(new), Some(&1000));
+}
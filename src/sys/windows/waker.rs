@@ -0,0 +1,38 @@
+use super::selector::Selector;
+use crate::event::Event;
+use crate::readiness::Readiness;
+use crate::token::Token;
+use miow::iocp::CompletionStatus;
+use miow::Overlapped;
+use std::io;
+
+//Completion key of wake-ups, see `ACCEPT_KEY`
+pub(crate) const WAKER_KEY: usize = 7;
+
+//Nothing is registered: a wake-up is posted straight to the port
+pub(crate) struct Waker {
+    selector: Selector,
+    token: Token,
+}
+
+impl Waker {
+    pub(crate) fn new(selector: &Selector, token: Token) -> io::Result<Waker> {
+        Ok(Waker {
+            selector: selector.clone(),
+            token,
+        })
+    }
+
+    pub(crate) fn wake(&self) -> io::Result<()> {
+        //The token rides in the overlapped pointer, nothing is behind it
+        let status =
+            CompletionStatus::new(0, WAKER_KEY, usize::from(self.token) as *mut Overlapped);
+        self.selector.port().post(status)
+    }
+}
+
+//Handles a completion taken from the port with `WAKER_KEY`
+pub(crate) fn complete(status: &CompletionStatus, events: &mut Vec<Event>) {
+    let token = Token::from(status.overlapped() as usize);
+    events.push(Event::new(Readiness::READABLE, token));
+}
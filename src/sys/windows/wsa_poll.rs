@@ -0,0 +1,379 @@
+//Stand-in for the AFD device where opening it is denied, as in AppContainer
+//sandboxes. A helper thread runs WSAPoll() over the sockets that have a poll
+//submitted, and completes each poll the way the kernel would: the results are
+//written to its AFD_POLL_INFO and its OVERLAPPED is posted to the port with
+//the key of AFD completions. The selector can't tell the difference.
+use super::afd::{
+    AFD_POLL_ABORT, AFD_POLL_ACCEPT, AFD_POLL_CONNECT_FAIL, AFD_POLL_DISCONNECT,
+    AFD_POLL_HANDLE_INFO, AFD_POLL_INFO, AFD_POLL_LOCAL_CLOSE, AFD_POLL_RECEIVE,
+    AFD_POLL_RECEIVE_EXPEDITED, AFD_POLL_SEND,
+};
+use super::net::{get_opt, set_opt};
+use std::collections::HashMap;
+use std::io;
+use std::net::UdpSocket;
+use std::os::windows::io::AsRawSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::ntdef::NTSTATUS;
+use winapi::shared::ntstatus::{STATUS_CANCELLED, STATUS_PENDING, STATUS_SUCCESS};
+use winapi::shared::ws2def::{SOCKADDR_STORAGE, SOL_SOCKET, SO_ERROR};
+use winapi::um::ioapiset::PostQueuedCompletionStatus;
+use winapi::um::minwinbase::OVERLAPPED;
+use winapi::um::winnt::HANDLE;
+use winapi::um::winsock2::{
+    getpeername, WSAGetLastError, WSAPoll, POLLERR, POLLHUP, POLLNVAL, POLLRDBAND, POLLRDNORM,
+    POLLWRNORM, SOCKET, SOCKET_ERROR, WSAPOLLFD,
+};
+
+//A poll submitted and not completed yet. The pointers are into the payload
+//of a registration, which stays put until the completion has been handled.
+struct Request {
+    //tells this submission apart from a later one of the same payload
+    id: u64,
+    overlapped: *mut OVERLAPPED,
+    count: *mut ULONG,
+    handles: *mut AFD_POLL_HANDLE_INFO,
+    len: usize,
+    //only reported what wasn't asked for, see `run`
+    parked: bool,
+}
+
+struct State {
+    //by overlapped address
+    requests: HashMap<usize, Request>,
+    next_id: u64,
+    stopped: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    iocp: HANDLE,
+    //bound and connected to itself: a datagram sent wakes up WSAPoll()
+    waker: UdpSocket,
+    //set while a wakeup datagram is on its way
+    wake_pending: AtomicBool,
+}
+
+//Raw pointers and handles inside are only touched with `state` locked
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+pub(crate) struct WsaPoll {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WsaPoll {
+    pub fn new(iocp: HANDLE) -> io::Result<WsaPoll> {
+        let waker = UdpSocket::bind("127.0.0.1:0")?;
+        waker.connect(waker.local_addr()?)?;
+        waker.set_nonblocking(true)?;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                requests: HashMap::new(),
+                next_id: 0,
+                stopped: false,
+            }),
+            iocp,
+            waker,
+            wake_pending: AtomicBool::new(false),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("iocp-wrapper-wsapoll".into())
+                .spawn(move || run(&shared))?
+        };
+        Ok(WsaPoll {
+            shared,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    //Like afd_poll(): the poll is pending once this returns, and completes
+    //through the port
+    pub fn submit<const N: usize>(
+        &self,
+        poll_info: &mut AFD_POLL_INFO<N>,
+        overlapped: &mut OVERLAPPED,
+    ) -> io::Result<()> {
+        debug_assert!(poll_info.NumberOfHandles as usize <= N);
+        let mut state = self.shared.state.lock().unwrap();
+        overlapped.Internal = STATUS_PENDING as usize;
+        let id = state.next_id;
+        state.next_id += 1;
+        state.requests.insert(
+            overlapped as *mut OVERLAPPED as usize,
+            Request {
+                id,
+                overlapped,
+                count: &mut poll_info.NumberOfHandles,
+                handles: poll_info.Handles.as_mut_ptr(),
+                len: poll_info.NumberOfHandles as usize,
+                parked: false,
+            },
+        );
+        drop(state);
+        self.shared.wake();
+        Ok(())
+    }
+
+    //Like afd_cancel(): the poll completes as cancelled, unless it completed
+    //already
+    pub fn cancel(&self, overlapped: &mut OVERLAPPED) {
+        let mut state = self.shared.state.lock().unwrap();
+        let key = overlapped as *mut OVERLAPPED as usize;
+        if let Some(request) = state.requests.remove(&key) {
+            unsafe { complete(&self.shared, &state, &request, STATUS_CANCELLED) };
+            drop(state);
+            self.shared.wake();
+        }
+    }
+
+    //Nothing is posted once this returns, the port may go
+    pub fn stop(&self) {
+        self.shared.state.lock().unwrap().stopped = true;
+        self.shared.wake();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WsaPoll {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Shared {
+    fn wake(&self) {
+        if !self.wake_pending.swap(true, Ordering::SeqCst) {
+            let _ = self.waker.send(&[0]);
+        }
+    }
+}
+
+//How often polls parked by `run` are looked at again
+const PARK_INTERVAL: c_int = 50;
+
+fn run(shared: &Shared) {
+    let waker = shared.waker.as_raw_socket() as SOCKET;
+    let mut fds = Vec::new();
+    let mut parked_fds = Vec::new();
+    //requests polled, by key and id, whether they were parked, and the index
+    //of their first handle in `fds` or `parked_fds`
+    let mut polled = Vec::new();
+    loop {
+        fds.clear();
+        parked_fds.clear();
+        polled.clear();
+        fds.push(poll_fd(waker, POLLRDNORM));
+        {
+            let state = shared.state.lock().unwrap();
+            if state.stopped {
+                return;
+            }
+            for (&key, request) in state.requests.iter() {
+                let fds = if request.parked {
+                    &mut parked_fds
+                } else {
+                    &mut fds
+                };
+                polled.push((key, request.id, request.parked, fds.len()));
+                for handle in unsafe { request.handles() } {
+                    fds.push(poll_fd(
+                        handle.Handle as SOCKET,
+                        to_poll_events(handle.Events),
+                    ));
+                }
+            }
+        }
+
+        let mut error = None;
+        let timeout = if parked_fds.is_empty() {
+            -1
+        } else {
+            error = poll(&mut parked_fds, 0).err();
+            PARK_INTERVAL
+        };
+        if error.is_none() {
+            error = poll(&mut fds, timeout).err();
+        }
+        if fds[0].revents != 0 {
+            //Cleared first: a wakeup sent while draining is not lost
+            shared.wake_pending.store(false, Ordering::SeqCst);
+            let mut buf = [0; 16];
+            while shared.waker.recv(&mut buf).is_ok() {}
+        }
+
+        let mut state = shared.state.lock().unwrap();
+        if state.stopped {
+            return;
+        }
+        for &(key, id, parked, first) in polled.iter() {
+            let request = match state.requests.get_mut(&key) {
+                Some(request) if request.id == id => request,
+                //Cancelled, and maybe submitted again, while we were waiting
+                _ => continue,
+            };
+            let status = match error {
+                Some(error) => ntwin32_status(error),
+                None => {
+                    let fds = if parked { &parked_fds } else { &fds };
+                    let fds = &fds[first..first + request.len];
+                    match unsafe { fill(request, fds) } {
+                        Filled::Ready => STATUS_SUCCESS,
+                        //Reported something that isn't asked for, a hang-up
+                        //most likely. It would be reported again right away,
+                        //so it is only looked at now and then.
+                        Filled::Unwanted => {
+                            request.parked = true;
+                            continue;
+                        }
+                        Filled::Nothing => continue,
+                    }
+                }
+            };
+            let request = state.requests.remove(&key).unwrap();
+            unsafe { complete(shared, &state, &request, status) };
+        }
+    }
+}
+
+fn poll(fds: &mut [WSAPOLLFD], timeout: c_int) -> Result<(), c_int> {
+    if fds.is_empty() {
+        return Ok(());
+    }
+    match unsafe { WSAPoll(fds.as_mut_ptr(), fds.len() as ULONG, timeout) } {
+        SOCKET_ERROR => Err(unsafe { WSAGetLastError() }),
+        _ => Ok(()),
+    }
+}
+
+impl Request {
+    unsafe fn handles(&self) -> &[AFD_POLL_HANDLE_INFO] {
+        std::slice::from_raw_parts(self.handles, self.len)
+    }
+}
+
+fn poll_fd(fd: SOCKET, events: i16) -> WSAPOLLFD {
+    WSAPOLLFD {
+        fd,
+        events,
+        revents: 0,
+    }
+}
+
+//WSAPoll() rejects the flags it reports on its own
+fn to_poll_events(afd_events: ULONG) -> i16 {
+    let mut events = 0;
+    if afd_events & (AFD_POLL_RECEIVE | AFD_POLL_ACCEPT) != 0 {
+        events |= POLLRDNORM;
+    }
+    if afd_events & AFD_POLL_RECEIVE_EXPEDITED != 0 {
+        events |= POLLRDBAND;
+    }
+    if afd_events & AFD_POLL_SEND != 0 {
+        events |= POLLWRNORM;
+    }
+    events
+}
+
+//What AFD would have reported for `revents`, of the events asked for
+fn to_afd_events(socket: SOCKET, revents: i16, afd_events: ULONG) -> (ULONG, NTSTATUS) {
+    if revents & POLLNVAL != 0 {
+        return (AFD_POLL_LOCAL_CLOSE & afd_events, STATUS_SUCCESS);
+    }
+    let mut events = 0;
+    let mut status = STATUS_SUCCESS;
+    if revents & POLLRDNORM != 0 {
+        events |= AFD_POLL_RECEIVE | AFD_POLL_ACCEPT;
+    }
+    if revents & POLLRDBAND != 0 {
+        events |= AFD_POLL_RECEIVE_EXPEDITED;
+    }
+    if revents & POLLWRNORM != 0 {
+        events |= AFD_POLL_SEND;
+    }
+    if revents & POLLHUP != 0 {
+        events |= AFD_POLL_DISCONNECT;
+    }
+    if revents & POLLERR != 0 {
+        //Taking SO_ERROR clears it, it is put right back. The status carries
+        //it too, the selector stores what it finds there as it does for AFD.
+        let error = get_opt::<c_int>(socket, SOL_SOCKET, SO_ERROR).unwrap_or(0);
+        if error != 0 {
+            let _ = set_opt(socket, SOL_SOCKET, SO_ERROR, error);
+        }
+        if error != 0 && !is_connected(socket) {
+            events |= AFD_POLL_CONNECT_FAIL;
+            status = ntwin32_status(error);
+        } else {
+            events |= AFD_POLL_ABORT;
+        }
+    }
+    (events & afd_events, status)
+}
+
+fn is_connected(socket: SOCKET) -> bool {
+    let mut addr: SOCKADDR_STORAGE = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<SOCKADDR_STORAGE>() as c_int;
+    unsafe { getpeername(socket, &mut addr as *mut _ as *mut _, &mut len) != SOCKET_ERROR }
+}
+
+//The NTSTATUS of facility NTWIN32 RtlNtStatusToDosError() turns back into
+//the Win32 or winsock code `code`
+fn ntwin32_status(code: c_int) -> NTSTATUS {
+    (0xC007_0000u32 | (code as u32 & 0xFFFF)) as NTSTATUS
+}
+
+enum Filled {
+    Ready,
+    Unwanted,
+    Nothing,
+}
+
+//Writes the handles that got events to the front, as AFD does. Nothing is
+//written unless one did.
+unsafe fn fill(request: &Request, fds: &[WSAPOLLFD]) -> Filled {
+    let results: Vec<_> = request
+        .handles()
+        .iter()
+        .zip(fds)
+        .map(|(handle, fd)| {
+            let (events, status) = to_afd_events(fd.fd, fd.revents, handle.Events);
+            (handle.Handle, events, status)
+        })
+        .filter(|&(_, events, _)| events != 0)
+        .collect();
+    if results.is_empty() {
+        return if fds.iter().any(|fd| fd.revents != 0) {
+            Filled::Unwanted
+        } else {
+            Filled::Nothing
+        };
+    }
+    for (i, &(handle, events, status)) in results.iter().enumerate() {
+        *request.handles.add(i) = AFD_POLL_HANDLE_INFO {
+            Handle: handle,
+            Events: events,
+            Status: status,
+        };
+    }
+    *request.count = results.len() as ULONG;
+    Filled::Ready
+}
+
+//With `state` locked: once stopped the port may be gone, nothing is posted
+unsafe fn complete(shared: &Shared, state: &State, request: &Request, status: NTSTATUS) {
+    (*request.overlapped).Internal = status as usize;
+    if !state.stopped {
+        PostQueuedCompletionStatus(shared.iocp, 0, 0, request.overlapped);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::event;
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::token::Token;
+use crate::wait::ThreadPoolWait;
+use std::convert::TryFrom;
+use std::io;
+use std::mem;
+use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::ptr::null_mut;
+use std::time::Duration;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::synchapi::{
+    CancelWaitableTimer, CreateWaitableTimerExW, SetWaitableTimer, WaitForSingleObject,
+};
+use winapi::um::winnt::{HANDLE, LARGE_INTEGER, LONG, TIMER_ALL_ACCESS};
+
+/// A timer firing as events: a readable event with the registration's token
+/// each time it expires.
+///
+/// Built on a waitable timer and the same thread pool wait as
+/// [`WaitableHandle`]. Setting or cancelling the timer supersedes what it
+/// was set to before, nothing of an earlier deadline is reported after that.
+/// The due times follow the system clock's tick, 15.6ms by default.
+///
+/// [`WaitableHandle`]: crate::WaitableHandle
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::{Events, Interests, Poll, Timer, Token};
+/// use std::time::Duration;
+///
+/// let mut poll = Poll::new()?;
+/// let timer = Timer::new()?;
+/// poll.registry()
+///     .register(&timer, Token(0), Interests::READABLE)?;
+/// timer.set_oneshot(Duration::from_millis(50))?;
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Timer {
+    handle: HANDLE,
+    wait: ThreadPoolWait,
+}
+
+//The timer handle is only touched through thread safe APIs
+unsafe impl Send for Timer {}
+unsafe impl Sync for Timer {}
+
+impl Timer {
+    /// Creates a timer, not set.
+    pub fn new() -> io::Result<Timer> {
+        //A synchronization timer: the wait consumes every expiry
+        let handle = unsafe { CreateWaitableTimerExW(null_mut(), null_mut(), 0, TIMER_ALL_ACCESS) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Timer {
+            handle,
+            wait: ThreadPoolWait::new(),
+        })
+    }
+
+    /// Fires once, `after` from now.
+    pub fn set_oneshot(&self, after: Duration) -> io::Result<()> {
+        self.set(after, 0)
+    }
+
+    /// Fires every `period` from now on, until cancelled or set again. The
+    /// period is rounded up to whole milliseconds.
+    pub fn set_periodic(&self, period: Duration) -> io::Result<()> {
+        let millis = period.as_nanos().div_ceil(1_000_000);
+        let millis = LONG::try_from(millis.max(1))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "timer period is too long"))?;
+        self.set(period, millis)
+    }
+
+    /// Stops the timer. An expiry not polled yet isn't reported anymore.
+    pub fn cancel(&self) -> io::Result<()> {
+        self.wait.paused(self.handle, false, || {
+            if unsafe { CancelWaitableTimer(self.handle) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.consume();
+            Ok(())
+        })
+    }
+
+    fn set(&self, after: Duration, period: LONG) -> io::Result<()> {
+        //Negative: relative, in 100ns
+        let due = i64::try_from(after.as_nanos() / 100).unwrap_or(i64::MAX);
+        let mut due_time: LARGE_INTEGER = unsafe { mem::zeroed() };
+        unsafe { *due_time.QuadPart_mut() = -due.max(1) };
+
+        self.wait.paused(self.handle, false, || {
+            //Setting keeps the signaled state, an expiry of the old deadline
+            //in between would be seen by the new wait
+            unsafe { CancelWaitableTimer(self.handle) };
+            self.consume();
+            let r =
+                unsafe { SetWaitableTimer(self.handle, &due_time, period, None, null_mut(), 0) };
+            if r == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        })
+    }
+
+    //Resets the timer if it expired with no wait to see it
+    fn consume(&self) {
+        unsafe { WaitForSingleObject(self.handle, 0) };
+    }
+}
+
+impl AsRawHandle for Timer {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.handle as RawHandle
+    }
+}
+
+impl event::Source for Timer {
+    fn register(&self, registry: &Registry, token: Token, _interests: Interests) -> io::Result<()> {
+        self.wait.register(self.handle, registry, token, false)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        self.wait.reregister(self.handle, registry, token, false)
+    }
+
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        self.wait.deregister()
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.wait.stop();
+        unsafe { CloseHandle(self.handle) };
+    }
+}
+
+#[cfg(test)]
+fn count_events(poll: &mut crate::Poll, window: Duration) -> io::Result<Vec<Duration>> {
+    use crate::Events;
+    use std::time::Instant;
+
+    let mut events = Events::with_capacity(16);
+    let mut fired = Vec::new();
+    let start = Instant::now();
+    while start.elapsed() < window {
+        let timeout = window.checked_sub(start.elapsed()).unwrap_or_default();
+        poll.poll(&mut events, Some(timeout))?;
+        for i in 0..events.len() {
+            let event = events.get(i).unwrap();
+            assert_eq!(event::token(event), Token(1));
+            assert!(event::is_readable(event));
+            fired.push(start.elapsed());
+        }
+    }
+    Ok(fired)
+}
+
+#[test]
+fn test_timer_oneshot() -> io::Result<()> {
+    use crate::Poll;
+
+    let mut poll = Poll::new()?;
+    let timer = Timer::new()?;
+    poll.registry()
+        .register(&timer, Token(1), Interests::READABLE)?;
+
+    timer.set_oneshot(Duration::from_millis(50))?;
+    let fired = count_events(&mut poll, Duration::from_millis(300))?;
+    assert_eq!(fired.len(), 1);
+    //A tick early at worst
+    assert!(fired[0] >= Duration::from_millis(34), "{:?}", fired);
+    assert!(fired[0] < Duration::from_millis(200), "{:?}", fired);
+
+    Ok(())
+}
+
+#[test]
+fn test_timer_periodic() -> io::Result<()> {
+    use crate::Poll;
+
+    let mut poll = Poll::new()?;
+    let timer = Timer::new()?;
+    poll.registry()
+        .register(&timer, Token(1), Interests::READABLE)?;
+
+    timer.set_periodic(Duration::from_millis(20))?;
+    let fired = count_events(&mut poll, Duration::from_millis(100))?;
+    assert!(fired.len() >= 4, "{:?}", fired);
+
+    //Nothing after a cancel, not even an expiry already posted
+    timer.cancel()?;
+    let fired = count_events(&mut poll, Duration::from_millis(100))?;
+    assert!(fired.is_empty(), "{:?}", fired);
+
+    Ok(())
+}
+
+#[test]
+fn test_timer_reset() -> io::Result<()> {
+    use crate::Poll;
+
+    let mut poll = Poll::new()?;
+    let timer = Timer::new()?;
+    poll.registry()
+        .register(&timer, Token(1), Interests::READABLE)?;
+
+    //The second deadline supersedes the first
+    timer.set_oneshot(Duration::from_millis(20))?;
+    timer.set_oneshot(Duration::from_millis(200))?;
+    let fired = count_events(&mut poll, Duration::from_millis(400))?;
+    assert_eq!(fired.len(), 1);
+    assert!(fired[0] >= Duration::from_millis(150), "{:?}", fired);
+
+    //Also once the first one expired, unpolled
+    timer.set_oneshot(Duration::from_millis(10))?;
+    std::thread::sleep(Duration::from_millis(50));
+    timer.set_oneshot(Duration::from_secs(10))?;
+    let fired = count_events(&mut poll, Duration::from_millis(100))?;
+    assert!(fired.is_empty(), "{:?}", fired);
+
+    Ok(())
+}
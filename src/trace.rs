@@ -0,0 +1,214 @@
+#[cfg(feature = "trace")]
+use crate::interests::Interests;
+#[cfg(feature = "trace")]
+use crate::readiness::Readiness;
+#[cfg(feature = "trace")]
+use crate::token::Token;
+#[cfg(feature = "trace")]
+use std::fmt;
+#[cfg(feature = "trace")]
+use std::time::Duration;
+
+//Logs `$event` through `log`, under the module it happens in. Without the
+//`trace` feature this is nothing at all: the event isn't even built.
+macro_rules! trace {
+    ($event:expr) => {
+        #[cfg(feature = "trace")]
+        {
+            let event: crate::trace::Trace = $event;
+            log::log!(event.level(), "{}", event);
+        }
+    };
+}
+
+//A step of the poller's work. Steps of a socket start with `token=N`, to
+//follow a single one through the log. Kernel polls are only made on Windows.
+#[cfg(feature = "trace")]
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Trace {
+    Register {
+        token: Token,
+        interests: Interests,
+    },
+    Reregister {
+        token: Token,
+        interests: Interests,
+    },
+    Deregister {
+        token: Token,
+    },
+    //A readiness poll was handed to the kernel for the socket, for the
+    //raw AFD event flags `afd_events`. `error` is the OS error code it
+    //failed with, if it did.
+    Submit {
+        token: Token,
+        afd_events: u32,
+        error: Option<i32>,
+    },
+    //The poll in flight was cancelled, to change interests or to deregister.
+    Cancel {
+        token: Token,
+    },
+    //A poll completed with the NTSTATUS `status` and the raw AFD event
+    //flags `afd_events`, and was translated to `readiness`. None if no
+    //event came out of it, as for a cancelled poll.
+    Completion {
+        token: Token,
+        status: i32,
+        afd_events: u32,
+        readiness: Option<Readiness>,
+    },
+    //A deregistered socket's state was freed, or kept for reuse, now that
+    //the kernel was done with its last poll.
+    Reclaim {
+        token: Token,
+    },
+    //The socket was still registered when the `Poll` was dropped, see
+    //`Poll::registered_count`.
+    Leak {
+        token: Token,
+    },
+    //A call to `Poll::poll` started.
+    PollEnter {
+        timeout: Option<Duration>,
+    },
+    //A call to `Poll::poll` returned with `events` events.
+    PollExit {
+        events: usize,
+    },
+}
+
+#[cfg(feature = "trace")]
+impl Trace {
+    //The registry's own work at debug, each poll and completion at trace
+    pub(crate) fn level(&self) -> log::Level {
+        match *self {
+            Trace::Register { .. }
+            | Trace::Reregister { .. }
+            | Trace::Deregister { .. }
+            | Trace::Leak { .. } => log::Level::Debug,
+            _ => log::Level::Trace,
+        }
+    }
+
+    fn token(&self) -> Option<Token> {
+        match *self {
+            Trace::Register { token, .. }
+            | Trace::Reregister { token, .. }
+            | Trace::Deregister { token }
+            | Trace::Submit { token, .. }
+            | Trace::Cancel { token }
+            | Trace::Completion { token, .. }
+            | Trace::Reclaim { token }
+            | Trace::Leak { token } => Some(token),
+            Trace::PollEnter { .. } | Trace::PollExit { .. } => None,
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+impl fmt::Display for Trace {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(token) = self.token() {
+            write!(fmt, "token={} ", usize::from(token))?;
+        }
+        match *self {
+            Trace::Register { interests, .. } => write!(fmt, "register {:?}", interests),
+            Trace::Reregister { interests, .. } => write!(fmt, "reregister {:?}", interests),
+            Trace::Deregister { .. } => fmt.write_str("deregister"),
+            Trace::Submit {
+                afd_events, error, ..
+            } => {
+                write!(fmt, "submit afd_events={:#x}", afd_events)?;
+                match error {
+                    Some(error) => write!(fmt, " error={}", error),
+                    None => Ok(()),
+                }
+            }
+            Trace::Cancel { .. } => fmt.write_str("cancel"),
+            Trace::Completion {
+                status,
+                afd_events,
+                readiness,
+                ..
+            } => write!(
+                fmt,
+                "completion status={:#x} afd_events={:#x} readiness={:?}",
+                status, afd_events, readiness
+            ),
+            Trace::Reclaim { .. } => fmt.write_str("reclaim"),
+            Trace::Leak { .. } => fmt.write_str("leak"),
+            Trace::PollEnter { timeout } => write!(fmt, "poll timeout={:?}", timeout),
+            Trace::PollExit { events } => write!(fmt, "poll returned events={}", events),
+        }
+    }
+}
+
+//Runs `f`, returning with it the messages of this crate it logged on the
+//current thread, as tests of other threads log through the same logger
+#[cfg(all(test, feature = "trace"))]
+pub(crate) fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    use std::cell::RefCell;
+    use std::sync::Once;
+
+    thread_local! {
+        static LINES: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    }
+
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+            metadata.target().starts_with("iocp_wrapper")
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            let _ = LINES.try_with(|lines| {
+                if let Some(ref mut lines) = *lines.borrow_mut() {
+                    lines.push(record.args().to_string());
+                }
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture;
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+    LINES.with(|lines| *lines.borrow_mut() = Some(Vec::new()));
+    let r = f();
+    (r, LINES.with(|lines| lines.borrow_mut().take().unwrap()))
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_trace_display() {
+    let register = Trace::Register {
+        token: Token(3),
+        interests: Interests::READABLE,
+    };
+    assert_eq!(register.to_string(), "token=3 register READABLE");
+    assert_eq!(register.level(), log::Level::Debug);
+    let submit = Trace::Submit {
+        token: Token(3),
+        afd_events: 0x1f,
+        error: Some(6),
+    };
+    assert_eq!(submit.to_string(), "token=3 submit afd_events=0x1f error=6");
+    assert_eq!(submit.level(), log::Level::Trace);
+    let exit = Trace::PollExit { events: 2 };
+    assert_eq!(exit.to_string(), "poll returned events=2");
+
+    let ((), lines) = capture(|| {
+        trace!(register.clone());
+    });
+    assert_eq!(lines, ["token=3 register READABLE"]);
+}
@@ -0,0 +1,420 @@
+use crate::error::{misuse, ErrorKind};
+use crate::event::{self, Event};
+use crate::interests::Interests;
+use crate::poll::Registry;
+use crate::readiness::Readiness;
+use crate::sys::Selector;
+use crate::token::Token;
+use miow::iocp::CompletionStatus;
+use miow::Overlapped;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::ptr::null_mut;
+use std::sync::{Arc, Mutex};
+use winapi::shared::ntdef::{BOOLEAN, PVOID};
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::minwinbase::OVERLAPPED;
+use winapi::um::threadpoollegacyapiset::UnregisterWaitEx;
+use winapi::um::winbase::{RegisterWaitForSingleObject, INFINITE};
+use winapi::um::winnt::{HANDLE, WT_EXECUTEDEFAULT, WT_EXECUTEONLYONCE};
+
+//Completion key of thread pool waits, see `ACCEPT_KEY`
+pub(crate) const WAIT_KEY: usize = 3;
+
+/// How often a [`WaitableHandle`] is reported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitMode {
+    /// Once, for the first signal after registering.
+    Once,
+    /// For every wait that succeeds. An auto-reset event or a semaphore is
+    /// reported once per signal, a manual-reset event over and over until it
+    /// is reset.
+    Recurring,
+}
+
+/// Reports a waitable handle getting signaled as an event: an event, a
+/// semaphore, a mutex, anything `WaitForSingleObject` takes.
+///
+/// Registering starts a thread pool wait on the handle, each signal it sees
+/// is a readable event with the registration's token. Deregistering waits
+/// for a callback in flight, nothing is reported for the handle afterwards.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::{Events, Interests, Poll, Token, WaitMode, WaitableHandle};
+/// use std::process::Command;
+///
+/// let mut poll = Poll::new()?;
+/// //Any type owning the handle, here a process: signaled once it exits
+/// let child = Command::new("cmd").args(["/C", "exit"]).spawn()?;
+/// let handle = WaitableHandle::new(child, WaitMode::Once);
+/// poll.registry()
+///     .register(&handle, Token(0), Interests::READABLE)?;
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct WaitableHandle<H> {
+    inner: H,
+    mode: WaitMode,
+    wait: ThreadPoolWait,
+}
+
+impl<H: AsRawHandle> WaitableHandle<H> {
+    pub fn new(handle: H, mode: WaitMode) -> WaitableHandle<H> {
+        WaitableHandle {
+            inner: handle,
+            mode,
+            wait: ThreadPoolWait::new(),
+        }
+    }
+
+    /// Returns the handle, deregistered first.
+    pub fn into_inner(self) -> H {
+        self.wait.stop();
+        //Moved out past `Drop`, which would only stop the wait again
+        let this = ManuallyDrop::new(self);
+        unsafe {
+            drop(std::ptr::read(&this.wait));
+            std::ptr::read(&this.inner)
+        }
+    }
+
+    pub fn mode(&self) -> WaitMode {
+        self.mode
+    }
+}
+
+impl<H: AsRawHandle> AsRawHandle for WaitableHandle<H> {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.inner.as_raw_handle()
+    }
+}
+
+impl<H: AsRawHandle> event::Source for WaitableHandle<H> {
+    fn register(&self, registry: &Registry, token: Token, _interests: Interests) -> io::Result<()> {
+        let handle = self.inner.as_raw_handle() as HANDLE;
+        self.wait
+            .register(handle, registry, token, self.mode == WaitMode::Once)
+    }
+
+    fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interests,
+    ) -> io::Result<()> {
+        let handle = self.inner.as_raw_handle() as HANDLE;
+        self.wait
+            .reregister(handle, registry, token, self.mode == WaitMode::Once)
+    }
+
+    fn deregister(&self, _registry: &Registry) -> io::Result<()> {
+        self.wait.deregister()
+    }
+}
+
+impl<H> Drop for WaitableHandle<H> {
+    fn drop(&mut self) {
+        self.wait.stop();
+    }
+}
+
+//A thread pool wait posting a packet to the port each time its handle is
+//signaled. The packets of an earlier registration are dropped unreported.
+pub(crate) struct ThreadPoolWait {
+    current: Arc<Mutex<Option<(usize, Token)>>>,
+    //wait of the current registration, and the number of registrations so far
+    state: Mutex<(Option<Wait>, usize)>,
+}
+
+struct Wait {
+    handle: HANDLE,
+    token: Token,
+    //the wait's own reference, every packet in flight holds another one
+    context: *const Context,
+}
+
+//Leaked if the port is closed with packets still queued
+struct Context {
+    current: Arc<Mutex<Option<(usize, Token)>>>,
+    generation: usize,
+    selector: Selector,
+}
+
+//The raw handles are only touched under the lock, through thread safe APIs
+unsafe impl Send for ThreadPoolWait {}
+unsafe impl Sync for ThreadPoolWait {}
+
+impl ThreadPoolWait {
+    pub fn new() -> ThreadPoolWait {
+        ThreadPoolWait {
+            current: Arc::new(Mutex::new(None)),
+            state: Mutex::new((None, 0)),
+        }
+    }
+
+    pub fn register(
+        &self,
+        handle: HANDLE,
+        registry: &Registry,
+        token: Token,
+        once: bool,
+    ) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.0.is_some() {
+            return Err(misuse(
+                ErrorKind::AlreadyRegistered,
+                Some(token),
+                "handle is already registered",
+            ));
+        }
+        self.start(&mut state, handle, registry.selector(), token, once)
+    }
+
+    pub fn reregister(
+        &self,
+        handle: HANDLE,
+        registry: &Registry,
+        token: Token,
+        once: bool,
+    ) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match state.0.take() {
+            Some(wait) => self.stop_wait(wait),
+            None => return Err(not_registered(Some(token))),
+        }
+        self.start(&mut state, handle, registry.selector(), token, once)
+    }
+
+    //Runs `f` with the wait stopped, then starts it again with the same
+    //token. What was posted before is dropped unreported.
+    pub fn paused<F>(&self, handle: HANDLE, once: bool, f: F) -> io::Result<()>
+    where
+        F: FnOnce() -> io::Result<()>,
+    {
+        let mut state = self.state.lock().unwrap();
+        let wait = match state.0.take() {
+            Some(wait) => wait,
+            None => return f(),
+        };
+        let (token, selector) = (wait.token, unsafe { (*wait.context).selector.clone() });
+        self.stop_wait(wait);
+
+        let r = f();
+        self.start(&mut state, handle, &selector, token, once)?;
+        r
+    }
+
+    pub fn deregister(&self) -> io::Result<()> {
+        if self.stop() {
+            Ok(())
+        } else {
+            Err(not_registered(None))
+        }
+    }
+
+    //Returns false if there was no wait to stop
+    pub fn stop(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.0.take() {
+            Some(wait) => {
+                self.stop_wait(wait);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn start(
+        &self,
+        state: &mut (Option<Wait>, usize),
+        handle: HANDLE,
+        selector: &Selector,
+        token: Token,
+        once: bool,
+    ) -> io::Result<()> {
+        state.1 += 1;
+        let generation = state.1;
+        *self.current.lock().unwrap() = Some((generation, token));
+
+        let context = Arc::into_raw(Arc::new(Context {
+            current: self.current.clone(),
+            generation,
+            selector: selector.clone(),
+        }));
+        let flags = if once {
+            WT_EXECUTEONLYONCE
+        } else {
+            WT_EXECUTEDEFAULT
+        };
+        let mut wait = null_mut();
+        let r = unsafe {
+            RegisterWaitForSingleObject(
+                &mut wait,
+                handle,
+                Some(signaled),
+                context as PVOID,
+                INFINITE,
+                flags,
+            )
+        };
+        if r == 0 {
+            let e = io::Error::last_os_error();
+            drop(unsafe { Arc::from_raw(context) });
+            *self.current.lock().unwrap() = None;
+            return Err(e);
+        }
+
+        state.0 = Some(Wait {
+            handle: wait,
+            token,
+            context,
+        });
+        Ok(())
+    }
+
+    fn stop_wait(&self, wait: Wait) {
+        *self.current.lock().unwrap() = None;
+        //Blocks until a callback in flight returned: no post comes after this
+        unsafe { UnregisterWaitEx(wait.handle, INVALID_HANDLE_VALUE) };
+        drop(unsafe { Arc::from_raw(wait.context) });
+    }
+}
+
+fn not_registered(token: Option<Token>) -> io::Error {
+    misuse(ErrorKind::NotRegistered, token, "handle is not registered")
+}
+
+//Runs on a thread pool thread each time the handle is signaled
+unsafe extern "system" fn signaled(context: PVOID, _timed_out: BOOLEAN) {
+    //Borrowed from the wait, the packet gets a reference of its own
+    let context = ManuallyDrop::new(Arc::from_raw(context as *const Context));
+    let packet = Arc::into_raw(Arc::clone(&context));
+
+    let status = CompletionStatus::new(0, WAIT_KEY, packet as *mut Overlapped);
+    if context.selector.port().post(status).is_err() {
+        drop(Arc::from_raw(packet));
+    }
+}
+
+//Handles a completion taken from the port with `WAIT_KEY`
+pub(crate) unsafe fn complete(overlapped: *mut OVERLAPPED, events: &mut Vec<Event>) {
+    let context = Arc::from_raw(overlapped as *const Context);
+    let current = *context.current.lock().unwrap();
+    match current {
+        Some((generation, token)) if generation == context.generation => {
+            events.push(Event::new(Readiness::READABLE, token));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+struct TestEvent(HANDLE);
+
+#[cfg(test)]
+impl TestEvent {
+    //Auto-reset: every successful wait consumes one signal
+    fn new() -> io::Result<TestEvent> {
+        use winapi::um::synchapi::CreateEventW;
+
+        let handle = unsafe { CreateEventW(null_mut(), 0, 0, null_mut()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(TestEvent(handle))
+    }
+
+    //From another thread, like foreign code would
+    fn signal_from_thread(&self) {
+        use winapi::um::synchapi::SetEvent;
+
+        let handle = self.0 as usize;
+        std::thread::spawn(move || unsafe { SetEvent(handle as HANDLE) })
+            .join()
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+impl AsRawHandle for TestEvent {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.0 as RawHandle
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestEvent {
+    fn drop(&mut self) {
+        unsafe { winapi::um::handleapi::CloseHandle(self.0) };
+    }
+}
+
+#[test]
+fn test_waitable_handle_recurring() -> io::Result<()> {
+    use crate::event::{is_readable, token};
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let handle = WaitableHandle::new(TestEvent::new()?, WaitMode::Recurring);
+    poll.registry()
+        .register(&handle, Token(5), Interests::READABLE)?;
+
+    for _ in 0..5 {
+        handle.inner.signal_from_thread();
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(token(events.get(0).unwrap()), Token(5));
+        assert!(is_readable(events.get(0).unwrap()));
+
+        poll.poll(&mut events, Some(Duration::from_millis(50)))?;
+        assert!(events.is_empty());
+    }
+
+    //No late post after deregistering
+    poll.registry().deregister(&handle)?;
+    handle.inner.signal_from_thread();
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_waitable_handle_once() -> io::Result<()> {
+    use crate::event::token;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let handle = WaitableHandle::new(TestEvent::new()?, WaitMode::Once);
+    poll.registry()
+        .register(&handle, Token(6), Interests::READABLE)?;
+
+    handle.inner.signal_from_thread();
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(token(events.get(0).unwrap()), Token(6));
+
+    handle.inner.signal_from_thread();
+    poll.poll(&mut events, Some(Duration::from_millis(100)))?;
+    assert!(events.is_empty());
+
+    //Rearmed by a reregistration, the pending signal is seen right away
+    poll.registry()
+        .reregister(&handle, Token(7), Interests::READABLE)?;
+    poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(token(events.get(0).unwrap()), Token(7));
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use crate::poll::Registry;
+use crate::sys;
+use crate::token::Token;
+use std::io;
+
+/// Wakes up a thread blocked in [`Poll::poll`] from any other thread.
+///
+/// A wake-up is a readable event carrying the waker's token. On Windows
+/// nothing is registered: the waker posts straight to the poll's completion
+/// port, each call to [`Waker::wake`] an event of its own. With epoll the
+/// waker is an eventfd registered for it, with kqueue an `EVFILT_USER` filter
+/// of the poll's kqueue, and wake-ups before the next poll are merged into one
+/// event.
+///
+/// [`Poll::poll`]: crate::Poll::poll
+///
+/// # Examples
+///
+#[cfg_attr(feature = "os-poll", doc = "```no_run")]
+#[cfg_attr(not(feature = "os-poll"), doc = "```ignore")]
+/// # fn main() -> std::io::Result<()> {
+/// use iocp_wrapper::{Events, Poll, Token, Waker};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let mut poll = Poll::new()?;
+/// let waker = Arc::new(Waker::new(poll.registry(), Token(0))?);
+/// let remote = waker.clone();
+/// thread::spawn(move || remote.wake());
+///
+/// let mut events = Events::with_capacity(8);
+/// poll.poll(&mut events, None)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Waker {
+    inner: sys::Waker,
+}
+
+impl Waker {
+    pub fn new(registry: &Registry, token: Token) -> io::Result<Waker> {
+        sys::Waker::new(registry.selector(), token).map(|inner| Waker { inner })
+    }
+
+    pub fn wake(&self) -> io::Result<()> {
+        self.inner.wake()
+    }
+}